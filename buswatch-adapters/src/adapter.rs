@@ -0,0 +1,43 @@
+//! The common interface implemented by every adapter.
+
+use std::future::Future;
+
+use buswatch_types::Snapshot;
+
+use crate::AdapterError;
+
+/// A source of snapshots collected from a message bus.
+///
+/// Implemented by [`RabbitMqAdapter`](crate::rabbitmq::RabbitMqAdapter),
+/// [`KafkaAdapter`](crate::kafka::KafkaAdapter) and
+/// [`NatsAdapter`](crate::nats::NatsAdapter), and usable for your own
+/// collectors. Implementations can write `async fn collect`; the returned
+/// future must be `Send` so adapters can be polled from spawned tasks.
+///
+/// ## Example
+///
+/// ```rust
+/// use buswatch_adapters::{Adapter, AdapterError, Snapshot};
+///
+/// struct Fixed;
+///
+/// impl Adapter for Fixed {
+///     fn name(&self) -> String {
+///         "fixed".to_string()
+///     }
+///
+///     async fn collect(&self) -> Result<Snapshot, AdapterError> {
+///         Ok(Snapshot::builder()
+///             .module("worker", |m| m.read("jobs", |r| r.count(1)))
+///             .build())
+///     }
+/// }
+/// ```
+pub trait Adapter: Send + Sync {
+    /// A short description of this adapter, used in errors and logs
+    /// (e.g. `rabbitmq:http://localhost:15672`).
+    fn name(&self) -> String;
+
+    /// Collect a snapshot of the message bus.
+    fn collect(&self) -> impl Future<Output = Result<Snapshot, AdapterError>> + Send;
+}
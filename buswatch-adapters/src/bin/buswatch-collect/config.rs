@@ -0,0 +1,382 @@
+//! The TOML configuration file.
+//!
+//! ```toml
+//! interval = "5s"
+//!
+//! [health]
+//! listen = "0.0.0.0:8080"
+//!
+//! [[rabbitmq]]
+//! endpoint = "http://rabbitmq:15672"
+//! username = "monitoring"
+//! password = "secret"
+//!
+//! [[kafka]]
+//! brokers = "kafka-1:9092,kafka-2:9092"
+//!
+//! [[nats]]
+//! url = "nats://nats:4222"
+//!
+//! [[outputs.file]]
+//! path = "/var/lib/buswatch/metrics.json"
+//!
+//! [[outputs.tcp]]
+//! listen = "0.0.0.0:9090"
+//!
+//! [[outputs.prometheus]]
+//! listen = "0.0.0.0:9100"
+//!
+//! [[outputs.otel]]
+//! endpoint = "http://otel-collector:4318"
+//! ```
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Deserializer};
+
+/// The whole configuration file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Time between polls
+    #[serde(default = "default_interval", deserialize_with = "duration")]
+    pub interval: Duration,
+    /// Retries of a failed collection within a poll
+    pub retries: Option<u32>,
+    /// Name every module after the adapter that reported it
+    #[serde(default)]
+    pub prefix_modules: bool,
+    /// Health endpoint; disabled when absent
+    pub health: Option<Health>,
+    #[serde(default)]
+    pub rabbitmq: Vec<RabbitMq>,
+    #[serde(default)]
+    pub kafka: Vec<Kafka>,
+    #[serde(default)]
+    pub nats: Vec<Nats>,
+    #[serde(default)]
+    pub outputs: Outputs,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Health {
+    pub listen: SocketAddr,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RabbitMq {
+    pub endpoint: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub vhost: Option<String>,
+    pub vhosts: Option<Vec<String>>,
+    #[serde(default)]
+    pub all_vhosts: bool,
+    /// Regular expression matching the queues to report
+    pub queue_filter: Option<String>,
+    /// Publishing channels per vhost looked up to name publishers; 0 disables
+    pub max_publisher_channels: Option<usize>,
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub timeout: Option<Duration>,
+    /// PEM file of extra CA certificates to trust
+    pub ca_certificate: Option<PathBuf>,
+    /// PEM client certificate for mutual TLS, with its PKCS#8 `client_key`
+    pub client_certificate: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+// Only read when built with the matching feature; otherwise rejected in `parse`
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Kafka {
+    pub brokers: String,
+    pub group_id: Option<String>,
+    pub group_filter: Option<String>,
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub timeout: Option<Duration>,
+}
+
+#[cfg_attr(not(feature = "nats"), allow(dead_code))]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Nats {
+    pub url: String,
+    pub credentials_file: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Outputs {
+    #[serde(default)]
+    pub file: Vec<FileOutput>,
+    #[serde(default)]
+    pub tcp: Vec<TcpOutput>,
+    #[serde(default)]
+    pub prometheus: Vec<PrometheusOutput>,
+    #[serde(default)]
+    pub otel: Vec<OtelOutput>,
+}
+
+impl Outputs {
+    fn is_empty(&self) -> bool {
+        self.file.is_empty()
+            && self.tcp.is_empty()
+            && self.prometheus.is_empty()
+            && self.otel.is_empty()
+    }
+}
+
+/// Write each snapshot to a JSON file, for `buswatch --file`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FileOutput {
+    pub path: PathBuf,
+}
+
+/// Serve newline-delimited JSON snapshots, for `buswatch --connect`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TcpOutput {
+    pub listen: SocketAddr,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrometheusOutput {
+    pub listen: String,
+    pub path: Option<String>,
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtelOutput {
+    pub endpoint: String,
+    pub service_name: Option<String>,
+    #[serde(default, deserialize_with = "optional_duration")]
+    pub export_interval: Option<Duration>,
+}
+
+impl Config {
+    /// Read and validate the configuration file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("loading {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let config: Config = toml::from_str(text)?;
+
+        if config.rabbitmq.is_empty() && config.kafka.is_empty() && config.nats.is_empty() {
+            bail!("no adapters configured; add a [[rabbitmq]], [[kafka]] or [[nats]] section");
+        }
+        if config.outputs.is_empty() {
+            bail!("no outputs configured; add an [[outputs.file]], [[outputs.tcp]], [[outputs.prometheus]] or [[outputs.otel]] section");
+        }
+        if config.interval.is_zero() {
+            bail!("interval must be greater than zero");
+        }
+        for rabbitmq in &config.rabbitmq {
+            let vhost_options = [
+                rabbitmq.vhost.is_some(),
+                rabbitmq.vhosts.is_some(),
+                rabbitmq.all_vhosts,
+            ];
+            if vhost_options.into_iter().filter(|set| *set).count() > 1 {
+                bail!(
+                    "[[rabbitmq]] {}: set only one of vhost, vhosts and all_vhosts",
+                    rabbitmq.endpoint
+                );
+            }
+            if rabbitmq.client_certificate.is_some() != rabbitmq.client_key.is_some() {
+                bail!(
+                    "[[rabbitmq]] {}: client_certificate and client_key must be set together",
+                    rabbitmq.endpoint
+                );
+            }
+        }
+        #[cfg(not(feature = "kafka"))]
+        if !config.kafka.is_empty() {
+            bail!("[[kafka]] adapters need buswatch-collect built with the `kafka` feature");
+        }
+        #[cfg(not(feature = "nats"))]
+        if !config.nats.is_empty() {
+            bail!("[[nats]] adapters need buswatch-collect built with the `nats` feature");
+        }
+        Ok(config)
+    }
+}
+
+fn default_interval() -> Duration {
+    buswatch_adapters::collector::DEFAULT_INTERVAL
+}
+
+fn duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    parse_duration(&text).map_err(serde::de::Error::custom)
+}
+
+fn optional_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    duration(deserializer).map(Some)
+}
+
+/// Parse durations like "500ms", "5s" or "1m".
+fn parse_duration(text: &str) -> Result<Duration> {
+    let text = text.trim();
+    // Longer suffixes first, so "ms" isn't read as "s"
+    for (suffix, millis) in [("ms", 1.0), ("s", 1_000.0), ("m", 60_000.0)] {
+        if let Some(value) = text.strip_suffix(suffix) {
+            let value: f64 = value
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid duration {:?}", text))?;
+            if !value.is_finite() || value < 0.0 {
+                bail!("invalid duration {:?}", text);
+            }
+            return Ok(Duration::from_secs_f64(value * millis / 1_000.0));
+        }
+    }
+    bail!(
+        "invalid duration {:?}; use a unit such as \"500ms\", \"5s\" or \"1m\"",
+        text
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = r#"
+        [[rabbitmq]]
+        endpoint = "http://localhost:15672"
+
+        [[outputs.file]]
+        path = "metrics.json"
+    "#;
+
+    #[test]
+    fn parses_minimal_config_with_defaults() {
+        let config = Config::parse(MINIMAL).unwrap();
+        assert_eq!(config.interval, Duration::from_secs(5));
+        assert!(!config.prefix_modules);
+        assert!(config.health.is_none());
+        assert_eq!(config.rabbitmq[0].endpoint, "http://localhost:15672");
+        assert_eq!(config.outputs.file[0].path, PathBuf::from("metrics.json"));
+    }
+
+    #[test]
+    fn parses_full_config() {
+        let config = Config::parse(
+            r#"
+            interval = "2.5s"
+            retries = 4
+            prefix_modules = true
+
+            [health]
+            listen = "127.0.0.1:8080"
+
+            [[rabbitmq]]
+            endpoint = "http://a:15672"
+            username = "monitoring"
+            password = "secret"
+            vhost = "orders"
+            timeout = "500ms"
+
+            [[rabbitmq]]
+            endpoint = "https://b:15671"
+            vhosts = ["orders", "billing"]
+            queue_filter = "^orders\\."
+            max_publisher_channels = 0
+            ca_certificate = "/etc/buswatch/ca.pem"
+            client_certificate = "/etc/buswatch/client.pem"
+            client_key = "/etc/buswatch/client-key.pem"
+
+            [[outputs.tcp]]
+            listen = "0.0.0.0:9090"
+
+            [[outputs.prometheus]]
+            listen = "0.0.0.0:9100"
+            namespace = "rabbit"
+
+            [[outputs.otel]]
+            endpoint = "http://collector:4318"
+            export_interval = "1m"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.interval, Duration::from_millis(2500));
+        assert_eq!(config.retries, Some(4));
+        assert!(config.prefix_modules);
+        assert_eq!(config.health.unwrap().listen.port(), 8080);
+        assert_eq!(config.rabbitmq.len(), 2);
+        assert_eq!(config.rabbitmq[0].timeout, Some(Duration::from_millis(500)));
+        assert_eq!(
+            config.rabbitmq[1].vhosts.as_deref(),
+            Some(&["orders".to_string(), "billing".to_string()][..])
+        );
+        assert_eq!(
+            config.rabbitmq[1].queue_filter.as_deref(),
+            Some("^orders\\.")
+        );
+        assert_eq!(config.rabbitmq[1].max_publisher_channels, Some(0));
+        assert_eq!(
+            config.rabbitmq[1].client_key.as_deref(),
+            Some(Path::new("/etc/buswatch/client-key.pem"))
+        );
+        assert_eq!(config.outputs.tcp[0].listen.port(), 9090);
+        assert_eq!(
+            config.outputs.prometheus[0].namespace.as_deref(),
+            Some("rabbit")
+        );
+        assert_eq!(
+            config.outputs.otel[0].export_interval,
+            Some(Duration::from_secs(60))
+        );
+    }
+
+    #[test]
+    fn rejects_incomplete_or_unknown_config() {
+        let no_outputs = "[[rabbitmq]]\nendpoint = \"http://localhost:15672\"\n";
+        assert!(Config::parse(no_outputs).is_err());
+
+        let no_adapters = "[[outputs.file]]\npath = \"metrics.json\"\n";
+        assert!(Config::parse(no_adapters).is_err());
+
+        let typo = format!("intervall = \"5s\"\n{}", MINIMAL);
+        assert!(Config::parse(&typo).is_err());
+
+        let zero = format!("interval = \"0s\"\n{}", MINIMAL);
+        assert!(Config::parse(&zero).is_err());
+
+        let two_vhosts = MINIMAL.replace(
+            "endpoint = \"http://localhost:15672\"",
+            "endpoint = \"http://localhost:15672\"\nvhost = \"/\"\nall_vhosts = true",
+        );
+        assert!(Config::parse(&two_vhosts).is_err());
+
+        let key_only = MINIMAL.replace(
+            "endpoint = \"http://localhost:15672\"",
+            "endpoint = \"http://localhost:15672\"\nclient_key = \"key.pem\"",
+        );
+        assert!(Config::parse(&key_only).is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert!(parse_duration("5").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration("fast").is_err());
+    }
+}
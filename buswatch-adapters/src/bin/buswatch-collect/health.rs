@@ -0,0 +1,253 @@
+//! Per-adapter health tracking and the health HTTP endpoint.
+//!
+//! - `GET /healthz` always answers 200 while the process is running, with
+//!   each adapter's status as JSON.
+//! - `GET /readyz` answers 200 once every adapter has collected
+//!   successfully within the staleness limit, and 503 otherwise.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use buswatch_adapters::{Adapter, AdapterError, Snapshot};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tracing::{debug, warn};
+
+/// The latest outcome of each adapter's collections.
+#[derive(Debug)]
+pub struct HealthState {
+    /// How long after its last success an adapter counts as unhealthy
+    stale_after: Duration,
+    adapters: Mutex<BTreeMap<String, AdapterHealth>>,
+}
+
+#[derive(Debug, Default)]
+struct AdapterHealth {
+    last_success: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl HealthState {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            stale_after,
+            adapters: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Wrap `adapter` so its collections are recorded here.
+    pub fn monitor<A: Adapter>(self: &Arc<Self>, adapter: A) -> Monitored<A> {
+        let name = adapter.name();
+        self.adapters
+            .lock()
+            .unwrap()
+            .insert(name.clone(), AdapterHealth::default());
+        Monitored {
+            inner: adapter,
+            name,
+            health: self.clone(),
+        }
+    }
+
+    fn record(&self, name: &str, result: &Result<Snapshot, AdapterError>) {
+        let mut adapters = self.adapters.lock().unwrap();
+        let entry = adapters.entry(name.to_string()).or_default();
+        match result {
+            Ok(_) => {
+                entry.last_success = Some(Instant::now());
+                entry.last_error = None;
+            }
+            Err(e) => entry.last_error = Some(e.to_string()),
+        }
+    }
+
+    /// Whether every adapter has succeeded recently, and the status of each
+    /// as JSON.
+    fn report(&self) -> (bool, serde_json::Value) {
+        let adapters = self.adapters.lock().unwrap();
+        let mut ready = true;
+        let statuses: serde_json::Map<_, _> = adapters
+            .iter()
+            .map(|(name, health)| {
+                let age = health.last_success.map(|at| at.elapsed());
+                let healthy = age.is_some_and(|age| age <= self.stale_after);
+                ready &= healthy;
+                let status = serde_json::json!({
+                    "healthy": healthy,
+                    "last_success_seconds_ago": age.map(|age| age.as_secs_f64()),
+                    "last_error": health.last_error,
+                });
+                (name.clone(), status)
+            })
+            .collect();
+        let body = serde_json::json!({
+            "status": if ready { "ok" } else { "degraded" },
+            "adapters": statuses,
+        });
+        (ready, body)
+    }
+}
+
+/// An adapter whose collections are recorded in a [`HealthState`] and logged.
+pub struct Monitored<A> {
+    inner: A,
+    name: String,
+    health: Arc<HealthState>,
+}
+
+impl<A: Adapter> Adapter for Monitored<A> {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    async fn collect(&self) -> Result<Snapshot, AdapterError> {
+        let started = Instant::now();
+        let result = self.inner.collect().await;
+        self.health.record(&self.name, &result);
+        match &result {
+            Ok(snapshot) => debug!(
+                adapter = %self.name,
+                modules = snapshot.modules.len(),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "collected"
+            ),
+            Err(e) => debug!(adapter = %self.name, error = %e, "collection attempt failed"),
+        }
+        result
+    }
+}
+
+/// Serve the health endpoints until `shutdown` fires.
+pub async fn serve(
+    listener: TcpListener,
+    state: Arc<HealthState>,
+    shutdown: oneshot::Receiver<()>,
+) {
+    tokio::pin!(shutdown);
+    loop {
+        let stream = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!(error = %e, "health endpoint failed to accept a connection");
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                let response = respond(&req, &state);
+                async move { Ok::<_, Infallible>(response) }
+            });
+            let _ = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+fn respond<B>(req: &Request<B>, state: &HealthState) -> Response<Full<Bytes>> {
+    let status = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/healthz") => StatusCode::OK,
+        (&Method::GET, "/readyz") => {
+            if state.report().0 {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        }
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Full::new(Bytes::from_static(b"Not Found")))
+                .unwrap();
+        }
+    };
+    let (_, body) = state.report();
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fails;
+
+    impl Adapter for Fails {
+        fn name(&self) -> String {
+            "fails".to_string()
+        }
+
+        async fn collect(&self) -> Result<Snapshot, AdapterError> {
+            Err(AdapterError::Timeout)
+        }
+    }
+
+    struct Works;
+
+    impl Adapter for Works {
+        fn name(&self) -> String {
+            "works".to_string()
+        }
+
+        async fn collect(&self) -> Result<Snapshot, AdapterError> {
+            Ok(Snapshot::builder().build())
+        }
+    }
+
+    fn get(state: &HealthState, path: &str) -> Response<Full<Bytes>> {
+        let req = Request::builder().uri(path).body(()).unwrap();
+        respond(&req, state)
+    }
+
+    #[tokio::test]
+    async fn ready_once_every_adapter_succeeds() {
+        let state = Arc::new(HealthState::new(Duration::from_secs(60)));
+        let works = state.monitor(Works);
+        let fails = state.monitor(Fails);
+
+        // Nothing collected yet
+        assert_eq!(
+            get(&state, "/readyz").status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(get(&state, "/healthz").status(), StatusCode::OK);
+
+        works.collect().await.unwrap();
+        fails.collect().await.unwrap_err();
+        let (ready, body) = state.report();
+        assert!(!ready);
+        assert_eq!(body["status"], "degraded");
+        assert_eq!(body["adapters"]["works"]["healthy"], true);
+        assert_eq!(body["adapters"]["fails"]["last_error"], "Request timed out");
+
+        let state = Arc::new(HealthState::new(Duration::from_secs(60)));
+        state.monitor(Works).collect().await.unwrap();
+        assert_eq!(get(&state, "/readyz").status(), StatusCode::OK);
+        assert_eq!(get(&state, "/metrics").status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn stale_successes_are_unhealthy() {
+        let state = Arc::new(HealthState::new(Duration::ZERO));
+        state.monitor(Works).collect().await.unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(!state.report().0);
+    }
+}
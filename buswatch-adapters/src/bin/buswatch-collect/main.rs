@@ -0,0 +1,387 @@
+//! buswatch-collect - a sidecar that polls message broker APIs and publishes
+//! the resulting snapshots.
+//!
+//! Adapters, the poll interval and outputs come from a TOML file (see
+//! [`config`]). Send SIGHUP to reload it; SIGTERM or Ctrl-C shuts down after
+//! flushing the outputs.
+
+mod config;
+mod health;
+mod tcp;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use buswatch_adapters::rabbitmq::RabbitMqAdapter;
+use buswatch_adapters::{CollectError, Collector};
+use buswatch_sdk::otel::OtelConfig;
+use buswatch_sdk::prometheus::{PrometheusConfig, PrometheusExporter};
+use buswatch_sdk::Output;
+use clap::{Parser, ValueEnum};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+
+use crate::config::Config;
+use crate::health::HealthState;
+
+/// Collect message bus metrics from broker APIs and publish them for buswatch,
+/// Prometheus or OpenTelemetry
+#[derive(Parser, Debug)]
+#[command(name = "buswatch-collect", version, about)]
+struct Args {
+    /// Path to the TOML configuration file
+    #[arg(short, long, default_value = "buswatch-collect.toml")]
+    config: PathBuf,
+
+    /// Log output format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    /// Most verbose log level to emit (error, warn, info, debug, trace)
+    #[arg(long, default_value = "info")]
+    log_level: tracing::Level,
+
+    /// Check the configuration file and exit
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let logs = tracing_subscriber::fmt()
+        .with_max_level(args.log_level)
+        .with_writer(std::io::stderr);
+    match args.log_format {
+        LogFormat::Text => logs.init(),
+        LogFormat::Json => logs.json().init(),
+    }
+
+    let config = Config::load(&args.config)?;
+    if args.check {
+        println!("{}: OK", args.config.display());
+        return Ok(());
+    }
+
+    tokio::runtime::Runtime::new()
+        .context("starting the tokio runtime")?
+        .block_on(run(args.config, config))
+}
+
+async fn run(path: PathBuf, mut config: Config) -> Result<()> {
+    let mut pipeline = Pipeline::start(&config).await?;
+    info!(config = %path.display(), "started");
+
+    let mut signals = Signals::new()?;
+    while let Signal::Reload = signals.next().await {
+        info!(config = %path.display(), "reloading configuration");
+        let new = match Config::load(&path) {
+            Ok(new) => new,
+            Err(e) => {
+                error!(
+                    error = format!("{:#}", e),
+                    "invalid configuration; keeping the current one"
+                );
+                continue;
+            }
+        };
+
+        // Listeners can't be bound twice, so stop before starting again
+        pipeline.stop().await;
+        match Pipeline::start(&new).await {
+            Ok(started) => {
+                pipeline = started;
+                config = new;
+                info!("configuration reloaded");
+            }
+            Err(e) => {
+                error!(
+                    error = format!("{:#}", e),
+                    "failed to apply configuration; restoring the previous one"
+                );
+                match restore(&config, &mut signals).await {
+                    Some(restored) => pipeline = restored,
+                    None => {
+                        info!("shutting down");
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    info!("shutting down");
+    pipeline.stop().await;
+    Ok(())
+}
+
+/// Delay before retrying to restore a configuration; doubles after each
+/// attempt.
+const RESTORE_BACKOFF: Duration = Duration::from_secs(1);
+/// Longest delay between attempts to restore a configuration.
+const MAX_RESTORE_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Start the pipeline for a configuration that ran before, retrying until
+/// it starts or a stop signal arrives, in which case `None` is returned.
+///
+/// What failed, such as a port taken by another process in the meantime,
+/// may clear up, so this keeps trying rather than exiting. A reload signal
+/// retries at once.
+async fn restore(config: &Config, signals: &mut Signals) -> Option<Pipeline> {
+    let mut backoff = RESTORE_BACKOFF;
+    loop {
+        match Pipeline::start(config).await {
+            Ok(pipeline) => {
+                info!("previous configuration restored");
+                return Some(pipeline);
+            }
+            Err(e) => error!(
+                error = format!("{:#}", e),
+                retry_in = ?backoff,
+                "failed to restore the previous configuration"
+            ),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            signal = signals.next() => {
+                if let Signal::Stop = signal {
+                    return None;
+                }
+            }
+        }
+        backoff = (backoff * 2).min(MAX_RESTORE_BACKOFF);
+    }
+}
+
+/// A running collector with its health and snapshot servers.
+struct Pipeline {
+    collector: buswatch_adapters::collector::CollectorHandle,
+    health: Option<(oneshot::Sender<()>, JoinHandle<()>)>,
+    snapshot_servers: Vec<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    async fn start(config: &Config) -> Result<Self> {
+        // Allow a couple of missed polls before reporting an adapter unhealthy
+        let state = Arc::new(HealthState::new(config.interval * 3));
+
+        let mut builder = Collector::builder()
+            .interval(config.interval)
+            .prefix_modules(config.prefix_modules)
+            .on_error(|e| match e {
+                CollectError::Adapter { adapter, error } => {
+                    warn!(adapter = %adapter, error = %error, "collection failed")
+                }
+                CollectError::Emit(e) => {
+                    warn!(output = %e.output, error = %e.error, "failed to emit snapshot")
+                }
+            });
+        if let Some(retries) = config.retries {
+            builder = builder.retries(retries);
+        }
+
+        for rabbitmq in &config.rabbitmq {
+            let mut adapter = RabbitMqAdapter::builder().endpoint(&rabbitmq.endpoint);
+            if rabbitmq.username.is_some() || rabbitmq.password.is_some() {
+                adapter = adapter.credentials(
+                    rabbitmq.username.as_deref().unwrap_or("guest"),
+                    rabbitmq.password.as_deref().unwrap_or("guest"),
+                );
+            }
+            if let Some(vhost) = &rabbitmq.vhost {
+                adapter = adapter.vhost(vhost);
+            }
+            if let Some(vhosts) = &rabbitmq.vhosts {
+                adapter = adapter.vhosts(vhosts);
+            }
+            if rabbitmq.all_vhosts {
+                adapter = adapter.all_vhosts();
+            }
+            if let Some(filter) = &rabbitmq.queue_filter {
+                adapter = adapter.queue_filter(filter);
+            }
+            if let Some(max) = rabbitmq.max_publisher_channels {
+                adapter = adapter.max_publisher_channels(max);
+            }
+            if let Some(timeout) = rabbitmq.timeout {
+                adapter = adapter.timeout(timeout);
+            }
+            if let Some(path) = &rabbitmq.ca_certificate {
+                adapter = adapter.ca_certificate(path);
+            }
+            if let (Some(cert), Some(key)) = (&rabbitmq.client_certificate, &rabbitmq.client_key) {
+                adapter = adapter.client_certificate(cert, key);
+            }
+            let adapter = adapter
+                .build()
+                .with_context(|| format!("creating RabbitMQ adapter for {}", rabbitmq.endpoint))?;
+            builder = builder.adapter(state.monitor(adapter));
+        }
+
+        #[cfg(feature = "kafka")]
+        for kafka in &config.kafka {
+            let mut adapter =
+                buswatch_adapters::kafka::KafkaAdapter::builder().brokers(&kafka.brokers);
+            if let Some(group_id) = &kafka.group_id {
+                adapter = adapter.group_id(group_id);
+            }
+            if let Some(filter) = &kafka.group_filter {
+                adapter = adapter.group_filter(filter);
+            }
+            if let Some(timeout) = kafka.timeout {
+                adapter = adapter.timeout(timeout);
+            }
+            let adapter = adapter
+                .build()
+                .with_context(|| format!("creating Kafka adapter for {}", kafka.brokers))?;
+            builder = builder.adapter(state.monitor(adapter));
+        }
+
+        #[cfg(feature = "nats")]
+        for nats in &config.nats {
+            let mut adapter = buswatch_adapters::nats::NatsAdapter::builder().url(&nats.url);
+            if let Some(path) = &nats.credentials_file {
+                adapter = adapter.credentials_file(path);
+            }
+            let adapter = adapter
+                .build()
+                .await
+                .with_context(|| format!("connecting to NATS at {}", nats.url))?;
+            builder = builder.adapter(state.monitor(adapter));
+        }
+
+        for file in &config.outputs.file {
+            builder = builder.output(Output::file(&file.path));
+        }
+
+        let mut snapshot_servers = Vec::new();
+        for tcp in &config.outputs.tcp {
+            let listener = TcpListener::bind(tcp.listen)
+                .await
+                .with_context(|| format!("binding snapshot server to {}", tcp.listen))?;
+            let (output, snapshots) = Output::channel(16);
+            builder = builder.output(output);
+            snapshot_servers.push(tokio::spawn(tcp::serve(listener, snapshots)));
+        }
+
+        for prometheus in &config.outputs.prometheus {
+            let mut exporter = PrometheusConfig::builder().listen_addr(&prometheus.listen);
+            if let Some(path) = &prometheus.path {
+                exporter = exporter.metrics_path(path);
+            }
+            if let Some(namespace) = &prometheus.namespace {
+                exporter = exporter.namespace(namespace);
+            }
+            let exporter = PrometheusExporter::new(exporter.build());
+            exporter
+                .bind()
+                .await
+                .with_context(|| format!("binding Prometheus endpoint to {}", prometheus.listen))?;
+            builder = builder.output(Output::Prometheus(Arc::new(exporter)));
+        }
+
+        for otel in &config.outputs.otel {
+            let mut exporter = OtelConfig::builder()
+                .endpoint(&otel.endpoint)
+                .service_name(otel.service_name.as_deref().unwrap_or("buswatch-collect"));
+            if let Some(interval) = otel.export_interval {
+                exporter = exporter.export_interval(interval);
+            }
+            let output = Output::otel(exporter.build())
+                .map_err(|e| anyhow!(e))
+                .with_context(|| format!("creating OTLP exporter for {}", otel.endpoint))?;
+            builder = builder.output(output);
+        }
+
+        let health = match &config.health {
+            Some(health) => {
+                let listener = TcpListener::bind(health.listen)
+                    .await
+                    .with_context(|| format!("binding health endpoint to {}", health.listen))?;
+                let (stop, stopped) = oneshot::channel();
+                Some((stop, tokio::spawn(health::serve(listener, state, stopped))))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            collector: builder.build().start(),
+            health,
+            snapshot_servers,
+        })
+    }
+
+    async fn stop(self) {
+        if let Err(errors) = self.collector.shutdown().await {
+            for e in errors {
+                warn!(output = %e.output, error = %e.error, "failed to flush output");
+            }
+        }
+        if let Some((stop, server)) = self.health {
+            let _ = stop.send(());
+            let _ = server.await;
+        }
+        // These finish once the collector has dropped its outputs
+        for server in self.snapshot_servers {
+            let _ = server.await;
+        }
+    }
+}
+
+enum Signal {
+    Reload,
+    Stop,
+}
+
+#[cfg(unix)]
+struct Signals {
+    hangup: tokio::signal::unix::Signal,
+    terminate: tokio::signal::unix::Signal,
+}
+
+#[cfg(unix)]
+impl Signals {
+    fn new() -> Result<Self> {
+        use tokio::signal::unix::{signal, SignalKind};
+        Ok(Self {
+            hangup: signal(SignalKind::hangup()).context("installing SIGHUP handler")?,
+            terminate: signal(SignalKind::terminate()).context("installing SIGTERM handler")?,
+        })
+    }
+
+    async fn next(&mut self) -> Signal {
+        tokio::select! {
+            _ = self.hangup.recv() => Signal::Reload,
+            _ = self.terminate.recv() => Signal::Stop,
+            _ = tokio::signal::ctrl_c() => Signal::Stop,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+struct Signals;
+
+#[cfg(not(unix))]
+impl Signals {
+    fn new() -> Result<Self> {
+        Ok(Self)
+    }
+
+    async fn next(&mut self) -> Signal {
+        let _ = tokio::signal::ctrl_c().await;
+        Signal::Stop
+    }
+}
@@ -0,0 +1,104 @@
+//! Serving snapshots over TCP to `buswatch --connect` clients.
+
+use std::time::Duration;
+
+use buswatch_adapters::Snapshot;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// How long a client may take to accept one snapshot before it is dropped.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Send every snapshot from `snapshots` to each connected client as a line of
+/// JSON, until the channel closes. New clients get the latest snapshot
+/// straight away.
+pub async fn serve(listener: TcpListener, mut snapshots: mpsc::Receiver<Snapshot>) {
+    let mut clients: Vec<TcpStream> = Vec::new();
+    let mut latest: Option<Vec<u8>> = None;
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((mut stream, peer)) => {
+                    debug!(%peer, "snapshot client connected");
+                    if let Some(line) = &latest {
+                        if write(&mut stream, line).await.is_err() {
+                            continue;
+                        }
+                    }
+                    clients.push(stream);
+                }
+                Err(e) => warn!(error = %e, "snapshot server failed to accept a connection"),
+            },
+            snapshot = snapshots.recv() => {
+                let Some(snapshot) = snapshot else { break };
+                let mut line = match serde_json::to_vec(&snapshot) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        warn!(error = %e, "failed to encode snapshot");
+                        continue;
+                    }
+                };
+                line.push(b'\n');
+
+                let mut connected = Vec::with_capacity(clients.len());
+                for mut client in clients.drain(..) {
+                    if write(&mut client, &line).await.is_ok() {
+                        connected.push(client);
+                    } else {
+                        debug!("snapshot client disconnected");
+                    }
+                }
+                clients = connected;
+                latest = Some(line);
+            }
+        }
+    }
+}
+
+async fn write(stream: &mut TcpStream, line: &[u8]) -> std::io::Result<()> {
+    tokio::time::timeout(WRITE_TIMEOUT, stream.write_all(line))
+        .await
+        .unwrap_or_else(|_| Err(std::io::ErrorKind::TimedOut.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+
+    #[tokio::test]
+    async fn clients_receive_latest_and_new_snapshots() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel(4);
+        let server = tokio::spawn(serve(listener, rx));
+
+        let first = Snapshot::builder()
+            .module("orders", |m| m.read("messages", |r| r.count(1)))
+            .build();
+        tx.send(first).await.unwrap();
+        // Let the server record it as the latest before connecting
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut lines = BufReader::new(stream).lines();
+        let latest: Snapshot =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(latest.modules["orders"].reads["messages"].count, 1);
+
+        let second = Snapshot::builder()
+            .module("orders", |m| m.read("messages", |r| r.count(2)))
+            .build();
+        tx.send(second).await.unwrap();
+        let next: Snapshot =
+            serde_json::from_str(&lines.next_line().await.unwrap().unwrap()).unwrap();
+        assert_eq!(next.modules["orders"].reads["messages"].count, 2);
+
+        // Closing the channel stops the server
+        drop(tx);
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,811 @@
+//! Polling adapters on a schedule.
+//!
+//! A [`Collector`] polls one or more [`Adapter`]s on an interval and sends
+//! the results to `buswatch-sdk` [`Output`]s, so adapters can feed a file,
+//! TCP stream, Prometheus endpoint or OTLP collector without a hand-written
+//! loop. Each poll:
+//!
+//! - collects from every adapter concurrently, retrying transient failures
+//!   with exponential backoff
+//! - fills in the `rate` of any read or write the adapter left empty, from
+//!   the change in its count since the previous poll
+//! - merges the adapters' snapshots into one and emits it to every output
+//!
+//! A module reported by several adapters gets the topics of all of them.
+//! If two adapters report the same topic of a module, the later adapter's
+//! module is renamed to `<adapter>/<module>` rather than overwriting the
+//! other; [`prefix_modules`](CollectorBuilder::prefix_modules) renames every
+//! module this way.
+//!
+//! Adapters that still fail after retrying are left out of that snapshot
+//! and reported to the [`on_error`](CollectorBuilder::on_error) handler.
+//! To consume snapshots in-process, add an [`Output::channel`].
+//!
+//! Outputs other than those enabled by default in `buswatch-sdk` need the
+//! matching SDK feature, e.g. `buswatch-sdk = { features = ["prometheus"] }`.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use buswatch_adapters::collector::Collector;
+//! use buswatch_adapters::rabbitmq::RabbitMqAdapter;
+//! use buswatch_sdk::Output;
+//! use std::time::Duration;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), Box<dyn std::error::Error>> {
+//!     let adapter = RabbitMqAdapter::builder()
+//!         .endpoint("http://localhost:15672")
+//!         .credentials("guest", "guest")
+//!         .build()?;
+//!
+//!     let collector = Collector::builder()
+//!         .adapter(adapter)
+//!         .output(Output::file("metrics.json"))
+//!         .interval(Duration::from_secs(5))
+//!         .on_error(|e| eprintln!("buswatch: {}", e))
+//!         .build();
+//!
+//!     let handle = collector.start();
+//!
+//!     tokio::signal::ctrl_c().await?;
+//!     let _ = handle.shutdown().await;
+//!     Ok(())
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use buswatch_sdk::{EmitError, Output, DEFAULT_SHUTDOWN_TIMEOUT};
+use buswatch_types::Snapshot;
+use thiserror::Error;
+
+use crate::{Adapter, AdapterError};
+
+/// Default time between polls.
+///
+/// Broker management APIs typically refresh their statistics every few
+/// seconds, so polling more often mostly returns the same values.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A failure reported to the [`on_error`](CollectorBuilder::on_error) handler.
+#[derive(Debug, Error)]
+pub enum CollectError {
+    /// An adapter failed, after any retries.
+    #[error("failed to collect from {adapter}: {error}")]
+    Adapter {
+        /// The failing adapter, as returned by [`Adapter::name`]
+        adapter: String,
+        /// The last error it returned
+        #[source]
+        error: AdapterError,
+    },
+
+    /// A snapshot could not be emitted to an output, or the server an
+    /// output started (Prometheus) ran into an error.
+    #[error(transparent)]
+    Emit(#[from] EmitError),
+}
+
+/// How failed collections are retried within a poll.
+#[derive(Debug, Clone, Copy)]
+struct Retry {
+    /// Total attempts, including the first
+    attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+/// [`Adapter`] with a boxed future, so different adapters can be stored
+/// together.
+trait DynAdapter: Send + Sync {
+    fn name(&self) -> String;
+    fn collect_boxed(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Snapshot, AdapterError>> + Send + '_>>;
+}
+
+impl<A: Adapter> DynAdapter for A {
+    fn name(&self) -> String {
+        Adapter::name(self)
+    }
+
+    fn collect_boxed(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Snapshot, AdapterError>> + Send + '_>> {
+        Box::pin(self.collect())
+    }
+}
+
+#[derive(Clone)]
+struct ErrorHandler(Arc<dyn Fn(&CollectError) + Send + Sync>);
+
+/// Whether a series is a read or a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Read,
+    Write,
+}
+
+/// Index of the adapter, module, topic and direction of a series.
+type SeriesKey = (usize, String, String, Direction);
+
+/// Polls adapters on an interval and emits the merged snapshots.
+///
+/// Create one with [`Collector::builder`], then either [`start`](Self::start)
+/// it in the background or drive it yourself with [`poll`](Self::poll).
+pub struct Collector {
+    adapters: Vec<Arc<dyn DynAdapter>>,
+    outputs: Vec<Output>,
+    interval: Duration,
+    retry: Retry,
+    on_error: Option<ErrorHandler>,
+    /// Whether every module is prefixed with its adapter's name
+    prefix_modules: bool,
+    /// Count and snapshot timestamp of each series at the previous poll
+    previous: HashMap<SeriesKey, (u64, u64)>,
+}
+
+impl Collector {
+    /// Create a new builder for configuring the collector.
+    pub fn builder() -> CollectorBuilder {
+        CollectorBuilder::default()
+    }
+
+    /// Collect from every adapter once and return the merged snapshot.
+    ///
+    /// Adapters that fail after retrying are reported to the
+    /// [`on_error`](CollectorBuilder::on_error) handler and left out.
+    /// Nothing is emitted; [`start`](Self::start) does that.
+    pub async fn poll(&mut self) -> Snapshot {
+        let mut polls = tokio::task::JoinSet::new();
+        let mut names = HashMap::with_capacity(self.adapters.len());
+        for (index, adapter) in self.adapters.iter().enumerate() {
+            let task = adapter.clone();
+            let retry = self.retry;
+            let poll = polls.spawn(async move { collect_with_retry(task.as_ref(), retry).await });
+            names.insert(poll.id(), (index, adapter.name()));
+        }
+
+        let mut results = Vec::with_capacity(self.adapters.len());
+        while let Some(joined) = polls.join_next_with_id().await {
+            let (id, result) = match joined {
+                Ok((id, result)) => (id, result),
+                // A panicking adapter is reported like any other failure
+                Err(e) => (e.id(), Err(AdapterError::Connection(e.to_string()))),
+            };
+            if let Some((index, name)) = names.remove(&id) {
+                results.push((index, name, result));
+            }
+        }
+        // Merge in the order adapters were added
+        results.sort_by_key(|(index, _, _)| *index);
+
+        let mut merged = Snapshot::new();
+        let mut current = HashMap::with_capacity(self.previous.len());
+        for (index, adapter, result) in results {
+            match result {
+                Ok(mut snapshot) => {
+                    self.fill_rates(index, &mut snapshot, &mut current);
+                    merge(&mut merged, &adapter, snapshot, self.prefix_modules);
+                }
+                Err(error) => {
+                    // Keep the last counts, so rates resume once it recovers
+                    current.extend(
+                        self.previous
+                            .iter()
+                            .filter(|(key, _)| key.0 == index)
+                            .map(|(key, value)| (key.clone(), *value)),
+                    );
+                    self.report(&CollectError::Adapter { adapter, error });
+                }
+            }
+        }
+        self.previous = current;
+        merged
+    }
+
+    /// Start polling in the background, emitting each snapshot to every
+    /// output.
+    ///
+    /// Servers needed by outputs (Prometheus) are started here and stopped
+    /// on shutdown. Must be called from within a tokio runtime.
+    pub fn start(mut self) -> CollectorHandle {
+        use tokio::sync::watch;
+
+        let (stop_tx, mut stop_rx) = watch::channel(false);
+        let servers: Vec<_> = self
+            .outputs
+            .iter()
+            .filter_map(|output| {
+                let mut stopped = stop_rx.clone();
+                let on_error = self.on_error.clone();
+                output.serve(
+                    async move {
+                        let _ = stopped.wait_for(|stopped| *stopped).await;
+                    },
+                    move |error| {
+                        if let Some(handler) = &on_error {
+                            (handler.0)(&CollectError::Emit(error));
+                        }
+                    },
+                )
+            })
+            .collect();
+
+        let task = tokio::spawn(async move {
+            let mut timer = tokio::time::interval(self.interval);
+            // A slow poll delays the next one instead of bunching them up
+            timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = timer.tick() => {
+                        let snapshot = self.poll().await;
+                        self.emit(&snapshot).await;
+                    }
+                    // Stopped, or the handle is gone
+                    _ = stop_rx.changed() => break,
+                }
+            }
+
+            let deadline = tokio::time::Instant::now() + DEFAULT_SHUTDOWN_TIMEOUT;
+            let errors = self.close(deadline).await;
+            for server in servers {
+                let _ = tokio::time::timeout_at(deadline, server).await;
+            }
+            errors
+        });
+
+        CollectorHandle {
+            stop_tx,
+            task: Some(task),
+        }
+    }
+
+    /// Emit a snapshot to every output, reporting failures.
+    async fn emit(&self, snapshot: &Snapshot) {
+        for output in &self.outputs {
+            if let Err(error) = output.emit(snapshot).await {
+                self.report(&CollectError::Emit(EmitError {
+                    output: output.name(),
+                    error,
+                }));
+            }
+        }
+    }
+
+    /// Flush and close every output, giving up at `deadline`.
+    async fn close(&self, deadline: tokio::time::Instant) -> Vec<EmitError> {
+        let mut errors = Vec::new();
+        for output in &self.outputs {
+            let result = tokio::time::timeout_at(deadline, async {
+                output.flush().await?;
+                output.close().await
+            })
+            .await
+            .unwrap_or_else(|_| {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    "shutdown timed out",
+                ))
+            });
+            if let Err(error) = result {
+                let error = CollectError::Emit(EmitError {
+                    output: output.name(),
+                    error,
+                });
+                self.report(&error);
+                if let CollectError::Emit(error) = error {
+                    errors.push(error);
+                }
+            }
+        }
+        errors
+    }
+
+    /// Fill in missing rates from the change in count since the previous
+    /// poll, recording this poll's counts in `current`.
+    fn fill_rates(
+        &self,
+        index: usize,
+        snapshot: &mut Snapshot,
+        current: &mut HashMap<SeriesKey, (u64, u64)>,
+    ) {
+        let timestamp = snapshot.timestamp_ms;
+        for (module, metrics) in &mut snapshot.modules {
+            let reads = metrics
+                .reads
+                .iter_mut()
+                .map(|(topic, m)| (topic, Direction::Read, m.count, &mut m.rate));
+            let writes = metrics
+                .writes
+                .iter_mut()
+                .map(|(topic, m)| (topic, Direction::Write, m.count, &mut m.rate));
+            for (topic, direction, count, rate) in reads.chain(writes) {
+                let key = (index, module.clone(), topic.clone(), direction);
+                if rate.is_none() {
+                    *rate = self
+                        .previous
+                        .get(&key)
+                        .and_then(|&(before, at)| rate_between(before, at, count, timestamp));
+                }
+                current.insert(key, (count, timestamp));
+            }
+        }
+    }
+
+    fn report(&self, error: &CollectError) {
+        if let Some(handler) = &self.on_error {
+            (handler.0)(error);
+        }
+    }
+}
+
+impl std::fmt::Debug for Collector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let adapters: Vec<_> = self.adapters.iter().map(|a| a.name()).collect();
+        f.debug_struct("Collector")
+            .field("adapters", &adapters)
+            .field("outputs", &self.outputs)
+            .field("interval", &self.interval)
+            .field("retry", &self.retry)
+            .field("on_error", &self.on_error.is_some())
+            .field("prefix_modules", &self.prefix_modules)
+            .finish()
+    }
+}
+
+/// Collect from `adapter`, retrying transient failures.
+async fn collect_with_retry(
+    adapter: &dyn DynAdapter,
+    retry: Retry,
+) -> Result<Snapshot, AdapterError> {
+    let mut backoff = retry.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match adapter.collect_boxed().await {
+            Ok(snapshot) => return Ok(snapshot),
+            Err(e) if e.is_transient() && attempt < retry.attempts => {
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(retry.max_backoff);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Messages per second between two readings of a cumulative count, unless
+/// the count was reset or no time passed.
+fn rate_between(before: u64, before_ms: u64, count: u64, now_ms: u64) -> Option<f64> {
+    if count < before || now_ms <= before_ms {
+        return None;
+    }
+    Some((count - before) as f64 / ((now_ms - before_ms) as f64 / 1000.0))
+}
+
+/// Add the modules `adapter` reported to `merged`.
+///
+/// A module reported by several adapters gets the topics of all of them.
+/// One that shares a topic with a module already merged is added as
+/// `<adapter>/<module>` instead, as is every module if `prefix` is set.
+fn merge(merged: &mut Snapshot, adapter: &str, snapshot: Snapshot, prefix: bool) {
+    for (name, metrics) in snapshot.modules {
+        let conflicts = merged.modules.get(&name).is_some_and(|existing| {
+            metrics.reads.keys().any(|t| existing.reads.contains_key(t))
+                || metrics
+                    .writes
+                    .keys()
+                    .any(|t| existing.writes.contains_key(t))
+        });
+        let name = if prefix || conflicts {
+            format!("{}/{}", adapter, name)
+        } else {
+            name
+        };
+
+        match merged.modules.get_mut(&name) {
+            Some(existing) => {
+                existing.reads.extend(metrics.reads);
+                existing.writes.extend(metrics.writes);
+                existing.evicted_topics = match (existing.evicted_topics, metrics.evicted_topics) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+                existing.tracked_topics = match (existing.tracked_topics, metrics.tracked_topics) {
+                    (Some(a), Some(b)) => Some(a + b),
+                    (a, b) => a.or(b),
+                };
+            }
+            None => {
+                merged.modules.insert(name, metrics);
+            }
+        }
+    }
+}
+
+/// Builder for [`Collector`].
+#[derive(Default)]
+pub struct CollectorBuilder {
+    adapters: Vec<Arc<dyn DynAdapter>>,
+    outputs: Vec<Output>,
+    interval: Option<Duration>,
+    retries: Option<u32>,
+    initial_backoff: Option<Duration>,
+    max_backoff: Option<Duration>,
+    on_error: Option<ErrorHandler>,
+    prefix_modules: bool,
+}
+
+impl CollectorBuilder {
+    /// Add an adapter to poll.
+    pub fn adapter(mut self, adapter: impl Adapter + 'static) -> Self {
+        self.adapters.push(Arc::new(adapter));
+        self
+    }
+
+    /// Add an output destination.
+    pub fn output(mut self, output: Output) -> Self {
+        self.outputs.push(output);
+        self
+    }
+
+    /// Set the time between polls. Defaults to [`DEFAULT_INTERVAL`].
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Set how many times a transient failure is retried within a poll.
+    /// Defaults to 2; 0 disables retries.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = Some(retries);
+        self
+    }
+
+    /// Set the delay before the first retry and the most it may grow to;
+    /// it doubles after each attempt. Defaults to 500ms and 5 seconds.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = Some(initial);
+        self.max_backoff = Some(max);
+        self
+    }
+
+    /// Set a handler for adapter and output failures.
+    ///
+    /// Without one, failures are dropped: the adapter is left out of that
+    /// snapshot, and an output that failed is tried again on the next poll.
+    /// Errors from output servers are passed to it too; they don't stop the
+    /// server.
+    pub fn on_error<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&CollectError) + Send + Sync + 'static,
+    {
+        self.on_error = Some(ErrorHandler(Arc::new(handler)));
+        self
+    }
+
+    /// Name every module `<adapter>/<module>`, after the adapter that
+    /// reported it.
+    ///
+    /// Without this, modules keep their names and only one that reports a
+    /// topic another adapter already reported for the same module is
+    /// prefixed. Defaults to off.
+    pub fn prefix_modules(mut self, enabled: bool) -> Self {
+        self.prefix_modules = enabled;
+        self
+    }
+
+    /// Build the collector.
+    pub fn build(self) -> Collector {
+        let initial_backoff = self.initial_backoff.unwrap_or(Duration::from_millis(500));
+        Collector {
+            adapters: self.adapters,
+            outputs: self.outputs,
+            interval: self.interval.unwrap_or(DEFAULT_INTERVAL),
+            retry: Retry {
+                attempts: self.retries.unwrap_or(2).saturating_add(1),
+                initial_backoff,
+                max_backoff: self
+                    .max_backoff
+                    .unwrap_or(Duration::from_secs(5))
+                    .max(initial_backoff),
+            },
+            on_error: self.on_error,
+            prefix_modules: self.prefix_modules,
+            previous: HashMap::new(),
+        }
+    }
+}
+
+impl std::fmt::Debug for CollectorBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CollectorBuilder")
+            .field("adapters", &self.adapters.len())
+            .field("outputs", &self.outputs)
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Handle to a running [`Collector`].
+///
+/// Dropping the handle also stops the collector, without waiting for its
+/// outputs to be closed.
+#[must_use = "the collector stops when the handle is dropped"]
+pub struct CollectorHandle {
+    stop_tx: tokio::sync::watch::Sender<bool>,
+    task: Option<tokio::task::JoinHandle<Vec<EmitError>>>,
+}
+
+impl CollectorHandle {
+    /// Stop polling, then flush and close every output.
+    ///
+    /// A poll in progress is finished and emitted first. Waits at most
+    /// [`DEFAULT_SHUTDOWN_TIMEOUT`] for the outputs. Returns the outputs
+    /// that failed to close or timed out.
+    pub async fn shutdown(mut self) -> Result<(), Vec<EmitError>> {
+        let _ = self.stop_tx.send(true);
+        let Some(task) = self.task.take() else {
+            return Ok(());
+        };
+
+        let errors = match task.await {
+            Ok(errors) => errors,
+            Err(e) => vec![EmitError {
+                output: "collector task".to_string(),
+                error: std::io::Error::other(e),
+            }],
+        };
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+impl Drop for CollectorHandle {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(true);
+    }
+}
+
+impl std::fmt::Debug for CollectorHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CollectorHandle").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// An adapter returning scripted results, then an empty snapshot.
+    #[derive(Clone)]
+    struct Scripted {
+        name: &'static str,
+        results: Arc<Mutex<VecDeque<Result<Snapshot, AdapterError>>>>,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl Scripted {
+        fn new(name: &'static str, results: Vec<Result<Snapshot, AdapterError>>) -> Self {
+            Self {
+                name,
+                results: Arc::new(Mutex::new(results.into())),
+                calls: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+    }
+
+    impl Adapter for Scripted {
+        fn name(&self) -> String {
+            self.name.to_string()
+        }
+
+        async fn collect(&self) -> Result<Snapshot, AdapterError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            let next = self.results.lock().unwrap().pop_front();
+            next.unwrap_or_else(|| Ok(Snapshot::builder().build()))
+        }
+    }
+
+    fn queue(timestamp_ms: u64, module: &str, count: u64) -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(timestamp_ms)
+            .module(module, |m| {
+                m.read("messages", |r| r.count(count).backlog(3))
+                    .write("messages", |w| w.count(count).rate(7.0))
+            })
+            .build()
+    }
+
+    fn collecting_errors() -> (
+        Arc<Mutex<Vec<String>>>,
+        impl Fn(&CollectError) + Send + Sync,
+    ) {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let sink = errors.clone();
+        (errors, move |e: &CollectError| {
+            sink.lock().unwrap().push(e.to_string())
+        })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retries_transient_failures() {
+        let adapter = Scripted::new(
+            "flaky",
+            vec![
+                Err(AdapterError::Timeout),
+                Err(AdapterError::Connection("refused".to_string())),
+                Ok(queue(1_000, "orders", 1)),
+            ],
+        );
+        let mut collector = Collector::builder().adapter(adapter.clone()).build();
+
+        let snapshot = collector.poll().await;
+        assert_eq!(adapter.calls.load(Ordering::SeqCst), 3);
+        assert!(snapshot.modules.contains_key("orders"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn gives_up_and_reports_failures() {
+        let auth = Scripted::new("auth", vec![Err(AdapterError::Auth("denied".to_string()))]);
+        let down = Scripted::new(
+            "down",
+            vec![
+                Err(AdapterError::Timeout),
+                Err(AdapterError::Timeout),
+                Err(AdapterError::Timeout),
+            ],
+        );
+        let healthy = Scripted::new("healthy", vec![Ok(queue(1_000, "orders", 1))]);
+        let (errors, on_error) = collecting_errors();
+        let mut collector = Collector::builder()
+            .adapter(auth.clone())
+            .adapter(down.clone())
+            .adapter(healthy)
+            .retries(1)
+            .on_error(on_error)
+            .build();
+
+        let snapshot = collector.poll().await;
+        // Auth failures are not retried; the timeout is retried once
+        assert_eq!(auth.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(down.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(snapshot.modules.len(), 1);
+        assert_eq!(
+            *errors.lock().unwrap(),
+            vec![
+                "failed to collect from auth: Authentication failed: denied".to_string(),
+                "failed to collect from down: Request timed out".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn computes_missing_rates_from_successive_polls() {
+        let adapter = Scripted::new(
+            "rabbit",
+            vec![
+                Ok(queue(10_000, "orders", 100)),
+                Ok(queue(12_000, "orders", 150)),
+                Ok(queue(14_000, "orders", 20)),
+            ],
+        );
+        let mut collector = Collector::builder().adapter(adapter).build();
+
+        let first = collector.poll().await;
+        assert_eq!(first.modules["orders"].reads["messages"].rate, None);
+
+        let second = collector.poll().await;
+        assert_eq!(second.modules["orders"].reads["messages"].rate, Some(25.0));
+        // Rates reported by the adapter are kept
+        assert_eq!(second.modules["orders"].writes["messages"].rate, Some(7.0));
+
+        // The count went down, so the broker was reset
+        let third = collector.poll().await;
+        assert_eq!(third.modules["orders"].reads["messages"].rate, None);
+    }
+
+    #[tokio::test]
+    async fn failed_poll_keeps_previous_counts() {
+        let adapter = Scripted::new(
+            "rabbit",
+            vec![
+                Ok(queue(10_000, "orders", 100)),
+                Err(AdapterError::Auth("denied".to_string())),
+                Ok(queue(14_000, "orders", 150)),
+            ],
+        );
+        let mut collector = Collector::builder().adapter(adapter).build();
+
+        collector.poll().await;
+        assert!(collector.poll().await.modules.is_empty());
+
+        let third = collector.poll().await;
+        assert_eq!(third.modules["orders"].reads["messages"].rate, Some(12.5));
+    }
+
+    #[tokio::test]
+    async fn merges_adapters() {
+        let first = Scripted::new(
+            "first",
+            vec![Ok(Snapshot::builder()
+                .module("shared", |m| m.read("a", |r| r.count(1)))
+                .module("only-first", |m| m.read("a", |r| r.count(1)))
+                .build())],
+        );
+        let second = Scripted::new(
+            "second",
+            vec![Ok(Snapshot::builder()
+                .module("shared", |m| m.read("b", |r| r.count(2)))
+                .build())],
+        );
+        let mut collector = Collector::builder().adapter(first).adapter(second).build();
+
+        let snapshot = collector.poll().await;
+        assert_eq!(snapshot.modules.len(), 2);
+        let shared = &snapshot.modules["shared"];
+        assert_eq!(shared.reads["a"].count, 1);
+        assert_eq!(shared.reads["b"].count, 2);
+    }
+
+    #[tokio::test]
+    async fn prefixes_modules_with_conflicting_topics() {
+        let first = Scripted::new("first", vec![Ok(queue(1_000, "orders", 1))]);
+        let second = Scripted::new("second", vec![Ok(queue(1_000, "orders", 2))]);
+        let mut collector = Collector::builder().adapter(first).adapter(second).build();
+
+        let snapshot = collector.poll().await;
+        assert_eq!(snapshot.modules.len(), 2);
+        assert_eq!(snapshot.modules["orders"].reads["messages"].count, 1);
+        assert_eq!(snapshot.modules["second/orders"].reads["messages"].count, 2);
+    }
+
+    #[tokio::test]
+    async fn prefix_modules_names_every_module_after_its_adapter() {
+        let first = Scripted::new("first", vec![Ok(queue(1_000, "orders", 1))]);
+        let second = Scripted::new("second", vec![Ok(queue(1_000, "billing", 2))]);
+        let mut collector = Collector::builder()
+            .adapter(first)
+            .adapter(second)
+            .prefix_modules(true)
+            .build();
+
+        let snapshot = collector.poll().await;
+        let names: Vec<_> = snapshot.modules.keys().map(String::as_str).collect();
+        assert_eq!(names, vec!["first/orders", "second/billing"]);
+    }
+
+    #[tokio::test]
+    async fn start_emits_to_outputs_until_shutdown() {
+        let adapter = Scripted::new(
+            "rabbit",
+            vec![Ok(queue(1_000, "orders", 1)), Ok(queue(2_000, "orders", 2))],
+        );
+        let (output, mut rx) = Output::channel(8);
+        let collector = Collector::builder()
+            .adapter(adapter)
+            .output(output)
+            .interval(Duration::from_millis(10))
+            .build();
+
+        let handle = collector.start();
+        let first = rx.recv().await.unwrap();
+        let second = rx.recv().await.unwrap();
+        assert_eq!(first.modules["orders"].reads["messages"].count, 1);
+        assert_eq!(second.modules["orders"].reads["messages"].count, 2);
+
+        handle.shutdown().await.unwrap();
+    }
+}
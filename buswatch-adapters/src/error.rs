@@ -28,6 +28,24 @@ pub enum AdapterError {
     /// Feature not supported by this message bus version.
     #[error("Feature not supported: {0}")]
     Unsupported(String),
+
+    /// The adapter was configured with an invalid option.
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+}
+
+impl AdapterError {
+    /// Whether retrying the same request may succeed.
+    ///
+    /// Connection failures, timeouts and HTTP errors are transient; bad
+    /// credentials, unparseable responses, unsupported features and invalid
+    /// configuration are not.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            AdapterError::Http(_) | AdapterError::Connection(_) | AdapterError::Timeout
+        )
+    }
 }
 
 #[cfg(feature = "rabbitmq")]
@@ -83,6 +101,26 @@ mod tests {
         assert_eq!(err.to_string(), "Feature not supported: feature X");
     }
 
+    #[test]
+    fn error_display_config() {
+        let err = AdapterError::Config("invalid queue filter".to_string());
+        assert_eq!(
+            err.to_string(),
+            "Invalid configuration: invalid queue filter"
+        );
+    }
+
+    #[test]
+    fn error_is_transient() {
+        assert!(AdapterError::Timeout.is_transient());
+        assert!(AdapterError::Connection("refused".to_string()).is_transient());
+        assert!(AdapterError::Http("503".to_string()).is_transient());
+        assert!(!AdapterError::Auth("bad credentials".to_string()).is_transient());
+        assert!(!AdapterError::Parse("invalid JSON".to_string()).is_transient());
+        assert!(!AdapterError::Unsupported("feature X".to_string()).is_transient());
+        assert!(!AdapterError::Config("bad regex".to_string()).is_transient());
+    }
+
     #[test]
     fn error_is_debug() {
         let err = AdapterError::Timeout;
@@ -47,13 +47,14 @@ use rdkafka::TopicPartitionList;
 
 use buswatch_types::{ModuleMetrics, ReadMetrics, SchemaVersion, Snapshot};
 
-use crate::AdapterError;
+use crate::{Adapter, AdapterError};
 
 /// Kafka adapter for collecting consumer group metrics.
 pub struct KafkaAdapter {
     #[allow(dead_code)]
     admin: AdminClient<DefaultClientContext>,
     consumer: BaseConsumer,
+    brokers: String,
     group_filter: Option<String>,
     timeout: Duration,
 }
@@ -169,6 +170,8 @@ impl KafkaAdapter {
         Ok(ModuleMetrics {
             reads,
             writes: BTreeMap::new(),
+            evicted_topics: None,
+            tracked_topics: None,
         })
     }
 }
@@ -176,12 +179,23 @@ impl KafkaAdapter {
 impl std::fmt::Debug for KafkaAdapter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("KafkaAdapter")
+            .field("brokers", &self.brokers)
             .field("group_filter", &self.group_filter)
             .field("timeout", &self.timeout)
             .finish()
     }
 }
 
+impl Adapter for KafkaAdapter {
+    fn name(&self) -> String {
+        format!("kafka:{}", self.brokers)
+    }
+
+    async fn collect(&self) -> Result<Snapshot, AdapterError> {
+        KafkaAdapter::collect(self).await
+    }
+}
+
 /// Builder for KafkaAdapter.
 #[derive(Debug, Default)]
 pub struct KafkaAdapterBuilder {
@@ -239,6 +253,7 @@ impl KafkaAdapterBuilder {
         Ok(KafkaAdapter {
             admin,
             consumer,
+            brokers,
             group_filter: self.group_filter,
             timeout,
         })
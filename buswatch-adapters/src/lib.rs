@@ -7,11 +7,16 @@
 //!
 //! ## Supported Systems
 //!
-//! - **RabbitMQ** (`rabbitmq` feature) - Collects queue depths, consumer counts,
-//!   and message rates via the RabbitMQ Management API
+//! - **RabbitMQ** (`rabbitmq` feature) - Collects the flow from publishers
+//!   through exchanges to consumers, with queue depths, unacked messages and
+//!   message rates, via the RabbitMQ Management API
 //! - **Kafka** (`kafka` feature) - Collects consumer group lag and partition metrics
 //! - **NATS** (`nats` feature) - Collects JetStream consumer and stream metrics
 //!
+//! Every adapter implements [`Adapter`]. A `Collector` (`collector` feature)
+//! polls adapters on an interval and sends the merged snapshots to
+//! `buswatch-sdk` outputs.
+//!
 //! ## Quick Start (RabbitMQ)
 //!
 //! ```rust,ignore
@@ -23,7 +28,7 @@
 //!     let adapter = RabbitMqAdapter::builder()
 //!         .endpoint("http://localhost:15672")
 //!         .credentials("guest", "guest")
-//!         .build();
+//!         .build()?;
 //!
 //!     // Collect a snapshot
 //!     let snapshot = adapter.collect().await?;
@@ -33,6 +38,9 @@
 //! }
 //! ```
 
+mod adapter;
+#[cfg(feature = "collector")]
+pub mod collector;
 pub mod error;
 
 #[cfg(feature = "rabbitmq")]
@@ -44,7 +52,11 @@ pub mod kafka;
 #[cfg(feature = "nats")]
 pub mod nats;
 
+pub use adapter::Adapter;
 pub use error::AdapterError;
 
+#[cfg(feature = "collector")]
+pub use collector::{CollectError, Collector};
+
 // Re-export types for convenience
 pub use buswatch_types::{ModuleMetrics, ReadMetrics, Snapshot, WriteMetrics};
@@ -41,11 +41,12 @@ use futures_util::StreamExt;
 
 use buswatch_types::{ModuleMetrics, ReadMetrics, SchemaVersion, Snapshot, WriteMetrics};
 
-use crate::AdapterError;
+use crate::{Adapter, AdapterError};
 
 /// NATS JetStream adapter for collecting stream and consumer metrics.
 pub struct NatsAdapter {
     jetstream: jetstream::Context,
+    url: String,
 }
 
 impl NatsAdapter {
@@ -133,13 +134,30 @@ impl NatsAdapter {
             reads.insert(consumer_name, read_metrics);
         }
 
-        Ok(ModuleMetrics { reads, writes })
+        Ok(ModuleMetrics {
+            reads,
+            writes,
+            evicted_topics: None,
+            tracked_topics: None,
+        })
     }
 }
 
 impl std::fmt::Debug for NatsAdapter {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("NatsAdapter").finish()
+        f.debug_struct("NatsAdapter")
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl Adapter for NatsAdapter {
+    fn name(&self) -> String {
+        format!("nats:{}", self.url)
+    }
+
+    async fn collect(&self) -> Result<Snapshot, AdapterError> {
+        NatsAdapter::collect(self).await
     }
 }
 
@@ -185,7 +203,7 @@ impl NatsAdapterBuilder {
 
         let jetstream = jetstream::new(client);
 
-        Ok(NatsAdapter { jetstream })
+        Ok(NatsAdapter { jetstream, url })
     }
 }
 
@@ -3,12 +3,55 @@
 //! This adapter collects metrics from RabbitMQ by querying the Management API,
 //! which is typically available on port 15672.
 //!
+//! ## Flow Graph
+//!
+//! Snapshots model how messages move through the broker, so the TUI Flow view
+//! shows publishers, exchanges and consumers:
+//!
+//! - **Topics** are exchanges. The default exchange is reported as
+//!   `amq.default`.
+//! - **Consumer applications** are modules reading the exchanges bound to the
+//!   queues they consume. An application is identified by the
+//!   `connection_name` its client set, falling back to the consumer tag.
+//! - **Each queue is counted once.** Its deliveries, backlog, unacknowledged
+//!   messages and rate are split evenly between the applications consuming
+//!   it, which RabbitMQ delivers to in turn, and between the exchanges bound
+//!   to it, whose shares the API doesn't report. Summing a topic across
+//!   modules, or a module across topics, gives the queues' real totals.
+//! - **Queues without consumers** are modules themselves, so a backlog nobody
+//!   is reading still shows up.
+//! - **Publishers** are modules writing the exchanges their channels publish
+//!   to, identified like consumers. Per-exchange channel stats need
+//!   `management.rates_mode = detailed`; otherwise publishes into an exchange
+//!   are reported by an `(unknown publisher)` module.
+//! - **Several vhosts** are collected into one graph. Their topics and
+//!   queue modules are prefixed with the vhost, as in `orders:billing`, so
+//!   same-named exchanges in different vhosts stay apart.
+//!
 //! ## Metrics Collected
 //!
-//! - **Queue depth** (backlog): Number of messages ready in each queue
-//! - **Consumer count**: Number of consumers attached to each queue
-//! - **Message rates**: Publish and deliver rates per queue
-//! - **Unacked messages**: Messages delivered but not yet acknowledged
+//! - **Backlog**: messages ready in the queues behind each read
+//! - **In-flight**: messages delivered but not yet acknowledged
+//! - **Counts and rates**: deliveries for reads, publishes for writes
+//! - **Pending**: age of the oldest message in the queues behind each read,
+//!   from `head_message_timestamp`. This needs publishers to set the AMQP
+//!   `timestamp` property.
+//!
+//! ## Large Brokers
+//!
+//! Queues and exchanges are fetched page by page, and every listing asks
+//! only for the columns the graph needs. Use
+//! [`queue_filter`](RabbitMqAdapterBuilder::queue_filter) to report only
+//! some queues; the broker filters the queue listing itself. Attributing publishes takes a request per publishing
+//! channel, so it is skipped beyond
+//! [`max_publisher_channels`](RabbitMqAdapterBuilder::max_publisher_channels).
+//!
+//! ## TLS
+//!
+//! HTTPS endpoints are verified against the system's root certificates plus
+//! any given with [`ca_certificate`](RabbitMqAdapterBuilder::ca_certificate).
+//! Brokers requiring mutual TLS need a
+//! [`client_certificate`](RabbitMqAdapterBuilder::client_certificate).
 //!
 //! ## Example
 //!
@@ -21,15 +64,14 @@
 //!         .endpoint("http://localhost:15672")
 //!         .credentials("guest", "guest")
 //!         .vhost("/")
-//!         .build();
+//!         .build()?;
 //!
 //!     let snapshot = adapter.collect().await?;
 //!
-//!     for (queue_name, metrics) in &snapshot.modules {
-//!         println!("Queue: {}", queue_name);
-//!         if let Some(read) = metrics.reads.get("messages") {
-//!             println!("  Ready: {:?}", read.backlog);
-//!             println!("  Rate: {:?} msg/s", read.rate);
+//!     for (module, metrics) in &snapshot.modules {
+//!         println!("Module: {}", module);
+//!         for (exchange, read) in &metrics.reads {
+//!             println!("  Reads {}: {:?} ready, {:?} unacked", exchange, read.backlog, read.inflight);
 //!         }
 //!     }
 //!
@@ -37,15 +79,49 @@
 //! }
 //! ```
 
-use std::collections::BTreeMap;
-use std::time::Duration;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use reqwest::Client;
-use serde::Deserialize;
+use futures_util::{StreamExt, TryStreamExt};
+use regex::Regex;
+use reqwest::{Certificate, Client, Identity};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
 
 use buswatch_types::{ModuleMetrics, ReadMetrics, SchemaVersion, Snapshot, WriteMetrics};
 
-use crate::AdapterError;
+use crate::{Adapter, AdapterError};
+
+/// Name reported for the default exchange, whose real name is empty.
+const DEFAULT_EXCHANGE: &str = "amq.default";
+
+/// Module reporting publishes that can't be attributed to a channel.
+const UNKNOWN_PUBLISHER: &str = "(unknown publisher)";
+
+/// Channel details fetched at once when attributing publishes.
+const CHANNEL_CONCURRENCY: usize = 8;
+
+/// Default limit on the publishing channels looked up per vhost.
+const DEFAULT_MAX_PUBLISHER_CHANNELS: usize = 100;
+
+/// Vhosts fetched at once.
+const VHOST_CONCURRENCY: usize = 4;
+
+/// Largest page the Management API serves.
+const MAX_PAGE_SIZE: usize = 500;
+
+// Columns requested from each listing, so large brokers don't send
+// everything they know about every object
+const QUEUE_COLUMNS: &str = "name,messages_ready,messages_unacknowledged,head_message_timestamp,\
+    message_stats.deliver_get,message_stats.deliver_get_details.rate";
+const EXCHANGE_COLUMNS: &str =
+    "name,message_stats.publish_in,message_stats.publish_in_details.rate";
+const BINDING_COLUMNS: &str = "source,destination,destination_type";
+const CONSUMER_COLUMNS: &str = "consumer_tag,queue.name,channel_details.connection_name";
+const CONNECTION_COLUMNS: &str = "name,client_properties.connection_name";
+const CHANNEL_COLUMNS: &str = "name,connection_details.name,message_stats.publish";
 
 /// RabbitMQ adapter for collecting queue metrics.
 #[derive(Debug, Clone)]
@@ -54,7 +130,18 @@ pub struct RabbitMqAdapter {
     endpoint: String,
     username: String,
     password: String,
-    vhost: String,
+    vhosts: Vhosts,
+    queue_filter: Option<Regex>,
+    page_size: usize,
+    max_publisher_channels: usize,
+}
+
+/// The vhosts an adapter collects.
+#[derive(Debug, Clone, PartialEq)]
+enum Vhosts {
+    Listed(Vec<String>),
+    /// Every vhost on the broker, listed on each collection
+    All,
 }
 
 impl RabbitMqAdapter {
@@ -63,69 +150,217 @@ impl RabbitMqAdapter {
         RabbitMqAdapterBuilder::default()
     }
 
-    /// Collect a snapshot of all queue metrics.
+    /// Collect a snapshot of the flow graph across the configured vhosts.
     pub async fn collect(&self) -> Result<Snapshot, AdapterError> {
-        let queues = self.fetch_queues().await?;
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let vhosts = match &self.vhosts {
+            Vhosts::Listed(vhosts) => vhosts.clone(),
+            Vhosts::All => self.list_vhosts().await?,
+        };
+        // A single named vhost keeps plain names
+        let qualify = !matches!(&self.vhosts, Vhosts::Listed(vhosts) if vhosts.len() == 1);
 
-        let mut modules = BTreeMap::new();
+        let topologies: Vec<Topology> = futures_util::stream::iter(vhosts)
+            .map(|vhost| async move { self.fetch_topology(&vhost, qualify, timestamp_ms).await })
+            .buffered(VHOST_CONCURRENCY)
+            .try_collect()
+            .await?;
 
-        for queue in queues {
-            let module_metrics = self.queue_to_metrics(&queue);
-            modules.insert(queue.name, module_metrics);
+        // Qualified names don't collide, so only applications span vhosts
+        let mut modules: BTreeMap<String, ModuleMetrics> = BTreeMap::new();
+        for topology in &topologies {
+            for (name, graph) in topology.flow_graph() {
+                let module = modules.entry(name).or_default();
+                module.reads.extend(graph.reads);
+                module.writes.extend(graph.writes);
+            }
         }
 
         Ok(Snapshot {
             version: SchemaVersion::current(),
-            timestamp_ms: std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as u64,
+            timestamp_ms,
             modules,
         })
     }
 
-    /// Collect metrics for a specific queue.
+    /// Collect metrics for a specific queue, as a module reading the
+    /// exchanges bound to it.
+    ///
+    /// The queue is looked up in the first configured vhost, or `/` when
+    /// collecting every vhost.
     pub async fn collect_queue(&self, queue_name: &str) -> Result<ModuleMetrics, AdapterError> {
-        let queue = self.fetch_queue(queue_name).await?;
-        Ok(self.queue_to_metrics(&queue))
+        let vhost = match &self.vhosts {
+            Vhosts::Listed(vhosts) => vhosts.first().map_or("/", String::as_str),
+            Vhosts::All => "/",
+        };
+        let path = format!(
+            "/api/queues/{}/{}",
+            urlencoded(vhost),
+            urlencoded(queue_name)
+        );
+        let queue: QueueInfo = self
+            .get_optional(path.clone())
+            .await?
+            .ok_or_else(|| AdapterError::Http(format!("Queue '{}' not found", queue_name)))?;
+        let bindings = self
+            .get(format!("{}/bindings?columns={}", path, BINDING_COLUMNS))
+            .await?;
+
+        let topology = Topology {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            queues: vec![queue],
+            bindings,
+            ..Topology::default()
+        };
+        Ok(topology.flow_graph().remove(queue_name).unwrap_or_default())
     }
 
-    async fn fetch_queues(&self) -> Result<Vec<QueueInfo>, AdapterError> {
-        let url = format!("{}/api/queues/{}", self.endpoint, urlencoded(&self.vhost));
+    /// Fetch everything the flow graph needs from one vhost.
+    async fn fetch_topology(
+        &self,
+        vhost: &str,
+        qualify: bool,
+        timestamp_ms: u64,
+    ) -> Result<Topology, AdapterError> {
+        let path = urlencoded(vhost);
+        let (mut queues, exchanges, bindings, consumers, connections, channels) = futures_util::try_join!(
+            self.get_all::<QueueInfo>(
+                format!("/api/queues/{}", path),
+                QUEUE_COLUMNS,
+                self.queue_filter.as_ref()
+            ),
+            self.get_all::<ExchangeInfo>(
+                format!("/api/exchanges/{}", path),
+                EXCHANGE_COLUMNS,
+                None
+            ),
+            self.get::<Vec<BindingInfo>>(format!(
+                "/api/bindings/{}?columns={}",
+                path, BINDING_COLUMNS
+            )),
+            self.get::<Vec<ConsumerInfo>>(format!(
+                "/api/consumers/{}?columns={}",
+                path, CONSUMER_COLUMNS
+            )),
+            self.get::<Vec<ConnectionInfo>>(format!(
+                "/api/vhosts/{}/connections?columns={}",
+                path, CONNECTION_COLUMNS
+            )),
+            async {
+                if self.max_publisher_channels == 0 {
+                    return Ok(Vec::new());
+                }
+                self.get::<Vec<ChannelInfo>>(format!(
+                    "/api/vhosts/{}/channels?columns={}",
+                    path, CHANNEL_COLUMNS
+                ))
+                .await
+            },
+        )?;
+        // The broker's regex dialect may differ, and older brokers ignore
+        // the filter, so check the names here too
+        if let Some(filter) = &self.queue_filter {
+            queues.retain(|queue| filter.is_match(&queue.name));
+        }
+        let channels = self.fetch_publishing_channels(channels).await?;
+
+        Ok(Topology {
+            vhost: qualify.then(|| vhost.to_string()),
+            timestamp_ms,
+            queues,
+            exchanges,
+            bindings,
+            consumers,
+            connections,
+            channels,
+        })
+    }
 
-        let response = self
-            .client
-            .get(&url)
-            .basic_auth(&self.username, Some(&self.password))
-            .send()
-            .await?;
+    /// Names of every vhost on the broker.
+    async fn list_vhosts(&self) -> Result<Vec<String>, AdapterError> {
+        let vhosts: Vec<NameRef> = self.get("/api/vhosts?columns=name".to_string()).await?;
+        Ok(vhosts.into_iter().map(|vhost| vhost.name).collect())
+    }
 
-        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
-            return Err(AdapterError::Auth("Invalid credentials".to_string()));
+    /// Replace the channels that have published with their details, which
+    /// break publishes down by exchange. Channels that closed in the meantime
+    /// are dropped.
+    ///
+    /// Each channel is a request of its own, so if more than
+    /// `max_publisher_channels` have published none are fetched, leaving
+    /// their publishes to the `(unknown publisher)` module.
+    async fn fetch_publishing_channels(
+        &self,
+        channels: Vec<ChannelInfo>,
+    ) -> Result<Vec<ChannelInfo>, AdapterError> {
+        let publishing: Vec<ChannelInfo> = channels
+            .into_iter()
+            .filter(ChannelInfo::has_published)
+            .collect();
+        if publishing.len() > self.max_publisher_channels {
+            return Ok(Vec::new());
         }
 
-        if !response.status().is_success() {
-            return Err(AdapterError::Http(format!(
-                "API returned status {}",
-                response.status()
-            )));
-        }
+        let details: Vec<Option<ChannelInfo>> = futures_util::stream::iter(publishing)
+            .map(|channel| async move {
+                self.get_optional(format!("/api/channels/{}", urlencoded(&channel.name)))
+                    .await
+            })
+            .buffered(CHANNEL_CONCURRENCY)
+            .try_collect()
+            .await?;
+        Ok(details.into_iter().flatten().collect())
+    }
 
-        let queues: Vec<QueueInfo> = response
-            .json()
-            .await
-            .map_err(|e| AdapterError::Parse(e.to_string()))?;
+    /// Fetch every page of a paginated Management API listing, with only
+    /// the given columns and, given a `name` filter, only the items whose
+    /// names match it.
+    async fn get_all<T: DeserializeOwned>(
+        &self,
+        path: String,
+        columns: &str,
+        name: Option<&Regex>,
+    ) -> Result<Vec<T>, AdapterError> {
+        let name = name.map_or_else(String::new, |name| {
+            format!("&name={}&use_regex=true", urlencoded(name.as_str()))
+        });
+        let mut items = Vec::new();
+        let mut page = 1;
+        loop {
+            let response: Page<T> = self
+                .get(format!(
+                    "{}?page={}&page_size={}&columns={}{}",
+                    path, page, self.page_size, columns, name
+                ))
+                .await?;
+            items.extend(response.items);
+            if page >= response.page_count {
+                return Ok(items);
+            }
+            page += 1;
+        }
+    }
 
-        Ok(queues)
+    /// Fetch a Management API path, failing if it doesn't exist.
+    async fn get<T: DeserializeOwned>(&self, path: String) -> Result<T, AdapterError> {
+        self.get_optional(path.clone())
+            .await?
+            .ok_or_else(|| AdapterError::Http(format!("{} not found", path)))
     }
 
-    async fn fetch_queue(&self, queue_name: &str) -> Result<QueueInfo, AdapterError> {
-        let url = format!(
-            "{}/api/queues/{}/{}",
-            self.endpoint,
-            urlencoded(&self.vhost),
-            urlencoded(queue_name)
-        );
+    /// Fetch a Management API path, returning `None` if it doesn't exist.
+    async fn get_optional<T: DeserializeOwned>(
+        &self,
+        path: String,
+    ) -> Result<Option<T>, AdapterError> {
+        let url = format!("{}{}", self.endpoint, path);
 
         let response = self
             .client
@@ -139,10 +374,7 @@ impl RabbitMqAdapter {
         }
 
         if response.status() == reqwest::StatusCode::NOT_FOUND {
-            return Err(AdapterError::Http(format!(
-                "Queue '{}' not found",
-                queue_name
-            )));
+            return Ok(None);
         }
 
         if !response.status().is_success() {
@@ -152,45 +384,21 @@ impl RabbitMqAdapter {
             )));
         }
 
-        let queue: QueueInfo = response
+        response
             .json()
             .await
-            .map_err(|e| AdapterError::Parse(e.to_string()))?;
-
-        Ok(queue)
+            .map(Some)
+            .map_err(|e| AdapterError::Parse(e.to_string()))
     }
+}
 
-    fn queue_to_metrics(&self, queue: &QueueInfo) -> ModuleMetrics {
-        let mut reads = BTreeMap::new();
-        let mut writes = BTreeMap::new();
-
-        // Consumer metrics (reading from the queue)
-        if queue.consumers > 0 {
-            let mut read_metrics = ReadMetrics::new(queue.messages_delivered.unwrap_or(0));
-            read_metrics.backlog = Some(queue.messages_ready);
-            if let Some(rate) = queue
-                .message_stats
-                .as_ref()
-                .and_then(|s| s.deliver_get_rate())
-            {
-                read_metrics.rate = Some(rate);
-            }
-            reads.insert("messages".to_string(), read_metrics);
-        } else {
-            // No consumers, just show backlog
-            let mut read_metrics = ReadMetrics::new(0);
-            read_metrics.backlog = Some(queue.messages_ready);
-            reads.insert("messages".to_string(), read_metrics);
-        }
-
-        // Publisher metrics (writing to the queue)
-        let mut write_metrics = WriteMetrics::new(queue.messages_published.unwrap_or(0));
-        if let Some(rate) = queue.message_stats.as_ref().and_then(|s| s.publish_rate()) {
-            write_metrics.rate = Some(rate);
-        }
-        writes.insert("messages".to_string(), write_metrics);
+impl Adapter for RabbitMqAdapter {
+    fn name(&self) -> String {
+        format!("rabbitmq:{}", self.endpoint)
+    }
 
-        ModuleMetrics { reads, writes }
+    async fn collect(&self) -> Result<Snapshot, AdapterError> {
+        RabbitMqAdapter::collect(self).await
     }
 }
 
@@ -200,8 +408,13 @@ pub struct RabbitMqAdapterBuilder {
     endpoint: Option<String>,
     username: Option<String>,
     password: Option<String>,
-    vhost: Option<String>,
+    vhosts: Option<Vhosts>,
+    queue_filter: Option<String>,
+    page_size: Option<usize>,
+    max_publisher_channels: Option<usize>,
     timeout: Option<Duration>,
+    ca_certificate: Option<PathBuf>,
+    client_certificate: Option<(PathBuf, PathBuf)>,
 }
 
 impl RabbitMqAdapterBuilder {
@@ -220,7 +433,53 @@ impl RabbitMqAdapterBuilder {
 
     /// Set the vhost to query (default: "/").
     pub fn vhost(mut self, vhost: impl Into<String>) -> Self {
-        self.vhost = Some(vhost.into());
+        self.vhosts = Some(Vhosts::Listed(vec![vhost.into()]));
+        self
+    }
+
+    /// Query several vhosts, prefixing their topics and queue modules with
+    /// the vhost.
+    pub fn vhosts<I, S>(mut self, vhosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.vhosts = Some(Vhosts::Listed(vhosts.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Query every vhost on the broker, prefixing their topics and queue
+    /// modules with the vhost. The vhosts are listed on each collection.
+    pub fn all_vhosts(mut self) -> Self {
+        self.vhosts = Some(Vhosts::All);
+        self
+    }
+
+    /// Only report queues whose names match the regular expression
+    /// `pattern`. Exchanges and publishers are reported regardless.
+    ///
+    /// The pattern is sent to the broker so it lists only matching queues,
+    /// and checked again against every queue it returns.
+    pub fn queue_filter(mut self, pattern: impl Into<String>) -> Self {
+        self.queue_filter = Some(pattern.into());
+        self
+    }
+
+    /// Set how many queues or exchanges to fetch per request (default and
+    /// maximum: 500).
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Set how many publishing channels per vhost may be looked up to
+    /// attribute publishes to applications (default: 100).
+    ///
+    /// Each takes a request of its own on every collection. With more
+    /// channels than this, or a limit of 0, publishes are reported by the
+    /// `(unknown publisher)` module instead.
+    pub fn max_publisher_channels(mut self, max: usize) -> Self {
+        self.max_publisher_channels = Some(max);
         self
     }
 
@@ -230,30 +489,289 @@ impl RabbitMqAdapterBuilder {
         self
     }
 
+    /// Trust the PEM-encoded CA certificates in `path`, in addition to the
+    /// system's root certificates.
+    pub fn ca_certificate(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_certificate = Some(path.into());
+        self
+    }
+
+    /// Present a client certificate for mutual TLS: a PEM-encoded
+    /// certificate chain in `cert` and its PKCS#8 private key in `key`.
+    pub fn client_certificate(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.client_certificate = Some((cert.into(), key.into()));
+        self
+    }
+
     /// Build the adapter.
-    pub fn build(self) -> RabbitMqAdapter {
+    ///
+    /// Fails if the queue filter isn't a valid regular expression or a
+    /// certificate can't be loaded.
+    pub fn build(self) -> Result<RabbitMqAdapter, AdapterError> {
         let timeout = self.timeout.unwrap_or(Duration::from_secs(10));
+        let mut client = Client::builder().timeout(timeout);
+
+        if let Some(path) = &self.ca_certificate {
+            let certificates = Certificate::from_pem_bundle(&read(path)?)
+                .map_err(|e| AdapterError::Config(format!("{}: {}", path.display(), e)))?;
+            if certificates.is_empty() {
+                return Err(AdapterError::Config(format!(
+                    "{}: no certificates found",
+                    path.display()
+                )));
+            }
+            for certificate in certificates {
+                client = client.add_root_certificate(certificate);
+            }
+        }
+
+        if let Some((cert, key)) = &self.client_certificate {
+            let identity = Identity::from_pkcs8_pem(&read(cert)?, &read(key)?)
+                .map_err(|e| AdapterError::Config(format!("{}: {}", cert.display(), e)))?;
+            client = client.identity(identity);
+        }
 
-        let client = Client::builder()
-            .timeout(timeout)
+        let client = client
             .build()
-            .expect("Failed to build HTTP client");
+            .map_err(|e| AdapterError::Config(e.to_string()))?;
 
-        RabbitMqAdapter {
+        let queue_filter = self
+            .queue_filter
+            .map(|pattern| {
+                Regex::new(&pattern)
+                    .map_err(|e| AdapterError::Config(format!("invalid queue filter: {}", e)))
+            })
+            .transpose()?;
+
+        Ok(RabbitMqAdapter {
             client,
             endpoint: self
                 .endpoint
                 .unwrap_or_else(|| "http://localhost:15672".to_string()),
             username: self.username.unwrap_or_else(|| "guest".to_string()),
             password: self.password.unwrap_or_else(|| "guest".to_string()),
-            vhost: self.vhost.unwrap_or_else(|| "/".to_string()),
-        }
+            vhosts: self
+                .vhosts
+                .unwrap_or_else(|| Vhosts::Listed(vec!["/".to_string()])),
+            queue_filter,
+            page_size: self
+                .page_size
+                .unwrap_or(MAX_PAGE_SIZE)
+                .clamp(1, MAX_PAGE_SIZE),
+            max_publisher_channels: self
+                .max_publisher_channels
+                .unwrap_or(DEFAULT_MAX_PUBLISHER_CHANNELS),
+        })
     }
 }
 
-// URL encode a string for use in paths
+/// Read a certificate or key file.
+fn read(path: &Path) -> Result<Vec<u8>, AdapterError> {
+    std::fs::read(path)
+        .map_err(|e| AdapterError::Config(format!("reading {}: {}", path.display(), e)))
+}
+
+/// Percent-encode a string for use as a path segment, leaving only
+/// unreserved characters as they are.
 fn urlencoded(s: &str) -> String {
-    s.replace('/', "%2F")
+    let mut encoded = String::with_capacity(s.len());
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
+
+/// Everything fetched from the Management API for one vhost.
+#[derive(Debug, Default)]
+struct Topology {
+    /// Vhost prefixing topic and queue module names, when collecting several
+    vhost: Option<String>,
+    /// When the topology was fetched, for message ages
+    timestamp_ms: u64,
+    queues: Vec<QueueInfo>,
+    exchanges: Vec<ExchangeInfo>,
+    bindings: Vec<BindingInfo>,
+    consumers: Vec<ConsumerInfo>,
+    connections: Vec<ConnectionInfo>,
+    /// Channels that have published, with their per-exchange publishes
+    channels: Vec<ChannelInfo>,
+}
+
+impl Topology {
+    /// Build modules reading and writing exchanges from the broker's state.
+    fn flow_graph(&self) -> BTreeMap<String, ModuleMetrics> {
+        // Client-provided names of connections that set one
+        let names: HashMap<&str, &str> = self
+            .connections
+            .iter()
+            .filter_map(|c| {
+                let name = c.client_properties.connection_name.as_deref()?;
+                (!name.is_empty()).then_some((c.name.as_str(), name))
+            })
+            .collect();
+        let application = |connection: &str, fallback: &str| {
+            names
+                .get(connection)
+                .copied()
+                .unwrap_or(fallback)
+                .to_string()
+        };
+
+        // Exchanges routing into each queue
+        let mut sources: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+        for binding in &self.bindings {
+            if binding.destination_type == "queue" {
+                sources
+                    .entry(&binding.destination)
+                    .or_default()
+                    .insert(&binding.source);
+            }
+        }
+
+        // Applications consuming each queue
+        let mut readers: HashMap<&str, BTreeSet<String>> = HashMap::new();
+        for consumer in &self.consumers {
+            readers
+                .entry(&consumer.queue.name)
+                .or_default()
+                .insert(application(
+                    &consumer.channel_details.connection_name,
+                    &consumer.consumer_tag,
+                ));
+        }
+
+        let mut modules: BTreeMap<String, ModuleMetrics> = BTreeMap::new();
+
+        for queue in &self.queues {
+            let read = queue.read_metrics(self.timestamp_ms);
+            let exchanges = self.exchange_topics(sources.get(queue.name.as_str()));
+            let owners = match readers.get(queue.name.as_str()) {
+                Some(applications) => applications.iter().cloned().collect(),
+                None => vec![self.qualified(&queue.name)],
+            };
+
+            // Split the queue between every (owner, exchange) pair
+            let parts = (owners.len() * exchanges.len()) as u64;
+            let mut part = 0;
+            for owner in owners {
+                let module = modules.entry(owner).or_default();
+                for exchange in &exchanges {
+                    let total = module
+                        .reads
+                        .entry(exchange.clone())
+                        .or_insert_with(|| ReadMetrics::new(0));
+                    let share = |n: u64| share(n, parts, part);
+                    total.count += share(read.count);
+                    total.backlog = add(total.backlog, read.backlog.map(share));
+                    total.inflight = add(total.inflight, read.inflight.map(share));
+                    total.rate = add(total.rate, read.rate.map(|r| r / parts as f64));
+                    // The oldest message across the queues
+                    total.pending = total.pending.max(read.pending);
+                    part += 1;
+                }
+            }
+        }
+
+        let mut attributed = HashSet::new();
+        for channel in &self.channels {
+            let connection = &channel.connection_details.name;
+            let module = modules
+                .entry(application(connection, connection))
+                .or_default();
+            for publish in &channel.publishes {
+                let exchange = self.exchange_topic(&publish.exchange.name);
+                let total = module
+                    .writes
+                    .entry(exchange.clone())
+                    .or_insert_with(|| WriteMetrics::new(0));
+                total.count += publish.stats.publish;
+                total.rate = add(
+                    total.rate,
+                    publish.stats.publish_details.as_ref().map(|d| d.rate),
+                );
+                attributed.insert(exchange);
+            }
+        }
+
+        // Publishes the channels didn't account for, e.g. without detailed rates
+        for exchange in &self.exchanges {
+            let name = self.exchange_topic(&exchange.name);
+            let Some(stats) = &exchange.message_stats else {
+                continue;
+            };
+            if stats.publish_in == 0 || attributed.contains(&name) {
+                continue;
+            }
+            let mut write = WriteMetrics::new(stats.publish_in);
+            write.rate = stats.publish_in_details.as_ref().map(|d| d.rate);
+            modules
+                .entry(UNKNOWN_PUBLISHER.to_string())
+                .or_default()
+                .writes
+                .insert(name, write);
+        }
+
+        modules
+    }
+
+    /// Prefix `name` with the vhost when collecting several.
+    fn qualified(&self, name: &str) -> String {
+        match &self.vhost {
+            Some(vhost) => format!("{}:{}", vhost, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// The topic name for an exchange.
+    fn exchange_topic(&self, name: &str) -> String {
+        if name.is_empty() {
+            self.qualified(DEFAULT_EXCHANGE)
+        } else {
+            self.qualified(name)
+        }
+    }
+
+    /// Topics for the exchanges bound to a queue. Every queue is bound to the
+    /// default exchange, which is left out when the queue has other bindings.
+    fn exchange_topics(&self, sources: Option<&BTreeSet<&str>>) -> Vec<String> {
+        let named: Vec<String> = sources
+            .into_iter()
+            .flatten()
+            .filter(|source| !source.is_empty())
+            .map(|source| self.qualified(source))
+            .collect();
+        if named.is_empty() {
+            vec![self.exchange_topic("")]
+        } else {
+            named
+        }
+    }
+}
+
+/// Sum two optional values, treating a missing one as absent rather than zero.
+/// Part `index` of `total` split into `parts` whole shares, the remainder
+/// going to the first ones so the shares add up to `total`.
+fn share(total: u64, parts: u64, index: u64) -> u64 {
+    total / parts + u64::from(index < total % parts)
+}
+
+fn add<T: std::ops::Add<Output = T>>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+/// One page of a paginated Management API listing.
+#[derive(Debug, Deserialize)]
+struct Page<T> {
+    items: Vec<T>,
+    #[serde(default)]
+    page_count: usize,
 }
 
 /// Queue information from the RabbitMQ Management API.
@@ -263,30 +781,45 @@ struct QueueInfo {
     #[serde(default)]
     messages_ready: u64,
     #[serde(default)]
-    #[allow(dead_code)]
     messages_unacknowledged: u64,
-    #[serde(default)]
-    consumers: u32,
-    #[serde(default)]
-    messages_delivered: Option<u64>,
-    #[serde(default)]
-    messages_published: Option<u64>,
+    /// When the oldest message was published, in seconds since the epoch
+    #[serde(default, deserialize_with = "timestamp")]
+    head_message_timestamp: Option<u64>,
     message_stats: Option<MessageStats>,
 }
 
+impl QueueInfo {
+    /// Reads of the queue as of `now_ms`.
+    fn read_metrics(&self, now_ms: u64) -> ReadMetrics {
+        let stats = self.message_stats.as_ref();
+        let mut read = ReadMetrics::new(stats.and_then(|s| s.deliver_get).unwrap_or(0));
+        read.backlog = Some(self.messages_ready);
+        read.inflight = Some(self.messages_unacknowledged);
+        read.rate = stats.and_then(|s| s.deliver_get_rate());
+        read.pending = self.head_message_timestamp.map(|published| {
+            Duration::from_millis(now_ms.saturating_sub(published.saturating_mul(1000))).into()
+        });
+        read
+    }
+}
+
+/// `head_message_timestamp` is empty rather than absent when the oldest
+/// message has no timestamp.
+fn timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    Ok(serde_json::Value::deserialize(deserializer)?.as_u64())
+}
+
 #[derive(Debug, Deserialize)]
 struct MessageStats {
-    #[serde(default, rename = "publish_details")]
-    publish_details: Option<RateDetails>,
+    #[serde(default)]
+    publish: Option<u64>,
+    #[serde(default)]
+    deliver_get: Option<u64>,
     #[serde(default, rename = "deliver_get_details")]
     deliver_get_details: Option<RateDetails>,
 }
 
 impl MessageStats {
-    fn publish_rate(&self) -> Option<f64> {
-        self.publish_details.as_ref().map(|d| d.rate)
-    }
-
     fn deliver_get_rate(&self) -> Option<f64> {
         self.deliver_get_details.as_ref().map(|d| d.rate)
     }
@@ -297,17 +830,217 @@ struct RateDetails {
     rate: f64,
 }
 
+/// Exchange information from the RabbitMQ Management API.
+#[derive(Debug, Deserialize)]
+struct ExchangeInfo {
+    name: String,
+    message_stats: Option<ExchangeStats>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExchangeStats {
+    #[serde(default)]
+    publish_in: u64,
+    publish_in_details: Option<RateDetails>,
+}
+
+/// A binding from an exchange to a queue or another exchange.
+#[derive(Debug, Deserialize)]
+struct BindingInfo {
+    source: String,
+    destination: String,
+    destination_type: String,
+}
+
+/// A consumer subscribed to a queue.
+#[derive(Debug, Deserialize)]
+struct ConsumerInfo {
+    #[serde(default)]
+    consumer_tag: String,
+    queue: NameRef,
+    #[serde(default)]
+    channel_details: ChannelDetails,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChannelDetails {
+    #[serde(default)]
+    connection_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NameRef {
+    name: String,
+}
+
+/// A client connection, with the name the client gave it.
+#[derive(Debug, Deserialize)]
+struct ConnectionInfo {
+    name: String,
+    #[serde(default)]
+    client_properties: ClientProperties,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ClientProperties {
+    connection_name: Option<String>,
+}
+
+/// A channel, with per-exchange publishes when fetched individually.
+#[derive(Debug, Deserialize)]
+struct ChannelInfo {
+    name: String,
+    #[serde(default)]
+    connection_details: ConnectionDetails,
+    message_stats: Option<MessageStats>,
+    #[serde(default)]
+    publishes: Vec<ChannelPublish>,
+}
+
+impl ChannelInfo {
+    fn has_published(&self) -> bool {
+        self.message_stats
+            .as_ref()
+            .and_then(|s| s.publish)
+            .is_some_and(|count| count > 0)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConnectionDetails {
+    #[serde(default)]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChannelPublish {
+    exchange: NameRef,
+    stats: PublishStats,
+}
+
+#[derive(Debug, Deserialize)]
+struct PublishStats {
+    #[serde(default)]
+    publish: u64,
+    publish_details: Option<RateDetails>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    use http_body_util::Full;
+    use hyper::body::Bytes;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Request, Response, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use serde_json::{json, Value};
+    use tokio::io::{AsyncRead, AsyncWrite};
+    use tokio::net::TcpListener;
+    use tokio_rustls::TlsAcceptor;
+
+    /// A vhost where `orders-api` publishes to the `orders` exchange, which
+    /// routes to `billing` (consumed by `billing-worker`) and `audit` (with
+    /// no consumers).
+    fn topology() -> Topology {
+        Topology {
+            queues: serde_json::from_str(
+                r#"[
+                    {
+                        "name": "billing",
+                        "messages_ready": 40,
+                        "messages_unacknowledged": 5,
+                        "consumers": 1,
+                        "message_stats": {
+                            "deliver_get": 900,
+                            "deliver_get_details": { "rate": 12.5 }
+                        }
+                    },
+                    { "name": "audit", "messages_ready": 1000, "messages_unacknowledged": 0 }
+                ]"#,
+            )
+            .unwrap(),
+            exchanges: serde_json::from_str(
+                r#"[
+                    { "name": "", "message_stats": { "publish_in": 0 } },
+                    {
+                        "name": "orders",
+                        "message_stats": { "publish_in": 950, "publish_in_details": { "rate": 13.0 } }
+                    },
+                    {
+                        "name": "events",
+                        "message_stats": { "publish_in": 20, "publish_in_details": { "rate": 1.0 } }
+                    }
+                ]"#,
+            )
+            .unwrap(),
+            bindings: serde_json::from_str(
+                r#"[
+                    { "source": "", "destination": "billing", "destination_type": "queue" },
+                    { "source": "", "destination": "audit", "destination_type": "queue" },
+                    { "source": "orders", "destination": "billing", "destination_type": "queue" },
+                    { "source": "orders", "destination": "audit", "destination_type": "queue" },
+                    { "source": "orders", "destination": "events", "destination_type": "exchange" }
+                ]"#,
+            )
+            .unwrap(),
+            consumers: serde_json::from_str(
+                r#"[
+                    {
+                        "consumer_tag": "amq.ctag-1",
+                        "queue": { "name": "billing", "vhost": "/" },
+                        "channel_details": { "connection_name": "10.0.0.2:5000 -> 10.0.0.1:5672", "number": 1 }
+                    }
+                ]"#,
+            )
+            .unwrap(),
+            connections: serde_json::from_str(
+                r#"[
+                    {
+                        "name": "10.0.0.2:5000 -> 10.0.0.1:5672",
+                        "client_properties": { "connection_name": "billing-worker" }
+                    },
+                    { "name": "10.0.0.3:6000 -> 10.0.0.1:5672", "client_properties": {} }
+                ]"#,
+            )
+            .unwrap(),
+            channels: serde_json::from_str(
+                r#"[
+                    {
+                        "name": "10.0.0.3:6000 -> 10.0.0.1:5672 (1)",
+                        "connection_details": { "name": "10.0.0.3:6000 -> 10.0.0.1:5672" },
+                        "message_stats": { "publish": 950 },
+                        "publishes": [
+                            {
+                                "exchange": { "name": "orders", "vhost": "/" },
+                                "stats": { "publish": 950, "publish_details": { "rate": 13.0 } }
+                            }
+                        ]
+                    }
+                ]"#,
+            )
+            .unwrap(),
+            ..Topology::default()
+        }
+    }
 
     #[test]
     fn test_builder_defaults() {
-        let adapter = RabbitMqAdapter::builder().build();
+        let adapter = RabbitMqAdapter::builder().build().unwrap();
         assert_eq!(adapter.endpoint, "http://localhost:15672");
         assert_eq!(adapter.username, "guest");
         assert_eq!(adapter.password, "guest");
-        assert_eq!(adapter.vhost, "/");
+        assert_eq!(adapter.vhosts, Vhosts::Listed(vec!["/".to_string()]));
+        assert!(adapter.queue_filter.is_none());
+        assert_eq!(adapter.page_size, MAX_PAGE_SIZE);
+        assert_eq!(
+            adapter.max_publisher_channels,
+            DEFAULT_MAX_PUBLISHER_CHANNELS
+        );
     }
 
     #[test]
@@ -316,12 +1049,66 @@ mod tests {
             .endpoint("http://rabbit.local:15672")
             .credentials("admin", "secret")
             .vhost("myapp")
-            .build();
+            .queue_filter("^orders\\.")
+            .page_size(100)
+            .build()
+            .unwrap();
 
         assert_eq!(adapter.endpoint, "http://rabbit.local:15672");
         assert_eq!(adapter.username, "admin");
         assert_eq!(adapter.password, "secret");
-        assert_eq!(adapter.vhost, "myapp");
+        assert_eq!(adapter.vhosts, Vhosts::Listed(vec!["myapp".to_string()]));
+        assert!(adapter.queue_filter.unwrap().is_match("orders.created"));
+        assert_eq!(adapter.page_size, 100);
+    }
+
+    #[test]
+    fn builder_vhosts() {
+        let adapter = RabbitMqAdapter::builder()
+            .vhosts(["/", "staging"])
+            .build()
+            .unwrap();
+        assert_eq!(
+            adapter.vhosts,
+            Vhosts::Listed(vec!["/".to_string(), "staging".to_string()])
+        );
+
+        let adapter = RabbitMqAdapter::builder().all_vhosts().build().unwrap();
+        assert_eq!(adapter.vhosts, Vhosts::All);
+    }
+
+    #[test]
+    fn builder_clamps_page_size() {
+        let adapter = RabbitMqAdapter::builder().page_size(0).build().unwrap();
+        assert_eq!(adapter.page_size, 1);
+
+        let adapter = RabbitMqAdapter::builder()
+            .page_size(10_000)
+            .build()
+            .unwrap();
+        assert_eq!(adapter.page_size, MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn builder_rejects_invalid_configuration() {
+        let err = RabbitMqAdapter::builder()
+            .queue_filter("orders(")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, AdapterError::Config(_)));
+        assert!(err.to_string().contains("invalid queue filter"));
+
+        let err = RabbitMqAdapter::builder()
+            .ca_certificate("/nonexistent/ca.pem")
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("/nonexistent/ca.pem"));
+
+        let err = RabbitMqAdapter::builder()
+            .client_certificate("/nonexistent/client.pem", "/nonexistent/client-key.pem")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, AdapterError::Config(_)));
     }
 
     #[test]
@@ -332,80 +1119,205 @@ mod tests {
     }
 
     #[test]
-    fn test_queue_to_metrics() {
-        let adapter = RabbitMqAdapter::builder().build();
-
-        let queue = QueueInfo {
-            name: "test-queue".to_string(),
-            messages_ready: 100,
-            messages_unacknowledged: 5,
-            consumers: 2,
-            messages_delivered: Some(500),
-            messages_published: Some(600),
-            message_stats: Some(MessageStats {
-                publish_details: Some(RateDetails { rate: 10.5 }),
-                deliver_get_details: Some(RateDetails { rate: 9.2 }),
-            }),
-        };
+    fn consumer_applications_read_bound_exchanges() {
+        let modules = topology().flow_graph();
+
+        // Named by the client's connection_name, not the queue
+        let read = &modules["billing-worker"].reads["orders"];
+        assert_eq!(read.count, 900);
+        assert_eq!(read.backlog, Some(40));
+        assert_eq!(read.inflight, Some(5));
+        assert_eq!(read.rate, Some(12.5));
+        assert!(!modules.contains_key("billing"));
+
+        // The default exchange is left out when a queue has other bindings
+        assert_eq!(modules["billing-worker"].reads.len(), 1);
+    }
 
-        let metrics = adapter.queue_to_metrics(&queue);
+    #[test]
+    fn queues_without_consumers_are_modules() {
+        let modules = topology().flow_graph();
 
-        let read = metrics.reads.get("messages").unwrap();
-        assert_eq!(read.count, 500);
-        assert_eq!(read.backlog, Some(100));
-        assert_eq!(read.rate, Some(9.2));
+        let read = &modules["audit"].reads["orders"];
+        assert_eq!(read.count, 0);
+        assert_eq!(read.backlog, Some(1000));
+        assert_eq!(read.rate, None);
+    }
 
-        let write = metrics.writes.get("messages").unwrap();
-        assert_eq!(write.count, 600);
-        assert_eq!(write.rate, Some(10.5));
+    #[test]
+    fn publishers_write_exchanges_from_channel_stats() {
+        let modules = topology().flow_graph();
+
+        // No connection_name, so named after the connection
+        let write = &modules["10.0.0.3:6000 -> 10.0.0.1:5672"].writes["orders"];
+        assert_eq!(write.count, 950);
+        assert_eq!(write.rate, Some(13.0));
+
+        // Publishes to `events` weren't seen on any channel
+        let unknown = &modules[UNKNOWN_PUBLISHER];
+        assert_eq!(unknown.writes.len(), 1);
+        assert_eq!(unknown.writes["events"].count, 20);
     }
 
     #[test]
-    fn queue_with_no_consumers_shows_backlog_only() {
-        let adapter = RabbitMqAdapter::builder().build();
-
-        let queue = QueueInfo {
-            name: "idle-queue".to_string(),
-            messages_ready: 50,
-            messages_unacknowledged: 0,
-            consumers: 0, // No consumers
-            messages_delivered: None,
-            messages_published: Some(50),
-            message_stats: None,
-        };
+    fn consumer_tag_names_application_without_connection_name() {
+        let mut topology = topology();
+        topology.connections.clear();
 
-        let metrics = adapter.queue_to_metrics(&queue);
+        let modules = topology.flow_graph();
+        assert!(modules["amq.ctag-1"].reads.contains_key("orders"));
+    }
+
+    #[test]
+    fn queues_consumed_by_one_application_are_summed() {
+        let mut topology = topology();
+        topology.consumers.extend(
+            serde_json::from_str::<Vec<ConsumerInfo>>(
+                r#"[{
+                    "consumer_tag": "amq.ctag-2",
+                    "queue": { "name": "audit" },
+                    "channel_details": { "connection_name": "10.0.0.2:5000 -> 10.0.0.1:5672" }
+                }]"#,
+            )
+            .unwrap(),
+        );
+
+        let modules = topology.flow_graph();
+        let read = &modules["billing-worker"].reads["orders"];
+        assert_eq!(read.backlog, Some(1040));
+        assert_eq!(read.inflight, Some(5));
+        assert_eq!(read.rate, Some(12.5));
+        assert!(!modules.contains_key("audit"));
+    }
+
+    #[test]
+    fn queue_bound_to_several_exchanges_is_counted_once() {
+        let mut topology = topology();
+        topology.bindings.extend(
+            serde_json::from_str::<Vec<BindingInfo>>(
+                r#"[{ "source": "events", "destination": "billing", "destination_type": "queue" }]"#,
+            )
+            .unwrap(),
+        );
+
+        let modules = topology.flow_graph();
+        let reads = &modules["billing-worker"].reads;
+        assert_eq!(reads.len(), 2);
+        let backlog: u64 = reads.values().filter_map(|r| r.backlog).sum();
+        let count: u64 = reads.values().map(|r| r.count).sum();
+        let inflight: u64 = reads.values().filter_map(|r| r.inflight).sum();
+        let rate: f64 = reads.values().filter_map(|r| r.rate).sum();
+        assert_eq!(backlog, 40);
+        assert_eq!(count, 900);
+        assert_eq!(inflight, 5);
+        assert_eq!(rate, 12.5);
+    }
 
-        let read = metrics.reads.get("messages").unwrap();
-        assert_eq!(read.count, 0); // No delivered messages
-        assert_eq!(read.backlog, Some(50)); // But backlog is shown
+    #[test]
+    fn queue_shared_by_applications_is_split_between_them() {
+        let mut topology = topology();
+        topology.consumers.extend(
+            serde_json::from_str::<Vec<ConsumerInfo>>(
+                r#"[{
+                    "consumer_tag": "amq.ctag-2",
+                    "queue": { "name": "billing" },
+                    "channel_details": { "connection_name": "10.0.0.4:7000 -> 10.0.0.1:5672" }
+                }]"#,
+            )
+            .unwrap(),
+        );
+
+        let modules = topology.flow_graph();
+        let worker = &modules["billing-worker"].reads["orders"];
+        let other = &modules["amq.ctag-2"].reads["orders"];
+        assert_eq!(worker.backlog, Some(20));
+        assert_eq!(other.backlog, Some(20));
+        assert_eq!(worker.inflight.unwrap() + other.inflight.unwrap(), 5);
+        assert_eq!(worker.count + other.count, 900);
+        assert_eq!(worker.rate, Some(6.25));
+    }
+
+    #[test]
+    fn pending_is_age_of_oldest_message() {
+        let mut topology = topology();
+        topology.timestamp_ms = 1_700_000_100_000;
+        topology.queues[0].head_message_timestamp = Some(1_700_000_090);
+        topology.queues[1].head_message_timestamp = Some(1_700_000_040);
+
+        let modules = topology.flow_graph();
+        assert_eq!(
+            modules["billing-worker"].reads["orders"].pending,
+            Some(Duration::from_secs(10).into())
+        );
+        assert_eq!(
+            modules["audit"].reads["orders"].pending,
+            Some(Duration::from_secs(60).into())
+        );
+
+        // An application consuming both queues reports the older message
+        topology.consumers.extend(
+            serde_json::from_str::<Vec<ConsumerInfo>>(
+                r#"[{
+                    "consumer_tag": "amq.ctag-2",
+                    "queue": { "name": "audit" },
+                    "channel_details": { "connection_name": "10.0.0.2:5000 -> 10.0.0.1:5672" }
+                }]"#,
+            )
+            .unwrap(),
+        );
+        let modules = topology.flow_graph();
+        assert_eq!(
+            modules["billing-worker"].reads["orders"].pending,
+            Some(Duration::from_secs(60).into())
+        );
     }
 
     #[test]
-    fn queue_with_no_message_stats_has_no_rates() {
-        let adapter = RabbitMqAdapter::builder().build();
-
-        let queue = QueueInfo {
-            name: "new-queue".to_string(),
-            messages_ready: 0,
-            messages_unacknowledged: 0,
-            consumers: 1,
-            messages_delivered: Some(10),
-            messages_published: Some(10),
-            message_stats: None,
+    fn vhost_prefixes_topics_and_queue_modules() {
+        let mut topology = topology();
+        topology.vhost = Some("shop".to_string());
+
+        let modules = topology.flow_graph();
+        assert!(modules["billing-worker"].reads.contains_key("shop:orders"));
+        assert!(modules["shop:audit"].reads.contains_key("shop:orders"));
+        assert!(modules["10.0.0.3:6000 -> 10.0.0.1:5672"]
+            .writes
+            .contains_key("shop:orders"));
+        assert!(modules[UNKNOWN_PUBLISHER]
+            .writes
+            .contains_key("shop:events"));
+
+        let topology = Topology {
+            vhost: Some("shop".to_string()),
+            queues: serde_json::from_str(r#"[{ "name": "rpc-replies" }]"#).unwrap(),
+            ..Topology::default()
         };
+        assert!(topology.flow_graph()["shop:rpc-replies"]
+            .reads
+            .contains_key("shop:amq.default"));
+    }
 
-        let metrics = adapter.queue_to_metrics(&queue);
+    #[test]
+    fn unbound_queue_reads_default_exchange() {
+        let topology = Topology {
+            queues: serde_json::from_str(r#"[{ "name": "rpc-replies", "messages_ready": 3 }]"#)
+                .unwrap(),
+            ..Topology::default()
+        };
 
-        assert!(metrics.reads.get("messages").unwrap().rate.is_none());
-        assert!(metrics.writes.get("messages").unwrap().rate.is_none());
+        let modules = topology.flow_graph();
+        assert_eq!(
+            modules["rpc-replies"].reads[DEFAULT_EXCHANGE].backlog,
+            Some(3)
+        );
     }
 
     #[test]
     fn builder_with_timeout() {
         let adapter = RabbitMqAdapter::builder()
             .timeout(Duration::from_secs(30))
-            .build();
+            .build()
+            .unwrap();
 
         // Can't directly test timeout, but verify it doesn't panic
         assert_eq!(adapter.endpoint, "http://localhost:15672");
@@ -417,31 +1329,39 @@ mod tests {
     }
 
     #[test]
-    fn urlencoded_preserves_other_special_chars() {
-        // Only slashes are encoded by this function
-        assert_eq!(urlencoded("name:with:colons"), "name:with:colons");
-        assert_eq!(urlencoded("name.with.dots"), "name.with.dots");
+    fn urlencoded_escapes_reserved_chars() {
+        assert_eq!(urlencoded("name:with:colons"), "name%3Awith%3Acolons");
+        assert_eq!(urlencoded("a b?c#d%e&f+g"), "a%20b%3Fc%23d%25e%26f%2Bg");
+        assert_eq!(urlencoded("caf\u{e9}"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn urlencoded_preserves_unreserved_chars() {
+        assert_eq!(
+            urlencoded("name.with-dots_and~tilde"),
+            "name.with-dots_and~tilde"
+        );
     }
 
     #[test]
     fn message_stats_rate_extraction() {
         let stats = MessageStats {
-            publish_details: Some(RateDetails { rate: 100.5 }),
+            publish: Some(100),
+            deliver_get: Some(99),
             deliver_get_details: Some(RateDetails { rate: 99.2 }),
         };
 
-        assert_eq!(stats.publish_rate(), Some(100.5));
         assert_eq!(stats.deliver_get_rate(), Some(99.2));
     }
 
     #[test]
     fn message_stats_missing_details_returns_none() {
         let stats = MessageStats {
-            publish_details: None,
+            publish: None,
+            deliver_get: None,
             deliver_get_details: None,
         };
 
-        assert_eq!(stats.publish_rate(), None);
         assert_eq!(stats.deliver_get_rate(), None);
     }
 
@@ -453,7 +1373,9 @@ mod tests {
             "messages_unacknowledged": 3,
             "consumers": 2,
             "message_stats": {
+                "publish": 100,
                 "publish_details": { "rate": 10.0 },
+                "deliver_get": 95,
                 "deliver_get_details": { "rate": 9.5 }
             }
         }"#;
@@ -461,8 +1383,8 @@ mod tests {
         let queue: QueueInfo = serde_json::from_str(json).unwrap();
         assert_eq!(queue.name, "orders");
         assert_eq!(queue.messages_ready, 42);
-        assert_eq!(queue.consumers, 2);
-        assert_eq!(queue.message_stats.unwrap().publish_rate(), Some(10.0));
+        assert_eq!(queue.messages_unacknowledged, 3);
+        assert_eq!(queue.message_stats.unwrap().deliver_get_rate(), Some(9.5));
     }
 
     #[test]
@@ -474,7 +1396,432 @@ mod tests {
         let queue: QueueInfo = serde_json::from_str(json).unwrap();
         assert_eq!(queue.name, "simple-queue");
         assert_eq!(queue.messages_ready, 0);
-        assert_eq!(queue.consumers, 0);
+        assert!(queue.head_message_timestamp.is_none());
         assert!(queue.message_stats.is_none());
     }
+
+    #[test]
+    fn queue_info_head_message_timestamp_may_be_empty() {
+        let queue: QueueInfo =
+            serde_json::from_str(r#"{ "name": "q", "head_message_timestamp": 1700000000 }"#)
+                .unwrap();
+        assert_eq!(queue.head_message_timestamp, Some(1_700_000_000));
+
+        let queue: QueueInfo =
+            serde_json::from_str(r#"{ "name": "q", "head_message_timestamp": "" }"#).unwrap();
+        assert!(queue.head_message_timestamp.is_none());
+    }
+
+    #[test]
+    fn channel_without_publishes_has_not_published() {
+        let channel: ChannelInfo =
+            serde_json::from_str(r#"{ "name": "c", "message_stats": { "deliver_get": 5 } }"#)
+                .unwrap();
+        assert!(!channel.has_published());
+        assert!(channel.publishes.is_empty());
+    }
+
+    /// Start a stand-in Management API, over TLS when given an acceptor,
+    /// answering each request's path and query with `respond`. Returns the
+    /// path and query of every request received.
+    async fn stand_in<F>(
+        tls: Option<TlsAcceptor>,
+        respond: F,
+    ) -> (SocketAddr, Arc<Mutex<Vec<String>>>)
+    where
+        F: Fn(&str) -> Result<Value, StatusCode> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let respond = Arc::new(respond);
+
+        let log = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (log, respond, tls) = (log.clone(), respond.clone(), tls.clone());
+                tokio::spawn(async move {
+                    match tls {
+                        // Failed handshakes are for the client to report
+                        Some(acceptor) => {
+                            if let Ok(stream) = acceptor.accept(stream).await {
+                                serve(stream, log, respond).await;
+                            }
+                        }
+                        None => serve(stream, log, respond).await,
+                    }
+                });
+            }
+        });
+
+        (addr, requests)
+    }
+
+    async fn serve<S, F>(stream: S, log: Arc<Mutex<Vec<String>>>, respond: Arc<F>)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(&str) -> Result<Value, StatusCode> + Send + Sync + 'static,
+    {
+        let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+            let uri = req.uri().path_and_query().map_or("", |p| p.as_str());
+            log.lock().unwrap().push(uri.to_string());
+            let response = match respond(uri) {
+                Ok(body) => Response::new(Full::new(Bytes::from(body.to_string()))),
+                Err(status) => Response::builder()
+                    .status(status)
+                    .body(Full::new(Bytes::new()))
+                    .unwrap(),
+            };
+            async move { Ok::<_, Infallible>(response) }
+        });
+        let _ = http1::Builder::new()
+            .serve_connection(TokioIo::new(stream), service)
+            .await;
+    }
+
+    /// A listing that fits on one page.
+    fn page(items: Value) -> Value {
+        json!({ "items": items, "page": 1, "page_count": 1 })
+    }
+
+    /// Every vhost has a `work` queue and a server-named one, and nothing
+    /// else; `/` and `staging` exist.
+    fn queues_in_every_vhost(uri: &str) -> Result<Value, StatusCode> {
+        let path = uri.split_once('?').map_or(uri, |(path, _)| path);
+        if path.starts_with("/api/queues/") {
+            Ok(page(json!([
+                { "name": "work", "messages_ready": 1 },
+                { "name": "amq.gen-123", "messages_ready": 2 }
+            ])))
+        } else if path.starts_with("/api/exchanges/") {
+            Ok(page(json!([])))
+        } else if path == "/api/vhosts" {
+            Ok(json!([{ "name": "/" }, { "name": "staging" }]))
+        } else {
+            Ok(json!([]))
+        }
+    }
+
+    #[tokio::test]
+    async fn collects_paginated_listings_with_columns() {
+        let published = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            - 30;
+        let (addr, requests) = stand_in(None, move |uri| {
+            let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+            match path {
+                "/api/queues/%2F" if query.starts_with("page=1&") => Ok(json!({
+                    "items": [{ "name": "billing", "messages_ready": 4, "head_message_timestamp": published }],
+                    "page": 1,
+                    "page_count": 2
+                })),
+                "/api/queues/%2F" => Ok(json!({
+                    "items": [{ "name": "audit", "messages_ready": 7 }],
+                    "page": 2,
+                    "page_count": 2
+                })),
+                "/api/exchanges/%2F" => Ok(page(json!([{ "name": "orders" }]))),
+                "/api/bindings/%2F" => Ok(json!([
+                    { "source": "orders", "destination": "billing", "destination_type": "queue" },
+                    { "source": "orders", "destination": "audit", "destination_type": "queue" }
+                ])),
+                "/api/consumers/%2F"
+                | "/api/vhosts/%2F/connections"
+                | "/api/vhosts/%2F/channels" => Ok(json!([])),
+                _ => Err(StatusCode::NOT_FOUND),
+            }
+        })
+        .await;
+
+        let adapter = RabbitMqAdapter::builder()
+            .endpoint(format!("http://{}", addr))
+            .page_size(1)
+            .build()
+            .unwrap();
+        let snapshot = adapter.collect().await.unwrap();
+
+        let read = &snapshot.modules["billing"].reads["orders"];
+        assert_eq!(read.backlog, Some(4));
+        let pending = Duration::from(read.pending.unwrap());
+        assert!(pending >= Duration::from_secs(30) && pending < Duration::from_secs(40));
+        assert_eq!(snapshot.modules["audit"].reads["orders"].backlog, Some(7));
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.contains(&format!(
+            "/api/queues/%2F?page=2&page_size=1&columns={}",
+            QUEUE_COLUMNS
+        )));
+        assert!(requests.contains(&format!("/api/bindings/%2F?columns={}", BINDING_COLUMNS)));
+    }
+
+    #[tokio::test]
+    async fn collects_several_vhosts_with_queue_filter() {
+        let (addr, requests) = stand_in(None, queues_in_every_vhost).await;
+
+        let adapter = RabbitMqAdapter::builder()
+            .endpoint(format!("http://{}", addr))
+            .vhosts(["orders", "a b"])
+            .queue_filter("^work$")
+            .build()
+            .unwrap();
+        let snapshot = adapter.collect().await.unwrap();
+
+        let modules: Vec<&str> = snapshot.modules.keys().map(String::as_str).collect();
+        assert_eq!(modules, ["a b:work", "orders:work"]);
+        assert_eq!(
+            snapshot.modules["orders:work"].reads["orders:amq.default"].backlog,
+            Some(1)
+        );
+
+        // Vhost names are percent-encoded in full
+        let requests = requests.lock().unwrap();
+        assert!(requests
+            .iter()
+            .any(|uri| uri.starts_with("/api/queues/a%20b?")));
+        // The broker filters queues too, but the stand-in ignores it
+        let queues: Vec<&String> = requests
+            .iter()
+            .filter(|uri| uri.starts_with("/api/queues/"))
+            .collect();
+        assert!(!queues.is_empty());
+        assert!(queues
+            .iter()
+            .all(|uri| uri.ends_with("&name=%5Ework%24&use_regex=true")));
+        assert!(requests
+            .iter()
+            .filter(|uri| uri.starts_with("/api/exchanges/"))
+            .all(|uri| !uri.contains("name=")));
+    }
+
+    #[tokio::test]
+    async fn collects_every_vhost() {
+        let (addr, requests) = stand_in(None, queues_in_every_vhost).await;
+
+        let adapter = RabbitMqAdapter::builder()
+            .endpoint(format!("http://{}", addr))
+            .all_vhosts()
+            .build()
+            .unwrap();
+        let snapshot = adapter.collect().await.unwrap();
+
+        let modules: Vec<&str> = snapshot.modules.keys().map(String::as_str).collect();
+        assert_eq!(
+            modules,
+            [
+                "/:amq.gen-123",
+                "/:work",
+                "staging:amq.gen-123",
+                "staging:work"
+            ]
+        );
+        assert!(requests
+            .lock()
+            .unwrap()
+            .contains(&"/api/vhosts?columns=name".to_string()));
+    }
+
+    /// Two channels publishing to `orders`, with per-exchange details.
+    fn publishing_channels(uri: &str) -> Result<Value, StatusCode> {
+        let path = uri.split_once('?').map_or(uri, |(path, _)| path);
+        let channel = |name: &str, count: u64| {
+            json!({
+                "name": name,
+                "connection_details": { "name": "app" },
+                "message_stats": { "publish": count },
+                "publishes": [{ "exchange": { "name": "orders" }, "stats": { "publish": count } }]
+            })
+        };
+        match path {
+            "/api/exchanges/%2F" => Ok(page(json!([
+                { "name": "orders", "message_stats": { "publish_in": 30 } }
+            ]))),
+            "/api/queues/%2F" => Ok(page(json!([]))),
+            "/api/vhosts/%2F/channels" => Ok(json!([channel("a (1)", 10), channel("b (1)", 20)])),
+            "/api/channels/a%20%281%29" => Ok(channel("a (1)", 10)),
+            "/api/channels/b%20%281%29" => Ok(channel("b (1)", 20)),
+            _ => Ok(json!([])),
+        }
+    }
+
+    #[tokio::test]
+    async fn publisher_channels_are_capped() {
+        let (addr, requests) = stand_in(None, publishing_channels).await;
+        let collect = |max| {
+            let adapter = RabbitMqAdapter::builder()
+                .endpoint(format!("http://{}", addr))
+                .max_publisher_channels(max)
+                .build()
+                .unwrap();
+            async move { adapter.collect().await.unwrap() }
+        };
+        let channel_requests = |requests: &Mutex<Vec<String>>| {
+            let mut requests = requests.lock().unwrap();
+            let count = requests
+                .iter()
+                .filter(|uri| uri.starts_with("/api/channels/"))
+                .count();
+            requests.clear();
+            count
+        };
+
+        let snapshot = collect(2).await;
+        assert_eq!(snapshot.modules["app"].writes["orders"].count, 30);
+        assert!(!snapshot.modules.contains_key(UNKNOWN_PUBLISHER));
+        assert_eq!(channel_requests(&requests), 2);
+
+        // Too many to look up, so none are
+        let snapshot = collect(1).await;
+        assert_eq!(
+            snapshot.modules[UNKNOWN_PUBLISHER].writes["orders"].count,
+            30
+        );
+        assert_eq!(channel_requests(&requests), 0);
+
+        // Disabled, so channels aren't even listed
+        let snapshot = collect(0).await;
+        assert_eq!(
+            snapshot.modules[UNKNOWN_PUBLISHER].writes["orders"].count,
+            30
+        );
+        assert_eq!(channel_requests(&requests), 0);
+        assert!(!requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|uri| uri.starts_with("/api/vhosts/%2F/channels")));
+    }
+
+    #[tokio::test]
+    async fn collect_queue_from_api() {
+        let (addr, _) = stand_in(None, |uri| match uri {
+            "/api/queues/%2F/orders%20v2" => Ok(json!({
+                "name": "orders v2",
+                "messages_ready": 3,
+                "head_message_timestamp": 1
+            })),
+            _ if uri.starts_with("/api/queues/%2F/orders%20v2/bindings?") => Ok(json!([])),
+            _ => Err(StatusCode::NOT_FOUND),
+        })
+        .await;
+
+        let adapter = RabbitMqAdapter::builder()
+            .endpoint(format!("http://{}", addr))
+            .build()
+            .unwrap();
+        let module = adapter.collect_queue("orders v2").await.unwrap();
+        let read = &module.reads[DEFAULT_EXCHANGE];
+        assert_eq!(read.backlog, Some(3));
+        assert!(read.pending.is_some());
+
+        let err = adapter.collect_queue("missing").await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "HTTP request failed: Queue 'missing' not found"
+        );
+    }
+
+    #[tokio::test]
+    async fn unauthorized_is_an_auth_error() {
+        let (addr, _) = stand_in(None, |_| Err(StatusCode::UNAUTHORIZED)).await;
+
+        let adapter = RabbitMqAdapter::builder()
+            .endpoint(format!("http://{}", addr))
+            .build()
+            .unwrap();
+        assert!(matches!(
+            adapter.collect().await,
+            Err(AdapterError::Auth(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn trusts_custom_ca_and_presents_client_certificate() {
+        use rcgen::{
+            BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+        };
+        use tokio_rustls::rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+        use tokio_rustls::rustls::server::WebPkiClientVerifier;
+        use tokio_rustls::rustls::{crypto, RootCertStore, ServerConfig};
+
+        let ca_key = KeyPair::generate().unwrap();
+        let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        ca_params
+            .distinguished_name
+            .push(DnType::CommonName, "buswatch test CA");
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+
+        let issue = |name: &str, usage| {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+            // Distinct from the CA's, or the certificate looks self-signed
+            params.distinguished_name.push(DnType::CommonName, name);
+            params.extended_key_usages = vec![usage];
+            (params.signed_by(&key, &ca, &ca_key).unwrap(), key)
+        };
+        let (server_cert, server_key) = issue("localhost", ExtendedKeyUsagePurpose::ServerAuth);
+        let (client_cert, client_key) = issue("monitoring", ExtendedKeyUsagePurpose::ClientAuth);
+
+        let provider = Arc::new(crypto::ring::default_provider());
+        let mut roots = RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        let verifier =
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone())
+                .build()
+                .unwrap();
+        let config = ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(
+                vec![server_cert.der().clone()],
+                PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(server_key.serialize_der())),
+            )
+            .unwrap();
+        let acceptor = TlsAcceptor::from(Arc::new(config));
+        let (addr, _) = stand_in(Some(acceptor), queues_in_every_vhost).await;
+
+        let dir =
+            std::env::temp_dir().join(format!("buswatch-rabbitmq-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, pem: String| {
+            let path = dir.join(name);
+            std::fs::write(&path, pem).unwrap();
+            path
+        };
+        let ca_path = write("ca.pem", ca.pem());
+        let cert_path = write("client.pem", client_cert.pem());
+        let key_path = write("client-key.pem", client_key.serialize_pem());
+
+        let endpoint = format!("https://localhost:{}", addr.port());
+        let adapter = RabbitMqAdapter::builder()
+            .endpoint(&endpoint)
+            .ca_certificate(&ca_path)
+            .client_certificate(&cert_path, &key_path)
+            .build()
+            .unwrap();
+        let snapshot = adapter.collect().await.unwrap();
+        assert!(snapshot.modules.contains_key("work"));
+
+        // The server requires a client certificate
+        let anonymous = RabbitMqAdapter::builder()
+            .endpoint(&endpoint)
+            .ca_certificate(&ca_path)
+            .build()
+            .unwrap();
+        assert!(anonymous.collect().await.is_err());
+
+        // And isn't trusted without the CA
+        let untrusting = RabbitMqAdapter::builder()
+            .endpoint(&endpoint)
+            .client_certificate(&cert_path, &key_path)
+            .build()
+            .unwrap();
+        assert!(untrusting.collect().await.is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
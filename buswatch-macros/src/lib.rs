@@ -0,0 +1,280 @@
+//! # buswatch-macros
+//!
+//! Attribute macros for instrumenting message handlers with buswatch.
+//!
+//! These are re-exported from `buswatch-sdk` behind its `macros` feature;
+//! depend on the SDK rather than on this crate directly.
+//!
+//! ```rust,ignore
+//! #[buswatch_sdk::handler(module = "orders", reads = "orders.new", writes = "orders.processed")]
+//! async fn process(order: Order) -> Result<(), Error> {
+//!     // ...
+//! }
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{
+    bracketed, parse_macro_input, Expr, Ident, Item, ItemFn, LitStr, ReturnType, Token, Type,
+};
+
+/// Instrument a message handler function.
+///
+/// # Arguments
+///
+/// * `module` - Module name to record metrics under (required)
+/// * `reads` - Topic, or `[..]` list of topics, the handler consumes from
+/// * `writes` - Topic, or `[..]` list of topics, the handler produces to
+/// * `instrumentor` - Expression evaluating to an `Instrumentor` (or a
+///   reference to one). Defaults to `Instrumentor::global()`. The module is
+///   registered with `register_retained`, so it isn't removed between calls.
+///
+/// At least one of `reads` or `writes` is required.
+///
+/// # Behavior
+///
+/// For each call, the handler body runs while holding a `start_read`
+/// pending guard for every `reads` topic, so in-flight calls show up as
+/// pending and their duration is recorded as latency. Afterwards a read is
+/// recorded on each `reads` topic.
+///
+/// If the function returns a `Result`, an `Ok` records a write on each
+/// `writes` topic and an `Err` records an error on each `reads` topic and
+/// a write error on each `writes` topic.
+/// Any other return type counts as success.
+///
+/// Works with both `fn` and `async fn`. A non-`async` function returning
+/// a future is measured until the future is created, not until it completes.
+#[proc_macro_attribute]
+pub fn handler(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as HandlerArgs);
+    let function = match parse_macro_input!(item as Item) {
+        Item::Fn(function) => function,
+        other => {
+            return syn::Error::new(
+                other.span(),
+                "`#[handler]` can only be applied to functions",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    match expand(args, function) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Parsed `#[handler(...)]` arguments.
+struct HandlerArgs {
+    module: LitStr,
+    reads: Vec<LitStr>,
+    writes: Vec<LitStr>,
+    instrumentor: Option<Expr>,
+}
+
+impl Parse for HandlerArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut module = None;
+        let mut reads = None;
+        let mut writes = None;
+        let mut instrumentor = None;
+
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+
+            match key.to_string().as_str() {
+                "module" => set_once(&mut module, &key, input.parse::<LitStr>()?)?,
+                "reads" => set_once(&mut reads, &key, parse_topics(input)?)?,
+                "writes" => set_once(&mut writes, &key, parse_topics(input)?)?,
+                "instrumentor" => set_once(&mut instrumentor, &key, input.parse::<Expr>()?)?,
+                other => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        format!(
+                            "unknown argument `{other}`, expected one of `module`, `reads`, `writes`, `instrumentor`"
+                        ),
+                    ))
+                }
+            }
+
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        let module = module.ok_or_else(|| {
+            syn::Error::new(
+                Span::call_site(),
+                "missing `module` argument, e.g. `#[handler(module = \"orders\", ...)]`",
+            )
+        })?;
+        if module.value().is_empty() {
+            return Err(syn::Error::new(module.span(), "`module` must not be empty"));
+        }
+
+        let reads = reads.unwrap_or_default();
+        let writes = writes.unwrap_or_default();
+        if reads.is_empty() && writes.is_empty() {
+            return Err(syn::Error::new(
+                Span::call_site(),
+                "expected at least one of `reads` or `writes`",
+            ));
+        }
+
+        Ok(Self {
+            module,
+            reads,
+            writes,
+            instrumentor,
+        })
+    }
+}
+
+/// Store an argument value, rejecting duplicates.
+fn set_once<T>(slot: &mut Option<T>, key: &Ident, value: T) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(syn::Error::new(
+            key.span(),
+            format!("duplicate `{key}` argument"),
+        ));
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// Parse a single topic string or a bracketed list of topic strings.
+fn parse_topics(input: ParseStream) -> syn::Result<Vec<LitStr>> {
+    let topics = if input.peek(syn::token::Bracket) {
+        let content;
+        bracketed!(content in input);
+        content
+            .parse_terminated(|p| p.parse::<LitStr>(), Token![,])?
+            .into_iter()
+            .collect()
+    } else {
+        vec![input.parse::<LitStr>()?]
+    };
+
+    if let Some(empty) = topics.iter().find(|t| t.value().is_empty()) {
+        return Err(syn::Error::new(
+            empty.span(),
+            "topic names must not be empty",
+        ));
+    }
+    Ok(topics)
+}
+
+fn expand(args: HandlerArgs, function: ItemFn) -> syn::Result<TokenStream2> {
+    if let Some(constness) = &function.sig.constness {
+        return Err(syn::Error::new(
+            constness.span(),
+            "`#[handler]` cannot be applied to a `const fn`",
+        ));
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+
+    let HandlerArgs {
+        module,
+        reads,
+        writes,
+        instrumentor,
+    } = args;
+
+    let handle = match instrumentor {
+        Some(instrumentor) => quote_spanned! {instrumentor.span()=>
+            let __buswatch_handle: &::buswatch_sdk::ModuleHandle =
+                &(#instrumentor).register_retained(#module);
+        },
+        None => quote! {
+            let __buswatch_handle: &::buswatch_sdk::ModuleHandle = {
+                static HANDLE: ::std::sync::OnceLock<::buswatch_sdk::ModuleHandle> =
+                    ::std::sync::OnceLock::new();
+                HANDLE.get_or_init(|| ::buswatch_sdk::Instrumentor::global().register(#module))
+            };
+        },
+    };
+
+    // Annotating the result lets `?` in the body infer its error conversion
+    let annotation = match &sig.output {
+        ReturnType::Default => quote!(: ()),
+        ReturnType::Type(_, ty) if matches!(**ty, Type::ImplTrait(_)) => quote!(),
+        ReturnType::Type(_, ty) => quote!(: #ty),
+    };
+
+    let invoke = if sig.asyncness.is_some() {
+        quote! { async move #block.await }
+    } else {
+        quote! { (move || #block)() }
+    };
+
+    let record_outcome = if returns_result(&sig.output) {
+        quote! {
+            match &__buswatch_result {
+                ::std::result::Result::Ok(_) => {
+                    #( __buswatch_handle.record_write(#writes, 1); )*
+                }
+                ::std::result::Result::Err(_) => {
+                    #( __buswatch_handle.record_error(#reads, 1); )*
+                    #( __buswatch_handle.record_write_error(#writes, 1); )*
+                }
+            }
+        }
+    } else {
+        quote! {
+            #( __buswatch_handle.record_write(#writes, 1); )*
+        }
+    };
+
+    // Guards are dropped before recording so the read shows as complete
+    let (hold_guards, release_guards) = if reads.is_empty() {
+        (quote!(), quote!())
+    } else {
+        (
+            quote! { let __buswatch_guards = ( #( __buswatch_handle.start_read(#reads), )* ); },
+            quote! { ::std::mem::drop(__buswatch_guards); },
+        )
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #handle
+            #hold_guards
+            #[allow(clippy::redundant_closure_call)]
+            let __buswatch_result #annotation = #invoke;
+            #release_guards
+
+            #( __buswatch_handle.record_read(#reads, 1); )*
+            #record_outcome
+            __buswatch_result
+        }
+    })
+}
+
+/// Check if a return type is spelled as a `Result`, including aliases such
+/// as `io::Result<T>` or `anyhow::Result<T>`.
+fn returns_result(output: &ReturnType) -> bool {
+    let ReturnType::Type(_, ty) = output else {
+        return false;
+    };
+    let Type::Path(path) = &**ty else {
+        return false;
+    };
+    path.path
+        .segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Result")
+}
@@ -0,0 +1,131 @@
+use buswatch_macros::handler;
+use buswatch_sdk::Instrumentor;
+
+#[derive(Debug)]
+struct Rejected;
+
+#[handler(module = "sync-processor", reads = "orders.new", writes = "orders.processed", instrumentor = instrumentor)]
+fn process(instrumentor: &Instrumentor, amount: u32) -> Result<u32, Rejected> {
+    if amount == 0 {
+        return Err(Rejected);
+    }
+    Ok(amount * 2)
+}
+
+#[handler(module = "async-processor", reads = ["a", "b"], writes = "out", instrumentor = instrumentor)]
+async fn process_async(instrumentor: &Instrumentor, amount: u32) -> Result<u32, Rejected> {
+    let doubled = check(amount)?;
+    tokio::task::yield_now().await;
+    Ok(doubled)
+}
+
+fn check(amount: u32) -> Result<u32, Rejected> {
+    if amount == 0 {
+        Err(Rejected)
+    } else {
+        Ok(amount * 2)
+    }
+}
+
+#[handler(module = "emitter", writes = "alerts", instrumentor = instrumentor)]
+fn emit(instrumentor: &Instrumentor, amount: u32) -> Result<u32, Rejected> {
+    check(amount)
+}
+
+#[handler(module = "notifier", reads = "orders.processed", instrumentor = instrumentor)]
+fn notify(instrumentor: &Instrumentor) -> usize {
+    // The call is in flight while the body runs
+    let snapshot = instrumentor.collect();
+    snapshot.modules["notifier"].reads["orders.processed"]
+        .inflight
+        .unwrap_or_default() as usize
+}
+
+#[handler(module = "global-producer", writes = "events")]
+fn publish() {}
+
+#[test]
+fn sync_handler_counts_success_and_failure() {
+    let instrumentor = Instrumentor::new();
+
+    assert_eq!(process(&instrumentor, 2).ok(), Some(4));
+    assert!(process(&instrumentor, 0).is_err());
+
+    let snapshot = instrumentor.collect();
+    let metrics = &snapshot.modules["sync-processor"];
+    let read = &metrics.reads["orders.new"];
+    assert_eq!(read.count, 2);
+    assert_eq!(read.errors, Some(1));
+    assert_eq!(read.inflight, None);
+    assert_eq!(read.latency.as_ref().unwrap().count, 2);
+    let write = &metrics.writes["orders.processed"];
+    assert_eq!(write.count, 1);
+    assert_eq!(write.errors, Some(1));
+}
+
+#[tokio::test]
+async fn async_handler_records_every_topic() {
+    let instrumentor = Instrumentor::new();
+
+    assert!(process_async(&instrumentor, 3).await.is_ok());
+    assert!(process_async(&instrumentor, 0).await.is_err());
+
+    let snapshot = instrumentor.collect();
+    let metrics = &snapshot.modules["async-processor"];
+    for topic in ["a", "b"] {
+        assert_eq!(metrics.reads[topic].count, 2);
+        assert_eq!(metrics.reads[topic].errors, Some(1));
+    }
+    assert_eq!(metrics.writes["out"].count, 1);
+}
+
+#[test]
+fn writes_only_handler_records_write_errors() {
+    let instrumentor = Instrumentor::new();
+
+    assert!(emit(&instrumentor, 1).is_ok());
+    assert!(emit(&instrumentor, 0).is_err());
+    assert!(emit(&instrumentor, 0).is_err());
+
+    let snapshot = instrumentor.collect();
+    let metrics = &snapshot.modules["emitter"];
+    assert!(metrics.reads.is_empty());
+    assert_eq!(metrics.writes["alerts"].count, 1);
+    assert_eq!(metrics.writes["alerts"].errors, Some(2));
+}
+
+#[test]
+fn handler_module_survives_remove_dropped_modules() {
+    let instrumentor = Instrumentor::builder().remove_dropped_modules(true).build();
+
+    assert!(emit(&instrumentor, 1).is_ok());
+    instrumentor.collect();
+    instrumentor.collect();
+    assert!(emit(&instrumentor, 1).is_ok());
+
+    let snapshot = instrumentor.collect();
+    assert_eq!(snapshot.modules["emitter"].writes["alerts"].count, 2);
+}
+
+#[test]
+fn handler_is_pending_while_running() {
+    let instrumentor = Instrumentor::new();
+    assert_eq!(notify(&instrumentor), 1);
+
+    let snapshot = instrumentor.collect();
+    let read = &snapshot.modules["notifier"].reads["orders.processed"];
+    assert_eq!(read.count, 1);
+    assert_eq!(read.inflight, None);
+}
+
+#[test]
+fn non_result_handler_uses_global_instrumentor() {
+    publish();
+    publish();
+
+    let snapshot = Instrumentor::global().collect();
+    assert_eq!(
+        snapshot.modules["global-producer"].writes["events"].count,
+        2
+    );
+}
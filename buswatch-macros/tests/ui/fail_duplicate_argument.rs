@@ -0,0 +1,6 @@
+use buswatch_macros::handler;
+
+#[handler(module = "orders", reads = "a", reads = "b")]
+fn process() {}
+
+fn main() {}
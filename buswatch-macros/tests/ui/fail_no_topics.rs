@@ -0,0 +1,6 @@
+use buswatch_macros::handler;
+
+#[handler(module = "orders")]
+fn process() {}
+
+fn main() {}
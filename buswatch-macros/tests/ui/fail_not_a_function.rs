@@ -0,0 +1,6 @@
+use buswatch_macros::handler;
+
+#[handler(module = "orders", reads = "orders.new")]
+struct Process;
+
+fn main() {}
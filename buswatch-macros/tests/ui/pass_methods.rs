@@ -0,0 +1,32 @@
+use buswatch_macros::handler;
+use buswatch_sdk::Instrumentor;
+
+struct Worker {
+    instrumentor: Instrumentor,
+}
+
+impl Worker {
+    #[handler(module = "worker", reads = "jobs", instrumentor = self.instrumentor)]
+    fn name(&self) -> &str {
+        "worker"
+    }
+
+    #[handler(module = "worker", reads = "jobs", writes = "results", instrumentor = &self.instrumentor)]
+    async fn run(&mut self, job: String) -> std::io::Result<usize> {
+        let len = job.parse::<usize>().map_err(std::io::Error::other)?;
+        Ok(len)
+    }
+}
+
+#[handler(module = "iter", writes = "numbers")]
+fn numbers() -> impl Iterator<Item = u32> {
+    0..3
+}
+
+fn main() {
+    let worker = Worker {
+        instrumentor: Instrumentor::new(),
+    };
+    let _ = worker.name();
+    let _ = numbers();
+}
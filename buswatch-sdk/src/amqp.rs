@@ -0,0 +1,431 @@
+//! AMQP (RabbitMQ) snapshot publishing.
+//!
+//! Publishes each snapshot to an exchange with a fixed routing key, in the
+//! format the buswatch TUI's `--subscribe` mode consumes. By default this
+//! matches Caryatid: CBOR payloads on the `caryatid.monitor.snapshot` topic.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use buswatch_sdk::{Instrumentor, Output};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let instrumentor = Instrumentor::builder()
+//!         .output(Output::amqp(
+//!             "amqp://127.0.0.1:5672/%2f",
+//!             "caryatid",
+//!             "caryatid.monitor.snapshot",
+//!         ))
+//!         .build();
+//!
+//!     let _emission = instrumentor.start();
+//! }
+//! ```
+
+use std::io;
+use std::time::{Duration, Instant};
+
+use buswatch_types::Snapshot;
+use lapin::options::{BasicPublishOptions, ExchangeDeclareOptions};
+use lapin::types::FieldTable;
+use lapin::{BasicProperties, Channel, Connection, ConnectionProperties, ExchangeKind};
+use tokio::sync::Mutex;
+
+pub use crate::encoding::Encoding;
+
+/// Delay before the first reconnect attempt after a failure.
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Upper bound for the reconnect delay.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Default timeout for opening a connection to the broker.
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Configuration for publishing snapshots to an AMQP exchange.
+#[derive(Debug, Clone)]
+pub struct AmqpConfig {
+    /// Broker URL (e.g., "amqp://127.0.0.1:5672/%2f")
+    pub url: String,
+    /// Exchange to publish to
+    pub exchange: String,
+    /// Routing key for every snapshot
+    pub routing_key: String,
+    /// Payload encoding
+    pub encoding: Encoding,
+    /// Declare the exchange as a durable topic exchange on connect
+    pub declare_exchange: bool,
+    /// Timeout for opening a connection to the broker
+    pub connect_timeout: Duration,
+}
+
+impl AmqpConfig {
+    /// Create a new builder for AmqpConfig.
+    pub fn builder() -> AmqpConfigBuilder {
+        AmqpConfigBuilder::default()
+    }
+}
+
+/// Builder for AmqpConfig.
+#[derive(Debug, Default)]
+pub struct AmqpConfigBuilder {
+    url: Option<String>,
+    exchange: Option<String>,
+    routing_key: Option<String>,
+    encoding: Option<Encoding>,
+    declare_exchange: Option<bool>,
+    connect_timeout: Option<Duration>,
+}
+
+impl AmqpConfigBuilder {
+    /// Set the broker URL.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the exchange name.
+    pub fn exchange(mut self, exchange: impl Into<String>) -> Self {
+        self.exchange = Some(exchange.into());
+        self
+    }
+
+    /// Set the routing key.
+    pub fn routing_key(mut self, routing_key: impl Into<String>) -> Self {
+        self.routing_key = Some(routing_key.into());
+        self
+    }
+
+    /// Set the payload encoding.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Set whether to declare the exchange on connect.
+    ///
+    /// Disable this when the exchange already exists with a different type.
+    pub fn declare_exchange(mut self, declare: bool) -> Self {
+        self.declare_exchange = Some(declare);
+        self
+    }
+
+    /// Set the connect timeout (default 10 seconds).
+    ///
+    /// A publish that times out fails, and the connection is retried with
+    /// backoff like any other failure.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Build the AmqpConfig.
+    pub fn build(self) -> AmqpConfig {
+        AmqpConfig {
+            url: self
+                .url
+                .unwrap_or_else(|| "amqp://127.0.0.1:5672/%2f".to_string()),
+            exchange: self.exchange.unwrap_or_else(|| "caryatid".to_string()),
+            routing_key: self
+                .routing_key
+                .unwrap_or_else(|| "caryatid.monitor.snapshot".to_string()),
+            encoding: self.encoding.unwrap_or_default(),
+            declare_exchange: self.declare_exchange.unwrap_or(true),
+            connect_timeout: self.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+        }
+    }
+}
+
+/// Connection state, guarded so only one emit reconnects at a time.
+#[derive(Default)]
+struct Link {
+    connection: Option<(Connection, Channel)>,
+    /// Earliest time the next reconnect may be attempted
+    retry_at: Option<Instant>,
+    /// Delay to apply after the next failure
+    backoff: Option<Duration>,
+}
+
+/// Publishes snapshots over a persistent AMQP connection.
+///
+/// The connection is opened on first publish and reused. After a failure
+/// it is dropped and re-established on a later publish, with exponential
+/// backoff between attempts.
+pub struct AmqpPublisher {
+    config: AmqpConfig,
+    link: Mutex<Link>,
+}
+
+impl AmqpPublisher {
+    /// Create a new publisher. No connection is made until the first publish.
+    pub fn new(config: AmqpConfig) -> Self {
+        Self {
+            config,
+            link: Mutex::new(Link::default()),
+        }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &AmqpConfig {
+        &self.config
+    }
+
+    /// Encode and publish a snapshot.
+    pub async fn publish(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let payload = self.config.encoding.encode(snapshot)?;
+        let mut link = self.link.lock().await;
+
+        let channel = match link.channel() {
+            Some(channel) => channel,
+            None => {
+                link.connection = None;
+                if link.retry_at.is_some_and(|at| Instant::now() < at) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        "AMQP connection down, waiting to reconnect",
+                    ));
+                }
+
+                match self.connect().await {
+                    Ok((conn, channel)) => {
+                        link.connection = Some((conn, channel.clone()));
+                        channel
+                    }
+                    Err(e) => {
+                        link.fail();
+                        return Err(e);
+                    }
+                }
+            }
+        };
+
+        let properties = BasicProperties::default()
+            .with_content_type(self.config.encoding.content_type().into())
+            .with_timestamp(snapshot.timestamp_ms / 1000);
+
+        let result = channel
+            .basic_publish(
+                &self.config.exchange,
+                &self.config.routing_key,
+                BasicPublishOptions::default(),
+                &payload,
+                properties,
+            )
+            .await;
+
+        match result {
+            Ok(_) => {
+                link.backoff = None;
+                link.retry_at = None;
+                Ok(())
+            }
+            Err(e) => {
+                link.connection = None;
+                link.fail();
+                Err(io::Error::other(e))
+            }
+        }
+    }
+
+    /// Open a connection and channel, declaring the exchange if configured.
+    async fn connect(&self) -> io::Result<(Connection, Channel)> {
+        let connect = Connection::connect(&self.config.url, ConnectionProperties::default());
+        let conn = tokio::time::timeout(self.config.connect_timeout, connect)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "AMQP connect timed out"))?
+            .map_err(io::Error::other)?;
+        let channel = conn.create_channel().await.map_err(io::Error::other)?;
+
+        if self.config.declare_exchange {
+            channel
+                .exchange_declare(
+                    &self.config.exchange,
+                    ExchangeKind::Topic,
+                    ExchangeDeclareOptions {
+                        durable: true,
+                        ..Default::default()
+                    },
+                    FieldTable::default(),
+                )
+                .await
+                .map_err(io::Error::other)?;
+        }
+
+        Ok((conn, channel))
+    }
+}
+
+impl Link {
+    /// The open channel, if the connection is still up.
+    fn channel(&self) -> Option<Channel> {
+        let (conn, channel) = self.connection.as_ref()?;
+        (conn.status().connected() && channel.status().connected()).then(|| channel.clone())
+    }
+
+    /// Schedule the next reconnect attempt, doubling the delay each time.
+    fn fail(&mut self) {
+        let delay = self.backoff.unwrap_or(INITIAL_RECONNECT_DELAY);
+        self.retry_at = Some(Instant::now() + delay);
+        self.backoff = Some((delay * 2).min(MAX_RECONNECT_DELAY));
+    }
+}
+
+impl std::fmt::Debug for AmqpPublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AmqpPublisher")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(1703160000000)
+            .module("producer", |m| {
+                m.write("orders", |w| w.count(10))
+                    .read("commands", |r| r.count(4).backlog(2))
+            })
+            .build()
+    }
+
+    #[test]
+    fn config_defaults_match_caryatid() {
+        let config = AmqpConfig::builder().build();
+        assert_eq!(config.exchange, "caryatid");
+        assert_eq!(config.routing_key, "caryatid.monitor.snapshot");
+        assert_eq!(config.encoding, Encoding::Cbor);
+        assert!(config.declare_exchange);
+        assert_eq!(config.connect_timeout, DEFAULT_CONNECT_TIMEOUT);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let mut link = Link::default();
+        link.fail();
+        assert_eq!(link.backoff, Some(Duration::from_secs(2)));
+        for _ in 0..10 {
+            link.fail();
+        }
+        assert_eq!(link.backoff, Some(MAX_RECONNECT_DELAY));
+    }
+
+    #[tokio::test]
+    async fn publish_fails_fast_while_waiting_to_reconnect() {
+        let publisher =
+            AmqpPublisher::new(AmqpConfig::builder().url("amqp://127.0.0.1:1/%2f").build());
+
+        assert!(publisher.publish(&snapshot()).await.is_err());
+        let err = publisher.publish(&snapshot()).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotConnected);
+    }
+
+    #[tokio::test]
+    async fn connect_times_out_when_broker_never_answers() {
+        // Accepted by the kernel, but the AMQP handshake never starts
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let publisher = AmqpPublisher::new(
+            AmqpConfig::builder()
+                .url(format!("amqp://{}/%2f", addr))
+                .connect_timeout(Duration::from_millis(100))
+                .build(),
+        );
+
+        let err = tokio::time::timeout(Duration::from_secs(5), publisher.publish(&snapshot()))
+            .await
+            .expect("publish should give up after the connect timeout")
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
+
+    /// Publishes to a local broker and reads the message back.
+    ///
+    /// Run with `cargo test --features amqp -- --ignored` and a broker at
+    /// `BUSWATCH_AMQP_URL` (default `amqp://127.0.0.1:5672/%2f`).
+    #[tokio::test]
+    #[ignore = "requires a running RabbitMQ broker"]
+    async fn publishes_to_local_broker() {
+        use futures::StreamExt;
+        use lapin::options::{BasicConsumeOptions, QueueBindOptions, QueueDeclareOptions};
+
+        let url = std::env::var("BUSWATCH_AMQP_URL")
+            .unwrap_or_else(|_| "amqp://127.0.0.1:5672/%2f".to_string());
+        let config = AmqpConfig::builder()
+            .url(&url)
+            .exchange("buswatch-test")
+            .routing_key("buswatch.test.snapshot")
+            .build();
+
+        // Bind a temporary queue before publishing
+        let conn = Connection::connect(&url, ConnectionProperties::default())
+            .await
+            .unwrap();
+        let channel = conn.create_channel().await.unwrap();
+        channel
+            .exchange_declare(
+                "buswatch-test",
+                ExchangeKind::Topic,
+                ExchangeDeclareOptions {
+                    durable: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+        let queue = channel
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+        channel
+            .queue_bind(
+                queue.name().as_str(),
+                "buswatch-test",
+                "buswatch.test.snapshot",
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+        let mut consumer = channel
+            .basic_consume(
+                queue.name().as_str(),
+                "buswatch-test",
+                BasicConsumeOptions {
+                    no_ack: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .unwrap();
+
+        let publisher = AmqpPublisher::new(config);
+        publisher.publish(&snapshot()).await.unwrap();
+
+        let delivery = consumer.next().await.unwrap().unwrap();
+        assert_eq!(
+            delivery
+                .properties
+                .content_type()
+                .as_ref()
+                .map(|s| s.as_str()),
+            Some("application/cbor")
+        );
+        let decoded: Snapshot = minicbor_serde::from_slice(&delivery.data).unwrap();
+        assert_eq!(decoded, snapshot());
+    }
+}
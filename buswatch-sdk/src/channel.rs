@@ -0,0 +1,893 @@
+//! Instrumented wrappers around tokio channels.
+//!
+//! These wrappers record reads, writes, backlog and pending time on the
+//! given module handles, so an in-process bus built on tokio channels
+//! doesn't need `record_read` / `record_write` calls at every send and recv.
+//!
+//! - [`instrumented`] wraps a bounded `mpsc` channel
+//! - [`instrumented_broadcast`] wraps a `broadcast` channel
+//! - [`instrumented_watch`] wraps a `watch` channel
+//!
+//! # Example
+//!
+//! ```rust
+//! use buswatch_sdk::{channel, Instrumentor};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let instrumentor = Instrumentor::new();
+//!     let producer = instrumentor.register("producer");
+//!     let consumer = instrumentor.register("consumer");
+//!
+//!     let (tx, mut rx) = channel::instrumented(&producer, &consumer, "orders", 16);
+//!
+//!     tx.send("order-1").await.unwrap();
+//!     assert_eq!(rx.recv().await, Some("order-1"));
+//!
+//!     let snapshot = instrumentor.collect();
+//!     assert_eq!(snapshot.modules["producer"].writes["orders"].count, 1);
+//!     assert_eq!(snapshot.modules["consumer"].reads["orders"].count, 1);
+//! }
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use tokio::sync::{broadcast, mpsc, watch};
+
+use crate::handle::ModuleHandle;
+
+/// Create an instrumented bounded `mpsc` channel.
+///
+/// Sends are recorded as writes on `handle_tx` and receives as reads on
+/// `handle_rx`. The number of queued messages is recorded as the reader's
+/// backlog, which drops to zero when the receiver is dropped. Time spent
+/// waiting for capacity on a full channel is recorded as write pending, and
+/// time spent waiting on an empty channel as read pending.
+///
+/// # Panics
+///
+/// Panics if `cap` is 0, like `tokio::sync::mpsc::channel`.
+pub fn instrumented<T>(
+    handle_tx: &ModuleHandle,
+    handle_rx: &ModuleHandle,
+    topic: &str,
+    cap: usize,
+) -> (InstrumentedSender<T>, InstrumentedReceiver<T>) {
+    let (tx, rx) = mpsc::channel(cap);
+    let topic: Arc<str> = Arc::from(topic);
+
+    let sender = InstrumentedSender {
+        inner: tx,
+        handle: handle_tx.clone(),
+        reader: handle_rx.clone(),
+        topic: topic.clone(),
+    };
+    let receiver = InstrumentedReceiver {
+        inner: rx,
+        handle: handle_rx.clone(),
+        topic,
+    };
+
+    (sender, receiver)
+}
+
+/// Sending half of an instrumented `mpsc` channel.
+///
+/// Created by [`instrumented`].
+pub struct InstrumentedSender<T> {
+    inner: mpsc::Sender<T>,
+    handle: ModuleHandle,
+    /// The receiving module, whose backlog is updated on every send.
+    reader: ModuleHandle,
+    topic: Arc<str>,
+}
+
+impl<T> InstrumentedSender<T> {
+    /// Send a value, waiting for capacity if the channel is full.
+    ///
+    /// The wait is recorded as write pending for the duration it takes
+    /// for capacity to become available.
+    pub async fn send(&self, value: T) -> Result<(), mpsc::error::SendError<T>> {
+        let value = match self.inner.try_send(value) {
+            Ok(()) => {
+                self.on_sent();
+                return Ok(());
+            }
+            Err(mpsc::error::TrySendError::Closed(value)) => {
+                return Err(mpsc::error::SendError(value));
+            }
+            Err(mpsc::error::TrySendError::Full(value)) => value,
+        };
+
+        // The channel is full, so the time until capacity frees up is backpressure
+        let guard = self.handle.start_write(&self.topic);
+        let result = self.inner.send(value).await;
+        drop(guard);
+
+        if result.is_ok() {
+            self.on_sent();
+        }
+        result
+    }
+
+    /// Attempt to send a value without waiting for capacity.
+    pub fn try_send(&self, value: T) -> Result<(), mpsc::error::TrySendError<T>> {
+        self.inner.try_send(value)?;
+        self.on_sent();
+        Ok(())
+    }
+
+    /// Returns the topic this channel is recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns the current capacity of the channel.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Returns true if the receiver has been dropped or closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
+
+    fn on_sent(&self) {
+        self.handle.record_write(&self.topic, 1);
+        let queued = self.inner.max_capacity() - self.inner.capacity();
+        self.reader.set_backlog(&self.topic, queued as u64);
+    }
+}
+
+impl<T> Clone for InstrumentedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            handle: self.handle.clone(),
+            reader: self.reader.clone(),
+            topic: self.topic.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for InstrumentedSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedSender")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+/// Receiving half of an instrumented `mpsc` channel.
+///
+/// Created by [`instrumented`].
+pub struct InstrumentedReceiver<T> {
+    inner: mpsc::Receiver<T>,
+    handle: ModuleHandle,
+    topic: Arc<str>,
+}
+
+impl<T> InstrumentedReceiver<T> {
+    /// Receive the next value, waiting if the channel is empty.
+    ///
+    /// The wait is recorded as read pending. Returns `None` once all
+    /// senders have been dropped and the channel is drained.
+    pub async fn recv(&mut self) -> Option<T> {
+        let value = match self.inner.try_recv() {
+            Ok(value) => value,
+            Err(mpsc::error::TryRecvError::Disconnected) => return None,
+            Err(mpsc::error::TryRecvError::Empty) => {
+                let _guard = self.handle.wait_read(&self.topic);
+                self.inner.recv().await?
+            }
+        };
+
+        self.on_received();
+        Some(value)
+    }
+
+    /// Attempt to receive a value without waiting.
+    pub fn try_recv(&mut self) -> Result<T, mpsc::error::TryRecvError> {
+        let value = self.inner.try_recv()?;
+        self.on_received();
+        Ok(value)
+    }
+
+    /// Returns the topic this channel is recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns the number of messages waiting in the channel.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns true if no messages are waiting in the channel.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Close the receiving half without dropping it.
+    pub fn close(&mut self) {
+        self.inner.close();
+    }
+
+    fn on_received(&self) {
+        self.handle.record_read(&self.topic, 1);
+        self.handle
+            .set_backlog(&self.topic, self.inner.len() as u64);
+    }
+}
+
+impl<T> Drop for InstrumentedReceiver<T> {
+    fn drop(&mut self) {
+        // Queued messages are discarded with the receiver
+        self.handle.set_backlog(&self.topic, 0);
+    }
+}
+
+impl<T> std::fmt::Debug for InstrumentedReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedReceiver")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+/// Create an instrumented `broadcast` channel.
+///
+/// Sends are recorded as writes on `handle_tx`. Each receiver records its
+/// reads on its own module handle, and every send updates the backlog of each
+/// receiving module to the number of values its slowest receiver has yet to
+/// see. Additional receivers are created with
+/// [`InstrumentedBroadcastSender::subscribe`].
+///
+/// # Panics
+///
+/// Panics if `cap` is 0, like `tokio::sync::broadcast::channel`.
+pub fn instrumented_broadcast<T: Clone>(
+    handle_tx: &ModuleHandle,
+    handle_rx: &ModuleHandle,
+    topic: &str,
+    cap: usize,
+) -> (
+    InstrumentedBroadcastSender<T>,
+    InstrumentedBroadcastReceiver<T>,
+) {
+    let (tx, rx) = broadcast::channel(cap);
+    let subscribers = Arc::new(Subscribers {
+        topic: Arc::from(topic),
+        receivers: Mutex::new(Vec::new()),
+        next_id: AtomicU64::new(0),
+    });
+
+    let receiver = InstrumentedBroadcastReceiver::new(rx, handle_rx, subscribers.clone());
+    let sender = InstrumentedBroadcastSender {
+        inner: tx,
+        handle: handle_tx.clone(),
+        subscribers,
+    };
+
+    (sender, receiver)
+}
+
+/// The receivers of a broadcast channel, shared between both halves so a
+/// send can update the backlog of every receiving module.
+struct Subscribers {
+    topic: Arc<str>,
+    receivers: Mutex<Vec<Subscriber>>,
+    next_id: AtomicU64,
+}
+
+struct Subscriber {
+    id: u64,
+    handle: ModuleHandle,
+    /// Values this receiver has yet to see.
+    unread: u64,
+}
+
+impl Subscribers {
+    fn add(&self, handle: &ModuleHandle) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.receivers.lock().push(Subscriber {
+            id,
+            handle: handle.clone(),
+            unread: 0,
+        });
+        id
+    }
+
+    fn remove(&self, id: u64) {
+        let mut receivers = self.receivers.lock();
+        if let Some(index) = receivers.iter().position(|r| r.id == id) {
+            let removed = receivers.swap_remove(index);
+            Self::publish(&receivers, &self.topic, &removed.handle);
+        }
+    }
+
+    /// Record the backlog of `handle`'s module as that of its furthest
+    /// behind receiver, or zero once it has none left.
+    fn publish(receivers: &[Subscriber], topic: &str, handle: &ModuleHandle) {
+        let backlog = receivers
+            .iter()
+            .filter(|r| r.handle.name() == handle.name())
+            .map(|r| r.unread)
+            .max()
+            .unwrap_or(0);
+        handle.set_backlog(topic, backlog);
+    }
+}
+
+/// Sending half of an instrumented `broadcast` channel.
+///
+/// Created by [`instrumented_broadcast`].
+pub struct InstrumentedBroadcastSender<T> {
+    inner: broadcast::Sender<T>,
+    handle: ModuleHandle,
+    subscribers: Arc<Subscribers>,
+}
+
+impl<T> InstrumentedBroadcastSender<T> {
+    /// Send a value to all active receivers.
+    ///
+    /// Broadcast channels never wait for capacity, so no write pending is
+    /// recorded. Returns the number of receivers the value was sent to.
+    pub fn send(&self, value: T) -> Result<usize, broadcast::error::SendError<T>> {
+        // Held across the send so a receiver's length and its tracked
+        // backlog can't be observed out of step
+        let mut receivers = self.subscribers.receivers.lock();
+        let count = self.inner.send(value)?;
+        self.handle.record_write(&self.subscribers.topic, 1);
+
+        for receiver in receivers.iter_mut() {
+            receiver.unread += 1;
+        }
+        for receiver in receivers.iter() {
+            Subscribers::publish(&receivers, &self.subscribers.topic, &receiver.handle);
+        }
+        Ok(count)
+    }
+
+    /// Create a new receiver whose reads are recorded on `handle`.
+    pub fn subscribe(&self, handle: &ModuleHandle) -> InstrumentedBroadcastReceiver<T> {
+        InstrumentedBroadcastReceiver::new(self.inner.subscribe(), handle, self.subscribers.clone())
+    }
+
+    /// Returns the topic this channel is recorded under.
+    pub fn topic(&self) -> &str {
+        &self.subscribers.topic
+    }
+
+    /// Returns the number of active receivers.
+    pub fn receiver_count(&self) -> usize {
+        self.inner.receiver_count()
+    }
+}
+
+impl<T> Clone for InstrumentedBroadcastSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            handle: self.handle.clone(),
+            subscribers: self.subscribers.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for InstrumentedBroadcastSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedBroadcastSender")
+            .field("module", &self.handle.name())
+            .field("topic", &self.subscribers.topic)
+            .finish()
+    }
+}
+
+/// Receiving half of an instrumented `broadcast` channel.
+///
+/// Created by [`instrumented_broadcast`] or
+/// [`InstrumentedBroadcastSender::subscribe`].
+pub struct InstrumentedBroadcastReceiver<T> {
+    inner: broadcast::Receiver<T>,
+    handle: ModuleHandle,
+    topic: Arc<str>,
+    subscribers: Arc<Subscribers>,
+    id: u64,
+}
+
+impl<T> InstrumentedBroadcastReceiver<T> {
+    fn new(
+        inner: broadcast::Receiver<T>,
+        handle: &ModuleHandle,
+        subscribers: Arc<Subscribers>,
+    ) -> Self {
+        let id = subscribers.add(handle);
+        Self {
+            inner,
+            handle: handle.clone(),
+            topic: subscribers.topic.clone(),
+            subscribers,
+            id,
+        }
+    }
+}
+
+impl<T: Clone> InstrumentedBroadcastReceiver<T> {
+    /// Receive the next value, waiting if none is available.
+    ///
+    /// The wait is recorded as read pending. Lagged and closed errors are
+    /// passed through unchanged.
+    pub async fn recv(&mut self) -> Result<T, broadcast::error::RecvError> {
+        let value = match self.inner.try_recv() {
+            Ok(value) => value,
+            Err(broadcast::error::TryRecvError::Lagged(n)) => {
+                self.sync_backlog();
+                return Err(broadcast::error::RecvError::Lagged(n));
+            }
+            Err(broadcast::error::TryRecvError::Closed) => {
+                return Err(broadcast::error::RecvError::Closed);
+            }
+            Err(broadcast::error::TryRecvError::Empty) => {
+                let _guard = self.handle.wait_read(&self.topic);
+                let result = self.inner.recv().await;
+                if let Err(broadcast::error::RecvError::Lagged(_)) = result {
+                    self.sync_backlog();
+                }
+                result?
+            }
+        };
+
+        self.on_received();
+        Ok(value)
+    }
+
+    /// Attempt to receive a value without waiting.
+    pub fn try_recv(&mut self) -> Result<T, broadcast::error::TryRecvError> {
+        let result = self.inner.try_recv();
+        match result {
+            Ok(_) => self.on_received(),
+            Err(broadcast::error::TryRecvError::Lagged(_)) => self.sync_backlog(),
+            Err(_) => {}
+        }
+        result
+    }
+}
+
+impl<T> InstrumentedBroadcastReceiver<T> {
+    /// Returns the topic this channel is recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns the number of values this receiver has yet to see.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns true if this receiver has seen every value sent so far.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn on_received(&self) {
+        self.handle.record_read(&self.topic, 1);
+        self.sync_backlog();
+    }
+
+    /// Reset this receiver's tracked backlog to what it has left to read.
+    fn sync_backlog(&self) {
+        let mut receivers = self.subscribers.receivers.lock();
+        if let Some(receiver) = receivers.iter_mut().find(|r| r.id == self.id) {
+            receiver.unread = self.inner.len() as u64;
+        }
+        Subscribers::publish(&receivers, &self.topic, &self.handle);
+    }
+}
+
+impl<T> Drop for InstrumentedBroadcastReceiver<T> {
+    fn drop(&mut self) {
+        // Values this receiver hadn't seen no longer count towards its module
+        self.subscribers.remove(self.id);
+    }
+}
+
+impl<T> std::fmt::Debug for InstrumentedBroadcastReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedBroadcastReceiver")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+/// Create an instrumented `watch` channel.
+///
+/// Sends are recorded as writes on `handle_tx`, and each observed change as
+/// a read on the receiver's module handle. A watch channel only holds the
+/// latest value, so no backlog is recorded. Additional receivers are
+/// created with [`InstrumentedWatchSender::subscribe`].
+pub fn instrumented_watch<T>(
+    handle_tx: &ModuleHandle,
+    handle_rx: &ModuleHandle,
+    topic: &str,
+    init: T,
+) -> (InstrumentedWatchSender<T>, InstrumentedWatchReceiver<T>) {
+    let (tx, rx) = watch::channel(init);
+    let topic: Arc<str> = Arc::from(topic);
+
+    let receiver = InstrumentedWatchReceiver {
+        inner: rx,
+        handle: handle_rx.clone(),
+        topic: topic.clone(),
+    };
+    let sender = InstrumentedWatchSender {
+        inner: tx,
+        handle: handle_tx.clone(),
+        topic,
+    };
+
+    (sender, receiver)
+}
+
+/// Sending half of an instrumented `watch` channel.
+///
+/// Created by [`instrumented_watch`].
+pub struct InstrumentedWatchSender<T> {
+    inner: watch::Sender<T>,
+    handle: ModuleHandle,
+    topic: Arc<str>,
+}
+
+impl<T> InstrumentedWatchSender<T> {
+    /// Send a new value, notifying all receivers.
+    pub fn send(&self, value: T) -> Result<(), watch::error::SendError<T>> {
+        self.inner.send(value)?;
+        self.handle.record_write(&self.topic, 1);
+        Ok(())
+    }
+
+    /// Create a new receiver whose reads are recorded on `handle`.
+    pub fn subscribe(&self, handle: &ModuleHandle) -> InstrumentedWatchReceiver<T> {
+        InstrumentedWatchReceiver {
+            inner: self.inner.subscribe(),
+            handle: handle.clone(),
+            topic: self.topic.clone(),
+        }
+    }
+
+    /// Returns a reference to the most recently sent value.
+    pub fn borrow(&self) -> watch::Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    /// Returns the topic this channel is recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+}
+
+impl<T> std::fmt::Debug for InstrumentedWatchSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedWatchSender")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+/// Receiving half of an instrumented `watch` channel.
+///
+/// Created by [`instrumented_watch`] or [`InstrumentedWatchSender::subscribe`].
+pub struct InstrumentedWatchReceiver<T> {
+    inner: watch::Receiver<T>,
+    handle: ModuleHandle,
+    topic: Arc<str>,
+}
+
+impl<T> InstrumentedWatchReceiver<T> {
+    /// Wait for a change notification, then mark the newest value as seen.
+    ///
+    /// The wait is recorded as read pending, and each change as one read.
+    pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
+        let waiting = !matches!(self.inner.has_changed(), Ok(true));
+        let guard = waiting.then(|| self.handle.wait_read(&self.topic));
+        self.inner.changed().await?;
+        drop(guard);
+
+        self.handle.record_read(&self.topic, 1);
+        Ok(())
+    }
+
+    /// Returns a reference to the most recently sent value.
+    pub fn borrow(&self) -> watch::Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    /// Returns a reference to the most recently sent value and marks it as seen.
+    pub fn borrow_and_update(&mut self) -> watch::Ref<'_, T> {
+        self.inner.borrow_and_update()
+    }
+
+    /// Returns the topic this channel is recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+}
+
+impl<T> Clone for InstrumentedWatchReceiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            handle: self.handle.clone(),
+            topic: self.topic.clone(),
+        }
+    }
+}
+
+impl<T> std::fmt::Debug for InstrumentedWatchReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedWatchReceiver")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instrumentor;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn mpsc_records_writes_and_reads() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let consumer = instrumentor.register("consumer");
+
+        let (tx, mut rx) = instrumented(&producer, &consumer, "orders", 8);
+
+        tx.send(1).await.unwrap();
+        tx.try_send(2).unwrap();
+        assert_eq!(rx.recv().await, Some(1));
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["producer"].writes["orders"].count, 2);
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert_eq!(read.count, 1);
+        assert_eq!(read.backlog, Some(1));
+    }
+
+    #[tokio::test]
+    async fn mpsc_backlog_tracks_queued_messages() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let consumer = instrumentor.register("consumer");
+
+        let (tx, mut rx) = instrumented(&producer, &consumer, "orders", 8);
+        for i in 0..5 {
+            tx.send(i).await.unwrap();
+        }
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(
+            snapshot.modules["consumer"].reads["orders"].backlog,
+            Some(5)
+        );
+
+        while rx.try_recv().is_ok() {}
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(
+            snapshot.modules["consumer"].reads["orders"].backlog,
+            Some(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn mpsc_dropped_receiver_clears_backlog() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let consumer = instrumentor.register("consumer");
+
+        let (tx, rx) = instrumented(&producer, &consumer, "orders", 8);
+        tx.send(1).await.unwrap();
+        tx.send(2).await.unwrap();
+        drop(rx);
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(
+            snapshot.modules["consumer"].reads["orders"].backlog,
+            Some(0)
+        );
+        assert!(tx.send(3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mpsc_full_channel_records_write_pending() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let consumer = instrumentor.register("consumer");
+
+        let (tx, mut rx) = instrumented(&producer, &consumer, "orders", 1);
+        tx.send(1).await.unwrap();
+
+        let blocked = tokio::spawn({
+            let tx = tx.clone();
+            async move { tx.send(2).await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let snapshot = instrumentor.collect();
+        assert!(snapshot.modules["producer"].writes["orders"]
+            .pending
+            .is_some());
+
+        assert_eq!(rx.recv().await, Some(1));
+        blocked.await.unwrap().unwrap();
+
+        let snapshot = instrumentor.collect();
+        let write = &snapshot.modules["producer"].writes["orders"];
+        assert!(write.pending.is_none());
+        assert_eq!(write.count, 2);
+    }
+
+    #[tokio::test]
+    async fn mpsc_empty_channel_records_read_pending() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let consumer = instrumentor.register("consumer");
+
+        let (tx, mut rx) = instrumented::<u32>(&producer, &consumer, "orders", 4);
+        let waiting = tokio::spawn(async move { rx.recv().await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let snapshot = instrumentor.collect();
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert!(read.pending.is_some());
+        assert!(read.inflight.is_none());
+
+        tx.send(7).await.unwrap();
+        assert_eq!(waiting.await.unwrap(), Some(7));
+
+        let snapshot = instrumentor.collect();
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert!(read.pending.is_none());
+        // Waiting for a message isn't handling one
+        assert!(read.latency.is_none());
+    }
+
+    #[tokio::test]
+    async fn mpsc_recv_returns_none_when_senders_dropped() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let consumer = instrumentor.register("consumer");
+
+        let (tx, mut rx) = instrumented::<u32>(&producer, &consumer, "orders", 4);
+        drop(tx);
+
+        assert_eq!(rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn broadcast_records_reads_per_subscriber() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let audit = instrumentor.register("audit");
+        let billing = instrumentor.register("billing");
+
+        let (tx, mut audit_rx) = instrumented_broadcast(&producer, &audit, "events", 8);
+        let mut billing_rx = tx.subscribe(&billing);
+
+        assert_eq!(tx.send("a").unwrap(), 2);
+        tx.send("b").unwrap();
+
+        assert_eq!(audit_rx.recv().await.unwrap(), "a");
+        assert_eq!(audit_rx.recv().await.unwrap(), "b");
+        assert_eq!(billing_rx.recv().await.unwrap(), "a");
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["producer"].writes["events"].count, 2);
+        assert_eq!(snapshot.modules["audit"].reads["events"].count, 2);
+        assert_eq!(snapshot.modules["audit"].reads["events"].backlog, Some(0));
+        assert_eq!(snapshot.modules["billing"].reads["events"].count, 1);
+        assert_eq!(snapshot.modules["billing"].reads["events"].backlog, Some(1));
+    }
+
+    #[tokio::test]
+    async fn broadcast_dropped_receiver_clears_backlog() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let audit = instrumentor.register("audit");
+
+        let (tx, mut audit_rx) = instrumented_broadcast(&producer, &audit, "events", 8);
+        tx.send("a").unwrap();
+        tx.send("b").unwrap();
+        audit_rx.recv().await.unwrap();
+        drop(audit_rx);
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["audit"].reads["events"].backlog, Some(0));
+    }
+
+    #[tokio::test]
+    async fn broadcast_backlog_rises_while_receiver_stalls() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let audit = instrumentor.register("audit");
+
+        let (tx, mut audit_rx) = instrumented_broadcast(&producer, &audit, "events", 8);
+        tx.send("a").unwrap();
+        audit_rx.recv().await.unwrap();
+
+        for event in ["b", "c", "d"] {
+            tx.send(event).unwrap();
+        }
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["audit"].reads["events"].backlog, Some(3));
+    }
+
+    #[tokio::test]
+    async fn broadcast_backlog_follows_slowest_receiver_of_module() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("producer");
+        let audit = instrumentor.register("audit");
+
+        let (tx, mut fast_rx) = instrumented_broadcast(&producer, &audit, "events", 8);
+        let mut slow_rx = tx.subscribe(&audit);
+        tx.send("a").unwrap();
+        tx.send("b").unwrap();
+
+        fast_rx.recv().await.unwrap();
+        fast_rx.recv().await.unwrap();
+        slow_rx.recv().await.unwrap();
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["audit"].reads["events"].backlog, Some(1));
+
+        drop(slow_rx);
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["audit"].reads["events"].backlog, Some(0));
+    }
+
+    #[tokio::test]
+    async fn watch_records_changes_as_reads() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("config-loader");
+        let consumer = instrumentor.register("worker");
+
+        let (tx, mut rx) = instrumented_watch(&producer, &consumer, "config", 0);
+
+        tx.send(1).unwrap();
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow_and_update(), 1);
+
+        tx.send(2).unwrap();
+        rx.changed().await.unwrap();
+        assert_eq!(*rx.borrow(), 2);
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["config-loader"].writes["config"].count, 2);
+        let read = &snapshot.modules["worker"].reads["config"];
+        assert_eq!(read.count, 2);
+        assert!(read.pending.is_none());
+    }
+
+    #[tokio::test]
+    async fn watch_changed_errors_when_sender_dropped() {
+        let instrumentor = Instrumentor::new();
+        let producer = instrumentor.register("config-loader");
+        let consumer = instrumentor.register("worker");
+
+        let (tx, mut rx) = instrumented_watch(&producer, &consumer, "config", 0);
+        drop(tx);
+
+        assert!(rx.changed().await.is_err());
+    }
+}
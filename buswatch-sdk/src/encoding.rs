@@ -0,0 +1,107 @@
+//! Payload encodings shared by the message bus outputs.
+
+use std::io;
+
+use buswatch_types::Snapshot;
+
+/// Payload encoding for published snapshots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// CBOR via serde, as consumed by the buswatch TUI and Caryatid.
+    #[default]
+    Cbor,
+    /// JSON.
+    Json,
+}
+
+impl Encoding {
+    /// The content type set on published messages.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Encoding::Cbor => "application/cbor",
+            Encoding::Json => "application/json",
+        }
+    }
+
+    /// Encode a snapshot.
+    pub fn encode(&self, snapshot: &Snapshot) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Cbor => minicbor_serde::to_vec(snapshot).map_err(io::Error::other),
+            Encoding::Json => Ok(serde_json::to_vec(snapshot)?),
+        }
+    }
+}
+
+/// Split a snapshot into one single-module snapshot per module.
+///
+/// Used by outputs that key messages by module, so that consumers can
+/// partition or filter on it. Each part keeps the original timestamp.
+#[cfg(any(feature = "kafka", feature = "nats"))]
+pub(crate) fn split_modules(snapshot: &Snapshot) -> impl Iterator<Item = (&str, Snapshot)> {
+    snapshot.modules.iter().map(|(name, metrics)| {
+        let part = Snapshot::builder()
+            .timestamp_ms(snapshot.timestamp_ms)
+            .module_metrics(name.clone(), metrics.clone())
+            .build();
+        (name.as_str(), part)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(1703160000000)
+            .module("producer", |m| {
+                m.write("orders", |w| w.count(10))
+                    .read("commands", |r| r.count(4).backlog(2))
+            })
+            .build()
+    }
+
+    #[test]
+    fn cbor_encoding_round_trips_through_minicbor_serde() {
+        let snapshot = snapshot();
+        let bytes = Encoding::Cbor.encode(&snapshot).unwrap();
+
+        // This is how the TUI's subscription modes decode messages
+        let decoded: Snapshot = minicbor_serde::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, snapshot);
+    }
+
+    #[test]
+    fn json_encoding_round_trips() {
+        let snapshot = snapshot();
+        let bytes = Encoding::Json.encode(&snapshot).unwrap();
+        let decoded: Snapshot = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(decoded, snapshot);
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(Encoding::Cbor.content_type(), "application/cbor");
+        assert_eq!(Encoding::Json.content_type(), "application/json");
+    }
+
+    #[cfg(any(feature = "kafka", feature = "nats"))]
+    #[test]
+    fn split_modules_keeps_timestamp() {
+        let snapshot = Snapshot::builder()
+            .timestamp_ms(42)
+            .module("a", |m| m.read("x", |r| r.count(1)))
+            .module("b", |m| m.write("y", |w| w.count(2)))
+            .build();
+
+        let parts: Vec<_> = split_modules(&snapshot).collect();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].0, "a");
+        assert_eq!(parts[1].0, "b");
+        for (name, part) in &parts {
+            assert_eq!(part.timestamp_ms, 42);
+            assert_eq!(part.len(), 1);
+            assert_eq!(part.get(name), snapshot.get(name));
+        }
+    }
+}
@@ -0,0 +1,610 @@
+//! File outputs: the latest snapshot, or a rotating JSONL history.
+//!
+//! [`Output::file`](crate::Output::file) keeps a single file holding the
+//! latest snapshot. Each snapshot is written to a temporary file next to it
+//! and renamed into place, so readers such as the TUI's `--file` mode never
+//! see a half-written file.
+//!
+//! [`Output::jsonl`](crate::Output::jsonl) appends every snapshot as one
+//! line of compact JSON, keeping a history on disk. The active file can be
+//! rotated by size or age; a file left over from an earlier run keeps the
+//! age it had on disk. Rotated files are renamed to
+//! `<stem>-<unix millis>.<ext>`, so sorting their names sorts them
+//! chronologically, and can be compressed with gzip (`gzip` feature) or
+//! zstd (`zstd` feature). Only the newest `max_files` rotated files are
+//! kept.
+//!
+//! Every line, in the active and the rotated files, is a complete
+//! [`Snapshot`], so the files can be replayed or archived as is.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use buswatch_sdk::Output;
+//! use buswatch_sdk::file::JsonlConfig;
+//! use std::time::Duration;
+//!
+//! let config = JsonlConfig::builder()
+//!     .path("metrics/history.jsonl")
+//!     .max_size(64 * 1024 * 1024)
+//!     .max_age(Duration::from_secs(3600))
+//!     .max_files(24)
+//!     .build();
+//!
+//! let output = Output::jsonl_with_config(config);
+//! ```
+
+// Only the emitters append to the history
+#![cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use buswatch_types::Snapshot;
+use parking_lot::Mutex;
+
+/// Path of the temporary file a snapshot is written to before being
+/// renamed over `path`.
+///
+/// It lives in the same directory so the rename stays on one filesystem.
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+/// Replace the contents of `path` without exposing a partial file.
+#[cfg(feature = "thread")]
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    fs::write(&temp, contents)?;
+    fs::rename(&temp, path)
+}
+
+/// Async version of [`write_atomic`].
+#[cfg(feature = "tokio")]
+pub(crate) async fn write_atomic_async(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temp = temp_path(path);
+    tokio::fs::write(&temp, contents).await?;
+    tokio::fs::rename(&temp, path).await
+}
+
+/// Compression applied to rotated JSONL files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Keep rotated files as plain JSONL
+    #[default]
+    None,
+    /// gzip rotated files to `<name>.gz`
+    #[cfg(feature = "gzip")]
+    Gzip,
+    /// zstd-compress rotated files to `<name>.zst`
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Compression {
+    /// Compress `path` into `path.<extension>` and remove the original.
+    #[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_variables))]
+    fn compress(&self, path: &Path) -> io::Result<()> {
+        match self {
+            Compression::None => Ok(()),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => {
+                let (mut input, output) = open_for_compression(path, "gz")?;
+                let mut encoder =
+                    flate2::write::GzEncoder::new(output, flate2::Compression::default());
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.sync_all()?;
+                fs::remove_file(path)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => {
+                let (mut input, output) = open_for_compression(path, "zst")?;
+                let mut encoder = zstd::stream::write::Encoder::new(output, 0)?;
+                io::copy(&mut input, &mut encoder)?;
+                encoder.finish()?.sync_all()?;
+                fs::remove_file(path)
+            }
+        }
+    }
+}
+
+/// Open `path` for reading and create `path.<extension>` for writing.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn open_for_compression(path: &Path, extension: &str) -> io::Result<(File, File)> {
+    let target = PathBuf::from(format!("{}.{}", path.display(), extension));
+    Ok((File::open(path)?, File::create(target)?))
+}
+
+/// Configuration for the JSONL history output.
+#[derive(Debug, Clone)]
+pub struct JsonlConfig {
+    /// Path of the active file (e.g., "metrics.jsonl")
+    pub path: PathBuf,
+    /// Rotate before the active file would grow beyond this many bytes
+    pub max_size: Option<u64>,
+    /// Rotate once the active file has been written to for this long
+    pub max_age: Option<Duration>,
+    /// Number of rotated files to keep; older ones are deleted
+    pub max_files: Option<usize>,
+    /// Compression for rotated files
+    pub compression: Compression,
+}
+
+impl JsonlConfig {
+    /// Create a new builder for JsonlConfig.
+    pub fn builder() -> JsonlConfigBuilder {
+        JsonlConfigBuilder::default()
+    }
+}
+
+/// Builder for JsonlConfig.
+#[derive(Debug, Default)]
+pub struct JsonlConfigBuilder {
+    path: Option<PathBuf>,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    max_files: Option<usize>,
+    compression: Option<Compression>,
+}
+
+impl JsonlConfigBuilder {
+    /// Set the path of the active file.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Rotate when the active file reaches this size in bytes.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Rotate when the active file reaches this age.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Keep at most this many rotated files.
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = Some(count);
+        self
+    }
+
+    /// Compress rotated files.
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    /// Build the JsonlConfig.
+    ///
+    /// Without `max_size` or `max_age` the file is never rotated.
+    pub fn build(self) -> JsonlConfig {
+        JsonlConfig {
+            path: self.path.unwrap_or_else(|| PathBuf::from("buswatch.jsonl")),
+            max_size: self.max_size,
+            max_age: self.max_age,
+            max_files: self.max_files,
+            compression: self.compression.unwrap_or_default(),
+        }
+    }
+}
+
+/// The open active file.
+struct ActiveFile {
+    file: File,
+    size: u64,
+    opened_at: Instant,
+    /// Age the file already had on disk when opened
+    age_at_open: Duration,
+}
+
+impl ActiveFile {
+    /// How long the file has been written to, including earlier runs.
+    fn age(&self) -> Duration {
+        self.age_at_open + self.opened_at.elapsed()
+    }
+}
+
+/// Appends snapshots to a rotating JSONL file.
+pub struct JsonlOutput {
+    config: JsonlConfig,
+    active: Mutex<Option<ActiveFile>>,
+}
+
+impl JsonlOutput {
+    /// Create a new JSONL output. The file is opened on the first append.
+    pub fn new(config: JsonlConfig) -> Self {
+        Self {
+            config,
+            active: Mutex::new(None),
+        }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &JsonlConfig {
+        &self.config
+    }
+
+    /// Append a snapshot as one line, rotating first if needed.
+    ///
+    /// This does blocking file I/O, including compression of the rotated
+    /// file.
+    pub fn append(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let mut line = serde_json::to_vec(snapshot)?;
+        line.push(b'\n');
+
+        // Taken out so that a failed rotation or write reopens the file on
+        // the next append
+        let mut active = self.active.lock();
+        let mut file = match active.take() {
+            Some(file) => file,
+            // A file left by an earlier run may already be due
+            None => self.open()?,
+        };
+        if self.should_rotate(&file, line.len() as u64) {
+            drop(file);
+            self.rotate()?;
+            file = self.open()?;
+        }
+
+        file.file.write_all(&line)?;
+        file.size += line.len() as u64;
+        *active = Some(file);
+        Ok(())
+    }
+
+    fn open(&self) -> io::Result<ActiveFile> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.config.path)?;
+        let metadata = file.metadata()?;
+        let size = metadata.len();
+
+        // A file left by an earlier run is as old as its first write; fall
+        // back to the last one where creation times aren't available
+        let age_at_open = if size == 0 {
+            Duration::ZERO
+        } else {
+            metadata
+                .created()
+                .or_else(|_| metadata.modified())
+                .ok()
+                .and_then(|time| time.elapsed().ok())
+                .unwrap_or_default()
+        };
+        Ok(ActiveFile {
+            file,
+            size,
+            opened_at: Instant::now(),
+            age_at_open,
+        })
+    }
+
+    fn should_rotate(&self, file: &ActiveFile, incoming: u64) -> bool {
+        if file.size == 0 {
+            return false;
+        }
+        let too_big = self
+            .config
+            .max_size
+            .is_some_and(|max| file.size + incoming > max);
+        let too_old = self.config.max_age.is_some_and(|max| file.age() >= max);
+        too_big || too_old
+    }
+
+    /// `(directory, stem, extension)` of the active file.
+    fn name_parts(&self) -> (PathBuf, String, String) {
+        let path = &self.config.path;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "jsonl".to_string());
+        (dir, stem, extension)
+    }
+
+    /// Move the active file aside, compress it, and apply retention.
+    fn rotate(&self) -> io::Result<()> {
+        let (dir, stem, extension) = self.name_parts();
+        let mut millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        // Keep names unique and ordered when rotating within a millisecond
+        let prefix = format!("{}-", stem);
+        if let Some(last) = self.rotated_files()?.last() {
+            let previous = last
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_prefix(&prefix))
+                .and_then(|rest| rest.split('.').next())
+                .and_then(|digits| digits.parse::<u128>().ok());
+            if let Some(previous) = previous {
+                millis = millis.max(previous + 1);
+            }
+        }
+
+        // Zero-padded so names sort chronologically
+        let rotated = dir.join(format!("{}-{:013}.{}", stem, millis, extension));
+        fs::rename(&self.config.path, &rotated)?;
+        self.config.compression.compress(&rotated)?;
+        self.apply_retention()
+    }
+
+    /// Delete the oldest rotated files beyond `max_files`.
+    fn apply_retention(&self) -> io::Result<()> {
+        let Some(max_files) = self.config.max_files else {
+            return Ok(());
+        };
+        let mut rotated = self.rotated_files()?;
+        if rotated.len() > max_files {
+            for path in rotated.drain(..rotated.len() - max_files) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rotated files belonging to this output, oldest first.
+    pub fn rotated_files(&self) -> io::Result<Vec<PathBuf>> {
+        let (dir, stem, extension) = self.name_parts();
+        let prefix = format!("{}-", stem);
+        let infix = format!(".{}", extension);
+
+        let mut rotated: Vec<PathBuf> = fs::read_dir(&dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                name.strip_prefix(&prefix).is_some_and(|rest| {
+                    rest.starts_with(|c: char| c.is_ascii_digit()) && rest.contains(&infix)
+                })
+            })
+            .map(|entry| entry.path())
+            .collect();
+        rotated.sort();
+        Ok(rotated)
+    }
+}
+
+impl std::fmt::Debug for JsonlOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("JsonlOutput")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read};
+
+    /// A fresh directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("buswatch-sdk-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn snapshot(count: u64) -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(count)
+            .module("producer", |m| m.write("orders", |w| w.count(count)))
+            .build()
+    }
+
+    fn read_lines(reader: impl Read) -> Vec<Snapshot> {
+        BufReader::new(reader)
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn temp_path_is_hidden_sibling() {
+        assert_eq!(
+            temp_path(Path::new("out/metrics.json")),
+            PathBuf::from("out/.metrics.json.tmp")
+        );
+    }
+
+    #[cfg(feature = "thread")]
+    #[test]
+    fn write_atomic_replaces_file() {
+        let dir = TempDir::new("atomic");
+        let path = dir.0.join("metrics.json");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn file_output_replaces_file_atomically() {
+        let dir = TempDir::new("atomic-async");
+        let path = dir.0.join("metrics.json");
+        let output = crate::Output::file(&path);
+
+        output.emit(&snapshot(1)).await.unwrap();
+        output.emit(&snapshot(2)).await.unwrap();
+
+        let written: Snapshot = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        assert_eq!(written, snapshot(2));
+        assert!(!temp_path(&path).exists());
+    }
+
+    #[test]
+    fn appends_one_snapshot_per_line() {
+        let dir = TempDir::new("append");
+        let path = dir.0.join("history.jsonl");
+        let output = JsonlOutput::new(JsonlConfig::builder().path(&path).build());
+
+        for count in 1..=3 {
+            output.append(&snapshot(count)).unwrap();
+        }
+
+        let lines = read_lines(File::open(&path).unwrap());
+        let timestamps: Vec<_> = lines.iter().map(|s| s.timestamp_ms).collect();
+        assert_eq!(timestamps, vec![1, 2, 3]);
+        assert!(output.rotated_files().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rotates_by_size_and_keeps_max_files() {
+        let dir = TempDir::new("rotate");
+        let path = dir.0.join("history.jsonl");
+        let line_len = serde_json::to_vec(&snapshot(1)).unwrap().len() as u64 + 1;
+        let output = JsonlOutput::new(
+            JsonlConfig::builder()
+                .path(&path)
+                .max_size(line_len * 2)
+                .max_files(2)
+                .build(),
+        );
+
+        // Two snapshots per file: 1-2, 3-4, 5-6 rotated, 7 active
+        for count in 1..=7 {
+            output.append(&snapshot(count)).unwrap();
+        }
+
+        let rotated = output.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 2);
+        let kept: Vec<_> = rotated
+            .iter()
+            .flat_map(|path| read_lines(File::open(path).unwrap()))
+            .map(|s| s.timestamp_ms)
+            .collect();
+        assert_eq!(kept, vec![3, 4, 5, 6]);
+
+        let active = read_lines(File::open(&path).unwrap());
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].timestamp_ms, 7);
+    }
+
+    #[test]
+    fn rotates_by_age() {
+        let dir = TempDir::new("age");
+        let path = dir.0.join("history.jsonl");
+        let output = JsonlOutput::new(
+            JsonlConfig::builder()
+                .path(&path)
+                .max_age(Duration::ZERO)
+                .build(),
+        );
+
+        for count in 1..=3 {
+            output.append(&snapshot(count)).unwrap();
+        }
+
+        assert_eq!(output.rotated_files().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn age_of_existing_file_carries_over_reopen() {
+        let dir = TempDir::new("age-reopen");
+        let path = dir.0.join("history.jsonl");
+        let config = JsonlConfig::builder()
+            .path(&path)
+            .max_age(Duration::from_millis(200))
+            .build();
+
+        JsonlOutput::new(config.clone())
+            .append(&snapshot(1))
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+
+        // A restarted output rotates the stale file instead of appending
+        let output = JsonlOutput::new(config);
+        output.append(&snapshot(2)).unwrap();
+
+        let rotated = output.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(
+            read_lines(File::open(&rotated[0]).unwrap()),
+            vec![snapshot(1)]
+        );
+        assert_eq!(read_lines(File::open(&path).unwrap()), vec![snapshot(2)]);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn gzips_rotated_files() {
+        let dir = TempDir::new("gzip");
+        let path = dir.0.join("history.jsonl");
+        let output = JsonlOutput::new(
+            JsonlConfig::builder()
+                .path(&path)
+                .max_age(Duration::ZERO)
+                .compression(Compression::Gzip)
+                .build(),
+        );
+
+        output.append(&snapshot(1)).unwrap();
+        output.append(&snapshot(2)).unwrap();
+
+        let rotated = output.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(rotated[0].extension().unwrap(), "gz");
+        let lines = read_lines(flate2::read::GzDecoder::new(
+            File::open(&rotated[0]).unwrap(),
+        ));
+        assert_eq!(lines, vec![snapshot(1)]);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn zstd_compresses_rotated_files() {
+        let dir = TempDir::new("zstd");
+        let path = dir.0.join("history.jsonl");
+        let output = JsonlOutput::new(
+            JsonlConfig::builder()
+                .path(&path)
+                .max_age(Duration::ZERO)
+                .compression(Compression::Zstd)
+                .build(),
+        );
+
+        output.append(&snapshot(1)).unwrap();
+        output.append(&snapshot(2)).unwrap();
+
+        let rotated = output.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 1);
+        assert_eq!(rotated[0].extension().unwrap(), "zst");
+        let decoder = zstd::stream::read::Decoder::new(File::open(&rotated[0]).unwrap()).unwrap();
+        assert_eq!(read_lines(decoder), vec![snapshot(1)]);
+    }
+}
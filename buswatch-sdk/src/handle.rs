@@ -4,7 +4,7 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::Instant;
 
-use crate::state::{GlobalState, ModuleState};
+use crate::state::{GlobalState, ModuleState, SharedClock, OVERFLOW_TOPIC};
 
 /// A handle for recording metrics for a specific module.
 ///
@@ -30,7 +30,10 @@ use crate::state::{GlobalState, ModuleState};
 /// // ... do the read ...
 /// drop(guard); // Clears pending state
 /// ```
-#[derive(Clone)]
+///
+/// Handles are cheap to clone. When the instrumentor is built with
+/// `remove_dropped_modules(true)`, a module is removed once its last handle
+/// has been dropped and it has been reported.
 pub struct ModuleHandle {
     pub(crate) state: Arc<ModuleState>,
     pub(crate) global: Arc<GlobalState>,
@@ -38,6 +41,25 @@ pub struct ModuleHandle {
 }
 
 impl ModuleHandle {
+    /// Register `name` with the global state and create a handle for it.
+    pub(crate) fn new(global: Arc<GlobalState>, name: &str) -> Self {
+        Self {
+            state: global.acquire_module(name),
+            global,
+            name: name.to_string(),
+        }
+    }
+
+    /// Like [`new`](Self::new), for a module that stays registered after
+    /// this handle is dropped.
+    pub(crate) fn retained(global: Arc<GlobalState>, name: &str) -> Self {
+        Self {
+            state: global.acquire_retained(name),
+            global,
+            name: name.to_string(),
+        }
+    }
+
     /// Record that messages were read from a topic.
     ///
     /// # Arguments
@@ -49,6 +71,14 @@ impl ModuleHandle {
         read_state.count.fetch_add(count, Ordering::Relaxed);
     }
 
+    /// Record that handling of messages read from a topic failed.
+    ///
+    /// Failed messages should still be counted with [`record_read`](Self::record_read).
+    pub fn record_error(&self, topic: &str, count: u64) {
+        let read_state = self.state.get_or_create_read(topic);
+        read_state.errors.fetch_add(count, Ordering::Relaxed);
+    }
+
     /// Record that messages were written to a topic.
     ///
     /// # Arguments
@@ -60,15 +90,32 @@ impl ModuleHandle {
         write_state.count.fetch_add(count, Ordering::Relaxed);
 
         // Also update global write counter for backlog computation
+        let topic = if write_state.overflow {
+            OVERFLOW_TOPIC
+        } else {
+            topic
+        };
         let global_counter = self.global.get_topic_write_counter(topic);
         global_counter.fetch_add(count, Ordering::Relaxed);
     }
 
+    /// Record that writes to a topic failed.
+    ///
+    /// Failed writes are not counted by [`record_write`](Self::record_write).
+    pub fn record_write_error(&self, topic: &str, count: u64) {
+        let write_state = self.state.get_or_create_write(topic);
+        write_state.errors.fetch_add(count, Ordering::Relaxed);
+    }
+
     /// Start tracking a pending read operation.
     ///
     /// Returns a guard that clears the pending state when dropped.
     /// This is useful for tracking how long reads are blocked.
     ///
+    /// Guards may overlap: each one counts as an in-flight read, pending
+    /// reports the oldest outstanding one, and the guard's lifetime is
+    /// recorded in the topic's latency histogram when it drops.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -82,10 +129,12 @@ impl ModuleHandle {
     /// ```
     pub fn start_read(&self, topic: &str) -> PendingGuard {
         let read_state = self.state.get_or_create_read(topic);
-        *read_state.pending_since.write() = Some(Instant::now());
+        let id = read_state.inflight.lock().start(self.state.clock.now());
 
         PendingGuard {
             state: PendingState::Read(read_state),
+            clock: self.state.clock.clone(),
+            id,
         }
     }
 
@@ -95,26 +144,112 @@ impl ModuleHandle {
     /// This is useful for tracking backpressure (slow consumers).
     pub fn start_write(&self, topic: &str) -> PendingGuard {
         let write_state = self.state.get_or_create_write(topic);
-        *write_state.pending_since.write() = Some(Instant::now());
+        let id = write_state.inflight.lock().start(self.state.clock.now());
 
         PendingGuard {
             state: PendingState::Write(write_state),
+            clock: self.state.clock.clone(),
+            id,
+        }
+    }
+
+    /// Start tracking an idle wait for a message on a read topic.
+    ///
+    /// The wait is reported as read pending, but not as an in-flight read,
+    /// and isn't recorded in the latency histogram.
+    #[cfg(any(feature = "tokio", feature = "futures"))]
+    pub(crate) fn wait_read(&self, topic: &str) -> PendingGuard {
+        let read_state = self.state.get_or_create_read(topic);
+        let id = read_state.inflight.lock().wait(self.state.clock.now());
+
+        PendingGuard {
+            state: PendingState::Read(read_state),
+            clock: self.state.clock.clone(),
+            id,
+        }
+    }
+
+    /// Start tracking an idle wait for capacity on a write topic.
+    ///
+    /// Like [`wait_read`](Self::wait_read), this is reported as write pending
+    /// only.
+    #[cfg(feature = "futures")]
+    pub(crate) fn wait_write(&self, topic: &str) -> PendingGuard {
+        let write_state = self.state.get_or_create_write(topic);
+        let id = write_state.inflight.lock().wait(self.state.clock.now());
+
+        PendingGuard {
+            state: PendingState::Write(write_state),
+            clock: self.state.clock.clone(),
+            id,
         }
     }
 
     /// Set the pending duration for a read directly.
     ///
     /// Use this if you're computing pending time yourself rather than
-    /// using the guard-based API.
+    /// using the guard-based API. A directly-set pending time counts as one
+    /// in-flight read alongside any outstanding guards.
     pub fn set_read_pending(&self, topic: &str, since: Option<Instant>) {
         let read_state = self.state.get_or_create_read(topic);
-        *read_state.pending_since.write() = since;
+        read_state.inflight.lock().set_manual(since);
     }
 
     /// Set the pending duration for a write directly.
     pub fn set_write_pending(&self, topic: &str, since: Option<Instant>) {
         let write_state = self.state.get_or_create_write(topic);
-        *write_state.pending_since.write() = since;
+        write_state.inflight.lock().set_manual(since);
+    }
+
+    /// Record the backlog of a read topic as measured from the underlying queue.
+    ///
+    /// Use this when the bus client knows the real queue depth or consumer
+    /// lag. A measured backlog takes precedence over the estimate computed
+    /// from write counts, and is reported with
+    /// [`BacklogSource::Measured`](buswatch_types::BacklogSource::Measured).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use buswatch_sdk::Instrumentor;
+    /// # let instrumentor = Instrumentor::new();
+    /// # let handle = instrumentor.register("consumer");
+    /// // e.g. from a Kafka consumer's lag or a queue's message count
+    /// handle.set_backlog("orders.new", 42);
+    /// ```
+    pub fn set_backlog(&self, topic: &str, backlog: u64) {
+        let read_state = self.state.get_or_create_read(topic);
+        *read_state.backlog.write() = Some(backlog);
+    }
+
+    /// Declare that this module is a member of a consumer group.
+    ///
+    /// Members of a group split a topic's messages between them, so the
+    /// estimated backlog of each member is the topic's writes minus the
+    /// reads of all members together. Modules outside a group, or in
+    /// different groups, each consume every message (fan-out).
+    ///
+    /// The group applies to the module, so it is shared by all of its
+    /// handles.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # use buswatch_sdk::Instrumentor;
+    /// # let instrumentor = Instrumentor::new();
+    /// for id in 0..4 {
+    ///     let worker = instrumentor.register(&format!("order-worker-{id}"));
+    ///     worker.set_consumer_group("order-workers");
+    /// }
+    /// ```
+    pub fn set_consumer_group(&self, group: &str) {
+        *self.state.group.write() = Some(group.to_string());
+    }
+
+    /// Record how many topics the last snapshot this module emitted carried.
+    #[cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+    pub(crate) fn set_tracked_topics(&self, topics: u64) {
+        *self.state.tracked_topics.write() = Some(topics);
     }
 
     /// Get the module name.
@@ -123,6 +258,23 @@ impl ModuleHandle {
     }
 }
 
+impl Clone for ModuleHandle {
+    fn clone(&self) -> Self {
+        self.state.handles.fetch_add(1, Ordering::Relaxed);
+        Self {
+            state: self.state.clone(),
+            global: self.global.clone(),
+            name: self.name.clone(),
+        }
+    }
+}
+
+impl Drop for ModuleHandle {
+    fn drop(&mut self) {
+        self.state.handles.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 impl std::fmt::Debug for ModuleHandle {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ModuleHandle")
@@ -139,16 +291,30 @@ enum PendingState {
 
 /// Guard that clears pending state when dropped.
 ///
-/// This implements RAII-style tracking of pending operations.
+/// This implements RAII-style tracking of pending operations. Each guard
+/// tracks its own operation, so dropping one leaves others on the same
+/// topic in flight.
 pub struct PendingGuard {
     state: PendingState,
+    clock: SharedClock,
+    id: u64,
 }
 
 impl Drop for PendingGuard {
     fn drop(&mut self) {
         match &self.state {
-            PendingState::Read(s) => *s.pending_since.write() = None,
-            PendingState::Write(s) => *s.pending_since.write() = None,
+            PendingState::Read(s) => {
+                if let Some(started) = s.inflight.lock().finish(self.id) {
+                    s.latency
+                        .record(self.clock.now().saturating_duration_since(started));
+                }
+            }
+            PendingState::Write(s) => {
+                if let Some(started) = s.inflight.lock().finish(self.id) {
+                    s.latency
+                        .record(self.clock.now().saturating_duration_since(started));
+                }
+            }
         }
     }
 }
@@ -157,15 +323,11 @@ impl Drop for PendingGuard {
 mod tests {
     use super::*;
     use crate::state::GlobalState;
+    use buswatch_types::{ManualClock, Microseconds};
+    use std::time::Duration;
 
     fn create_handle() -> ModuleHandle {
-        let global = Arc::new(GlobalState::default());
-        let state = global.register_module("test");
-        ModuleHandle {
-            state,
-            global,
-            name: "test".to_string(),
-        }
+        ModuleHandle::new(Arc::new(GlobalState::default()), "test")
     }
 
     #[test]
@@ -187,6 +349,30 @@ mod tests {
         assert_eq!(metrics.writes.get("topic").unwrap().count, 10);
     }
 
+    #[test]
+    fn test_record_error() {
+        let handle = create_handle();
+        handle.record_read("topic", 3);
+        handle.record_error("topic", 1);
+
+        let metrics = handle.state.collect();
+        let read = metrics.reads.get("topic").unwrap();
+        assert_eq!(read.count, 3);
+        assert_eq!(read.errors, Some(1));
+    }
+
+    #[test]
+    fn test_record_write_error() {
+        let handle = create_handle();
+        handle.record_write("topic", 2);
+        handle.record_write_error("topic", 1);
+
+        let metrics = handle.state.collect();
+        let write = metrics.writes.get("topic").unwrap();
+        assert_eq!(write.count, 2);
+        assert_eq!(write.errors, Some(1));
+    }
+
     #[test]
     fn test_pending_guard() {
         let handle = create_handle();
@@ -195,23 +381,18 @@ mod tests {
             let _guard = handle.start_read("topic");
             // While guard is held, pending should be set
             let state = handle.state.get_or_create_read("topic");
-            assert!(state.pending_since.read().is_some());
+            assert!(state.inflight.lock().oldest().is_some());
         }
 
         // After guard is dropped, pending should be cleared
         let state = handle.state.get_or_create_read("topic");
-        assert!(state.pending_since.read().is_none());
+        assert!(state.inflight.lock().oldest().is_none());
     }
 
     #[test]
     fn record_write_updates_global_topic_counter() {
         let global = Arc::new(GlobalState::default());
-        let state = global.register_module("producer");
-        let handle = ModuleHandle {
-            state,
-            global: global.clone(),
-            name: "producer".to_string(),
-        };
+        let handle = ModuleHandle::new(global.clone(), "producer");
 
         handle.record_write("events", 50);
         handle.record_write("events", 25);
@@ -220,6 +401,39 @@ mod tests {
         assert_eq!(counter.load(std::sync::atomic::Ordering::Relaxed), 75);
     }
 
+    #[test]
+    fn overflow_writes_update_overflow_topic_counter() {
+        let global = Arc::new(GlobalState::with_limits(crate::state::Limits {
+            max_topics: Some(1),
+            ..Default::default()
+        }));
+        let handle = ModuleHandle::new(global.clone(), "producer");
+
+        handle.record_write("a", 1);
+        handle.record_write("b", 2);
+
+        let counts = global.topic_write_counts.read();
+        assert!(!counts.contains_key("b"));
+        assert_eq!(counts[OVERFLOW_TOPIC].load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn dropping_last_handle_removes_module_when_enabled() {
+        let global = Arc::new(GlobalState::with_limits(crate::state::Limits {
+            remove_dropped_modules: true,
+            ..Default::default()
+        }));
+        let handle = ModuleHandle::new(global.clone(), "worker");
+        let clone = handle.clone();
+        drop(handle);
+        global.collect();
+        assert!(global.modules.read().contains_key("worker"));
+
+        drop(clone);
+        assert!(global.collect().modules.contains_key("worker"));
+        assert!(!global.modules.read().contains_key("worker"));
+    }
+
     #[test]
     fn start_write_sets_pending_and_guard_clears_it() {
         let handle = create_handle();
@@ -227,11 +441,11 @@ mod tests {
         {
             let _guard = handle.start_write("output");
             let state = handle.state.get_or_create_write("output");
-            assert!(state.pending_since.read().is_some());
+            assert!(state.inflight.lock().oldest().is_some());
         }
 
         let state = handle.state.get_or_create_write("output");
-        assert!(state.pending_since.read().is_none());
+        assert!(state.inflight.lock().oldest().is_none());
     }
 
     #[test]
@@ -240,10 +454,10 @@ mod tests {
 
         handle.set_read_pending("topic", Some(Instant::now()));
         let state = handle.state.get_or_create_read("topic");
-        assert!(state.pending_since.read().is_some());
+        assert!(state.inflight.lock().oldest().is_some());
 
         handle.set_read_pending("topic", None);
-        assert!(state.pending_since.read().is_none());
+        assert!(state.inflight.lock().oldest().is_none());
     }
 
     #[test]
@@ -252,10 +466,10 @@ mod tests {
 
         handle.set_write_pending("topic", Some(Instant::now()));
         let state = handle.state.get_or_create_write("topic");
-        assert!(state.pending_since.read().is_some());
+        assert!(state.inflight.lock().oldest().is_some());
 
         handle.set_write_pending("topic", None);
-        assert!(state.pending_since.read().is_none());
+        assert!(state.inflight.lock().oldest().is_none());
     }
 
     #[test]
@@ -291,19 +505,68 @@ mod tests {
         assert_eq!(metrics.writes.get("topic-c").unwrap().count, 30);
     }
 
+    fn manual_handle() -> (ModuleHandle, ManualClock) {
+        let clock = ManualClock::default();
+        let global = GlobalState::default().with_clock(SharedClock::new(Arc::new(clock.clone())));
+        (ModuleHandle::new(Arc::new(global), "test"), clock)
+    }
+
     #[test]
     fn pending_guard_measures_elapsed_time() {
-        let handle = create_handle();
+        let (handle, clock) = manual_handle();
 
-        let _guard = handle.start_read("topic");
-        std::thread::sleep(std::time::Duration::from_millis(10));
+        let guard = handle.start_read("topic");
+        clock.advance(Duration::from_millis(10));
 
         let metrics = handle.state.collect();
         let pending = metrics.reads.get("topic").unwrap().pending;
+        assert_eq!(pending, Some(Microseconds::from_millis(10)));
+
+        clock.advance(Duration::from_millis(5));
+        drop(guard);
+        let latency = handle.state.collect().reads["topic"]
+            .latency
+            .clone()
+            .unwrap();
+        assert_eq!(latency.count, 1);
+        assert_eq!(latency.sum, Microseconds::from_millis(15));
+    }
+
+    #[test]
+    fn overlapping_guards_track_each_operation() {
+        let (handle, clock) = manual_handle();
+
+        let first = handle.start_read("topic");
+        clock.advance(Duration::from_millis(10));
+        let second = handle.start_read("topic");
+        clock.advance(Duration::from_millis(2));
+
+        let read = handle.state.collect().reads["topic"].clone();
+        assert_eq!(read.inflight, Some(2));
+        assert_eq!(read.pending, Some(Microseconds::from_millis(12)));
+
+        // Dropping the older guard must not clear the newer one
+        drop(first);
+        let read = handle.state.collect().reads["topic"].clone();
+        assert_eq!(read.inflight, Some(1));
+        assert_eq!(read.pending, Some(Microseconds::from_millis(2)));
+
+        drop(second);
+        let read = handle.state.collect().reads["topic"].clone();
+        assert_eq!(read.inflight, None);
+        assert!(read.pending.is_none());
+    }
+
+    #[test]
+    fn dropped_guard_records_latency() {
+        let handle = create_handle();
+
+        drop(handle.start_write("output"));
+        drop(handle.start_write("output"));
 
-        assert!(pending.is_some());
-        // Should be at least 10ms (10000 microseconds)
-        assert!(pending.unwrap().as_micros() >= 10000);
+        let write = handle.state.collect().writes["output"].clone();
+        let latency = write.latency.expect("latency should be recorded");
+        assert_eq!(latency.count, 2);
     }
 
     #[test]
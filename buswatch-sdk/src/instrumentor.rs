@@ -1,11 +1,20 @@
 //! The main Instrumentor type for collecting and emitting metrics.
 
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
 
+use buswatch_types::{Clock, Snapshot};
+
 use crate::handle::ModuleHandle;
-use crate::output::Output;
-use crate::state::GlobalState;
+use crate::output::{EmitError, Output};
+use crate::state::{GlobalState, Limits, SharedClock};
+
+/// Process-wide instrumentor used by `#[handler]` functions.
+static GLOBAL: OnceLock<Instrumentor> = OnceLock::new();
+
+/// Name of the module the SDK reports its own metrics under, when enabled
+/// with [`InstrumentorBuilder::self_metrics`].
+pub const SELF_MODULE: &str = "buswatch-sdk";
 
 /// The main entry point for instrumenting a message bus.
 ///
@@ -28,7 +37,7 @@ use crate::state::GlobalState;
 ///     let handle = instrumentor.register("my-service");
 ///
 ///     // Start background emission
-///     instrumentor.start();
+///     let _emission = instrumentor.start();
 ///
 ///     // Record some metrics
 ///     handle.record_read("events", 10);
@@ -41,8 +50,13 @@ use crate::state::GlobalState;
 #[derive(Debug)]
 pub struct Instrumentor {
     state: Arc<GlobalState>,
+    // Only read by the emitters
+    #[cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
     outputs: Arc<Vec<Output>>,
+    #[cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
     interval: Duration,
+    #[cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+    reporter: Reporter,
 }
 
 impl Instrumentor {
@@ -54,6 +68,7 @@ impl Instrumentor {
             state: Arc::new(GlobalState::default()),
             outputs: Arc::new(Vec::new()),
             interval: Duration::from_secs(1),
+            reporter: Reporter::default(),
         }
     }
 
@@ -62,6 +77,23 @@ impl Instrumentor {
         InstrumentorBuilder::new()
     }
 
+    /// Install this instrumentor as the process-wide global.
+    ///
+    /// Returns the instrumentor back if a global was already set, including
+    /// the default one created by an earlier call to [`Instrumentor::global`].
+    pub fn set_global(self) -> Result<&'static Instrumentor, Instrumentor> {
+        GLOBAL.set(self)?;
+        Ok(Self::global())
+    }
+
+    /// Get the process-wide global instrumentor.
+    ///
+    /// If none was installed with [`Instrumentor::set_global`], a default
+    /// instrumentor with no outputs is created.
+    pub fn global() -> &'static Instrumentor {
+        GLOBAL.get_or_init(Instrumentor::new)
+    }
+
     /// Register a module and get a handle for recording metrics.
     ///
     /// If a module with this name already exists, returns a handle to
@@ -71,12 +103,40 @@ impl Instrumentor {
     ///
     /// * `name` - The module name (e.g., "order-processor", "notification-sender")
     pub fn register(&self, name: &str) -> ModuleHandle {
-        let module_state = self.state.register_module(name);
-        ModuleHandle {
-            state: module_state,
-            global: self.state.clone(),
-            name: name.to_string(),
-        }
+        ModuleHandle::new(self.state.clone(), name)
+    }
+
+    /// Register a module that stays registered between uses.
+    ///
+    /// Like [`register`](Self::register), except that the instrumentor
+    /// keeps a handle to the module until it is
+    /// [`unregister`](Self::unregister)ed. With
+    /// [`remove_dropped_modules`](InstrumentorBuilder::remove_dropped_modules),
+    /// the module is then not removed when the returned handle drops. This
+    /// suits handles created per message, as `#[handler]` does.
+    pub fn register_retained(&self, name: &str) -> ModuleHandle {
+        ModuleHandle::retained(self.state.clone(), name)
+    }
+
+    /// Remove a module so it stops being reported.
+    ///
+    /// The module appears in the next snapshot one final time with its last
+    /// values. Handles that are still held keep working but no longer
+    /// contribute to snapshots; registering the name again starts a fresh
+    /// module.
+    ///
+    /// Returns `false` if no module with this name is registered.
+    pub fn unregister(&self, name: &str) -> bool {
+        self.state.unregister(name)
+    }
+
+    /// Create a `tracing` layer that records metrics from bus spans into
+    /// this instrumentor.
+    ///
+    /// See [`BuswatchLayer`](crate::layer::BuswatchLayer) for the span fields it recognizes.
+    #[cfg(feature = "tracing")]
+    pub fn layer(&self) -> crate::layer::BuswatchLayer {
+        crate::layer::BuswatchLayer::new(self.state.clone())
     }
 
     /// Collect a snapshot of all current metrics.
@@ -92,60 +152,320 @@ impl Instrumentor {
     /// This spawns a tokio task that periodically collects and emits
     /// snapshots to all configured outputs.
     ///
-    /// For Prometheus outputs, this also starts the HTTP server to serve metrics.
+    /// For Prometheus outputs, this also starts the HTTP server to serve
+    /// metrics, which shuts down gracefully after the final snapshot.
     ///
-    /// Returns a handle that can be used to stop the emission.
+    /// Returns a handle that stops the emission. Keep it alive for as long
+    /// as snapshots should be emitted, and call
+    /// [`shutdown`](EmissionHandle::shutdown) before exiting so the final
+    /// counts are written.
     #[cfg(feature = "tokio")]
     pub fn start(&self) -> EmissionHandle {
         use tokio::sync::watch;
 
-        let (stop_tx, stop_rx) = watch::channel(false);
+        let (stop_tx, mut stop_rx) = watch::channel(None);
         let state = self.state.clone();
         let outputs = self.outputs.clone();
+        let reporter = self.reporter.clone();
         let interval = self.interval;
 
-        // Start Prometheus HTTP servers for any Prometheus outputs
-        #[cfg(feature = "prometheus")]
-        for output in outputs.iter() {
-            if let Output::Prometheus(exporter) = output {
-                exporter.start_server();
-            }
-        }
+        // Start servers for outputs that need one (Prometheus), to be
+        // stopped after the final snapshot
+        let (server_stop, stopped) = watch::channel(false);
+        let servers: Vec<_> = outputs
+            .iter()
+            .filter_map(|output| {
+                let mut stopped = stopped.clone();
+                let reporter = reporter.clone();
+                output.serve(
+                    async move {
+                        let _ = stopped.wait_for(|stopped| *stopped).await;
+                    },
+                    move |error| reporter.report_error(&error),
+                )
+            })
+            .collect();
 
-        tokio::spawn(async move {
+        let task = tokio::spawn(async move {
             let mut interval_timer = tokio::time::interval(interval);
-            let mut stop_rx = stop_rx;
 
-            loop {
+            let timeout = loop {
                 tokio::select! {
                     _ = interval_timer.tick() => {
                         let snapshot = state.collect();
-                        for output in outputs.iter() {
-                            let _ = output.emit(&snapshot).await;
-                        }
+                        emit_all(&outputs, &snapshot, &reporter, None).await;
                     }
-                    _ = stop_rx.changed() => {
-                        if *stop_rx.borrow() {
-                            break;
+                    changed = stop_rx.changed() => {
+                        match changed {
+                            Ok(()) => {
+                                if let Some(timeout) = *stop_rx.borrow() {
+                                    break timeout;
+                                }
+                            }
+                            // Handle gone without signalling; flush anyway
+                            Err(_) => break DEFAULT_SHUTDOWN_TIMEOUT,
                         }
                     }
                 }
+            };
+
+            // Final flush so the last interval's counts are not lost
+            let snapshot = state.collect();
+            let deadline = tokio::time::Instant::now() + timeout;
+            let errors = emit_all(&outputs, &snapshot, &reporter, Some(deadline)).await;
+
+            let _ = server_stop.send(true);
+            for server in servers {
+                let _ = tokio::time::timeout_at(deadline, server).await;
             }
+
+            errors
         });
 
-        EmissionHandle { stop_tx }
+        EmissionHandle {
+            stop_tx,
+            task: Some(task),
+        }
     }
 
     /// Emit a snapshot to all outputs immediately.
+    ///
+    /// Failures are reported to the [`on_error`](InstrumentorBuilder::on_error)
+    /// handler, as with background emission.
     #[cfg(feature = "tokio")]
     pub async fn emit_now(&self) {
         let snapshot = self.state.collect();
-        for output in self.outputs.iter() {
-            let _ = output.emit(&snapshot).await;
+        emit_all(&self.outputs, &snapshot, &self.reporter, None).await;
+    }
+
+    /// Start background emission of snapshots on a dedicated thread.
+    ///
+    /// Like [`start`](Self::start), for applications that do not run a
+    /// tokio runtime. Outputs are written with blocking I/O; Prometheus,
+    /// Prometheus push, AMQP, Kafka and NATS outputs need a tokio runtime and
+    /// fail with `Unsupported`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use buswatch_sdk::{Instrumentor, Output};
+    ///
+    /// let instrumentor = Instrumentor::builder()
+    ///     .output(Output::file("metrics.json"))
+    ///     .build();
+    /// let emission = instrumentor.start_thread();
+    ///
+    /// // ... run the pipeline ...
+    ///
+    /// emission.shutdown().expect("final snapshot was not delivered");
+    /// ```
+    #[cfg(feature = "thread")]
+    pub fn start_thread(&self) -> ThreadEmissionHandle {
+        use std::sync::mpsc::RecvTimeoutError;
+        use std::time::Instant;
+
+        let (stop_tx, stop_rx) = std::sync::mpsc::channel::<Duration>();
+        let state = self.state.clone();
+        let outputs = self.outputs.clone();
+        let reporter = self.reporter.clone();
+        let interval = self.interval;
+
+        let thread = std::thread::Builder::new()
+            .name("buswatch-emitter".to_string())
+            .spawn(move || {
+                // The first snapshot is emitted immediately, as with `start`
+                let mut next_tick = Instant::now();
+
+                let timeout = loop {
+                    let wait = next_tick.saturating_duration_since(Instant::now());
+                    match stop_rx.recv_timeout(wait) {
+                        Ok(timeout) => break timeout,
+                        // Handle gone without signalling; flush anyway
+                        Err(RecvTimeoutError::Disconnected) => break DEFAULT_SHUTDOWN_TIMEOUT,
+                        Err(RecvTimeoutError::Timeout) => {
+                            let snapshot = state.collect();
+                            emit_all_blocking(&outputs, &snapshot, &reporter, None);
+                            next_tick += interval;
+                        }
+                    }
+                };
+
+                // Final flush so the last interval's counts are not lost
+                let snapshot = state.collect();
+                let deadline = Instant::now() + timeout;
+                emit_all_blocking(&outputs, &snapshot, &reporter, Some(deadline))
+            })
+            .expect("failed to spawn emitter thread");
+
+        ThreadEmissionHandle {
+            stop_tx: Some(stop_tx),
+            thread: Some(thread),
+        }
+    }
+
+    /// Emit a snapshot to all outputs immediately, blocking the current
+    /// thread.
+    ///
+    /// Failures are reported to the [`on_error`](InstrumentorBuilder::on_error)
+    /// handler, as with background emission.
+    #[cfg(feature = "thread")]
+    pub fn emit_now_blocking(&self) {
+        let snapshot = self.state.collect();
+        emit_all_blocking(&self.outputs, &snapshot, &self.reporter, None);
+    }
+}
+
+/// Emit a snapshot to every output, reporting failures and self-metrics.
+///
+/// With a deadline, each output is also flushed and closed, and any output
+/// that has not finished by the deadline fails with `TimedOut`.
+#[cfg(feature = "tokio")]
+async fn emit_all(
+    outputs: &[Output],
+    snapshot: &Snapshot,
+    reporter: &Reporter,
+    deadline: Option<tokio::time::Instant>,
+) -> Vec<EmitError> {
+    let mut errors = Vec::new();
+    for output in outputs {
+        let name = output.name();
+        let guard = reporter.start_emit(&name);
+
+        let result = match deadline {
+            None => output.emit(snapshot).await,
+            Some(deadline) => {
+                let emit = async {
+                    output.emit(snapshot).await?;
+                    output.flush().await?;
+                    output.close().await
+                };
+                tokio::time::timeout_at(deadline, emit)
+                    .await
+                    .unwrap_or_else(|_| {
+                        Err(std::io::Error::new(
+                            std::io::ErrorKind::TimedOut,
+                            "timed out during final flush",
+                        ))
+                    })
+            }
+        };
+        drop(guard);
+
+        errors.extend(reporter.report(name, snapshot, result));
+    }
+    errors
+}
+
+/// Blocking counterpart of [`emit_all`] for the `thread` emitter.
+///
+/// Emits cannot be interrupted, so the deadline applies to flushing, and
+/// outputs reached after it has passed fail with `TimedOut`.
+#[cfg(feature = "thread")]
+fn emit_all_blocking(
+    outputs: &[Output],
+    snapshot: &Snapshot,
+    reporter: &Reporter,
+    deadline: Option<std::time::Instant>,
+) -> Vec<EmitError> {
+    let mut errors = Vec::new();
+    for output in outputs {
+        let name = output.name();
+        let guard = reporter.start_emit(&name);
+
+        let result = match deadline {
+            None => output.emit_blocking(snapshot),
+            Some(deadline) => {
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                if remaining.is_zero() {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "timed out during final flush",
+                    ))
+                } else {
+                    output
+                        .emit_blocking(snapshot)
+                        .and_then(|()| output.flush_blocking(remaining))
+                }
+            }
+        };
+        drop(guard);
+
+        errors.extend(reporter.report(name, snapshot, result));
+    }
+    errors
+}
+
+/// Callback invoked when an output fails to emit.
+#[derive(Clone)]
+struct ErrorHandler(Arc<dyn Fn(&EmitError) + Send + Sync>);
+
+impl std::fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ErrorHandler(..)")
+    }
+}
+
+/// Where the outcome of each emit is reported.
+#[derive(Debug, Clone, Default)]
+struct Reporter {
+    on_error: Option<ErrorHandler>,
+    /// Handle for the SDK's own module, if self-metrics are enabled
+    self_metrics: Option<ModuleHandle>,
+}
+
+impl Reporter {
+    /// Start timing an emit to the output called `name`, if self-metrics
+    /// are enabled.
+    #[cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+    fn start_emit(&self, name: &str) -> Option<crate::PendingGuard> {
+        self.self_metrics
+            .as_ref()
+            .map(|handle| handle.start_write(name))
+    }
+
+    /// Record the result of emitting `snapshot` to the output called `name`,
+    /// returning the error, if any, after passing it to the handler.
+    #[cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+    fn report(
+        &self,
+        name: String,
+        snapshot: &Snapshot,
+        result: std::io::Result<()>,
+    ) -> Option<EmitError> {
+        if let Some(handle) = &self.self_metrics {
+            match &result {
+                Ok(()) => handle.record_write(&name, 1),
+                Err(_) => handle.record_write_error(&name, 1),
+            }
+            handle.set_tracked_topics(tracked_topics(snapshot));
+        }
+
+        let error = EmitError {
+            output: name,
+            error: result.err()?,
+        };
+        self.report_error(&error);
+        Some(error)
+    }
+
+    /// Pass an error to the handler, if any.
+    fn report_error(&self, error: &EmitError) {
+        if let Some(handler) = &self.on_error {
+            (handler.0)(error);
         }
     }
 }
 
+/// Number of topics tracked in a snapshot, excluding the SDK's own module.
+fn tracked_topics(snapshot: &Snapshot) -> u64 {
+    snapshot
+        .modules
+        .iter()
+        .filter(|(name, _)| name.as_str() != SELF_MODULE)
+        .map(|(_, module)| (module.reads.len() + module.writes.len()) as u64)
+        .sum()
+}
+
 impl Default for Instrumentor {
     fn default() -> Self {
         Self::new()
@@ -157,6 +477,10 @@ impl Default for Instrumentor {
 pub struct InstrumentorBuilder {
     outputs: Vec<Output>,
     interval: Option<Duration>,
+    on_error: Option<ErrorHandler>,
+    self_metrics: bool,
+    limits: Limits,
+    clock: Option<Arc<dyn Clock>>,
 }
 
 impl InstrumentorBuilder {
@@ -181,29 +505,298 @@ impl InstrumentorBuilder {
         self
     }
 
+    /// Set a handler called whenever an output fails to emit a snapshot.
+    ///
+    /// Without one, failures are only visible through
+    /// [`self_metrics`](Self::self_metrics). The handler runs on the
+    /// emission task, so it should return quickly. Errors from the servers
+    /// started by [`Instrumentor::start`], such as a Prometheus client
+    /// failing the TLS handshake, are passed to it too.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::{Instrumentor, Output};
+    ///
+    /// let instrumentor = Instrumentor::builder()
+    ///     .output(Output::tcp("localhost:9090"))
+    ///     .on_error(|e| eprintln!("buswatch: {}", e))
+    ///     .build();
+    /// ```
+    pub fn on_error<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&EmitError) + Send + Sync + 'static,
+    {
+        self.on_error = Some(ErrorHandler(Arc::new(handler)));
+        self
+    }
+
+    /// Include the SDK's own metrics in each snapshot, as a module named
+    /// [`SELF_MODULE`] (`buswatch-sdk`).
+    ///
+    /// Each output appears as a write topic named after [`Output::name`],
+    /// counting successful emits, failed emits (`errors`) and emit
+    /// latency. The module's `tracked_topics` reports how many topics the
+    /// last emitted snapshot contained.
+    ///
+    /// Defaults to off.
+    pub fn self_metrics(mut self, enabled: bool) -> Self {
+        self.self_metrics = enabled;
+        self
+    }
+
+    /// Remove topics that have not been used for `ttl`.
+    ///
+    /// Idle topics are evicted when a snapshot is collected, unless an
+    /// operation on them is still pending. Each module reports how many
+    /// topics it has lost this way as `evicted_topics`. A topic that is used
+    /// again after eviction starts from zero.
+    ///
+    /// Defaults to keeping topics forever.
+    pub fn topic_ttl(mut self, ttl: Duration) -> Self {
+        self.limits.topic_ttl = Some(ttl);
+        self
+    }
+
+    /// Limit the number of read topics and write topics tracked per module.
+    ///
+    /// Once a module has `max` read (or write) topics, further topics are
+    /// recorded together under [`OVERFLOW_TOPIC`](crate::OVERFLOW_TOPIC)
+    /// (`_overflow`), bounding cardinality when topic names are dynamic.
+    ///
+    /// Defaults to unlimited.
+    pub fn max_topics_per_module(mut self, max: usize) -> Self {
+        self.limits.max_topics = Some(max);
+        self
+    }
+
+    /// Remove a module once all of its [`ModuleHandle`]s have been dropped.
+    ///
+    /// The module is reported one final time before it is removed. Modules
+    /// recorded by the `tracing` layer or `#[handler]`, or registered with
+    /// [`Instrumentor::register_retained`], keep a handle of their own and
+    /// stay until they are unregistered.
+    ///
+    /// Defaults to off.
+    pub fn remove_dropped_modules(mut self, enabled: bool) -> Self {
+        self.limits.remove_dropped_modules = enabled;
+        self
+    }
+
+    /// Set the clock used for rates, pending durations, topic TTLs and
+    /// snapshot timestamps.
+    ///
+    /// Defaults to [`SystemClock`](buswatch_types::SystemClock). Tests can
+    /// pass a [`ManualClock`](buswatch_types::ManualClock) and advance it
+    /// instead of sleeping.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::{Instrumentor, ManualClock};
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::default();
+    /// let instrumentor = Instrumentor::builder().clock(clock.clone()).build();
+    /// let handle = instrumentor.register("worker");
+    ///
+    /// let guard = handle.start_read("jobs");
+    /// clock.advance(Duration::from_secs(3));
+    ///
+    /// let snapshot = instrumentor.collect();
+    /// let pending = snapshot.modules["worker"].reads["jobs"].pending.unwrap();
+    /// assert_eq!(pending.as_secs(), 3);
+    /// # drop(guard);
+    /// ```
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Some(Arc::new(clock));
+        self
+    }
+
     /// Build the instrumentor.
     pub fn build(self) -> Instrumentor {
+        let clock = self.clock.map(SharedClock::new).unwrap_or_default();
+        let state = Arc::new(GlobalState::with_limits(self.limits).with_clock(clock));
+        let self_metrics = self
+            .self_metrics
+            .then(|| ModuleHandle::new(state.clone(), SELF_MODULE));
+
         Instrumentor {
-            state: Arc::new(GlobalState::default()),
+            state,
             outputs: Arc::new(self.outputs),
             interval: self.interval.unwrap_or(Duration::from_secs(1)),
+            reporter: Reporter {
+                on_error: self.on_error,
+                self_metrics,
+            },
         }
     }
 }
 
+/// How long a final flush may take when none is given, e.g. when an
+/// emission handle is dropped.
+#[cfg(any(feature = "tokio", feature = "thread"))]
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Handle for controlling background emission.
 ///
-/// Drop this handle to stop emission, or call `stop()` explicitly.
+/// Call [`shutdown`](Self::shutdown) to stop emission after a final flush
+/// and find out whether every output received it. Dropping the handle (or
+/// calling [`stop`](Self::stop)) also stops emission and starts the final
+/// flush, but does not wait for it; if the runtime shuts down first, the
+/// final snapshot may be lost.
 #[cfg(feature = "tokio")]
+#[must_use = "emission stops when the handle is dropped"]
 pub struct EmissionHandle {
-    stop_tx: tokio::sync::watch::Sender<bool>,
+    stop_tx: tokio::sync::watch::Sender<Option<Duration>>,
+    task: Option<tokio::task::JoinHandle<Vec<EmitError>>>,
 }
 
 #[cfg(feature = "tokio")]
 impl EmissionHandle {
-    /// Stop background emission.
+    /// Stop background emission without waiting for the final flush.
     pub fn stop(self) {
-        let _ = self.stop_tx.send(true);
+        // Drop sends the stop signal
+    }
+
+    /// Stop background emission, emit one final snapshot to every output,
+    /// and wait for it to be delivered.
+    ///
+    /// Waits at most [`DEFAULT_SHUTDOWN_TIMEOUT`] in total. Returns the
+    /// outputs that failed or timed out; they are also reported to the
+    /// [`on_error`](InstrumentorBuilder::on_error) handler.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use buswatch_sdk::{Instrumentor, Output};
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let instrumentor = Instrumentor::builder()
+    ///         .output(Output::tcp("localhost:9090"))
+    ///         .build();
+    ///     let emission = instrumentor.start();
+    ///
+    ///     // ... run the batch job ...
+    ///
+    ///     if let Err(errors) = emission.shutdown().await {
+    ///         for e in errors {
+    ///             eprintln!("{}", e);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub async fn shutdown(self) -> Result<(), Vec<EmitError>> {
+        self.shutdown_timeout(DEFAULT_SHUTDOWN_TIMEOUT).await
+    }
+
+    /// Like [`shutdown`](Self::shutdown), with a custom limit on how long
+    /// the final flush may take.
+    pub async fn shutdown_timeout(mut self, timeout: Duration) -> Result<(), Vec<EmitError>> {
+        let _ = self.stop_tx.send(Some(timeout));
+        let Some(task) = self.task.take() else {
+            return Ok(());
+        };
+
+        let errors = match task.await {
+            Ok(errors) => errors,
+            Err(e) => vec![EmitError {
+                output: "emission task".to_string(),
+                error: std::io::Error::other(e),
+            }],
+        };
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Drop for EmissionHandle {
+    fn drop(&mut self) {
+        if self.task.is_some() {
+            let _ = self.stop_tx.send(Some(DEFAULT_SHUTDOWN_TIMEOUT));
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl std::fmt::Debug for EmissionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmissionHandle").finish_non_exhaustive()
+    }
+}
+
+/// Handle for controlling emission started with
+/// [`Instrumentor::start_thread`].
+///
+/// Has the same shutdown semantics as [`EmissionHandle`]: dropping it stops
+/// emission and starts a final flush on the emitter thread without waiting
+/// for it.
+#[cfg(feature = "thread")]
+#[must_use = "emission stops when the handle is dropped"]
+pub struct ThreadEmissionHandle {
+    stop_tx: Option<std::sync::mpsc::Sender<Duration>>,
+    thread: Option<std::thread::JoinHandle<Vec<EmitError>>>,
+}
+
+#[cfg(feature = "thread")]
+impl ThreadEmissionHandle {
+    /// Stop background emission without waiting for the final flush.
+    pub fn stop(self) {
+        // Drop sends the stop signal
+    }
+
+    /// Stop background emission, emit one final snapshot to every output,
+    /// and wait for it to be delivered.
+    ///
+    /// Waits at most [`DEFAULT_SHUTDOWN_TIMEOUT`] for queued snapshots to be
+    /// flushed. Returns the outputs that failed or timed out.
+    pub fn shutdown(self) -> Result<(), Vec<EmitError>> {
+        self.shutdown_timeout(DEFAULT_SHUTDOWN_TIMEOUT)
+    }
+
+    /// Like [`shutdown`](Self::shutdown), with a custom limit on how long
+    /// the final flush may take.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Result<(), Vec<EmitError>> {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            let _ = stop_tx.send(timeout);
+        }
+        let Some(thread) = self.thread.take() else {
+            return Ok(());
+        };
+
+        let errors = thread.join().unwrap_or_else(|_| {
+            vec![EmitError {
+                output: "emission thread".to_string(),
+                error: std::io::Error::other("emission thread panicked"),
+            }]
+        });
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[cfg(feature = "thread")]
+impl Drop for ThreadEmissionHandle {
+    fn drop(&mut self) {
+        if let Some(stop_tx) = self.stop_tx.take() {
+            let _ = stop_tx.send(DEFAULT_SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(feature = "thread")]
+impl std::fmt::Debug for ThreadEmissionHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadEmissionHandle")
+            .finish_non_exhaustive()
     }
 }
 
@@ -218,6 +811,13 @@ mod tests {
         assert_eq!(handle.name(), "test-module");
     }
 
+    #[test]
+    fn set_global_fails_once_installed() {
+        let global = Instrumentor::global();
+        assert!(Instrumentor::new().set_global().is_err());
+        assert!(std::ptr::eq(global, Instrumentor::global()));
+    }
+
     #[test]
     fn test_instrumentor_collect() {
         let instrumentor = Instrumentor::new();
@@ -265,6 +865,210 @@ mod tests {
         assert_eq!(instrumentor.outputs.len(), 1);
     }
 
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn emit_errors_are_reported_to_handler() {
+        use std::sync::Mutex;
+
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let (output, rx) = Output::channel(1);
+        drop(rx);
+
+        let seen = errors.clone();
+        let instrumentor = Instrumentor::builder()
+            .output(output)
+            .on_error(move |e| {
+                seen.lock()
+                    .unwrap()
+                    .push((e.output.clone(), e.error.kind()))
+            })
+            .build();
+
+        instrumentor.emit_now().await;
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(
+            *errors,
+            vec![("channel".to_string(), std::io::ErrorKind::BrokenPipe)]
+        );
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn self_metrics_track_emits_per_output() {
+        let (output, _rx) = Output::channel(1);
+        let instrumentor = Instrumentor::builder()
+            .output(output)
+            .self_metrics(true)
+            .build();
+        let handle = instrumentor.register("producer");
+        handle.record_write("events", 1);
+        handle.record_read("commands", 1);
+
+        // The second emit fails because nothing drains the channel
+        instrumentor.emit_now().await;
+        instrumentor.emit_now().await;
+
+        let snapshot = instrumentor.collect();
+        let sdk = &snapshot.modules[SELF_MODULE];
+        let emits = &sdk.writes["channel"];
+        assert_eq!(emits.count, 1);
+        assert_eq!(emits.errors, Some(1));
+        assert_eq!(emits.latency.as_ref().unwrap().count, 2);
+        assert!(sdk.reads.is_empty());
+        assert_eq!(sdk.tracked_topics, Some(2));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn shutdown_emits_final_snapshot() {
+        let (output, mut rx) = Output::channel(4);
+        let instrumentor = Instrumentor::builder()
+            .output(output)
+            .interval(Duration::from_secs(3600))
+            .build();
+        let handle = instrumentor.register("producer");
+
+        let emission = instrumentor.start();
+        // The first tick fires immediately
+        let first = rx.recv().await.unwrap();
+        assert!(first.modules["producer"].writes.is_empty());
+
+        handle.record_write("events", 3);
+        emission.shutdown().await.unwrap();
+
+        let last = rx.recv().await.unwrap();
+        assert_eq!(last.modules["producer"].writes["events"].count, 3);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn shutdown_returns_per_output_errors() {
+        let (ok, _rx) = Output::channel(4);
+        let (closed, rx) = Output::channel(4);
+        drop(rx);
+        let instrumentor = Instrumentor::builder()
+            .output(ok)
+            .output(closed)
+            .interval(Duration::from_secs(3600))
+            .build();
+
+        let errors = instrumentor.start().shutdown().await.unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].output, "channel");
+        assert_eq!(errors[0].error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn shutdown_times_out_on_undelivered_tcp() {
+        // Reserve a port, then close it so connects are refused
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let instrumentor = Instrumentor::builder()
+            .output(Output::tcp(addr.to_string()))
+            .interval(Duration::from_secs(3600))
+            .build();
+
+        let errors = instrumentor
+            .start()
+            .shutdown_timeout(Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].output, format!("tcp:{}", addr));
+        assert_eq!(errors[0].error.kind(), std::io::ErrorKind::TimedOut);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn dropping_handle_emits_final_snapshot() {
+        let (output, mut rx) = Output::channel(4);
+        let instrumentor = Instrumentor::builder()
+            .output(output)
+            .interval(Duration::from_secs(3600))
+            .build();
+        let handle = instrumentor.register("producer");
+
+        let emission = instrumentor.start();
+        rx.recv().await.unwrap();
+        handle.record_write("events", 1);
+        drop(emission);
+
+        let last = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(last.modules["producer"].writes["events"].count, 1);
+    }
+
+    #[cfg(feature = "thread")]
+    #[test]
+    fn thread_emitter_emits_and_flushes_on_shutdown() {
+        let (output, rx) = Output::sync_channel(4);
+        let instrumentor = Instrumentor::builder()
+            .output(output)
+            .interval(Duration::from_secs(3600))
+            .build();
+        let handle = instrumentor.register("producer");
+
+        let emission = instrumentor.start_thread();
+        // The first tick fires immediately
+        let first = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(first.modules["producer"].writes.is_empty());
+
+        handle.record_write("events", 3);
+        emission.shutdown().unwrap();
+
+        let last = rx.try_recv().unwrap();
+        assert_eq!(last.modules["producer"].writes["events"].count, 3);
+    }
+
+    #[cfg(feature = "thread")]
+    #[test]
+    fn thread_emitter_flushes_when_dropped() {
+        let (output, rx) = Output::sync_channel(4);
+        let instrumentor = Instrumentor::builder()
+            .output(output)
+            .interval(Duration::from_secs(3600))
+            .build();
+        let handle = instrumentor.register("producer");
+
+        let emission = instrumentor.start_thread();
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.record_write("events", 1);
+        drop(emission);
+
+        let last = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(last.modules["producer"].writes["events"].count, 1);
+    }
+
+    #[cfg(feature = "thread")]
+    #[test]
+    fn thread_emitter_reports_per_output_errors() {
+        let (ok, _rx) = Output::sync_channel(4);
+        let (closed, rx) = Output::sync_channel(4);
+        drop(rx);
+        let instrumentor = Instrumentor::builder()
+            .output(ok)
+            .output(closed)
+            .interval(Duration::from_secs(3600))
+            .build();
+
+        let errors = instrumentor.start_thread().shutdown().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].output, "channel");
+        assert_eq!(errors[0].error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn self_metrics_off_by_default() {
+        let instrumentor = Instrumentor::builder().build();
+        assert!(instrumentor.collect().modules.is_empty());
+    }
+
     #[test]
     fn default_interval_is_one_second() {
         let instrumentor = Instrumentor::new();
@@ -0,0 +1,251 @@
+//! Kafka snapshot publishing.
+//!
+//! Each snapshot is split by module and published as one record per module,
+//! keyed by the module name. Records for a module therefore always land on
+//! the same partition, and consumers can compact or filter on the key. The
+//! buswatch TUI's `--kafka` mode merges the records back into a snapshot.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use buswatch_sdk::{Instrumentor, Output};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let instrumentor = Instrumentor::builder()
+//!         .output(Output::kafka("localhost:9092", "buswatch.snapshots").unwrap())
+//!         .build();
+//!
+//!     let _emission = instrumentor.start();
+//! }
+//! ```
+
+use std::io;
+use std::time::Duration;
+
+use buswatch_types::Snapshot;
+use rdkafka::config::ClientConfig;
+use rdkafka::error::KafkaError;
+use rdkafka::message::{Header, OwnedHeaders};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+
+use crate::encoding::split_modules;
+pub use crate::encoding::Encoding;
+
+/// Configuration for publishing snapshots to a Kafka topic.
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    /// Comma-separated bootstrap brokers (e.g., "localhost:9092")
+    pub brokers: String,
+    /// Topic to publish to
+    pub topic: String,
+    /// Payload encoding
+    pub encoding: Encoding,
+    /// How long to wait for a record to be delivered
+    pub delivery_timeout: Duration,
+    /// Extra librdkafka producer properties (e.g., SASL settings)
+    pub properties: Vec<(String, String)>,
+}
+
+impl KafkaConfig {
+    /// Create a new builder for KafkaConfig.
+    pub fn builder() -> KafkaConfigBuilder {
+        KafkaConfigBuilder::default()
+    }
+}
+
+/// Builder for KafkaConfig.
+#[derive(Debug, Default)]
+pub struct KafkaConfigBuilder {
+    brokers: Option<String>,
+    topic: Option<String>,
+    encoding: Option<Encoding>,
+    delivery_timeout: Option<Duration>,
+    properties: Vec<(String, String)>,
+}
+
+impl KafkaConfigBuilder {
+    /// Set the bootstrap brokers.
+    pub fn brokers(mut self, brokers: impl Into<String>) -> Self {
+        self.brokers = Some(brokers.into());
+        self
+    }
+
+    /// Set the topic.
+    pub fn topic(mut self, topic: impl Into<String>) -> Self {
+        self.topic = Some(topic.into());
+        self
+    }
+
+    /// Set the payload encoding.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Set how long to wait for each record to be delivered.
+    pub fn delivery_timeout(mut self, timeout: Duration) -> Self {
+        self.delivery_timeout = Some(timeout);
+        self
+    }
+
+    /// Set an additional librdkafka producer property.
+    pub fn property(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.properties.push((key.into(), value.into()));
+        self
+    }
+
+    /// Build the KafkaConfig.
+    pub fn build(self) -> KafkaConfig {
+        KafkaConfig {
+            brokers: self.brokers.unwrap_or_else(|| "localhost:9092".to_string()),
+            topic: self
+                .topic
+                .unwrap_or_else(|| "caryatid.monitor.snapshot".to_string()),
+            encoding: self.encoding.unwrap_or_default(),
+            delivery_timeout: self.delivery_timeout.unwrap_or(Duration::from_secs(5)),
+            properties: self.properties,
+        }
+    }
+}
+
+/// Publishes snapshots to Kafka, one record per module.
+///
+/// librdkafka manages broker connections itself, reconnecting in the
+/// background; a publish made while the cluster is unreachable fails once
+/// the delivery timeout expires.
+pub struct KafkaPublisher {
+    config: KafkaConfig,
+    producer: FutureProducer,
+}
+
+impl KafkaPublisher {
+    /// Create a new publisher.
+    ///
+    /// Fails only if the configuration is rejected by librdkafka; no
+    /// connection is required at this point.
+    pub fn new(config: KafkaConfig) -> Result<Self, KafkaError> {
+        let mut client = ClientConfig::new();
+        client.set("bootstrap.servers", &config.brokers).set(
+            "message.timeout.ms",
+            config.delivery_timeout.as_millis().to_string(),
+        );
+        for (key, value) in &config.properties {
+            client.set(key, value);
+        }
+        let producer = client.create()?;
+
+        Ok(Self { config, producer })
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &KafkaConfig {
+        &self.config
+    }
+
+    /// Encode and publish a snapshot, one record per module.
+    pub async fn publish(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let content_type = self.config.encoding.content_type();
+
+        for (module, part) in split_modules(snapshot) {
+            let payload = self.config.encoding.encode(&part)?;
+            let headers = OwnedHeaders::new().insert(Header {
+                key: "content-type",
+                value: Some(content_type),
+            });
+            let record = FutureRecord::to(&self.config.topic)
+                .key(module)
+                .payload(&payload)
+                .timestamp(snapshot.timestamp_ms as i64)
+                .headers(headers);
+
+            self.producer
+                .send(record, Timeout::After(self.config.delivery_timeout))
+                .await
+                .map_err(|(e, _)| io::Error::other(e))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Debug for KafkaPublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KafkaPublisher")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_defaults() {
+        let config = KafkaConfig::builder().build();
+        assert_eq!(config.brokers, "localhost:9092");
+        assert_eq!(config.topic, "caryatid.monitor.snapshot");
+        assert_eq!(config.encoding, Encoding::Cbor);
+        assert_eq!(config.delivery_timeout, Duration::from_secs(5));
+        assert!(config.properties.is_empty());
+    }
+
+    #[test]
+    fn invalid_property_is_rejected() {
+        let config = KafkaConfig::builder()
+            .property("not.a.real.property", "1")
+            .build();
+        assert!(KafkaPublisher::new(config).is_err());
+    }
+
+    /// Publishes to a local single-node broker and reads the records back.
+    ///
+    /// Run with `cargo test --features kafka -- --ignored` and a broker at
+    /// `BUSWATCH_KAFKA_BROKERS` (default `localhost:9092`).
+    #[tokio::test]
+    #[ignore = "requires a running Kafka broker"]
+    async fn publishes_to_local_broker() {
+        use rdkafka::consumer::{Consumer, StreamConsumer};
+        use rdkafka::Message;
+
+        let brokers =
+            std::env::var("BUSWATCH_KAFKA_BROKERS").unwrap_or_else(|_| "localhost:9092".into());
+        let topic = format!("buswatch-test-{}", std::process::id());
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &brokers)
+            .set("group.id", &topic)
+            .set("auto.offset.reset", "earliest")
+            .create()
+            .unwrap();
+
+        let publisher = KafkaPublisher::new(
+            KafkaConfig::builder()
+                .brokers(&brokers)
+                .topic(&topic)
+                .build(),
+        )
+        .unwrap();
+
+        let snapshot = Snapshot::builder()
+            .timestamp_ms(1703160000000)
+            .module("producer", |m| m.write("orders", |w| w.count(10)))
+            .module("consumer", |m| m.read("orders", |r| r.count(7)))
+            .build();
+        publisher.publish(&snapshot).await.unwrap();
+
+        consumer.subscribe(&[&topic]).unwrap();
+        let mut received = Snapshot::new();
+        while received.len() < 2 {
+            let message = consumer.recv().await.unwrap();
+            let key = std::str::from_utf8(message.key().unwrap()).unwrap();
+            let part: Snapshot = minicbor_serde::from_slice(message.payload().unwrap()).unwrap();
+            assert_eq!(part.len(), 1);
+            received
+                .modules
+                .insert(key.to_string(), part.modules[key].clone());
+        }
+        assert_eq!(received.modules, snapshot.modules);
+    }
+}
@@ -0,0 +1,352 @@
+//! A `tracing-subscriber` layer that derives metrics from spans.
+//!
+//! Handlers that are already instrumented with `tracing` can be measured by
+//! adding three fields to their spans instead of calling
+//! [`ModuleHandle::record_read`] by hand:
+//!
+//! | Field | Meaning |
+//! |-------|---------|
+//! | `bus.module` | Module the span belongs to |
+//! | `bus.topic` | Topic being read from or written to |
+//! | `bus.op` | `"read"` or `"write"` |
+//!
+//! While such a span is open it counts as an in-flight operation, so it
+//! shows up as pending. When it closes, one read or write is recorded and
+//! the span's lifetime is added to the topic's latency histogram.
+//!
+//! Fields may be declared `tracing::field::Empty` and recorded later; the
+//! span is tracked from the moment all three are known.
+//!
+//! # Example
+//!
+//! ```rust
+//! use buswatch_sdk::Instrumentor;
+//! use tracing_subscriber::layer::SubscriberExt;
+//!
+//! let instrumentor = Instrumentor::new();
+//! let subscriber = tracing_subscriber::registry().with(instrumentor.layer());
+//!
+//! tracing::subscriber::with_default(subscriber, || {
+//!     let span = tracing::info_span!(
+//!         "handle_order",
+//!         bus.module = "order-processor",
+//!         bus.topic = "orders.new",
+//!         bus.op = "read",
+//!     );
+//!     let _entered = span.enter();
+//!     // ... handle the message ...
+//! });
+//!
+//! let snapshot = instrumentor.collect();
+//! assert_eq!(snapshot.modules["order-processor"].reads["orders.new"].count, 1);
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record};
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::handle::{ModuleHandle, PendingGuard};
+use crate::state::GlobalState;
+
+/// Span field naming the module.
+pub const MODULE_FIELD: &str = "bus.module";
+/// Span field naming the topic.
+pub const TOPIC_FIELD: &str = "bus.topic";
+/// Span field naming the operation, `"read"` or `"write"`.
+pub const OP_FIELD: &str = "bus.op";
+
+/// A `tracing-subscriber` layer that records buswatch metrics from spans
+/// carrying `bus.module`, `bus.topic` and `bus.op` fields.
+///
+/// Create one with [`Instrumentor::layer`](crate::Instrumentor::layer).
+/// Spans without a `bus.op` field are ignored.
+pub struct BuswatchLayer {
+    global: Arc<GlobalState>,
+}
+
+impl BuswatchLayer {
+    pub(crate) fn new(global: Arc<GlobalState>) -> Self {
+        Self { global }
+    }
+}
+
+impl fmt::Debug for BuswatchLayer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BuswatchLayer").finish_non_exhaustive()
+    }
+}
+
+/// Direction of a bus operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Read,
+    Write,
+}
+
+impl Op {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "read" => Some(Op::Read),
+            "write" => Some(Op::Write),
+            _ => None,
+        }
+    }
+}
+
+/// Bus fields seen on a span, stored in its extensions.
+#[derive(Default)]
+struct BusSpan {
+    module: Option<String>,
+    topic: Option<String>,
+    op: Option<Op>,
+    /// Set once all fields are known
+    active: Option<ActiveSpan>,
+}
+
+struct ActiveSpan {
+    handle: ModuleHandle,
+    topic: String,
+    op: Op,
+    // Dropped on close to clear pending and record latency
+    _guard: PendingGuard,
+}
+
+impl BusSpan {
+    /// Start tracking the span if all fields are now known.
+    fn activate(&mut self, global: &Arc<GlobalState>) {
+        if self.active.is_some() {
+            return;
+        }
+        let (Some(module), Some(topic), Some(op)) = (&self.module, &self.topic, self.op) else {
+            return;
+        };
+
+        // Retained, so the module isn't removed between spans
+        let handle = ModuleHandle::retained(global.clone(), module);
+        let guard = match op {
+            Op::Read => handle.start_read(topic),
+            Op::Write => handle.start_write(topic),
+        };
+        self.active = Some(ActiveSpan {
+            handle,
+            topic: topic.clone(),
+            op,
+            _guard: guard,
+        });
+    }
+
+    fn set(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            MODULE_FIELD => self.module = Some(value.to_string()),
+            TOPIC_FIELD => self.topic = Some(value.to_string()),
+            OP_FIELD => self.op = Op::parse(value),
+            _ => {}
+        }
+    }
+}
+
+impl Visit for BusSpan {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.set(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // Covers `%value` fields, whose Debug output is their Display output
+        if field.name().starts_with("bus.") {
+            self.set(field, &format!("{:?}", value));
+        }
+    }
+}
+
+impl<S> Layer<S> for BuswatchLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().fields().field(OP_FIELD).is_none() {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut bus = BusSpan::default();
+        attrs.record(&mut bus);
+        bus.activate(&self.global);
+        span.extensions_mut().insert(bus);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(bus) = extensions.get_mut::<BusSpan>() {
+            values.record(bus);
+            bus.activate(&self.global);
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(bus) = span.extensions_mut().remove::<BusSpan>() else {
+            return;
+        };
+
+        if let Some(active) = bus.active {
+            match active.op {
+                Op::Read => active.handle.record_read(&active.topic, 1),
+                Op::Write => active.handle.record_write(&active.topic, 1),
+            }
+            // The guard drops here, recording the span's lifetime as latency
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Instrumentor;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    fn with_layer<F: FnOnce()>(instrumentor: &Instrumentor, f: F) {
+        let subscriber = tracing_subscriber::registry().with(instrumentor.layer());
+        tracing::subscriber::with_default(subscriber, f);
+    }
+
+    #[test]
+    fn read_span_records_read_and_latency() {
+        let instrumentor = Instrumentor::new();
+
+        with_layer(&instrumentor, || {
+            for _ in 0..3 {
+                let _span = tracing::info_span!(
+                    "handle",
+                    bus.module = "consumer",
+                    bus.topic = "orders",
+                    bus.op = "read"
+                )
+                .entered();
+            }
+        });
+
+        let snapshot = instrumentor.collect();
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert_eq!(read.count, 3);
+        assert_eq!(read.latency.as_ref().unwrap().count, 3);
+        assert!(read.pending.is_none());
+    }
+
+    #[test]
+    fn span_modules_survive_remove_dropped_modules() {
+        let instrumentor = Instrumentor::builder().remove_dropped_modules(true).build();
+        let read = || {
+            let _span = tracing::info_span!(
+                "handle",
+                bus.module = "consumer",
+                bus.topic = "orders",
+                bus.op = "read"
+            )
+            .entered();
+        };
+
+        with_layer(&instrumentor, read);
+        instrumentor.collect();
+        instrumentor.collect();
+        with_layer(&instrumentor, read);
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["consumer"].reads["orders"].count, 2);
+    }
+
+    #[test]
+    fn write_span_records_write() {
+        let instrumentor = Instrumentor::new();
+
+        with_layer(&instrumentor, || {
+            let module = "producer";
+            let _span = tracing::info_span!(
+                "publish",
+                bus.module = %module,
+                bus.topic = "events",
+                bus.op = "write"
+            )
+            .entered();
+        });
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["producer"].writes["events"].count, 1);
+    }
+
+    #[test]
+    fn open_span_is_inflight() {
+        let instrumentor = Instrumentor::new();
+
+        with_layer(&instrumentor, || {
+            let span = tracing::info_span!(
+                "handle",
+                bus.module = "consumer",
+                bus.topic = "orders",
+                bus.op = "read"
+            );
+
+            let snapshot = instrumentor.collect();
+            let read = &snapshot.modules["consumer"].reads["orders"];
+            assert_eq!(read.inflight, Some(1));
+            assert!(read.pending.is_some());
+            assert_eq!(read.count, 0);
+
+            drop(span);
+        });
+
+        let snapshot = instrumentor.collect();
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert_eq!(read.inflight, None);
+        assert_eq!(read.count, 1);
+    }
+
+    #[test]
+    fn fields_recorded_later_activate_span() {
+        let instrumentor = Instrumentor::new();
+
+        with_layer(&instrumentor, || {
+            let span = tracing::info_span!(
+                "handle",
+                bus.module = "consumer",
+                bus.topic = tracing::field::Empty,
+                bus.op = "read"
+            );
+            assert!(instrumentor.collect().modules.is_empty());
+
+            span.record("bus.topic", "orders");
+        });
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["consumer"].reads["orders"].count, 1);
+    }
+
+    #[test]
+    fn spans_without_bus_fields_are_ignored() {
+        let instrumentor = Instrumentor::new();
+
+        with_layer(&instrumentor, || {
+            let _plain = tracing::info_span!("plain", user = "alice").entered();
+            let _bad_op = tracing::info_span!(
+                "handle",
+                bus.module = "consumer",
+                bus.topic = "orders",
+                bus.op = "delete"
+            )
+            .entered();
+        });
+
+        assert!(instrumentor.collect().modules.is_empty());
+    }
+}
@@ -27,7 +27,7 @@
 //!     handle.record_write("orders.processed", 1);
 //!
 //!     // Start background emission (non-blocking)
-//!     instrumentor.start();
+//!     let _emission = instrumentor.start();
 //!
 //!     // ... your application runs ...
 //! }
@@ -36,15 +36,37 @@
 //! ## Features
 //!
 //! - **Simple API**: Just `record_read()` and `record_write()`
-//! - **Multiple outputs**: File, TCP, or custom channel
-//! - **Background emission**: Automatic periodic snapshots
+//! - **Multiple outputs**: File, rotating JSONL, TCP, StatsD, custom channel, Prometheus scraping
+//!   or push (`prometheus-push` feature), or a message bus: AMQP, Kafka or NATS (`amqp`, `kafka`
+//!   and `nats` features)
+//! - **Background emission**: Automatic periodic snapshots, on tokio or a plain
+//!   thread (`thread` feature)
+//! - **Instrumented channels**: Drop-in wrappers for tokio `mpsc`, `broadcast` and `watch`
+//! - **Stream and Sink adapters**: Instrument any `futures` consumer or producer (`futures` feature)
+//! - **Tracing integration**: Derive metrics from `bus.*` span fields (`tracing` feature)
+//! - **Handler macro**: `#[handler]` instruments message handler functions (`macros` feature)
 //! - **Thread-safe**: Use from any thread or async task
 //! - **Low overhead**: Lock-free counters where possible
 
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(feature = "tokio")]
+pub mod channel;
+#[cfg(any(feature = "amqp", feature = "kafka", feature = "nats"))]
+mod encoding;
+pub mod file;
 mod handle;
 mod instrumentor;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "tracing")]
+pub mod layer;
+#[cfg(feature = "nats")]
+pub mod nats;
 mod output;
 mod state;
+pub mod statsd;
+pub mod tcp;
 
 #[cfg(feature = "otel")]
 pub mod otel;
@@ -52,12 +74,43 @@ pub mod otel;
 #[cfg(feature = "prometheus")]
 pub mod prometheus;
 
-pub use handle::ModuleHandle;
-pub use instrumentor::{Instrumentor, InstrumentorBuilder};
-pub use output::Output;
+#[cfg(feature = "prometheus-push")]
+pub mod push;
+
+#[cfg(feature = "futures")]
+pub mod stream;
+
+pub use handle::{ModuleHandle, PendingGuard};
+pub use instrumentor::{Instrumentor, InstrumentorBuilder, SELF_MODULE};
+
+#[cfg(feature = "tokio")]
+pub use instrumentor::EmissionHandle;
+
+#[cfg(feature = "thread")]
+pub use instrumentor::ThreadEmissionHandle;
+
+#[cfg(any(feature = "tokio", feature = "thread"))]
+pub use instrumentor::DEFAULT_SHUTDOWN_TIMEOUT;
+pub use output::{EmitError, Output};
+pub use state::OVERFLOW_TOPIC;
+
+#[cfg(any(feature = "amqp", feature = "kafka", feature = "nats"))]
+pub use encoding::Encoding;
 
 #[cfg(feature = "otel")]
-pub use otel::{OtelConfig, OtelExporter};
+pub use otel::{OtelConfig, OtelExporter, OtelProtocol};
+
+#[cfg(feature = "futures")]
+pub use stream::{InstrumentSinkExt, InstrumentStreamExt};
+
+#[cfg(feature = "tracing")]
+pub use layer::BuswatchLayer;
+
+#[cfg(feature = "macros")]
+pub use buswatch_macros::handler;
 
 // Re-export types for convenience
-pub use buswatch_types::{Microseconds, ModuleMetrics, ReadMetrics, Snapshot, WriteMetrics};
+pub use buswatch_types::{
+    BacklogSource, Clock, ManualClock, Microseconds, ModuleMetrics, ReadMetrics, Snapshot,
+    SystemClock, WriteMetrics,
+};
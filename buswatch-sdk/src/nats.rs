@@ -0,0 +1,226 @@
+//! NATS snapshot publishing.
+//!
+//! Each snapshot is split by module and published as one message per
+//! module on a fixed subject. NATS messages have no key, so the module name
+//! is carried in the `Buswatch-Module` header alongside `Content-Type`. The
+//! buswatch TUI's `--nats` mode merges the messages back into a snapshot.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use buswatch_sdk::{Instrumentor, Output};
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let instrumentor = Instrumentor::builder()
+//!         .output(Output::nats("nats://localhost:4222", "buswatch.snapshots"))
+//!         .build();
+//!
+//!     let _emission = instrumentor.start();
+//! }
+//! ```
+
+use std::io;
+
+use async_nats::HeaderMap;
+use buswatch_types::Snapshot;
+use tokio::sync::Mutex;
+
+use crate::encoding::split_modules;
+pub use crate::encoding::Encoding;
+
+/// Header carrying the module name of a published snapshot.
+pub const MODULE_HEADER: &str = "Buswatch-Module";
+
+/// Configuration for publishing snapshots to a NATS subject.
+#[derive(Debug, Clone)]
+pub struct NatsConfig {
+    /// Server URL (e.g., "nats://localhost:4222")
+    pub url: String,
+    /// Subject to publish to
+    pub subject: String,
+    /// Payload encoding
+    pub encoding: Encoding,
+}
+
+impl NatsConfig {
+    /// Create a new builder for NatsConfig.
+    pub fn builder() -> NatsConfigBuilder {
+        NatsConfigBuilder::default()
+    }
+}
+
+/// Builder for NatsConfig.
+#[derive(Debug, Default)]
+pub struct NatsConfigBuilder {
+    url: Option<String>,
+    subject: Option<String>,
+    encoding: Option<Encoding>,
+}
+
+impl NatsConfigBuilder {
+    /// Set the server URL.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the subject.
+    pub fn subject(mut self, subject: impl Into<String>) -> Self {
+        self.subject = Some(subject.into());
+        self
+    }
+
+    /// Set the payload encoding.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+
+    /// Build the NatsConfig.
+    pub fn build(self) -> NatsConfig {
+        NatsConfig {
+            url: self
+                .url
+                .unwrap_or_else(|| "nats://localhost:4222".to_string()),
+            subject: self
+                .subject
+                .unwrap_or_else(|| "caryatid.monitor.snapshot".to_string()),
+            encoding: self.encoding.unwrap_or_default(),
+        }
+    }
+}
+
+/// Publishes snapshots to NATS, one message per module.
+///
+/// The client connects on first publish. Once connected, async-nats
+/// reconnects on its own; if the initial connection fails it is retried on
+/// the next publish.
+pub struct NatsPublisher {
+    config: NatsConfig,
+    client: Mutex<Option<async_nats::Client>>,
+}
+
+impl NatsPublisher {
+    /// Create a new publisher. No connection is made until the first publish.
+    pub fn new(config: NatsConfig) -> Self {
+        Self {
+            config,
+            client: Mutex::new(None),
+        }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &NatsConfig {
+        &self.config
+    }
+
+    /// Encode and publish a snapshot, one message per module.
+    pub async fn publish(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let client = {
+            let mut client = self.client.lock().await;
+            match client.as_ref() {
+                Some(client) => client.clone(),
+                None => {
+                    let connected = async_nats::connect(&self.config.url)
+                        .await
+                        .map_err(io::Error::other)?;
+                    client.insert(connected).clone()
+                }
+            }
+        };
+
+        for (module, part) in split_modules(snapshot) {
+            let payload = self.config.encoding.encode(&part)?;
+            let mut headers = HeaderMap::new();
+            headers.insert("Content-Type", self.config.encoding.content_type());
+            headers.insert(MODULE_HEADER, module);
+
+            client
+                .publish_with_headers(self.config.subject.clone(), headers, payload.into())
+                .await
+                .map_err(io::Error::other)?;
+        }
+
+        // Surface connection problems now rather than on the next snapshot
+        client.flush().await.map_err(io::Error::other)
+    }
+}
+
+impl std::fmt::Debug for NatsPublisher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NatsPublisher")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_defaults() {
+        let config = NatsConfig::builder().build();
+        assert_eq!(config.url, "nats://localhost:4222");
+        assert_eq!(config.subject, "caryatid.monitor.snapshot");
+        assert_eq!(config.encoding, Encoding::Cbor);
+    }
+
+    #[tokio::test]
+    async fn publish_fails_without_server() {
+        let publisher = NatsPublisher::new(NatsConfig::builder().url("nats://127.0.0.1:1").build());
+        let snapshot = Snapshot::builder()
+            .module("producer", |m| m.write("orders", |w| w.count(1)))
+            .build();
+        assert!(publisher.publish(&snapshot).await.is_err());
+    }
+
+    /// Publishes to a local server and reads the messages back.
+    ///
+    /// Run with `cargo test --features nats -- --ignored` and a server at
+    /// `BUSWATCH_NATS_URL` (default `nats://localhost:4222`).
+    #[tokio::test]
+    #[ignore = "requires a running NATS server"]
+    async fn publishes_to_local_server() {
+        use futures::StreamExt;
+
+        let url = std::env::var("BUSWATCH_NATS_URL")
+            .unwrap_or_else(|_| "nats://localhost:4222".to_string());
+        let subject = format!("buswatch.test.{}", std::process::id());
+
+        let subscriber_client = async_nats::connect(&url).await.unwrap();
+        let mut subscriber = subscriber_client.subscribe(subject.clone()).await.unwrap();
+        subscriber_client.flush().await.unwrap();
+
+        let publisher = NatsPublisher::new(
+            NatsConfig::builder()
+                .url(&url)
+                .subject(&subject)
+                .encoding(Encoding::Json)
+                .build(),
+        );
+        let snapshot = Snapshot::builder()
+            .timestamp_ms(1703160000000)
+            .module("producer", |m| m.write("orders", |w| w.count(10)))
+            .module("consumer", |m| m.read("orders", |r| r.count(7)))
+            .build();
+        publisher.publish(&snapshot).await.unwrap();
+
+        let mut received = Snapshot::new();
+        while received.len() < 2 {
+            let message = subscriber.next().await.unwrap();
+            let headers = message.headers.unwrap();
+            assert_eq!(
+                headers.get("Content-Type").map(|v| v.as_str()),
+                Some("application/json")
+            );
+            let module = headers.get(MODULE_HEADER).unwrap().as_str().to_string();
+            let part: Snapshot = serde_json::from_slice(&message.payload).unwrap();
+            received
+                .modules
+                .insert(module.clone(), part.modules[&module].clone());
+        }
+        assert_eq!(received.modules, snapshot.modules);
+    }
+}
@@ -3,6 +3,36 @@
 //! This module provides OTLP export functionality, converting buswatch
 //! snapshots to OpenTelemetry metrics format.
 //!
+//! Message counts are exported as observable counters and pending ages as
+//! gauges in seconds. Handling latency goes into histograms. Names and
+//! attributes follow the OpenTelemetry messaging semantic conventions where
+//! one applies:
+//!
+//! | Metric | Instrument | Unit |
+//! |--------|------------|------|
+//! | `messaging.client.consumed.messages` | counter | `{message}` |
+//! | `messaging.client.sent.messages` | counter | `{message}` |
+//! | `messaging.process.duration` | histogram | `s` |
+//! | `messaging.client.operation.duration` | histogram | `s` |
+//! | `buswatch.read.errors` | counter | `{message}` |
+//! | `buswatch.read.backlog` | gauge | `{message}` |
+//! | `buswatch.read.pending`, `buswatch.write.pending` | gauge | `s` |
+//! | `buswatch.read.inflight`, `buswatch.write.inflight` | gauge | `{operation}` |
+//! | `buswatch.read.rate`, `buswatch.write.rate` | gauge | `{message}/s` |
+//!
+//! Every metric carries `messaging.destination.name` (the topic) and
+//! `buswatch.module`. Failed writes are counted in
+//! `messaging.client.sent.messages` with an `error.type` attribute.
+//!
+//! Snapshots carry latency as bucket counts, which are replayed into the
+//! histograms at each bucket's upper bound. To keep emitting cheap on busy
+//! topics, at most 1000 observations are replayed per histogram per snapshot,
+//! scaled down in proportion across the buckets. Histogram counts are then a
+//! sample that keeps the shape of the distribution, so on a topic handling
+//! more than that between snapshots the exported `_count` and `_sum` fall
+//! below the real number and total duration of operations. Use the message
+//! counters for exact totals.
+//!
 //! # Example
 //!
 //! ```rust,no_run
@@ -15,6 +45,8 @@
 //!     let otel_config = OtelConfig::builder()
 //!         .endpoint("http://localhost:4318")
 //!         .service_name("my-service")
+//!         .export_interval(Duration::from_secs(10))
+//!         .resource_attribute("deployment.environment.name", "staging")
 //!         .build();
 //!
 //!     let instrumentor = Instrumentor::builder()
@@ -25,26 +57,72 @@
 //!     let handle = instrumentor.register("my-module");
 //!     handle.record_read("events", 10);
 //!
-//!     instrumentor.start();
+//!     let _emission = instrumentor.start();
 //! }
 //! ```
 
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
-use opentelemetry::metrics::{Gauge, Meter, MeterProvider};
+use opentelemetry::metrics::{Histogram, Meter, MeterProvider};
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::Resource;
+use parking_lot::{Mutex, RwLock};
+
+use buswatch_types::{LatencyHistogram, ReadMetrics, Snapshot, WriteMetrics};
+
+/// Default interval between OTLP exports, matching the OpenTelemetry SDK.
+pub const DEFAULT_EXPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Most latency observations replayed into a histogram per snapshot.
+const MAX_REPLAYED: u64 = 1_000;
+
+const DESTINATION_NAME: &str = "messaging.destination.name";
+const OPERATION_NAME: &str = "messaging.operation.name";
+const ERROR_TYPE: &str = "error.type";
+const MODULE: &str = "buswatch.module";
+
+/// Transport used to reach the OTLP endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OtelProtocol {
+    /// Protobuf over HTTP, usually on port 4318.
+    #[default]
+    HttpProtobuf,
+    /// gRPC, usually on port 4317 (requires the `otel-grpc` feature).
+    ///
+    /// The exporter must be created from within a tokio runtime.
+    #[cfg(feature = "otel-grpc")]
+    Grpc,
+}
 
-use buswatch_types::Snapshot;
+impl OtelProtocol {
+    fn default_endpoint(self) -> &'static str {
+        match self {
+            OtelProtocol::HttpProtobuf => "http://localhost:4318",
+            #[cfg(feature = "otel-grpc")]
+            OtelProtocol::Grpc => "http://localhost:4317",
+        }
+    }
+}
 
 /// Configuration for OpenTelemetry export.
 #[derive(Debug, Clone)]
 pub struct OtelConfig {
     /// OTLP endpoint (e.g., "http://localhost:4318")
+    ///
+    /// For HTTP, `/v1/metrics` is appended.
     pub endpoint: String,
     /// Service name for metrics attribution
     pub service_name: String,
+    /// Transport to the endpoint
+    pub protocol: OtelProtocol,
+    /// How often metrics are exported
+    pub export_interval: Duration,
+    /// Additional resource attributes, e.g. `deployment.environment.name`
+    pub resource_attributes: Vec<(String, String)>,
 }
 
 impl OtelConfig {
@@ -59,10 +137,16 @@ impl OtelConfig {
 pub struct OtelConfigBuilder {
     endpoint: Option<String>,
     service_name: Option<String>,
+    protocol: OtelProtocol,
+    export_interval: Option<Duration>,
+    resource_attributes: Vec<(String, String)>,
 }
 
 impl OtelConfigBuilder {
     /// Set the OTLP endpoint.
+    ///
+    /// Defaults to `http://localhost:4318`, or `http://localhost:4317` for
+    /// gRPC.
     pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
         self.endpoint = Some(endpoint.into());
         self
@@ -74,17 +158,47 @@ impl OtelConfigBuilder {
         self
     }
 
+    /// Set the transport. Defaults to protobuf over HTTP.
+    pub fn protocol(mut self, protocol: OtelProtocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Set how often metrics are exported.
+    ///
+    /// Defaults to [`DEFAULT_EXPORT_INTERVAL`] (60 seconds). Exports use the
+    /// latest snapshot, so an interval shorter than the instrumentor's
+    /// emission interval only repeats values.
+    pub fn export_interval(mut self, interval: Duration) -> Self {
+        self.export_interval = Some(interval);
+        self
+    }
+
+    /// Add a resource attribute describing the process, such as
+    /// `service.namespace` or `deployment.environment.name`.
+    pub fn resource_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.resource_attributes.push((key.into(), value.into()));
+        self
+    }
+
     /// Build the OtelConfig.
     pub fn build(self) -> OtelConfig {
         OtelConfig {
             endpoint: self
                 .endpoint
-                .unwrap_or_else(|| "http://localhost:4318".to_string()),
+                .unwrap_or_else(|| self.protocol.default_endpoint().to_string()),
             service_name: self.service_name.unwrap_or_else(|| "buswatch".to_string()),
+            protocol: self.protocol,
+            export_interval: self.export_interval.unwrap_or(DEFAULT_EXPORT_INTERVAL),
+            resource_attributes: self.resource_attributes,
         }
     }
 }
 
+/// The most recently recorded snapshot, read by observable instruments when
+/// the SDK collects.
+type Latest = Arc<RwLock<Option<Snapshot>>>;
+
 /// OpenTelemetry exporter for buswatch metrics.
 ///
 /// This exporter converts buswatch Snapshots into OpenTelemetry metrics
@@ -92,35 +206,45 @@ impl OtelConfigBuilder {
 pub struct OtelExporter {
     meter: Meter,
     _provider: Arc<SdkMeterProvider>,
-    // Gauges for metrics
-    read_count: Gauge<u64>,
-    read_backlog: Gauge<u64>,
-    read_pending: Gauge<u64>,
-    read_rate: Gauge<f64>,
-    write_count: Gauge<u64>,
-    write_pending: Gauge<u64>,
-    write_rate: Gauge<f64>,
+    latest: Latest,
+    process_duration: Histogram<f64>,
+    operation_duration: Histogram<f64>,
+    /// Histograms from the previous snapshot, keyed by (module, topic, is_read)
+    previous: Mutex<HashMap<(String, String, bool), LatencyHistogram>>,
 }
 
 impl OtelExporter {
     /// Create a new OtelExporter with the given configuration.
     pub fn new(config: &OtelConfig) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         use opentelemetry_otlp::MetricExporter;
-        use opentelemetry_sdk::metrics::PeriodicReader;
-        use opentelemetry_sdk::Resource;
 
         // Build the OTLP exporter
-        let exporter = MetricExporter::builder()
-            .with_http()
-            .with_endpoint(format!("{}/v1/metrics", config.endpoint))
-            .build()?;
+        let exporter = match config.protocol {
+            OtelProtocol::HttpProtobuf => MetricExporter::builder()
+                .with_http()
+                .with_endpoint(format!("{}/v1/metrics", config.endpoint))
+                .build()?,
+            #[cfg(feature = "otel-grpc")]
+            OtelProtocol::Grpc => MetricExporter::builder()
+                .with_tonic()
+                .with_endpoint(config.endpoint.clone())
+                .build()?,
+        };
 
         // Create a periodic reader with the exporter
-        let reader = PeriodicReader::builder(exporter).build();
+        let reader = PeriodicReader::builder(exporter)
+            .with_interval(config.export_interval)
+            .build();
 
-        // Create the meter provider with service name resource
+        // Create the meter provider with service name and custom resource attributes
         let resource = Resource::builder()
             .with_service_name(config.service_name.clone())
+            .with_attributes(
+                config
+                    .resource_attributes
+                    .iter()
+                    .map(|(key, value)| KeyValue::new(key.clone(), value.clone())),
+            )
             .build();
 
         let provider = SdkMeterProvider::builder()
@@ -128,101 +252,86 @@ impl OtelExporter {
             .with_resource(resource)
             .build();
 
-        let meter = provider.meter("buswatch");
-        let provider = Arc::new(provider);
-
-        // Create gauges for all metrics
-        let read_count = meter
-            .u64_gauge("buswatch.read.count")
-            .with_description("Total messages read from a topic")
-            .build();
-
-        let read_backlog = meter
-            .u64_gauge("buswatch.read.backlog")
-            .with_description("Estimated message backlog (unread messages)")
-            .build();
+        Ok(Self::with_provider(provider))
+    }
 
-        let read_pending = meter
-            .u64_gauge("buswatch.read.pending")
-            .with_description("Number of pending read operations")
-            .build();
+    /// Register instruments with `provider`.
+    fn with_provider(provider: SdkMeterProvider) -> Self {
+        let meter = provider.meter("buswatch");
+        let latest = Latest::default();
 
-        let read_rate = meter
-            .f64_gauge("buswatch.read.rate")
-            .with_description("Read rate in messages per second")
-            .build();
+        register_read_instruments(&meter, &latest);
+        register_write_instruments(&meter, &latest);
 
-        let write_count = meter
-            .u64_gauge("buswatch.write.count")
-            .with_description("Total messages written to a topic")
-            .build();
+        let boundaries: Vec<f64> = crate::state::LATENCY_BOUNDS_US
+            .iter()
+            .map(|us| *us as f64 / 1_000_000.0)
+            .collect();
 
-        let write_pending = meter
-            .u64_gauge("buswatch.write.pending")
-            .with_description("Number of pending write operations")
+        let process_duration = meter
+            .f64_histogram("messaging.process.duration")
+            .with_unit("s")
+            .with_description("Time taken to handle a message read from a topic")
+            .with_boundaries(boundaries.clone())
             .build();
 
-        let write_rate = meter
-            .f64_gauge("buswatch.write.rate")
-            .with_description("Write rate in messages per second")
+        let operation_duration = meter
+            .f64_histogram("messaging.client.operation.duration")
+            .with_unit("s")
+            .with_description("Time taken to write a message to a topic")
+            .with_boundaries(boundaries)
             .build();
 
-        Ok(Self {
+        Self {
             meter,
-            _provider: provider,
-            read_count,
-            read_backlog,
-            read_pending,
-            read_rate,
-            write_count,
-            write_pending,
-            write_rate,
-        })
+            _provider: Arc::new(provider),
+            latest,
+            process_duration,
+            operation_duration,
+            previous: Mutex::new(HashMap::new()),
+        }
     }
 
     /// Record a snapshot as OpenTelemetry metrics.
+    ///
+    /// Counters and gauges report the latest snapshot at the next export.
+    /// Latency observations made since the previous snapshot are added to
+    /// the histograms.
     pub fn record(&self, snapshot: &Snapshot) {
+        let mut previous = self.previous.lock();
+
         for (module_name, module_metrics) in &snapshot.modules {
-            // Record read metrics
             for (topic, read_metrics) in &module_metrics.reads {
-                let attributes = [
-                    KeyValue::new("module", module_name.clone()),
-                    KeyValue::new("topic", topic.clone()),
-                ];
-
-                self.read_count.record(read_metrics.count, &attributes);
-
-                if let Some(backlog) = read_metrics.backlog {
-                    self.read_backlog.record(backlog, &attributes);
-                }
-
-                if let Some(pending) = read_metrics.pending {
-                    self.read_pending.record(pending.as_micros(), &attributes);
-                }
-
-                if let Some(rate) = read_metrics.rate {
-                    self.read_rate.record(rate, &attributes);
+                if let Some(latency) = &read_metrics.latency {
+                    let key = (module_name.clone(), topic.clone(), true);
+                    let attributes = attributes(module_name, topic);
+                    replay(
+                        &self.process_duration,
+                        latency,
+                        previous.get(&key),
+                        &attributes,
+                    );
+                    previous.insert(key, latency.clone());
                 }
             }
 
-            // Record write metrics
             for (topic, write_metrics) in &module_metrics.writes {
-                let attributes = [
-                    KeyValue::new("module", module_name.clone()),
-                    KeyValue::new("topic", topic.clone()),
-                ];
-
-                self.write_count.record(write_metrics.count, &attributes);
-
-                if let Some(pending) = write_metrics.pending {
-                    self.write_pending.record(pending.as_micros(), &attributes);
-                }
-
-                if let Some(rate) = write_metrics.rate {
-                    self.write_rate.record(rate, &attributes);
+                if let Some(latency) = &write_metrics.latency {
+                    let key = (module_name.clone(), topic.clone(), false);
+                    let mut attributes = attributes(module_name, topic).to_vec();
+                    attributes.push(KeyValue::new(OPERATION_NAME, "send"));
+                    replay(
+                        &self.operation_duration,
+                        latency,
+                        previous.get(&key),
+                        &attributes,
+                    );
+                    previous.insert(key, latency.clone());
                 }
             }
         }
+
+        *self.latest.write() = Some(snapshot.clone());
     }
 
     /// Get a reference to the meter for custom metrics.
@@ -238,3 +347,424 @@ impl std::fmt::Debug for OtelExporter {
             .finish()
     }
 }
+
+fn attributes(module: &str, topic: &str) -> [KeyValue; 2] {
+    [
+        KeyValue::new(DESTINATION_NAME, topic.to_string()),
+        KeyValue::new(MODULE, module.to_string()),
+    ]
+}
+
+/// Call `f` for every read topic in the latest snapshot.
+fn for_each_read(latest: &Latest, mut f: impl FnMut(&[KeyValue], &ReadMetrics)) {
+    if let Some(snapshot) = latest.read().as_ref() {
+        for (module, metrics) in &snapshot.modules {
+            for (topic, read) in &metrics.reads {
+                f(&attributes(module, topic), read);
+            }
+        }
+    }
+}
+
+/// Call `f` for every write topic in the latest snapshot.
+fn for_each_write(latest: &Latest, mut f: impl FnMut(&[KeyValue], &WriteMetrics)) {
+    if let Some(snapshot) = latest.read().as_ref() {
+        for (module, metrics) in &snapshot.modules {
+            for (topic, write) in &metrics.writes {
+                f(&attributes(module, topic), write);
+            }
+        }
+    }
+}
+
+fn register_read_instruments(meter: &Meter, latest: &Latest) {
+    let state = latest.clone();
+    meter
+        .u64_observable_counter("messaging.client.consumed.messages")
+        .with_unit("{message}")
+        .with_description("Messages read from a topic")
+        .with_callback(move |observer| {
+            for_each_read(&state, |attributes, read| {
+                observer.observe(read.count, attributes);
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .u64_observable_counter("buswatch.read.errors")
+        .with_unit("{message}")
+        .with_description("Messages read from a topic whose handling failed")
+        .with_callback(move |observer| {
+            for_each_read(&state, |attributes, read| {
+                if let Some(errors) = read.errors {
+                    observer.observe(errors, attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .u64_observable_gauge("buswatch.read.backlog")
+        .with_unit("{message}")
+        .with_description("Messages waiting to be read from a topic")
+        .with_callback(move |observer| {
+            for_each_read(&state, |attributes, read| {
+                if let Some(backlog) = read.backlog {
+                    observer.observe(backlog, attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .f64_observable_gauge("buswatch.read.pending")
+        .with_unit("s")
+        .with_description("How long the oldest outstanding read has been pending")
+        .with_callback(move |observer| {
+            for_each_read(&state, |attributes, read| {
+                if let Some(pending) = read.pending {
+                    observer.observe(pending.to_duration().as_secs_f64(), attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .u64_observable_gauge("buswatch.read.inflight")
+        .with_unit("{operation}")
+        .with_description("Reads currently being handled")
+        .with_callback(move |observer| {
+            for_each_read(&state, |attributes, read| {
+                if let Some(inflight) = read.inflight {
+                    observer.observe(inflight, attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .f64_observable_gauge("buswatch.read.rate")
+        .with_unit("{message}/s")
+        .with_description("Read rate in messages per second")
+        .with_callback(move |observer| {
+            for_each_read(&state, |attributes, read| {
+                if let Some(rate) = read.rate {
+                    observer.observe(rate, attributes);
+                }
+            });
+        })
+        .build();
+}
+
+fn register_write_instruments(meter: &Meter, latest: &Latest) {
+    let state = latest.clone();
+    meter
+        .u64_observable_counter("messaging.client.sent.messages")
+        .with_unit("{message}")
+        .with_description("Messages written to a topic, with error.type set for failed writes")
+        .with_callback(move |observer| {
+            for_each_write(&state, |attributes, write| {
+                observer.observe(write.count, attributes);
+                if let Some(errors) = write.errors {
+                    let mut attributes = attributes.to_vec();
+                    attributes.push(KeyValue::new(ERROR_TYPE, "_OTHER"));
+                    observer.observe(errors, &attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .f64_observable_gauge("buswatch.write.pending")
+        .with_unit("s")
+        .with_description("How long the oldest outstanding write has been pending")
+        .with_callback(move |observer| {
+            for_each_write(&state, |attributes, write| {
+                if let Some(pending) = write.pending {
+                    observer.observe(pending.to_duration().as_secs_f64(), attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .u64_observable_gauge("buswatch.write.inflight")
+        .with_unit("{operation}")
+        .with_description("Writes currently in progress")
+        .with_callback(move |observer| {
+            for_each_write(&state, |attributes, write| {
+                if let Some(inflight) = write.inflight {
+                    observer.observe(inflight, attributes);
+                }
+            });
+        })
+        .build();
+
+    let state = latest.clone();
+    meter
+        .f64_observable_gauge("buswatch.write.rate")
+        .with_unit("{message}/s")
+        .with_description("Write rate in messages per second")
+        .with_callback(move |observer| {
+            for_each_write(&state, |attributes, write| {
+                if let Some(rate) = write.rate {
+                    observer.observe(rate, attributes);
+                }
+            });
+        })
+        .build();
+}
+
+/// Record the observations `current` gained since `previous` into
+/// `histogram`.
+///
+/// Snapshots only carry bucket counts, so each observation is replayed at
+/// its bucket's upper bound: bucket counts are exact and the sum is an upper
+/// estimate. Observations beyond the last bound are replayed at the mean of
+/// whatever sum the other buckets don't account for.
+///
+/// The SDK has no way to record a weighted observation, so at most
+/// [`MAX_REPLAYED`] are replayed per call, each bucket scaled down in
+/// proportion. Busy topics then export a sample with the true distribution.
+fn replay(
+    histogram: &Histogram<f64>,
+    current: &LatencyHistogram,
+    previous: Option<&LatencyHistogram>,
+    attributes: &[KeyValue],
+) {
+    // A histogram with different buckets or fewer observations has been
+    // reset, so everything in it is new
+    let previous = previous.filter(|p| p.bounds == current.bounds && p.count <= current.count);
+    let delta = |i: usize| {
+        let before = previous.and_then(|p| p.counts.get(i)).copied().unwrap_or(0);
+        current
+            .counts
+            .get(i)
+            .copied()
+            .unwrap_or(0)
+            .saturating_sub(before)
+    };
+
+    let total: u64 = (0..=current.bounds.len()).map(delta).sum();
+    let scale = (MAX_REPLAYED as f64 / total.max(1) as f64).min(1.0);
+    // Every bucket that gained observations keeps at least one
+    let replays = |n: u64| match n {
+        0 => 0,
+        n => ((n as f64 * scale).round() as u64).max(1),
+    };
+
+    let mut accounted_us = 0u64;
+    for (i, bound) in current.bounds.iter().enumerate() {
+        let n = delta(i);
+        let seconds = bound.to_duration().as_secs_f64();
+        for _ in 0..replays(n) {
+            histogram.record(seconds, attributes);
+        }
+        accounted_us = accounted_us.saturating_add(n.saturating_mul(bound.as_micros()));
+    }
+
+    let overflow = delta(current.bounds.len());
+    let sum_us = current
+        .sum
+        .as_micros()
+        .saturating_sub(previous.map_or(0, |p| p.sum.as_micros()));
+    if let Some(mean_us) = sum_us.saturating_sub(accounted_us).checked_div(overflow) {
+        let last_us = current.bounds.last().map_or(0, |b| b.as_micros());
+        let seconds = Duration::from_micros(mean_us.max(last_us)).as_secs_f64();
+        for _ in 0..replays(overflow) {
+            histogram.record(seconds, attributes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buswatch_types::Microseconds;
+    use opentelemetry_sdk::metrics::data::{AggregatedMetrics, MetricData, ResourceMetrics};
+    use opentelemetry_sdk::metrics::InMemoryMetricExporter;
+
+    fn test_exporter() -> (OtelExporter, SdkMeterProvider, InMemoryMetricExporter) {
+        let memory = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(memory.clone()).build())
+            .build();
+        (
+            OtelExporter::with_provider(provider.clone()),
+            provider,
+            memory,
+        )
+    }
+
+    fn exported(provider: &SdkMeterProvider, memory: &InMemoryMetricExporter) -> ResourceMetrics {
+        provider.force_flush().unwrap();
+        memory.get_finished_metrics().unwrap().pop().unwrap()
+    }
+
+    fn find<'a>(
+        metrics: &'a ResourceMetrics,
+        name: &str,
+    ) -> &'a opentelemetry_sdk::metrics::data::Metric {
+        metrics
+            .scope_metrics()
+            .flat_map(|scope| scope.metrics())
+            .find(|metric| metric.name() == name)
+            .unwrap_or_else(|| panic!("{} not exported", name))
+    }
+
+    fn has_attribute<'a>(
+        mut attributes: impl Iterator<Item = &'a KeyValue>,
+        key: &str,
+        value: &str,
+    ) -> bool {
+        attributes.any(|kv| kv.key.as_str() == key && kv.value.as_str() == value)
+    }
+
+    fn snapshot() -> Snapshot {
+        let mut latency = LatencyHistogram::new(vec![
+            Microseconds::from_millis(1),
+            Microseconds::from_millis(10),
+        ]);
+        latency.record(Microseconds::from_micros(500));
+        latency.record(Microseconds::from_millis(5));
+        latency.record(Microseconds::from_millis(5));
+
+        Snapshot::builder()
+            .module("consumer", |m| {
+                m.read("orders", |r| {
+                    r.count(100)
+                        .backlog(7)
+                        .pending(Duration::from_millis(1500))
+                        .latency(latency.clone())
+                })
+            })
+            .module("producer", |m| {
+                m.write("orders", |w| w.count(107).errors(2))
+            })
+            .build()
+    }
+
+    #[test]
+    fn config_defaults_follow_protocol() {
+        let config = OtelConfig::builder().build();
+        assert_eq!(config.endpoint, "http://localhost:4318");
+        assert_eq!(config.protocol, OtelProtocol::HttpProtobuf);
+        assert_eq!(config.export_interval, DEFAULT_EXPORT_INTERVAL);
+        assert!(config.resource_attributes.is_empty());
+
+        let config = OtelConfig::builder()
+            .export_interval(Duration::from_secs(5))
+            .resource_attribute("service.namespace", "shop")
+            .build();
+        assert_eq!(config.export_interval, Duration::from_secs(5));
+        assert_eq!(
+            config.resource_attributes,
+            vec![("service.namespace".to_string(), "shop".to_string())]
+        );
+    }
+
+    #[test]
+    fn counts_are_monotonic_sums() {
+        let (exporter, provider, memory) = test_exporter();
+        exporter.record(&snapshot());
+        let metrics = exported(&provider, &memory);
+
+        let consumed = find(&metrics, "messaging.client.consumed.messages");
+        assert_eq!(consumed.unit(), "{message}");
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = consumed.data() else {
+            panic!("consumed messages should be a u64 sum");
+        };
+        assert!(sum.is_monotonic());
+        let point = sum.data_points().next().unwrap();
+        assert_eq!(point.value(), 100);
+        assert!(has_attribute(
+            point.attributes(),
+            DESTINATION_NAME,
+            "orders"
+        ));
+        assert!(has_attribute(point.attributes(), MODULE, "consumer"));
+        assert!(!point
+            .attributes()
+            .any(|kv| kv.key.as_str() == "messaging.consumer.group.name"));
+
+        let sent = find(&metrics, "messaging.client.sent.messages");
+        let AggregatedMetrics::U64(MetricData::Sum(sum)) = sent.data() else {
+            panic!("sent messages should be a u64 sum");
+        };
+        let failed = sum
+            .data_points()
+            .find(|p| has_attribute(p.attributes(), ERROR_TYPE, "_OTHER"))
+            .unwrap();
+        assert_eq!(failed.value(), 2);
+    }
+
+    #[test]
+    fn pending_is_a_gauge_in_seconds() {
+        let (exporter, provider, memory) = test_exporter();
+        exporter.record(&snapshot());
+        let metrics = exported(&provider, &memory);
+
+        let pending = find(&metrics, "buswatch.read.pending");
+        assert_eq!(pending.unit(), "s");
+        let AggregatedMetrics::F64(MetricData::Gauge(gauge)) = pending.data() else {
+            panic!("pending should be an f64 gauge");
+        };
+        assert_eq!(gauge.data_points().next().unwrap().value(), 1.5);
+    }
+
+    #[test]
+    fn latency_is_exported_as_histogram_deltas() {
+        let (exporter, provider, memory) = test_exporter();
+        let first = snapshot();
+        exporter.record(&first);
+        // The same cumulative histogram again adds nothing
+        exporter.record(&first);
+        let metrics = exported(&provider, &memory);
+
+        let duration = find(&metrics, "messaging.process.duration");
+        assert_eq!(duration.unit(), "s");
+        let AggregatedMetrics::F64(MetricData::Histogram(histogram)) = duration.data() else {
+            panic!("process duration should be an f64 histogram");
+        };
+        let point = histogram.data_points().next().unwrap();
+        assert_eq!(point.count(), 3);
+        assert!(has_attribute(
+            point.attributes(),
+            DESTINATION_NAME,
+            "orders"
+        ));
+    }
+
+    #[test]
+    fn busy_histograms_replay_a_bounded_sample() {
+        let (exporter, provider, memory) = test_exporter();
+        let mut latency = LatencyHistogram::new(vec![
+            Microseconds::from_millis(1),
+            Microseconds::from_millis(10),
+        ]);
+        latency.counts = vec![900_000, 100_000, 1];
+        latency.count = 1_000_001;
+        let snapshot = Snapshot::builder()
+            .module("consumer", |m| {
+                m.read("orders", |r| r.count(1_000_001).latency(latency))
+            })
+            .build();
+        exporter.record(&snapshot);
+        let metrics = exported(&provider, &memory);
+
+        let duration = find(&metrics, "messaging.process.duration");
+        let AggregatedMetrics::F64(MetricData::Histogram(histogram)) = duration.data() else {
+            panic!("process duration should be an f64 histogram");
+        };
+        let point = histogram.data_points().next().unwrap();
+        // 900 + 100, plus one for the lone overflow
+        assert_eq!(point.count(), 1_001);
+    }
+}
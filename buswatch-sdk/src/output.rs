@@ -1,11 +1,23 @@
 //! Output backends for emitting snapshots.
 
 use std::path::PathBuf;
+use std::sync::Arc;
 
+#[cfg(any(feature = "tokio", feature = "thread"))]
 use buswatch_types::Snapshot;
 
-#[cfg(any(feature = "otel", feature = "prometheus"))]
-use std::sync::Arc;
+use crate::file::{JsonlConfig, JsonlOutput};
+use crate::statsd::{StatsdConfig, StatsdFlavor, StatsdOutput};
+use crate::tcp::{TcpConfig, TcpOutput};
+
+#[cfg(feature = "amqp")]
+use crate::amqp::{AmqpConfig, AmqpPublisher};
+
+#[cfg(feature = "kafka")]
+use crate::kafka::{KafkaConfig, KafkaPublisher};
+
+#[cfg(feature = "nats")]
+use crate::nats::{NatsConfig, NatsPublisher};
 
 #[cfg(feature = "otel")]
 use crate::otel::{OtelConfig, OtelExporter};
@@ -13,6 +25,9 @@ use crate::otel::{OtelConfig, OtelExporter};
 #[cfg(feature = "prometheus")]
 use crate::prometheus::{PrometheusConfig, PrometheusExporter};
 
+#[cfg(feature = "prometheus-push")]
+use crate::push::{PushConfig, PushOutput};
+
 /// Output destination for snapshots.
 ///
 /// Configure where the instrumentor should emit snapshots.
@@ -20,13 +35,26 @@ use crate::prometheus::{PrometheusConfig, PrometheusExporter};
 pub enum Output {
     /// Write snapshots to a JSON file.
     ///
-    /// The file is overwritten with each snapshot.
+    /// The file is replaced with each snapshot, by writing a temporary file
+    /// and renaming it into place.
     File(PathBuf),
 
+    /// Append snapshots to a JSONL file, with optional rotation.
+    ///
+    /// Use `Output::jsonl()` or `Output::jsonl_with_config()` to create this variant.
+    Jsonl(Arc<JsonlOutput>),
+
     /// Send snapshots to a TCP server.
     ///
-    /// Each snapshot is sent as a newline-delimited JSON message.
-    Tcp(String),
+    /// Each snapshot is sent as a newline-delimited JSON message over a
+    /// persistent, reconnecting connection. Use `Output::tcp()` or
+    /// `Output::tcp_with_config()` to create this variant.
+    Tcp(Arc<TcpOutput>),
+
+    /// Send metrics to a StatsD daemon or DogStatsD agent over UDP.
+    ///
+    /// Use `Output::statsd()` or `Output::statsd_with_config()` to create this variant.
+    Statsd(Arc<StatsdOutput>),
 
     /// Send snapshots through a channel.
     ///
@@ -34,6 +62,12 @@ pub enum Output {
     #[cfg(feature = "tokio")]
     Channel(tokio::sync::mpsc::Sender<Snapshot>),
 
+    /// Send snapshots through a `std::sync::mpsc` channel.
+    ///
+    /// Use `Output::sync_channel()` to create this variant and get the receiver.
+    #[cfg(feature = "thread")]
+    SyncChannel(std::sync::mpsc::SyncSender<Snapshot>),
+
     /// Export snapshots as OpenTelemetry metrics via OTLP.
     ///
     /// Use `Output::otel()` to create this variant.
@@ -45,6 +79,30 @@ pub enum Output {
     /// Use `Output::prometheus()` to create this variant.
     #[cfg(feature = "prometheus")]
     Prometheus(Arc<PrometheusExporter>),
+
+    /// Push metrics to a Prometheus Pushgateway or remote-write receiver.
+    ///
+    /// Use `Output::prometheus_push()` to create this variant.
+    #[cfg(feature = "prometheus-push")]
+    PrometheusPush(Arc<PushOutput>),
+
+    /// Publish snapshots to an AMQP (RabbitMQ) exchange.
+    ///
+    /// Use `Output::amqp()` or `Output::amqp_with_config()` to create this variant.
+    #[cfg(feature = "amqp")]
+    Amqp(Arc<AmqpPublisher>),
+
+    /// Publish snapshots to a Kafka topic, one record per module.
+    ///
+    /// Use `Output::kafka()` or `Output::kafka_with_config()` to create this variant.
+    #[cfg(feature = "kafka")]
+    Kafka(Arc<KafkaPublisher>),
+
+    /// Publish snapshots to a NATS subject, one message per module.
+    ///
+    /// Use `Output::nats()` or `Output::nats_with_config()` to create this variant.
+    #[cfg(feature = "nats")]
+    Nats(Arc<NatsPublisher>),
 }
 
 impl Output {
@@ -61,8 +119,34 @@ impl Output {
         Output::File(path.into())
     }
 
+    /// Create a JSONL output that appends each snapshot as a line.
+    ///
+    /// The file grows without bound; use
+    /// [`jsonl_with_config`](Self::jsonl_with_config) to rotate it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::Output;
+    ///
+    /// let output = Output::jsonl("history.jsonl");
+    /// ```
+    pub fn jsonl(path: impl Into<PathBuf>) -> Self {
+        Self::jsonl_with_config(JsonlConfig::builder().path(path).build())
+    }
+
+    /// Create a JSONL output from a full configuration, e.g. to rotate by
+    /// size or age and compress rotated files.
+    pub fn jsonl_with_config(config: JsonlConfig) -> Self {
+        Output::Jsonl(Arc::new(JsonlOutput::new(config)))
+    }
+
     /// Create a TCP output.
     ///
+    /// The connection is opened on the first emit and kept open. If it
+    /// drops, it is re-established with backoff while up to 16 snapshots
+    /// are buffered, dropping the oldest beyond that.
+    ///
     /// # Example
     ///
     /// ```rust
@@ -71,7 +155,47 @@ impl Output {
     /// let output = Output::tcp("localhost:9090");
     /// ```
     pub fn tcp(addr: impl Into<String>) -> Self {
-        Output::Tcp(addr.into())
+        Self::tcp_with_config(TcpConfig::builder().addr(addr).build())
+    }
+
+    /// Create a TCP output from a full configuration, e.g. to change the
+    /// queue size or timeouts.
+    pub fn tcp_with_config(config: TcpConfig) -> Self {
+        Output::Tcp(Arc::new(TcpOutput::new(config)))
+    }
+
+    /// Create a StatsD output.
+    ///
+    /// Counts are sent as counters of the increase since the previous
+    /// snapshot, backlog and pending as gauges, and latencies as timings.
+    /// [`StatsdFlavor::DogStatsd`] tags each metric with its module and
+    /// topic; plain StatsD puts them in the metric name.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::Output;
+    /// use buswatch_sdk::statsd::StatsdFlavor;
+    ///
+    /// let output = Output::statsd("127.0.0.1:8125", "myapp.bus", StatsdFlavor::DogStatsd);
+    /// ```
+    pub fn statsd(
+        addr: impl Into<String>,
+        prefix: impl Into<String>,
+        flavor: StatsdFlavor,
+    ) -> Self {
+        let config = StatsdConfig::builder()
+            .addr(addr)
+            .prefix(prefix)
+            .flavor(flavor)
+            .build();
+        Self::statsd_with_config(config)
+    }
+
+    /// Create a StatsD output from a full configuration, e.g. to add
+    /// constant tags or change the packet size.
+    pub fn statsd_with_config(config: StatsdConfig) -> Self {
+        Output::Statsd(Arc::new(StatsdOutput::new(config)))
     }
 
     /// Create a channel output and return both the output and receiver.
@@ -96,6 +220,27 @@ impl Output {
         (Output::Channel(tx), rx)
     }
 
+    /// Create a `std::sync::mpsc` channel output and return both the output
+    /// and receiver, for applications without an async runtime.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::Output;
+    ///
+    /// let (output, rx) = Output::sync_channel(16);
+    ///
+    /// // Later, receive snapshots
+    /// // for snapshot in rx {
+    /// //     println!("Got snapshot with {} modules", snapshot.len());
+    /// // }
+    /// ```
+    #[cfg(feature = "thread")]
+    pub fn sync_channel(buffer: usize) -> (Self, std::sync::mpsc::Receiver<Snapshot>) {
+        let (tx, rx) = std::sync::mpsc::sync_channel(buffer);
+        (Output::SyncChannel(tx), rx)
+    }
+
     /// Create an OpenTelemetry OTLP output.
     ///
     /// This exports metrics via OTLP to an OpenTelemetry collector or
@@ -144,28 +289,190 @@ impl Output {
         Output::Prometheus(Arc::new(exporter))
     }
 
+    /// Create an output that pushes metrics to a Prometheus Pushgateway, or
+    /// to a remote-write receiver with [`PushMode::RemoteWrite`](crate::push::PushMode).
+    ///
+    /// Use this for short-lived jobs and processes that Prometheus can't
+    /// scrape.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::Output;
+    /// use buswatch_sdk::push::PushConfig;
+    ///
+    /// let config = PushConfig::builder()
+    ///     .url("http://localhost:9091")
+    ///     .job("nightly-import")
+    ///     .build();
+    ///
+    /// let output = Output::prometheus_push(config);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend can't be initialized.
+    #[cfg(feature = "prometheus-push")]
+    pub fn prometheus_push(config: PushConfig) -> Self {
+        Output::PrometheusPush(Arc::new(PushOutput::new(config)))
+    }
+
+    /// Create an AMQP output publishing CBOR snapshots.
+    ///
+    /// The format matches what the buswatch TUI's `--subscribe` mode and
+    /// Caryatid consume. The connection is kept open between snapshots and
+    /// re-established with backoff if it drops.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::Output;
+    ///
+    /// let output = Output::amqp(
+    ///     "amqp://127.0.0.1:5672/%2f",
+    ///     "caryatid",
+    ///     "caryatid.monitor.snapshot",
+    /// );
+    /// ```
+    #[cfg(feature = "amqp")]
+    pub fn amqp(
+        url: impl Into<String>,
+        exchange: impl Into<String>,
+        routing_key: impl Into<String>,
+    ) -> Self {
+        let config = AmqpConfig::builder()
+            .url(url)
+            .exchange(exchange)
+            .routing_key(routing_key)
+            .build();
+        Self::amqp_with_config(config)
+    }
+
+    /// Create an AMQP output from a full configuration, e.g. to publish JSON.
+    #[cfg(feature = "amqp")]
+    pub fn amqp_with_config(config: AmqpConfig) -> Self {
+        Output::Amqp(Arc::new(AmqpPublisher::new(config)))
+    }
+
+    /// Create a Kafka output publishing CBOR snapshots.
+    ///
+    /// Each module is published as its own record, keyed by module name,
+    /// in the format the buswatch TUI's `--kafka` mode consumes.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// use buswatch_sdk::Output;
+    ///
+    /// let output = Output::kafka("localhost:9092", "buswatch.snapshots")
+    ///     .expect("Failed to create Kafka producer");
+    /// ```
+    #[cfg(feature = "kafka")]
+    pub fn kafka(
+        brokers: impl Into<String>,
+        topic: impl Into<String>,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let config = KafkaConfig::builder().brokers(brokers).topic(topic).build();
+        Self::kafka_with_config(config)
+    }
+
+    /// Create a Kafka output from a full configuration, e.g. to publish JSON.
+    #[cfg(feature = "kafka")]
+    pub fn kafka_with_config(
+        config: KafkaConfig,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let publisher = KafkaPublisher::new(config)?;
+        Ok(Output::Kafka(Arc::new(publisher)))
+    }
+
+    /// Create a NATS output publishing CBOR snapshots.
+    ///
+    /// Each module is published as its own message, with the module name in
+    /// the `Buswatch-Module` header, in the format the buswatch TUI's
+    /// `--nats` mode consumes. The connection is opened on first emit.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use buswatch_sdk::Output;
+    ///
+    /// let output = Output::nats("nats://localhost:4222", "buswatch.snapshots");
+    /// ```
+    #[cfg(feature = "nats")]
+    pub fn nats(url: impl Into<String>, subject: impl Into<String>) -> Self {
+        let config = NatsConfig::builder().url(url).subject(subject).build();
+        Self::nats_with_config(config)
+    }
+
+    /// Create a NATS output from a full configuration, e.g. to publish JSON.
+    #[cfg(feature = "nats")]
+    pub fn nats_with_config(config: NatsConfig) -> Self {
+        Output::Nats(Arc::new(NatsPublisher::new(config)))
+    }
+
+    /// A short description of this output, used in [`EmitError`] and as the
+    /// topic name in self-metrics (e.g. `tcp:localhost:9090`).
+    pub fn name(&self) -> String {
+        match self {
+            Output::File(path) => format!("file:{}", path.display()),
+            Output::Jsonl(output) => format!("jsonl:{}", output.config().path.display()),
+            Output::Tcp(output) => format!("tcp:{}", output.config().addr),
+            Output::Statsd(output) => format!("statsd:{}", output.config().addr),
+            #[cfg(feature = "tokio")]
+            Output::Channel(_) => "channel".to_string(),
+            #[cfg(feature = "thread")]
+            Output::SyncChannel(_) => "channel".to_string(),
+            #[cfg(feature = "otel")]
+            Output::Otel(_) => "otel".to_string(),
+            #[cfg(feature = "prometheus")]
+            Output::Prometheus(exporter) => format!("prometheus:{}", exporter.config().listen_addr),
+            #[cfg(feature = "prometheus-push")]
+            Output::PrometheusPush(output) => format!("prometheus-push:{}", output.config().url),
+            #[cfg(feature = "amqp")]
+            Output::Amqp(publisher) => format!(
+                "amqp:{}/{}",
+                publisher.config().exchange,
+                publisher.config().routing_key
+            ),
+            #[cfg(feature = "kafka")]
+            Output::Kafka(publisher) => format!("kafka:{}", publisher.config().topic),
+            #[cfg(feature = "nats")]
+            Output::Nats(publisher) => format!("nats:{}", publisher.config().subject),
+        }
+    }
+
     /// Emit a snapshot to this output.
+    ///
+    /// The [`Instrumentor`](crate::Instrumentor) calls this on every
+    /// interval. Call it directly to send snapshots that come from somewhere
+    /// else, such as a `buswatch-adapters` collector; start any server the
+    /// output needs with [`serve`](Self::serve) first.
     #[cfg(feature = "tokio")]
-    pub(crate) async fn emit(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+    pub async fn emit(&self, snapshot: &Snapshot) -> std::io::Result<()> {
         match self {
             Output::File(path) => {
                 let json = serde_json::to_string_pretty(snapshot)?;
-                tokio::fs::write(path, json).await?;
+                crate::file::write_atomic_async(path, json.as_bytes()).await?;
             }
-            Output::Tcp(addr) => {
-                use tokio::io::AsyncWriteExt;
-                use tokio::net::TcpStream;
-
-                // Try to connect and send (best effort)
-                if let Ok(mut stream) = TcpStream::connect(addr).await {
-                    let json = serde_json::to_string(snapshot)?;
-                    let _ = stream.write_all(json.as_bytes()).await;
-                    let _ = stream.write_all(b"\n").await;
-                }
+            Output::Jsonl(output) => {
+                let output = output.clone();
+                let snapshot = snapshot.clone();
+                tokio::task::spawn_blocking(move || output.append(&snapshot))
+                    .await
+                    .map_err(std::io::Error::other)??;
+            }
+            Output::Tcp(output) => {
+                output.send(snapshot).await?;
+            }
+            Output::Statsd(output) => {
+                output.send_async(snapshot).await?;
             }
             Output::Channel(tx) => {
-                // Best effort send (don't block if channel is full)
-                let _ = tx.try_send(snapshot.clone());
+                send_channel(tx, snapshot)?;
+            }
+            #[cfg(feature = "thread")]
+            Output::SyncChannel(tx) => {
+                send_sync(tx, snapshot)?;
             }
             #[cfg(feature = "otel")]
             Output::Otel(exporter) => {
@@ -177,7 +484,196 @@ impl Output {
                 // Update the latest snapshot for Prometheus scraping
                 exporter.record(snapshot);
             }
+            #[cfg(feature = "prometheus-push")]
+            Output::PrometheusPush(output) => {
+                output.push(snapshot).await?;
+            }
+            #[cfg(feature = "amqp")]
+            Output::Amqp(publisher) => {
+                publisher.publish(snapshot).await?;
+            }
+            #[cfg(feature = "kafka")]
+            Output::Kafka(publisher) => {
+                publisher.publish(snapshot).await?;
+            }
+            #[cfg(feature = "nats")]
+            Output::Nats(publisher) => {
+                publisher.publish(snapshot).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit a snapshot to this output from the `thread` emitter.
+    ///
+    /// Outputs that need a tokio runtime (Prometheus, Prometheus push, AMQP,
+    /// Kafka and NATS) fail with `Unsupported`.
+    #[cfg(feature = "thread")]
+    pub(crate) fn emit_blocking(&self, snapshot: &Snapshot) -> std::io::Result<()> {
+        match self {
+            Output::File(path) => {
+                let json = serde_json::to_string_pretty(snapshot)?;
+                crate::file::write_atomic(path, json.as_bytes())?;
+            }
+            Output::Jsonl(output) => {
+                output.append(snapshot)?;
+            }
+            Output::Tcp(output) => {
+                output.send_blocking(snapshot)?;
+            }
+            Output::Statsd(output) => {
+                output.send(snapshot)?;
+            }
+            Output::SyncChannel(tx) => {
+                send_sync(tx, snapshot)?;
+            }
+            #[cfg(feature = "tokio")]
+            Output::Channel(tx) => {
+                // try_send needs no runtime
+                send_channel(tx, snapshot)?;
+            }
+            #[cfg(feature = "otel")]
+            Output::Otel(exporter) => {
+                exporter.record(snapshot);
+            }
+            #[allow(unreachable_patterns)]
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    format!("{} requires the tokio emitter", self.name()),
+                ));
+            }
         }
         Ok(())
     }
+
+    /// Like [`flush`](Self::flush), blocking for at most `timeout`.
+    #[cfg(feature = "thread")]
+    pub(crate) fn flush_blocking(&self, timeout: std::time::Duration) -> std::io::Result<()> {
+        match self {
+            Output::Tcp(output) => output.flush_blocking(timeout),
+            _ => Ok(()),
+        }
+    }
+
+    /// Wait until previously emitted snapshots have been delivered.
+    ///
+    /// Only outputs that send in the background have anything to wait for;
+    /// the others deliver before `emit` returns.
+    #[cfg(feature = "tokio")]
+    pub async fn flush(&self) -> std::io::Result<()> {
+        match self {
+            Output::Tcp(output) => output.flush().await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Clean up after the final snapshot, when emission shuts down.
+    #[cfg(feature = "tokio")]
+    pub async fn close(&self) -> std::io::Result<()> {
+        match self {
+            #[cfg(feature = "prometheus-push")]
+            Output::PrometheusPush(output) => output.close().await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Start the server this output needs to be reachable, if any, stopping
+    /// it gracefully when `shutdown` completes.
+    ///
+    /// Errors the server runs into, such as failed TLS handshakes, are
+    /// passed to `on_error` and don't stop it. Only Prometheus outputs have
+    /// a server; for the others this returns `None`.
+    #[cfg(feature = "tokio")]
+    pub fn serve(
+        &self,
+        shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+        on_error: impl Fn(EmitError) + Send + Sync + 'static,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        match self {
+            #[cfg(feature = "prometheus")]
+            Output::Prometheus(exporter) => {
+                let name = self.name();
+                Some(
+                    exporter.start_server_with_error_handler(shutdown, move |error| {
+                        on_error(EmitError {
+                            output: name.clone(),
+                            error,
+                        })
+                    }),
+                )
+            }
+            _ => {
+                drop((shutdown, on_error));
+                None
+            }
+        }
+    }
+}
+
+/// A failure to emit a snapshot to one of the instrumentor's outputs, or of
+/// the server that makes it reachable (Prometheus).
+///
+/// Passed to the handler registered with
+/// [`InstrumentorBuilder::on_error`](crate::InstrumentorBuilder::on_error).
+#[derive(Debug)]
+pub struct EmitError {
+    /// The failing output, as returned by [`Output::name`]
+    pub output: String,
+    /// What went wrong
+    pub error: std::io::Error,
+}
+
+impl std::fmt::Display for EmitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "failed to emit to {}: {}", self.output, self.error)
+    }
+}
+
+impl std::error::Error for EmitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Send without blocking the emitter if the receiver is behind.
+#[cfg(feature = "tokio")]
+fn send_channel(
+    tx: &tokio::sync::mpsc::Sender<Snapshot>,
+    snapshot: &Snapshot,
+) -> std::io::Result<()> {
+    use tokio::sync::mpsc::error::TrySendError;
+
+    match tx.try_send(snapshot.clone()) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            "channel full, snapshot dropped",
+        )),
+        Err(TrySendError::Closed(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "channel receiver dropped",
+        )),
+    }
+}
+
+/// Send without blocking the emitter if the receiver is behind.
+#[cfg(feature = "thread")]
+fn send_sync(
+    tx: &std::sync::mpsc::SyncSender<Snapshot>,
+    snapshot: &Snapshot,
+) -> std::io::Result<()> {
+    use std::sync::mpsc::TrySendError;
+
+    match tx.try_send(snapshot.clone()) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::WouldBlock,
+            "channel full, snapshot dropped",
+        )),
+        Err(TrySendError::Disconnected(_)) => Err(std::io::Error::new(
+            std::io::ErrorKind::BrokenPipe,
+            "channel receiver dropped",
+        )),
+    }
 }
@@ -4,6 +4,11 @@
 //! Prometheus text-based exposition format, which can be scraped by Prometheus
 //! or compatible monitoring systems.
 //!
+//! Scrapers that ask for `application/openmetrics-text` in their `Accept`
+//! header receive OpenMetrics 1.0 instead, with `_total` counter samples,
+//! `_created` timestamps and a closing `# EOF`. The same server also serves
+//! the latest snapshot as JSON at [`SNAPSHOT_PATH`].
+//!
 //! ## Example
 //!
 //! ```rust,no_run
@@ -24,25 +29,59 @@
 //!     let handle = instrumentor.register("my-service");
 //!     handle.record_read("events", 100);
 //!
-//!     instrumentor.start();
+//!     let _emission = instrumentor.start();
 //!
 //!     // Metrics available at http://localhost:9090/metrics
 //! }
 //! ```
 
+use std::collections::HashMap;
 use std::convert::Infallible;
-use std::net::SocketAddr;
+use std::fmt::{Display, Write as _};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(feature = "prometheus-tls")]
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "prometheus-tls")]
+use std::time::SystemTime;
 
-use buswatch_types::Snapshot;
+use buswatch_types::{LatencyHistogram, Microseconds, Snapshot};
 use http_body_util::Full;
 use hyper::body::Bytes;
+use hyper::header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
+use hyper_util::rt::{TokioIo, TokioTimer};
 use parking_lot::RwLock;
-use tokio::net::TcpListener;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+#[cfg(feature = "prometheus-tls")]
+use tokio_rustls::TlsAcceptor;
+
+/// Path of the JSON snapshot endpoint.
+pub const SNAPSHOT_PATH: &str = "/api/snapshot";
+
+/// Upper bounds of the pending duration histogram buckets, in microseconds.
+const PENDING_BOUNDS_US: [u64; 12] = [
+    1_000,
+    10_000,
+    100_000,
+    250_000,
+    500_000,
+    1_000_000,
+    2_500_000,
+    5_000_000,
+    10_000_000,
+    30_000_000,
+    60_000_000,
+    300_000_000,
+];
 
 /// Configuration for Prometheus metrics endpoint.
 #[derive(Debug, Clone)]
@@ -53,6 +92,21 @@ pub struct PrometheusConfig {
     pub metrics_path: String,
     /// Optional namespace prefix for all metrics
     pub namespace: Option<String>,
+    /// Export histograms of the pending durations seen at each collection
+    pub pending_histogram: bool,
+    /// Credentials required for every endpoint except health checks
+    pub auth: Option<Auth>,
+    /// Networks allowed to connect; empty allows everyone
+    pub allowed_networks: Vec<IpNetwork>,
+    /// Maximum number of connections served at once
+    pub max_connections: Option<usize>,
+    /// Replace topic names with a keyed hash before exposing them
+    pub hash_topic_labels: bool,
+    /// Key mixed into topic hashes, so names can't be guessed by hashing candidates
+    pub topic_hash_key: Option<String>,
+    /// Serve HTTPS with this certificate and key
+    #[cfg(feature = "prometheus-tls")]
+    pub tls: Option<TlsConfig>,
 }
 
 impl Default for PrometheusConfig {
@@ -61,6 +115,14 @@ impl Default for PrometheusConfig {
             listen_addr: "0.0.0.0:9090".to_string(),
             metrics_path: "/metrics".to_string(),
             namespace: None,
+            pending_histogram: false,
+            auth: None,
+            allowed_networks: Vec::new(),
+            max_connections: None,
+            hash_topic_labels: false,
+            topic_hash_key: None,
+            #[cfg(feature = "prometheus-tls")]
+            tls: None,
         }
     }
 }
@@ -78,6 +140,14 @@ pub struct PrometheusConfigBuilder {
     listen_addr: Option<String>,
     metrics_path: Option<String>,
     namespace: Option<String>,
+    pending_histogram: bool,
+    auth: Option<Auth>,
+    allowed_networks: Vec<IpNetwork>,
+    max_connections: Option<usize>,
+    hash_topic_labels: bool,
+    topic_hash_key: Option<String>,
+    #[cfg(feature = "prometheus-tls")]
+    tls: Option<TlsConfig>,
 }
 
 impl PrometheusConfigBuilder {
@@ -99,6 +169,70 @@ impl PrometheusConfigBuilder {
         self
     }
 
+    /// Export `buswatch_read_pending_duration_seconds` and
+    /// `buswatch_write_pending_duration_seconds` histograms.
+    ///
+    /// Every snapshot adds the pending duration of each topic with an
+    /// outstanding operation, so the histograms show how long operations
+    /// stay pending over time rather than only at the latest scrape.
+    /// Disabled by default.
+    pub fn pending_histogram(mut self, enabled: bool) -> Self {
+        self.pending_histogram = enabled;
+        self
+    }
+
+    /// Require credentials for the metrics and snapshot endpoints.
+    ///
+    /// Health checks stay open so probes don't need them. Use with TLS, or
+    /// on a trusted network, as credentials are otherwise sent in the clear.
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Only accept connections from this network.
+    ///
+    /// Can be called repeatedly; connections from anywhere else are closed
+    /// without a response.
+    pub fn allow_network(mut self, network: IpNetwork) -> Self {
+        self.allowed_networks.push(network);
+        self
+    }
+
+    /// Limit how many connections are served at once.
+    ///
+    /// Further connections wait to be accepted until one closes.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Replace topic names with a hash in every exported label and in the
+    /// JSON snapshot.
+    ///
+    /// Hashes are stable across restarts, so series stay continuous.
+    pub fn hash_topic_labels(mut self, enabled: bool) -> Self {
+        self.hash_topic_labels = enabled;
+        self
+    }
+
+    /// Set a secret key for topic hashes.
+    ///
+    /// Without a key, anyone who can guess a topic name can confirm it by
+    /// hashing it. With one, topics are hashed with SipHash-2-4 under a key
+    /// derived from it, so hashes can't be computed without the key.
+    pub fn topic_hash_key(mut self, key: impl Into<String>) -> Self {
+        self.topic_hash_key = Some(key.into());
+        self
+    }
+
+    /// Serve HTTPS with a PEM certificate chain and private key.
+    #[cfg(feature = "prometheus-tls")]
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
     /// Build the PrometheusConfig.
     pub fn build(self) -> PrometheusConfig {
         PrometheusConfig {
@@ -107,24 +241,344 @@ impl PrometheusConfigBuilder {
                 .unwrap_or_else(|| "0.0.0.0:9090".to_string()),
             metrics_path: self.metrics_path.unwrap_or_else(|| "/metrics".to_string()),
             namespace: self.namespace,
+            pending_histogram: self.pending_histogram,
+            auth: self.auth,
+            allowed_networks: self.allowed_networks,
+            max_connections: self.max_connections,
+            hash_topic_labels: self.hash_topic_labels,
+            topic_hash_key: self.topic_hash_key,
+            #[cfg(feature = "prometheus-tls")]
+            tls: self.tls,
+        }
+    }
+}
+
+/// Credentials a scraper must present.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Auth {
+    /// HTTP basic authentication.
+    Basic {
+        /// Expected user name
+        username: String,
+        /// Expected password
+        password: String,
+    },
+    /// `Authorization: Bearer <token>`, as set by Prometheus'
+    /// `authorization` scrape option.
+    Bearer(String),
+}
+
+impl Auth {
+    /// Create basic authentication credentials.
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Auth::Basic {
+            username: username.into(),
+            password: password.into(),
+        }
+    }
+
+    /// Create a bearer token credential.
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Auth::Bearer(token.into())
+    }
+
+    fn scheme(&self) -> &'static str {
+        match self {
+            Auth::Basic { .. } => "Basic",
+            Auth::Bearer(_) => "Bearer",
+        }
+    }
+
+    /// The credentials part of the expected `Authorization` header.
+    fn credentials(&self) -> String {
+        match self {
+            Auth::Basic { username, password } => {
+                base64(format!("{}:{}", username, password).as_bytes(), false)
+            }
+            Auth::Bearer(token) => token.clone(),
+        }
+    }
+
+    /// Check an `Authorization` header value.
+    fn accepts(&self, header: Option<&str>) -> bool {
+        let Some((scheme, credentials)) = header.and_then(|h| h.trim().split_once(' ')) else {
+            return false;
+        };
+        scheme.eq_ignore_ascii_case(self.scheme())
+            & constant_time_eq(credentials.trim().as_bytes(), self.credentials().as_bytes())
+    }
+}
+
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Auth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"<redacted>")
+                .finish(),
+            Auth::Bearer(_) => f.debug_tuple("Bearer").field(&"<redacted>").finish(),
+        }
+    }
+}
+
+/// Compare secrets without returning early on the first difference.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// An IP network in CIDR notation, such as `10.0.0.0/8` or `::1/128`.
+///
+/// A bare address parses as a single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Create a network from an address and prefix length.
+    ///
+    /// Returns `None` if the prefix is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix <= bits).then_some(Self { addr, prefix })
+    }
+
+    /// Check whether `ip` is in this network.
+    ///
+    /// IPv4-mapped IPv6 addresses match IPv4 networks.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<IpAddr> for IpNetwork {
+    fn from(addr: IpAddr) -> Self {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        Self { addr, prefix }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = InvalidNetwork;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidNetwork(s.to_string());
+        match s.split_once('/') {
+            Some((addr, prefix)) => {
+                let addr = addr.parse().map_err(|_| invalid())?;
+                let prefix = prefix.parse().map_err(|_| invalid())?;
+                IpNetwork::new(addr, prefix).ok_or_else(invalid)
+            }
+            None => s
+                .parse::<IpAddr>()
+                .map(IpNetwork::from)
+                .map_err(|_| invalid()),
+        }
+    }
+}
+
+/// A string that is not a valid [`IpNetwork`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidNetwork(pub String);
+
+impl std::fmt::Display for InvalidNetwork {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid IP network: {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidNetwork {}
+
+/// Certificate and key files for serving HTTPS.
+///
+/// The files are checked for changes whenever a connection is accepted and
+/// reloaded if they were modified, so renewed certificates are picked up
+/// without a restart. If a reload fails, the previous certificate stays in
+/// use.
+#[cfg(feature = "prometheus-tls")]
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain, leaf first
+    pub cert_path: PathBuf,
+    /// PEM file with the private key
+    pub key_path: PathBuf,
+}
+
+#[cfg(feature = "prometheus-tls")]
+impl TlsConfig {
+    /// Create a TLS configuration from certificate and key paths.
+    pub fn new(cert_path: impl Into<PathBuf>, key_path: impl Into<PathBuf>) -> Self {
+        Self {
+            cert_path: cert_path.into(),
+            key_path: key_path.into(),
         }
     }
 }
 
+/// Exposition format served to a scraper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExpositionFormat {
+    /// Prometheus text format 0.0.4.
+    #[default]
+    Text,
+    /// OpenMetrics 1.0 text format.
+    OpenMetrics,
+}
+
+impl ExpositionFormat {
+    /// The `Content-Type` header for responses in this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExpositionFormat::Text => "text/plain; version=0.0.4; charset=utf-8",
+            ExpositionFormat::OpenMetrics => {
+                "application/openmetrics-text; version=1.0.0; charset=utf-8"
+            }
+        }
+    }
+
+    /// Pick a format from an `Accept` header.
+    ///
+    /// OpenMetrics is chosen when it is accepted with at least the quality
+    /// of the text format; anything else gets the text format.
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        let mut openmetrics = 0.0f32;
+        let mut text = 0.0f32;
+
+        for range in accept.unwrap_or("").split(',') {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or("").to_ascii_lowercase();
+            let quality = params
+                .find_map(|p| p.strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+
+            match media_type.as_str() {
+                "application/openmetrics-text" => openmetrics = openmetrics.max(quality),
+                "text/plain" | "text/*" | "*/*" => text = text.max(quality),
+                _ => {}
+            }
+        }
+
+        if openmetrics > 0.0 && openmetrics >= text {
+            ExpositionFormat::OpenMetrics
+        } else {
+            ExpositionFormat::Text
+        }
+    }
+}
+
+/// A topic seen by the exporter: (module, topic, is_read).
+type SeriesKey = (String, String, bool);
+
+/// State kept across snapshots for counter creation times and pending
+/// histograms.
+#[derive(Debug, Default)]
+struct Series {
+    pending_histogram: bool,
+    /// Unix milliseconds each counter started, and its last value
+    counters: HashMap<SeriesKey, (u64, u64)>,
+    /// Unix milliseconds each pending histogram started, and its buckets
+    pending: HashMap<SeriesKey, (u64, LatencyHistogram)>,
+}
+
+impl Series {
+    fn new(pending_histogram: bool) -> Self {
+        Self {
+            pending_histogram,
+            ..Self::default()
+        }
+    }
+
+    fn record(&mut self, snapshot: &Snapshot) {
+        let now = snapshot.timestamp_ms;
+        let mut seen = Vec::new();
+
+        for (module, metrics) in &snapshot.modules {
+            let reads = metrics
+                .reads
+                .iter()
+                .map(|(topic, read)| (topic, true, read.count, read.pending));
+            let writes = metrics
+                .writes
+                .iter()
+                .map(|(topic, write)| (topic, false, write.count, write.pending));
+
+            for (topic, is_read, count, pending) in reads.chain(writes) {
+                let key = (module.clone(), topic.clone(), is_read);
+
+                let counter = self.counters.entry(key.clone()).or_insert((now, count));
+                // A counter that went backwards was reset
+                if count < counter.1 {
+                    counter.0 = now;
+                }
+                counter.1 = count;
+
+                if self.pending_histogram {
+                    if let Some(pending) = pending.filter(|p| p.as_micros() > 0) {
+                        self.pending
+                            .entry(key.clone())
+                            .or_insert_with(|| (now, pending_histogram()))
+                            .1
+                            .record(pending);
+                    }
+                }
+
+                seen.push(key);
+            }
+        }
+
+        // Forget topics that are no longer reported, e.g. after eviction
+        let seen: std::collections::HashSet<_> = seen.into_iter().collect();
+        self.counters.retain(|key, _| seen.contains(key));
+        self.pending.retain(|key, _| seen.contains(key));
+    }
+}
+
+fn pending_histogram() -> LatencyHistogram {
+    LatencyHistogram::new(
+        PENDING_BOUNDS_US
+            .iter()
+            .map(|us| Microseconds::from_micros(*us))
+            .collect(),
+    )
+}
+
 /// Prometheus exporter that serves metrics over HTTP.
 #[derive(Debug)]
 pub struct PrometheusExporter {
     config: PrometheusConfig,
     /// Latest snapshot for serving
     latest_snapshot: Arc<RwLock<Option<Snapshot>>>,
+    series: Arc<RwLock<Series>>,
+    /// Listener bound by [`bind`](Self::bind), taken by the server
+    bound: parking_lot::Mutex<Option<Bound>>,
 }
 
 impl PrometheusExporter {
     /// Create a new Prometheus exporter.
     pub fn new(config: PrometheusConfig) -> Self {
+        let series = Series::new(config.pending_histogram);
         Self {
             config,
             latest_snapshot: Arc::new(RwLock::new(None)),
+            series: Arc::new(RwLock::new(series)),
+            bound: parking_lot::Mutex::new(None),
         }
     }
 
@@ -133,18 +587,46 @@ impl PrometheusExporter {
         &self.config
     }
 
+    /// Bind the listen address now, for the server started next to use.
+    ///
+    /// A server binds when it starts, in the background, so an invalid
+    /// address, a port in use or an unusable certificate only reaches the
+    /// error handler. Binding first reports them to the caller instead.
+    /// Must be called from within a tokio runtime.
+    pub async fn bind(&self) -> std::io::Result<()> {
+        let bound = Bound::new(&self.config).await?;
+        *self.bound.lock() = Some(bound);
+        Ok(())
+    }
+
+    /// Address of the listener bound by [`bind`](Self::bind), until a
+    /// server takes it.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.bound
+            .lock()
+            .as_ref()
+            .and_then(|bound| bound.listener.local_addr().ok())
+    }
+
     /// Update the latest snapshot.
     pub fn record(&self, snapshot: &Snapshot) {
-        *self.latest_snapshot.write() = Some(snapshot.clone());
+        let snapshot = if self.config.hash_topic_labels {
+            hash_topics(snapshot, self.config.topic_hash_key.as_deref())
+        } else {
+            snapshot.clone()
+        };
+        self.series.write().record(&snapshot);
+        *self.latest_snapshot.write() = Some(snapshot);
     }
 
     /// Get the current metrics in Prometheus exposition format.
     pub fn render(&self) -> String {
-        let snapshot = self.latest_snapshot.read();
-        match snapshot.as_ref() {
-            Some(s) => format_prometheus(s, self.config.namespace.as_deref()),
-            None => String::new(),
-        }
+        self.render_as(ExpositionFormat::Text)
+    }
+
+    /// Get the current metrics in the given exposition format.
+    pub fn render_as(&self, format: ExpositionFormat) -> String {
+        self.server_state().render(format)
     }
 
     /// Get a clone of the snapshot storage for sharing with the HTTP server.
@@ -152,6 +634,17 @@ impl PrometheusExporter {
         self.latest_snapshot.clone()
     }
 
+    fn server_state(&self) -> ServerState {
+        ServerState {
+            metrics_path: self.config.metrics_path.clone(),
+            namespace: self.config.namespace.clone(),
+            snapshot: self.latest_snapshot.clone(),
+            series: self.series.clone(),
+            auth: self.config.auth.clone().map(Arc::new),
+            allowed_networks: self.config.allowed_networks.clone().into(),
+        }
+    }
+
     /// Start the HTTP server to serve Prometheus metrics.
     ///
     /// This spawns a background task that listens for HTTP requests and serves
@@ -159,237 +652,802 @@ impl PrometheusExporter {
     ///
     /// Returns a `JoinHandle` that can be used to await the server or abort it.
     pub fn start_server(&self) -> tokio::task::JoinHandle<()> {
-        let listen_addr = self.config.listen_addr.clone();
-        let metrics_path = self.config.metrics_path.clone();
-        let namespace = self.config.namespace.clone();
-        let snapshot_storage = self.latest_snapshot.clone();
+        self.start_server_with_shutdown(std::future::pending())
+    }
+
+    /// Start the HTTP server, stopping gracefully when `shutdown` completes.
+    ///
+    /// On shutdown the server stops accepting connections, lets requests in
+    /// progress finish and closes idle connections. The returned handle
+    /// completes once every connection is closed. Errors are printed to
+    /// stderr; use
+    /// [`start_server_with_error_handler`](Self::start_server_with_error_handler)
+    /// to handle them yourself.
+    pub fn start_server_with_shutdown(
+        &self,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+    ) -> tokio::task::JoinHandle<()> {
+        self.start_server_with_error_handler(shutdown, |e| {
+            eprintln!("Prometheus server error: {}", e)
+        })
+    }
+
+    /// Start the HTTP server like
+    /// [`start_server_with_shutdown`](Self::start_server_with_shutdown),
+    /// passing errors to `on_error`.
+    ///
+    /// Besides failing to start, errors include failed accepts, TLS
+    /// handshakes and certificate reloads, and connections that end in an
+    /// error. Only failing to start stops the server; use
+    /// [`bind`](Self::bind) first to catch that up front.
+    ///
+    /// [`Instrumentor::start`](crate::Instrumentor::start) uses this to stop
+    /// the server after the final snapshot when its
+    /// [`EmissionHandle`](crate::EmissionHandle) shuts down, and to report
+    /// errors to its [`on_error`](crate::InstrumentorBuilder::on_error)
+    /// handler.
+    pub fn start_server_with_error_handler(
+        &self,
+        shutdown: impl Future<Output = ()> + Send + 'static,
+        on_error: impl Fn(std::io::Error) + Send + Sync + 'static,
+    ) -> tokio::task::JoinHandle<()> {
+        let bound = self.bound.lock().take();
+        let config = self.config.clone();
+        let state = self.server_state();
+        let on_error: ServerErrorHandler = Arc::new(on_error);
 
         tokio::spawn(async move {
-            if let Err(e) = run_server(listen_addr, metrics_path, namespace, snapshot_storage).await
-            {
-                eprintln!("Prometheus server error: {}", e);
-            }
+            let bound = match bound {
+                Some(bound) => bound,
+                None => match Bound::new(&config).await {
+                    Ok(bound) => bound,
+                    Err(e) => return on_error(e),
+                },
+            };
+            let server = Server {
+                state,
+                max_connections: config.max_connections,
+                idle_timeout: IDLE_TIMEOUT,
+                on_error,
+                #[cfg(feature = "prometheus-tls")]
+                tls: bound.tls,
+            };
+            server.run(bound.listener, shutdown).await
         })
     }
 }
 
-async fn run_server(
-    listen_addr: String,
+/// A listener for the server, with the certificate it serves.
+struct Bound {
+    listener: TcpListener,
+    #[cfg(feature = "prometheus-tls")]
+    tls: Option<Arc<TlsReloader>>,
+}
+
+impl Bound {
+    async fn new(config: &PrometheusConfig) -> std::io::Result<Self> {
+        let addr: SocketAddr = config.listen_addr.parse().map_err(|e| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid listen address {:?}: {}", config.listen_addr, e),
+            )
+        })?;
+        #[cfg(feature = "prometheus-tls")]
+        let tls = config
+            .tls
+            .clone()
+            .map(TlsReloader::new)
+            .transpose()?
+            .map(Arc::new);
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            #[cfg(feature = "prometheus-tls")]
+            tls,
+        })
+    }
+}
+
+impl std::fmt::Debug for Bound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bound")
+            .field("listener", &self.listener)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Replace every topic name in a snapshot with its hash.
+fn hash_topics(snapshot: &Snapshot, key: Option<&str>) -> Snapshot {
+    let mut hashed = snapshot.clone();
+    for metrics in hashed.modules.values_mut() {
+        metrics.reads = std::mem::take(&mut metrics.reads)
+            .into_iter()
+            .map(|(topic, read)| (hash_topic(&topic, key), read))
+            .collect();
+        metrics.writes = std::mem::take(&mut metrics.writes)
+            .into_iter()
+            .map(|(topic, write)| (hash_topic(&topic, key), write))
+            .collect();
+    }
+    hashed
+}
+
+/// SipHash-2-4 of the topic, as 16 hex digits, under a 128-bit key derived
+/// from `key`.
+///
+/// SipHash is a keyed pseudorandom function: without the key, known
+/// topic-hash pairs don't help compute the hash of another name. It is
+/// also stable across Rust versions and platforms, unlike the standard
+/// library's hasher, so exported series survive upgrades.
+fn hash_topic(topic: &str, key: Option<&str>) -> String {
+    let key = key.map_or((0, 0), |key| {
+        let derive = |domain: u8| siphash24((0, 0), &[&[domain], key.as_bytes()].concat());
+        (derive(0), derive(1))
+    });
+    format!("{:016x}", siphash24(key, topic.as_bytes()))
+}
+
+/// SipHash-2-4 of `data` under the key `(k0, k1)`.
+fn siphash24((k0, k1): (u64, u64), data: &[u8]) -> u64 {
+    fn round(v: &mut [u64; 4]) {
+        v[0] = v[0].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(13) ^ v[0];
+        v[0] = v[0].rotate_left(32);
+        v[2] = v[2].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(16) ^ v[2];
+        v[0] = v[0].wrapping_add(v[3]);
+        v[3] = v[3].rotate_left(21) ^ v[0];
+        v[2] = v[2].wrapping_add(v[1]);
+        v[1] = v[1].rotate_left(17) ^ v[2];
+        v[2] = v[2].rotate_left(32);
+    }
+    fn compress(v: &mut [u64; 4], m: u64) {
+        v[3] ^= m;
+        round(v);
+        round(v);
+        v[0] ^= m;
+    }
+
+    let mut v = [
+        k0 ^ 0x736f_6d65_7073_6575,
+        k1 ^ 0x646f_7261_6e64_6f6d,
+        k0 ^ 0x6c79_6765_6e65_7261,
+        k1 ^ 0x7465_6462_7974_6573,
+    ];
+    let blocks = data.chunks_exact(8);
+    let tail = blocks.remainder();
+    for block in blocks {
+        compress(&mut v, u64::from_le_bytes(block.try_into().unwrap()));
+    }
+    let mut last = [0u8; 8];
+    last[..tail.len()].copy_from_slice(tail);
+    last[7] = data.len() as u8;
+    compress(&mut v, u64::from_le_bytes(last));
+
+    v[2] ^= 0xff;
+    for _ in 0..4 {
+        round(&mut v);
+    }
+    v[0] ^ v[1] ^ v[2] ^ v[3]
+}
+
+/// Base64 with padding, using the URL-safe alphabet if `url_safe`.
+pub(crate) fn base64(input: &[u8], url_safe: bool) -> String {
+    const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let alphabet = if url_safe { URL_SAFE } else { STANDARD };
+
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(alphabet[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+/// Everything a request handler needs, shared between connections.
+#[derive(Debug, Clone)]
+struct ServerState {
     metrics_path: String,
     namespace: Option<String>,
-    snapshot_storage: Arc<RwLock<Option<Snapshot>>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let addr: SocketAddr = listen_addr.parse()?;
-    let listener = TcpListener::bind(addr).await?;
+    snapshot: Arc<RwLock<Option<Snapshot>>>,
+    series: Arc<RwLock<Series>>,
+    auth: Option<Arc<Auth>>,
+    allowed_networks: Arc<[IpNetwork]>,
+}
+
+impl ServerState {
+    fn render(&self, format: ExpositionFormat) -> String {
+        let snapshot = self.snapshot.read();
+        match snapshot.as_ref() {
+            Some(s) => render(
+                s,
+                self.namespace.as_deref(),
+                format,
+                Some(&self.series.read()),
+            ),
+            None => String::new(),
+        }
+    }
+
+    fn allows(&self, ip: IpAddr) -> bool {
+        self.allowed_networks.is_empty() || self.allowed_networks.iter().any(|n| n.contains(ip))
+    }
+}
 
-    loop {
-        let (stream, _) = listener.accept().await?;
-        let io = TokioIo::new(stream);
+/// Callback for errors the server runs into.
+type ServerErrorHandler = Arc<dyn Fn(std::io::Error) + Send + Sync>;
 
-        let metrics_path = metrics_path.clone();
-        let namespace = namespace.clone();
-        let snapshot_storage = snapshot_storage.clone();
+/// How long a client may take to send the headers of a request.
+const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
 
-        tokio::spawn(async move {
-            let service = service_fn(move |req: Request<hyper::body::Incoming>| {
-                let metrics_path = metrics_path.clone();
-                let namespace = namespace.clone();
-                let snapshot_storage = snapshot_storage.clone();
+/// How long a connection may go without a request before it is closed,
+/// freeing its slot under `max_connections`.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
 
-                async move {
-                    handle_request(req, &metrics_path, namespace.as_deref(), &snapshot_storage)
-                }
+/// How long to wait before accepting again after the listener fails, such
+/// as when the process is out of file descriptors.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The HTTP server behind [`PrometheusExporter::start_server`].
+struct Server {
+    state: ServerState,
+    max_connections: Option<usize>,
+    idle_timeout: Duration,
+    on_error: ServerErrorHandler,
+    #[cfg(feature = "prometheus-tls")]
+    tls: Option<Arc<TlsReloader>>,
+}
+
+impl Server {
+    async fn run(self, listener: TcpListener, shutdown: impl Future<Output = ()>) {
+        let limit = self
+            .max_connections
+            .map(|max| Arc::new(Semaphore::new(max)));
+        let (stop_tx, stop_rx) = watch::channel(false);
+        let mut connections = JoinSet::new();
+        let this = Arc::new(self);
+        tokio::pin!(shutdown);
+
+        loop {
+            // Wait for a free slot before accepting, so excess connections
+            // queue in the listen backlog instead of being served
+            let permit = match &limit {
+                Some(limit) => tokio::select! {
+                    permit = limit.clone().acquire_owned() => permit.ok(),
+                    _ = &mut shutdown => break,
+                },
+                None => None,
+            };
+
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        // Errors other than a connection going away, like
+                        // running out of file descriptors, won't clear up
+                        // by retrying at once
+                        let transient = is_connection_error(&e);
+                        (this.on_error)(e);
+                        if !transient {
+                            tokio::select! {
+                                _ = tokio::time::sleep(ACCEPT_BACKOFF) => {}
+                                _ = &mut shutdown => break,
+                            }
+                        }
+                        continue;
+                    }
+                },
+                _ = &mut shutdown => break,
+            };
+            if !this.state.allows(peer.ip()) {
+                continue;
+            }
+
+            let this = this.clone();
+            let stop = stop_rx.clone();
+            connections.spawn(async move {
+                this.connection(stream, stop).await;
+                drop(permit);
             });
+            while connections.try_join_next().is_some() {}
+        }
 
-            if let Err(e) = http1::Builder::new().serve_connection(io, service).await {
-                eprintln!("Prometheus connection error: {}", e);
+        // Stop accepting, then let open connections finish
+        drop(listener);
+        let _ = stop_tx.send(true);
+        while connections.join_next().await.is_some() {}
+    }
+
+    async fn connection(&self, stream: TcpStream, stop: watch::Receiver<bool>) {
+        #[cfg(feature = "prometheus-tls")]
+        if let Some(tls) = &self.tls {
+            let acceptor = tls.acceptor(&*self.on_error);
+            match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => self.serve(TokioIo::new(stream), stop).await,
+                Ok(Err(e)) => (self.on_error)(e),
+                Err(_) => {}
             }
-        });
+            return;
+        }
+
+        self.serve(TokioIo::new(stream), stop).await;
     }
-}
 
-fn handle_request(
-    req: Request<hyper::body::Incoming>,
-    metrics_path: &str,
-    namespace: Option<&str>,
-    snapshot_storage: &Arc<RwLock<Option<Snapshot>>>,
-) -> Result<Response<Full<Bytes>>, Infallible> {
-    let path = req.uri().path();
+    /// Serve HTTP/1 on one connection until it closes, goes idle or the
+    /// server stops.
+    async fn serve<I>(&self, io: I, mut stop: watch::Receiver<bool>)
+    where
+        I: hyper::rt::Read + hyper::rt::Write + Unpin + Send + 'static,
+    {
+        let last_request = Arc::new(parking_lot::Mutex::new(Instant::now()));
+        let service = {
+            let state = self.state.clone();
+            let last_request = last_request.clone();
+            service_fn(move |req: Request<hyper::body::Incoming>| {
+                *last_request.lock() = Instant::now();
+                let response = handle_request(&req, &state);
+                async move { Ok::<_, Infallible>(response) }
+            })
+        };
+        let connection = http1::Builder::new()
+            .timer(TokioTimer::new())
+            .header_read_timeout(HEADER_READ_TIMEOUT)
+            .serve_connection(io, service);
+        tokio::pin!(connection);
+
+        let idle = async {
+            loop {
+                let deadline = *last_request.lock() + self.idle_timeout;
+                if Instant::now() >= deadline {
+                    break;
+                }
+                tokio::time::sleep_until(deadline).await;
+            }
+        };
 
-    if path == metrics_path {
-        let snapshot = snapshot_storage.read();
-        let body = match snapshot.as_ref() {
-            Some(s) => format_prometheus(s, namespace),
-            None => String::new(),
+        let result = tokio::select! {
+            result = connection.as_mut() => result,
+            _ = async { stop.wait_for(|stopped| *stopped).await.map(|_| ()) } => {
+                connection.as_mut().graceful_shutdown();
+                connection.await
+            }
+            _ = idle => {
+                // Let a response still being written finish, within reason
+                connection.as_mut().graceful_shutdown();
+                tokio::time::timeout(self.idle_timeout, connection)
+                    .await
+                    .unwrap_or(Ok(()))
+            }
         };
+        if let Err(e) = result {
+            (self.on_error)(std::io::Error::other(e));
+        }
+    }
+}
 
-        Ok(Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
-            .body(Full::new(Bytes::from(body)))
-            .unwrap())
-    } else if path == "/health" || path == "/healthz" {
-        Ok(Response::builder()
+/// Whether an accept error concerns only the connection being accepted.
+fn is_connection_error(e: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    matches!(
+        e.kind(),
+        ErrorKind::ConnectionAborted
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::Interrupted
+    )
+}
+
+/// How long a client may take to complete the TLS handshake.
+#[cfg(feature = "prometheus-tls")]
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the configured certificate, reloading it when the files change.
+#[cfg(feature = "prometheus-tls")]
+struct TlsReloader {
+    config: TlsConfig,
+    current: parking_lot::Mutex<LoadedCert>,
+}
+
+#[cfg(feature = "prometheus-tls")]
+struct LoadedCert {
+    /// Modification times of the certificate and key when last checked
+    modified: (Option<SystemTime>, Option<SystemTime>),
+    acceptor: TlsAcceptor,
+}
+
+#[cfg(feature = "prometheus-tls")]
+impl TlsReloader {
+    /// Load the certificate, failing if it can't be used.
+    fn new(config: TlsConfig) -> std::io::Result<Self> {
+        let modified = Self::modified(&config);
+        let acceptor = load_tls(&config)?;
+        Ok(Self {
+            config,
+            current: parking_lot::Mutex::new(LoadedCert { modified, acceptor }),
+        })
+    }
+
+    fn modified(config: &TlsConfig) -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified =
+            |path: &std::path::Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        (modified(&config.cert_path), modified(&config.key_path))
+    }
+
+    /// The acceptor for the current certificate, reloading it if the files
+    /// changed since the last connection.
+    ///
+    /// A certificate that fails to reload is passed to `on_error`.
+    fn acceptor(&self, on_error: &(dyn Fn(std::io::Error) + Send + Sync)) -> TlsAcceptor {
+        let modified = Self::modified(&self.config);
+        let mut current = self.current.lock();
+        if modified != current.modified {
+            // Record the change even if loading fails, so a bad file is
+            // reported once rather than on every connection
+            current.modified = modified;
+            match load_tls(&self.config) {
+                Ok(acceptor) => current.acceptor = acceptor,
+                Err(e) => on_error(std::io::Error::new(
+                    e.kind(),
+                    format!("TLS reload failed, keeping previous certificate: {}", e),
+                )),
+            }
+        }
+        current.acceptor.clone()
+    }
+}
+
+#[cfg(feature = "prometheus-tls")]
+fn load_tls(config: &TlsConfig) -> std::io::Result<TlsAcceptor> {
+    use std::io::{Error, ErrorKind};
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use tokio_rustls::rustls::{crypto, ServerConfig};
+
+    let invalid = |e: &dyn std::fmt::Display, path: &std::path::Path| {
+        Error::new(ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    };
+
+    let certs = CertificateDer::pem_file_iter(&config.cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(&e, &config.cert_path))?;
+    let key = PrivateKeyDer::from_pem_file(&config.key_path)
+        .map_err(|e| invalid(&e, &config.key_path))?;
+
+    let mut server_config =
+        ServerConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|e| Error::new(ErrorKind::InvalidInput, e))?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(&e, &config.cert_path))?;
+    server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn handle_request<B>(req: &Request<B>, state: &ServerState) -> Response<Full<Bytes>> {
+    let path = req.uri().path();
+
+    if path == "/health" || path == "/healthz" {
+        return Response::builder()
             .status(StatusCode::OK)
-            .header("Content-Type", "text/plain")
+            .header(CONTENT_TYPE, "text/plain")
             .body(Full::new(Bytes::from("OK")))
-            .unwrap())
+            .unwrap();
+    }
+
+    if let Some(auth) = &state.auth {
+        let header = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+        if !auth.accepts(header) {
+            return Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .header(
+                    WWW_AUTHENTICATE,
+                    format!("{} realm=\"buswatch\"", auth.scheme()),
+                )
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Full::new(Bytes::from("Unauthorized")))
+                .unwrap();
+        }
+    }
+
+    if path == state.metrics_path {
+        let accept = req.headers().get(ACCEPT).and_then(|v| v.to_str().ok());
+        let format = ExpositionFormat::negotiate(accept);
+
+        Response::builder()
+            .status(StatusCode::OK)
+            .header(CONTENT_TYPE, format.content_type())
+            .body(Full::new(Bytes::from(state.render(format))))
+            .unwrap()
+    } else if path == SNAPSHOT_PATH {
+        let body = state.snapshot.read().as_ref().map(serde_json::to_vec);
+        match body {
+            Some(Ok(body)) => Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, "application/json")
+                .body(Full::new(Bytes::from(body)))
+                .unwrap(),
+            _ => Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .header(CONTENT_TYPE, "text/plain")
+                .body(Full::new(Bytes::from("No snapshot yet")))
+                .unwrap(),
+        }
     } else {
-        Ok(Response::builder()
+        Response::builder()
             .status(StatusCode::NOT_FOUND)
-            .header("Content-Type", "text/plain")
+            .header(CONTENT_TYPE, "text/plain")
             .body(Full::new(Bytes::from("Not Found")))
-            .unwrap())
+            .unwrap()
     }
 }
 
 /// Format a snapshot as Prometheus exposition format.
 pub fn format_prometheus(snapshot: &Snapshot, namespace: Option<&str>) -> String {
-    let mut output = String::new();
-    let prefix = namespace.map(|n| format!("{}_", n)).unwrap_or_default();
-
-    // Add HELP and TYPE comments for each metric family
-    output.push_str(&format!(
-        "# HELP {}buswatch_read_count Total number of messages read from a topic\n",
-        prefix
-    ));
-    output.push_str(&format!("# TYPE {}buswatch_read_count counter\n", prefix));
-
-    output.push_str(&format!(
-        "# HELP {}buswatch_write_count Total number of messages written to a topic\n",
-        prefix
-    ));
-    output.push_str(&format!("# TYPE {}buswatch_write_count counter\n", prefix));
-
-    output.push_str(&format!(
-        "# HELP {}buswatch_read_backlog Number of unread messages in topic backlog\n",
-        prefix
-    ));
-    output.push_str(&format!("# TYPE {}buswatch_read_backlog gauge\n", prefix));
-
-    output.push_str(&format!(
-        "# HELP {}buswatch_read_pending_seconds Time spent waiting for a read operation\n",
-        prefix
-    ));
-    output.push_str(&format!(
-        "# TYPE {}buswatch_read_pending_seconds gauge\n",
-        prefix
-    ));
-
-    output.push_str(&format!(
-        "# HELP {}buswatch_write_pending_seconds Time spent waiting for a write operation\n",
-        prefix
-    ));
-    output.push_str(&format!(
-        "# TYPE {}buswatch_write_pending_seconds gauge\n",
-        prefix
-    ));
-
-    output.push_str(&format!(
-        "# HELP {}buswatch_read_rate_per_second Messages read per second\n",
-        prefix
-    ));
-    output.push_str(&format!(
-        "# TYPE {}buswatch_read_rate_per_second gauge\n",
-        prefix
-    ));
-
-    output.push_str(&format!(
-        "# HELP {}buswatch_write_rate_per_second Messages written per second\n",
-        prefix
-    ));
-    output.push_str(&format!(
-        "# TYPE {}buswatch_write_rate_per_second gauge\n",
-        prefix
-    ));
-
-    // Emit metrics for each module
-    for (module_name, metrics) in &snapshot.modules {
-        let module_label = escape_label_value(module_name);
-
-        // Read metrics
-        for (topic, read) in &metrics.reads {
-            let topic_label = escape_label_value(topic);
-            let labels = format!("module=\"{}\",topic=\"{}\"", module_label, topic_label);
-
-            // Count (counter)
-            output.push_str(&format!(
-                "{}buswatch_read_count{{{}}} {}\n",
-                prefix, labels, read.count
-            ));
-
-            // Backlog (gauge)
-            if let Some(backlog) = read.backlog {
-                output.push_str(&format!(
-                    "{}buswatch_read_backlog{{{}}} {}\n",
-                    prefix, labels, backlog
-                ));
-            }
+    render(snapshot, namespace, ExpositionFormat::Text, None)
+}
 
-            // Pending time (gauge, in seconds)
-            if let Some(pending) = &read.pending {
-                let seconds = pending.as_micros() as f64 / 1_000_000.0;
-                output.push_str(&format!(
-                    "{}buswatch_read_pending_seconds{{{}}} {:.6}\n",
-                    prefix, labels, seconds
-                ));
-            }
+/// Format a snapshot as OpenMetrics.
+///
+/// Counter creation times are only known to a [`PrometheusExporter`] that
+/// has seen earlier snapshots, so this omits `_created` samples.
+pub fn format_openmetrics(snapshot: &Snapshot, namespace: Option<&str>) -> String {
+    render(snapshot, namespace, ExpositionFormat::OpenMetrics, None)
+}
 
-            // Rate (gauge)
-            if let Some(rate) = read.rate {
-                output.push_str(&format!(
-                    "{}buswatch_read_rate_per_second{{{}}} {:.2}\n",
-                    prefix, labels, rate
-                ));
-            }
+/// Writes metric families one at a time, as OpenMetrics requires.
+struct Exposition {
+    output: String,
+    prefix: String,
+    format: ExpositionFormat,
+}
+
+impl Exposition {
+    /// Start a metric family, returning its full name.
+    fn family(&mut self, name: &str, kind: &str, help: &str, unit: Option<&str>) -> String {
+        let name = format!("{}{}", self.prefix, name);
+        let _ = writeln!(self.output, "# HELP {} {}", name, help);
+        let _ = writeln!(self.output, "# TYPE {} {}", name, kind);
+        if let (ExpositionFormat::OpenMetrics, Some(unit)) = (self.format, unit) {
+            let _ = writeln!(self.output, "# UNIT {} {}", name, unit);
         }
+        name
+    }
+
+    fn sample(&mut self, name: &str, labels: &str, value: impl Display) {
+        if labels.is_empty() {
+            let _ = writeln!(self.output, "{} {}", name, value);
+        } else {
+            let _ = writeln!(self.output, "{}{{{}}} {}", name, labels, value);
+        }
+    }
 
-        // Write metrics
-        for (topic, write) in &metrics.writes {
-            let topic_label = escape_label_value(topic);
-            let labels = format!("module=\"{}\",topic=\"{}\"", module_label, topic_label);
-
-            // Count (counter)
-            output.push_str(&format!(
-                "{}buswatch_write_count{{{}}} {}\n",
-                prefix, labels, write.count
-            ));
-
-            // Pending time (gauge, in seconds)
-            if let Some(pending) = &write.pending {
-                let seconds = pending.as_micros() as f64 / 1_000_000.0;
-                output.push_str(&format!(
-                    "{}buswatch_write_pending_seconds{{{}}} {:.6}\n",
-                    prefix, labels, seconds
-                ));
+    /// Write a counter sample, with its creation time in OpenMetrics.
+    fn counter(&mut self, name: &str, labels: &str, value: u64, created_ms: Option<u64>) {
+        match self.format {
+            ExpositionFormat::Text => self.sample(name, labels, value),
+            ExpositionFormat::OpenMetrics => {
+                self.sample(&format!("{}_total", name), labels, value);
+                if let Some(created_ms) = created_ms {
+                    self.sample(&format!("{}_created", name), labels, seconds_ms(created_ms));
+                }
             }
+        }
+    }
 
-            // Rate (gauge)
-            if let Some(rate) = write.rate {
-                output.push_str(&format!(
-                    "{}buswatch_write_rate_per_second{{{}}} {:.2}\n",
-                    prefix, labels, rate
-                ));
+    /// Write the samples of a histogram in seconds.
+    fn histogram(
+        &mut self,
+        name: &str,
+        labels: &str,
+        histogram: &LatencyHistogram,
+        created_ms: u64,
+    ) {
+        let mut cumulative = 0;
+        for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+            cumulative += count;
+            let le = format!("{},le=\"{}\"", labels, seconds(*bound));
+            self.sample(&format!("{}_bucket", name), &le, cumulative);
+        }
+        let le = format!("{},le=\"+Inf\"", labels);
+        self.sample(&format!("{}_bucket", name), &le, histogram.count);
+        self.sample(&format!("{}_sum", name), labels, seconds(histogram.sum));
+        self.sample(&format!("{}_count", name), labels, histogram.count);
+        if self.format == ExpositionFormat::OpenMetrics {
+            self.sample(&format!("{}_created", name), labels, seconds_ms(created_ms));
+        }
+    }
+}
+
+fn seconds(value: Microseconds) -> f64 {
+    value.as_micros() as f64 / 1_000_000.0
+}
+
+fn seconds_ms(ms: u64) -> String {
+    format!("{:.3}", ms as f64 / 1000.0)
+}
+
+fn labels(module: &str, topic: &str) -> String {
+    format!(
+        "module=\"{}\",topic=\"{}\"",
+        escape_label_value(module),
+        escape_label_value(topic)
+    )
+}
+
+fn render(
+    snapshot: &Snapshot,
+    namespace: Option<&str>,
+    format: ExpositionFormat,
+    series: Option<&Series>,
+) -> String {
+    let mut out = Exposition {
+        output: String::new(),
+        prefix: namespace.map(|n| format!("{}_", n)).unwrap_or_default(),
+        format,
+    };
+    let reads = || {
+        snapshot.modules.iter().flat_map(|(module, metrics)| {
+            metrics
+                .reads
+                .iter()
+                .map(move |(topic, read)| (module, topic, read))
+        })
+    };
+    let writes = || {
+        snapshot.modules.iter().flat_map(|(module, metrics)| {
+            metrics
+                .writes
+                .iter()
+                .map(move |(topic, write)| (module, topic, write))
+        })
+    };
+    let created = |module: &str, topic: &str, is_read: bool| {
+        series.and_then(|s| {
+            s.counters
+                .get(&(module.to_string(), topic.to_string(), is_read))
+                .map(|(created, _)| *created)
+        })
+    };
+
+    let name = out.family(
+        "buswatch_read_count",
+        "counter",
+        "Total number of messages read from a topic",
+        None,
+    );
+    for (module, topic, read) in reads() {
+        let created = created(module, topic, true);
+        out.counter(&name, &labels(module, topic), read.count, created);
+    }
+
+    let name = out.family(
+        "buswatch_write_count",
+        "counter",
+        "Total number of messages written to a topic",
+        None,
+    );
+    for (module, topic, write) in writes() {
+        let created = created(module, topic, false);
+        out.counter(&name, &labels(module, topic), write.count, created);
+    }
+
+    let name = out.family(
+        "buswatch_read_backlog",
+        "gauge",
+        "Number of unread messages in topic backlog",
+        None,
+    );
+    for (module, topic, read) in reads() {
+        if let Some(backlog) = read.backlog {
+            out.sample(&name, &labels(module, topic), backlog);
+        }
+    }
+
+    let name = out.family(
+        "buswatch_read_pending_seconds",
+        "gauge",
+        "Time spent waiting for a read operation",
+        Some("seconds"),
+    );
+    for (module, topic, read) in reads() {
+        if let Some(pending) = read.pending {
+            let value = format!("{:.6}", seconds(pending));
+            out.sample(&name, &labels(module, topic), value);
+        }
+    }
+
+    let name = out.family(
+        "buswatch_write_pending_seconds",
+        "gauge",
+        "Time spent waiting for a write operation",
+        Some("seconds"),
+    );
+    for (module, topic, write) in writes() {
+        if let Some(pending) = write.pending {
+            let value = format!("{:.6}", seconds(pending));
+            out.sample(&name, &labels(module, topic), value);
+        }
+    }
+
+    let name = out.family(
+        "buswatch_read_rate_per_second",
+        "gauge",
+        "Messages read per second",
+        None,
+    );
+    for (module, topic, read) in reads() {
+        if let Some(rate) = read.rate {
+            out.sample(&name, &labels(module, topic), format!("{:.2}", rate));
+        }
+    }
+
+    let name = out.family(
+        "buswatch_write_rate_per_second",
+        "gauge",
+        "Messages written per second",
+        None,
+    );
+    for (module, topic, write) in writes() {
+        if let Some(rate) = write.rate {
+            out.sample(&name, &labels(module, topic), format!("{:.2}", rate));
+        }
+    }
+
+    if let Some(series) = series.filter(|s| s.pending_histogram) {
+        for (is_read, family, help) in [
+            (
+                true,
+                "buswatch_read_pending_duration_seconds",
+                "Pending read durations seen at each snapshot",
+            ),
+            (
+                false,
+                "buswatch_write_pending_duration_seconds",
+                "Pending write durations seen at each snapshot",
+            ),
+        ] {
+            let name = out.family(family, "histogram", help, Some("seconds"));
+            let mut histograms: Vec<_> = series
+                .pending
+                .iter()
+                .filter(|((_, _, read), _)| *read == is_read)
+                .collect();
+            histograms.sort_by(|a, b| a.0.cmp(b.0));
+            for ((module, topic, _), (created, histogram)) in histograms {
+                out.histogram(&name, &labels(module, topic), histogram, *created);
             }
         }
     }
 
-    // Add timestamp metric
-    output.push_str(&format!(
-        "# HELP {}buswatch_snapshot_timestamp_seconds Unix timestamp of the snapshot\n",
-        prefix
-    ));
-    output.push_str(&format!(
-        "# TYPE {}buswatch_snapshot_timestamp_seconds gauge\n",
-        prefix
-    ));
-    output.push_str(&format!(
-        "{}buswatch_snapshot_timestamp_seconds {:.3}\n",
-        prefix,
-        snapshot.timestamp_ms as f64 / 1000.0
-    ));
+    let name = out.family(
+        "buswatch_snapshot_timestamp_seconds",
+        "gauge",
+        "Unix timestamp of the snapshot",
+        Some("seconds"),
+    );
+    out.sample(&name, "", seconds_ms(snapshot.timestamp_ms));
 
-    output
+    if format == ExpositionFormat::OpenMetrics {
+        out.output.push_str("# EOF\n");
+    }
+
+    out.output
 }
 
 /// Escape a label value for Prometheus format.
@@ -416,6 +1474,10 @@ mod tests {
                 backlog: Some(50),
                 pending: Some(Microseconds::from_millis(100)),
                 rate: Some(50.5),
+                inflight: None,
+                latency: None,
+                errors: None,
+                backlog_source: None,
             },
         );
 
@@ -426,11 +1488,22 @@ mod tests {
                 count: 500,
                 pending: None,
                 rate: Some(25.0),
+                inflight: None,
+                latency: None,
+                errors: None,
             },
         );
 
         let mut modules = BTreeMap::new();
-        modules.insert("my-service".to_string(), ModuleMetrics { reads, writes });
+        modules.insert(
+            "my-service".to_string(),
+            ModuleMetrics {
+                reads,
+                writes,
+                evicted_topics: None,
+                tracked_topics: None,
+            },
+        );
 
         Snapshot {
             version: buswatch_types::SchemaVersion::current(),
@@ -496,6 +1569,7 @@ mod tests {
         assert_eq!(config.listen_addr, "0.0.0.0:9090");
         assert_eq!(config.metrics_path, "/metrics");
         assert_eq!(config.namespace, None);
+        assert!(!config.pending_histogram);
     }
 
     #[test]
@@ -562,6 +1636,10 @@ mod tests {
                 backlog: None,
                 pending: None,
                 rate: None,
+                inflight: None,
+                latency: None,
+                errors: None,
+                backlog_source: None,
             },
         );
         reads1.insert(
@@ -571,6 +1649,10 @@ mod tests {
                 backlog: Some(10),
                 pending: None,
                 rate: None,
+                inflight: None,
+                latency: None,
+                errors: None,
+                backlog_source: None,
             },
         );
         modules.insert(
@@ -578,6 +1660,8 @@ mod tests {
             ModuleMetrics {
                 reads: reads1,
                 writes: BTreeMap::new(),
+                evicted_topics: None,
+                tracked_topics: None,
             },
         );
 
@@ -589,6 +1673,9 @@ mod tests {
                 count: 50,
                 pending: None,
                 rate: Some(10.0),
+                inflight: None,
+                latency: None,
+                errors: None,
             },
         );
         modules.insert(
@@ -596,6 +1683,8 @@ mod tests {
             ModuleMetrics {
                 reads: BTreeMap::new(),
                 writes: writes2,
+                evicted_topics: None,
+                tracked_topics: None,
             },
         );
 
@@ -612,4 +1701,614 @@ mod tests {
         assert!(output.contains("module=\"service-2\",topic=\"output\""));
         assert!(output.contains("buswatch_read_backlog{module=\"service-1\",topic=\"topic-b\"} 10"));
     }
+
+    fn exporter_state(exporter: &PrometheusExporter) -> ServerState {
+        exporter.server_state()
+    }
+
+    fn get(state: &ServerState, path: &str, accept: Option<&str>) -> Response<Full<Bytes>> {
+        let mut req = Request::builder().uri(path);
+        if let Some(accept) = accept {
+            req = req.header(ACCEPT, accept);
+        }
+        handle_request(&req.body(()).unwrap(), state)
+    }
+
+    fn content_type(response: &Response<Full<Bytes>>) -> &str {
+        response.headers()[CONTENT_TYPE].to_str().unwrap()
+    }
+
+    #[test]
+    fn test_negotiate_format() {
+        assert_eq!(ExpositionFormat::negotiate(None), ExpositionFormat::Text);
+        assert_eq!(
+            ExpositionFormat::negotiate(Some("*/*")),
+            ExpositionFormat::Text
+        );
+        assert_eq!(
+            ExpositionFormat::negotiate(Some("application/openmetrics-text")),
+            ExpositionFormat::OpenMetrics
+        );
+        // What Prometheus sends by default
+        assert_eq!(
+            ExpositionFormat::negotiate(Some(
+                "application/openmetrics-text;version=1.0.0;q=0.5,\
+                 application/openmetrics-text;version=0.0.1;q=0.4,\
+                 text/plain;version=0.0.4;q=0.3,*/*;q=0.2"
+            )),
+            ExpositionFormat::OpenMetrics
+        );
+        assert_eq!(
+            ExpositionFormat::negotiate(Some(
+                "text/plain;version=0.0.4,application/openmetrics-text;q=0.5"
+            )),
+            ExpositionFormat::Text
+        );
+        assert_eq!(
+            ExpositionFormat::negotiate(Some("application/openmetrics-text;q=0")),
+            ExpositionFormat::Text
+        );
+    }
+
+    #[test]
+    fn test_format_openmetrics() {
+        let snapshot = create_test_snapshot();
+        let output = format_openmetrics(&snapshot, None);
+
+        assert!(output.contains("# TYPE buswatch_read_count counter\n"));
+        assert!(output
+            .contains("buswatch_read_count_total{module=\"my-service\",topic=\"events\"} 1000"));
+        assert!(output.contains("# UNIT buswatch_read_pending_seconds seconds"));
+        assert!(!output.contains("_created"));
+        assert!(output.ends_with("# EOF\n"));
+        assert!(!format_prometheus(&snapshot, None).contains("# EOF"));
+    }
+
+    #[test]
+    fn test_families_are_contiguous() {
+        let snapshot = create_test_snapshot();
+        let output = format_openmetrics(&snapshot, None);
+
+        // Every sample follows the TYPE line of its own family
+        let mut family = String::new();
+        for line in output.lines() {
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                family = rest.split(' ').next().unwrap().to_string();
+            } else if !line.starts_with('#') {
+                assert!(line.starts_with(&family), "{} outside {}", line, family);
+            }
+        }
+    }
+
+    #[test]
+    fn test_created_tracks_first_seen_and_resets() {
+        let exporter = PrometheusExporter::new(PrometheusConfig::default());
+        let mut snapshot = create_test_snapshot();
+        exporter.record(&snapshot);
+
+        snapshot.timestamp_ms += 5_000;
+        exporter.record(&snapshot);
+        let output = exporter.render_as(ExpositionFormat::OpenMetrics);
+        assert!(output.contains(
+            "buswatch_read_count_created{module=\"my-service\",topic=\"events\"} 1703160000.000"
+        ));
+
+        // The count going backwards means the counter restarted
+        snapshot.timestamp_ms += 5_000;
+        let module = snapshot.modules.get_mut("my-service").unwrap();
+        module.reads.get_mut("events").unwrap().count = 3;
+        exporter.record(&snapshot);
+        let output = exporter.render_as(ExpositionFormat::OpenMetrics);
+        assert!(output.contains(
+            "buswatch_read_count_created{module=\"my-service\",topic=\"events\"} 1703160010.000"
+        ));
+    }
+
+    #[test]
+    fn test_pending_histogram_is_opt_in() {
+        let exporter = PrometheusExporter::new(PrometheusConfig::default());
+        exporter.record(&create_test_snapshot());
+        assert!(!exporter.render().contains("pending_duration_seconds"));
+
+        let config = PrometheusConfig::builder().pending_histogram(true).build();
+        let exporter = PrometheusExporter::new(config);
+        let mut snapshot = create_test_snapshot();
+        exporter.record(&snapshot);
+        snapshot.timestamp_ms += 1_000;
+        exporter.record(&snapshot);
+
+        let output = exporter.render();
+        let labels = "module=\"my-service\",topic=\"events\"";
+        assert!(output.contains("# TYPE buswatch_read_pending_duration_seconds histogram"));
+        assert!(output.contains(&format!(
+            "buswatch_read_pending_duration_seconds_bucket{{{},le=\"0.01\"}} 0",
+            labels
+        )));
+        assert!(output.contains(&format!(
+            "buswatch_read_pending_duration_seconds_bucket{{{},le=\"0.1\"}} 2",
+            labels
+        )));
+        assert!(output.contains(&format!(
+            "buswatch_read_pending_duration_seconds_bucket{{{},le=\"+Inf\"}} 2",
+            labels
+        )));
+        assert!(output.contains(&format!(
+            "buswatch_read_pending_duration_seconds_sum{{{}}} 0.2",
+            labels
+        )));
+        assert!(output.contains(&format!(
+            "buswatch_read_pending_duration_seconds_count{{{}}} 2",
+            labels
+        )));
+    }
+
+    #[test]
+    fn test_removed_topics_are_forgotten() {
+        let config = PrometheusConfig::builder().pending_histogram(true).build();
+        let exporter = PrometheusExporter::new(config);
+        exporter.record(&create_test_snapshot());
+        exporter.record(&Snapshot::builder().build());
+
+        let series = exporter.series.read();
+        assert!(series.counters.is_empty());
+        assert!(series.pending.is_empty());
+    }
+
+    #[test]
+    fn test_metrics_endpoint_negotiates_format() {
+        let exporter = PrometheusExporter::new(PrometheusConfig::default());
+        exporter.record(&create_test_snapshot());
+        let state = exporter_state(&exporter);
+
+        let response = get(&state, "/metrics", None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            content_type(&response),
+            ExpositionFormat::Text.content_type()
+        );
+
+        let response = get(&state, "/metrics", Some("application/openmetrics-text"));
+        assert_eq!(
+            content_type(&response),
+            ExpositionFormat::OpenMetrics.content_type()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_endpoint() {
+        use http_body_util::BodyExt;
+
+        let exporter = PrometheusExporter::new(PrometheusConfig::default());
+        let state = exporter_state(&exporter);
+        let response = get(&state, SNAPSHOT_PATH, None);
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let snapshot = create_test_snapshot();
+        exporter.record(&snapshot);
+        let response = get(&state, SNAPSHOT_PATH, None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(content_type(&response), "application/json");
+
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let decoded: Snapshot = serde_json::from_slice(&body).unwrap();
+        assert_eq!(decoded, snapshot);
+    }
+
+    #[test]
+    fn test_health_and_not_found() {
+        let exporter = PrometheusExporter::new(PrometheusConfig::default());
+        let state = exporter_state(&exporter);
+        assert_eq!(get(&state, "/healthz", None).status(), StatusCode::OK);
+        assert_eq!(get(&state, "/nope", None).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_auth_accepts_matching_credentials() {
+        let basic = Auth::basic("prometheus", "s3cret");
+        // base64("prometheus:s3cret")
+        assert!(basic.accepts(Some("Basic cHJvbWV0aGV1czpzM2NyZXQ=")));
+        assert!(basic.accepts(Some("basic cHJvbWV0aGV1czpzM2NyZXQ=")));
+        assert!(!basic.accepts(Some("Basic cHJvbWV0aGV1czp3cm9uZw==")));
+        assert!(!basic.accepts(Some("Bearer s3cret")));
+        assert!(!basic.accepts(None));
+
+        let bearer = Auth::bearer("token-1");
+        assert!(bearer.accepts(Some("Bearer token-1")));
+        assert!(!bearer.accepts(Some("Bearer token-2")));
+        assert!(!format!("{:?}", basic).contains("s3cret"));
+        assert!(!format!("{:?}", bearer).contains("token-1"));
+    }
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b"", false), "");
+        assert_eq!(base64(b"abc", false), "YWJj");
+        assert_eq!(base64(b"ab", false), "YWI=");
+        assert_eq!(base64(b"\xfb\xff", false), "+/8=");
+        assert_eq!(base64(b"\xfb\xff", true), "-_8=");
+    }
+
+    #[test]
+    fn test_auth_guards_everything_but_health() {
+        let config = PrometheusConfig::builder()
+            .auth(Auth::bearer("token-1"))
+            .build();
+        let exporter = PrometheusExporter::new(config);
+        exporter.record(&create_test_snapshot());
+        let state = exporter_state(&exporter);
+
+        let response = get(&state, "/metrics", None);
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[WWW_AUTHENTICATE],
+            "Bearer realm=\"buswatch\""
+        );
+        assert_eq!(
+            get(&state, SNAPSHOT_PATH, None).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(get(&state, "/healthz", None).status(), StatusCode::OK);
+
+        let req = Request::builder()
+            .uri("/metrics")
+            .header(AUTHORIZATION, "Bearer token-1")
+            .body(())
+            .unwrap();
+        assert_eq!(handle_request(&req, &state).status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_ip_network() {
+        let net: IpNetwork = "10.0.0.0/8".parse().unwrap();
+        assert!(net.contains("10.1.2.3".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.2.3".parse().unwrap()));
+        assert!(!net.contains("11.0.0.1".parse().unwrap()));
+        assert!(!net.contains("::1".parse().unwrap()));
+
+        let host: IpNetwork = "::1".parse().unwrap();
+        assert!(host.contains("::1".parse().unwrap()));
+        assert!(!host.contains("::2".parse().unwrap()));
+
+        let any: IpNetwork = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains("192.168.1.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpNetwork>().is_err());
+        assert!("not-an-ip".parse::<IpNetwork>().is_err());
+    }
+
+    #[test]
+    fn test_siphash_reference_vectors() {
+        // From the SipHash paper: key 00..0f, messages 00..(n-1)
+        let key = (0x0706_0504_0302_0100, 0x0f0e_0d0c_0b0a_0908);
+        let message: Vec<u8> = (0..15).collect();
+        assert_eq!(siphash24(key, &[]), 0x726f_db47_dd0e_0e31);
+        assert_eq!(siphash24(key, &message), 0xa129_ca61_49be_45e5);
+    }
+
+    #[test]
+    fn test_topic_hash_depends_on_key() {
+        let a = hash_topic("events", Some("key-a"));
+        let b = hash_topic("events", Some("key-b"));
+        assert_ne!(a, b);
+        assert_eq!(a, hash_topic("events", Some("key-a")));
+        assert_ne!(a, hash_topic("orders", Some("key-a")));
+    }
+
+    #[test]
+    fn test_hashed_topic_labels() {
+        let config = PrometheusConfig::builder()
+            .hash_topic_labels(true)
+            .topic_hash_key("k")
+            .build();
+        let exporter = PrometheusExporter::new(config);
+        exporter.record(&create_test_snapshot());
+
+        let hashed = hash_topic("events", Some("k"));
+        assert_eq!(hashed.len(), 16);
+        assert_ne!(hashed, hash_topic("events", None));
+
+        let output = exporter.render();
+        assert!(!output.contains("topic=\"events\""));
+        assert!(output.contains(&format!(
+            "buswatch_read_count{{module=\"my-service\",topic=\"{}\"}} 1000",
+            hashed
+        )));
+
+        let snapshot = exporter.snapshot_storage().read().clone().unwrap();
+        let module = &snapshot.modules["my-service"];
+        assert!(module.reads.contains_key(&hashed));
+        assert!(!module.reads.contains_key("events"));
+    }
+
+    /// A server on an ephemeral port, stopped by sending on the channel.
+    struct TestServer {
+        addr: SocketAddr,
+        stop: Option<tokio::sync::oneshot::Sender<()>>,
+        task: tokio::task::JoinHandle<()>,
+        /// Errors the server reported
+        errors: Arc<parking_lot::Mutex<Vec<std::io::Error>>>,
+    }
+
+    async fn test_server(exporter: &PrometheusExporter) -> TestServer {
+        test_server_with_idle_timeout(exporter, IDLE_TIMEOUT).await
+    }
+
+    async fn test_server_with_idle_timeout(
+        exporter: &PrometheusExporter,
+        idle_timeout: Duration,
+    ) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let errors = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let server = Server {
+            state: exporter.server_state(),
+            max_connections: exporter.config.max_connections,
+            idle_timeout,
+            on_error: {
+                let errors = errors.clone();
+                Arc::new(move |e| errors.lock().push(e))
+            },
+            #[cfg(feature = "prometheus-tls")]
+            tls: exporter
+                .config
+                .tls
+                .clone()
+                .map(|tls| Arc::new(TlsReloader::new(tls).unwrap())),
+        };
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let task = tokio::spawn(server.run(listener, async {
+            let _ = stopped.await;
+        }));
+        TestServer {
+            addr,
+            stop: Some(stop),
+            task,
+            errors,
+        }
+    }
+
+    /// Send a request on `stream` and read the response until the server
+    /// closes the connection.
+    async fn raw_get<S>(mut stream: S, path: &str) -> String
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response).await;
+        response
+    }
+
+    #[tokio::test]
+    async fn test_server_serves_and_shuts_down_gracefully() {
+        let exporter = PrometheusExporter::new(PrometheusConfig::default());
+        exporter.record(&create_test_snapshot());
+        let mut server = test_server(&exporter).await;
+
+        let stream = TcpStream::connect(server.addr).await.unwrap();
+        let response = raw_get(stream, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains("buswatch_read_count"));
+
+        server.stop.take().unwrap().send(()).unwrap();
+        server.task.await.unwrap();
+        assert!(TcpStream::connect(server.addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_reports_errors_and_serves_on_the_bound_listener() {
+        let invalid = PrometheusExporter::new(
+            PrometheusConfig::builder()
+                .listen_addr("not an address")
+                .build(),
+        );
+        let err = invalid.bind().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+        let exporter = PrometheusExporter::new(
+            PrometheusConfig::builder()
+                .listen_addr("127.0.0.1:0")
+                .build(),
+        );
+        exporter.bind().await.unwrap();
+        let addr = exporter.local_addr().unwrap();
+
+        let taken = PrometheusExporter::new(
+            PrometheusConfig::builder()
+                .listen_addr(addr.to_string())
+                .build(),
+        );
+        let err = taken.bind().await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+
+        exporter.record(&create_test_snapshot());
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let task = exporter.start_server_with_error_handler(
+            async {
+                let _ = stopped.await;
+            },
+            |e| panic!("unexpected server error: {}", e),
+        );
+        assert!(exporter.local_addr().is_none());
+
+        let response = raw_get(TcpStream::connect(addr).await.unwrap(), "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+
+        stop.send(()).unwrap();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_rejects_networks_outside_allowlist() {
+        let config = PrometheusConfig::builder()
+            .allow_network("10.0.0.0/8".parse().unwrap())
+            .build();
+        let exporter = PrometheusExporter::new(config);
+        let server = test_server(&exporter).await;
+
+        let stream = TcpStream::connect(server.addr).await.unwrap();
+        assert_eq!(raw_get(stream, "/healthz").await, "");
+
+        let config = PrometheusConfig::builder()
+            .allow_network("127.0.0.1".parse().unwrap())
+            .build();
+        let exporter = PrometheusExporter::new(config);
+        let server = test_server(&exporter).await;
+
+        let stream = TcpStream::connect(server.addr).await.unwrap();
+        assert!(raw_get(stream, "/healthz")
+            .await
+            .starts_with("HTTP/1.1 200"));
+    }
+
+    #[tokio::test]
+    async fn test_server_limits_connections() {
+        use tokio::io::AsyncWriteExt;
+
+        let config = PrometheusConfig::builder().max_connections(1).build();
+        let exporter = PrometheusExporter::new(config);
+        let server = test_server(&exporter).await;
+
+        // Hold the only slot with an idle connection
+        let mut first = TcpStream::connect(server.addr).await.unwrap();
+        first
+            .write_all(b"GET /healthz HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let second = tokio::spawn(async move {
+            let stream = TcpStream::connect(server.addr).await.unwrap();
+            raw_get(stream, "/healthz").await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!second.is_finished());
+
+        drop(first);
+        let response = tokio::time::timeout(Duration::from_secs(5), second)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+    }
+
+    #[tokio::test]
+    async fn test_server_closes_idle_connections() {
+        let config = PrometheusConfig::builder().max_connections(1).build();
+        let exporter = PrometheusExporter::new(config);
+        let server = test_server_with_idle_timeout(&exporter, Duration::from_millis(100)).await;
+
+        // A connection that never sends a request gives up its slot
+        let _idle = TcpStream::connect(server.addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let stream = TcpStream::connect(server.addr).await.unwrap();
+        let response = tokio::time::timeout(Duration::from_secs(5), raw_get(stream, "/healthz"))
+            .await
+            .unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        // Closing an idle connection isn't an error
+        assert!(server.errors.lock().is_empty());
+    }
+
+    #[cfg(feature = "prometheus-tls")]
+    #[tokio::test]
+    async fn test_server_tls_reloads_certificate() {
+        use tokio_rustls::rustls::pki_types::ServerName;
+        use tokio_rustls::rustls::{crypto, ClientConfig, RootCertStore};
+        use tokio_rustls::TlsConnector;
+
+        let dir = std::env::temp_dir().join(format!("buswatch-sdk-tls-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cert_path = dir.join("cert.pem");
+        let key_path = dir.join("key.pem");
+
+        let write_cert = |age: u64| {
+            let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+            std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+            std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+            // Distinct modification times, however coarse the filesystem's
+            let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(age);
+            for path in [&cert_path, &key_path] {
+                let file = std::fs::File::options().write(true).open(path).unwrap();
+                file.set_modified(modified).unwrap();
+            }
+            cert.cert.der().clone()
+        };
+
+        let get_with = |addr: SocketAddr, trusted| async move {
+            let mut roots = RootCertStore::empty();
+            roots.add(trusted).unwrap();
+            let config =
+                ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+                    .with_safe_default_protocol_versions()
+                    .unwrap()
+                    .with_root_certificates(roots)
+                    .with_no_client_auth();
+            let stream = TcpStream::connect(addr).await.unwrap();
+            let name = ServerName::try_from("localhost").unwrap();
+            match TlsConnector::from(Arc::new(config))
+                .connect(name, stream)
+                .await
+            {
+                Ok(stream) => raw_get(stream, "/healthz").await,
+                Err(_) => String::new(),
+            }
+        };
+
+        let first = write_cert(1_000);
+        let config = PrometheusConfig::builder()
+            .tls(TlsConfig::new(&cert_path, &key_path))
+            .build();
+        let exporter = PrometheusExporter::new(config);
+        let server = test_server(&exporter).await;
+
+        assert!(get_with(server.addr, first.clone())
+            .await
+            .starts_with("HTTP/1.1 200"));
+
+        // A renewed certificate is served to the next connection
+        let second = write_cert(2_000);
+        assert!(get_with(server.addr, second.clone())
+            .await
+            .starts_with("HTTP/1.1 200"));
+        assert_eq!(get_with(server.addr, first).await, "");
+
+        // A broken renewal is reported and the previous certificate kept
+        std::fs::write(&cert_path, "not a certificate").unwrap();
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&cert_path)
+            .unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(3_000))
+            .unwrap();
+        assert!(get_with(server.addr, second.clone())
+            .await
+            .starts_with("HTTP/1.1 200"));
+        assert!(server
+            .errors
+            .lock()
+            .iter()
+            .any(|e| e.to_string().contains("TLS reload failed")));
+
+        // So is a client that doesn't speak TLS
+        let errors = server.errors.lock().len();
+        let stream = TcpStream::connect(server.addr).await.unwrap();
+        raw_get(stream, "/healthz").await;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while server.errors.lock().len() == errors {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,609 @@
+//! Pushing metrics to a Prometheus Pushgateway or remote-write receiver.
+//!
+//! Short-lived jobs and processes that Prometheus cannot reach (behind NAT,
+//! in CI, on laptops) can't be scraped by the
+//! [`PrometheusExporter`](crate::prometheus::PrometheusExporter) server.
+//! This output sends each snapshot instead:
+//!
+//! - [`PushMode::Pushgateway`] PUTs the Prometheus text format to
+//!   `<url>/metrics/job/<job>/<label>/<value>...`, replacing the group's
+//!   previous metrics. With `delete_on_shutdown`, the group is deleted when
+//!   the instrumentor shuts down so stale values don't linger.
+//! - [`PushMode::RemoteWrite`] (`remote-write` feature) POSTs a
+//!   snappy-compressed protobuf `WriteRequest` to a Prometheus-compatible
+//!   receiver such as Prometheus itself, Mimir, Thanos or VictoriaMetrics.
+//!   The job and grouping labels are added to every series.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use buswatch_sdk::{Instrumentor, Output};
+//! use buswatch_sdk::push::PushConfig;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let config = PushConfig::builder()
+//!         .url("http://pushgateway:9091")
+//!         .job("nightly-import")
+//!         .grouping_label("instance", "worker-1")
+//!         .delete_on_shutdown(true)
+//!         .build();
+//!
+//!     let instrumentor = Instrumentor::builder()
+//!         .output(Output::prometheus_push(config))
+//!         .build();
+//!
+//!     let emission = instrumentor.start();
+//!
+//!     // ... run the job ...
+//!
+//!     let _ = emission.shutdown().await;
+//! }
+//! ```
+
+use std::io;
+use std::time::Duration;
+
+use buswatch_types::Snapshot;
+
+use crate::prometheus::{base64, format_prometheus};
+
+/// Default timeout for each push request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where and how snapshots are pushed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PushMode {
+    /// PUT the text exposition format to a Pushgateway.
+    #[default]
+    Pushgateway,
+    /// POST Prometheus remote-write protobuf (requires the `remote-write`
+    /// feature).
+    #[cfg(feature = "remote-write")]
+    RemoteWrite,
+}
+
+/// Configuration for pushing metrics.
+#[derive(Debug, Clone)]
+pub struct PushConfig {
+    /// Pushgateway base URL (e.g., "http://localhost:9091"), or the full
+    /// remote-write endpoint (e.g., "http://localhost:9090/api/v1/write")
+    pub url: String,
+    /// How snapshots are sent
+    pub mode: PushMode,
+    /// Job name, the first grouping key
+    pub job: String,
+    /// Additional grouping labels, e.g. `instance`
+    pub grouping: Vec<(String, String)>,
+    /// Optional namespace prefix for all metrics
+    pub namespace: Option<String>,
+    /// Delete the Pushgateway group when the instrumentor shuts down
+    pub delete_on_shutdown: bool,
+    /// Timeout for each request
+    pub timeout: Duration,
+}
+
+impl PushConfig {
+    /// Create a new builder for PushConfig.
+    pub fn builder() -> PushConfigBuilder {
+        PushConfigBuilder::default()
+    }
+}
+
+/// Builder for PushConfig.
+#[derive(Debug, Default)]
+pub struct PushConfigBuilder {
+    url: Option<String>,
+    mode: PushMode,
+    job: Option<String>,
+    grouping: Vec<(String, String)>,
+    namespace: Option<String>,
+    delete_on_shutdown: bool,
+    timeout: Option<Duration>,
+}
+
+impl PushConfigBuilder {
+    /// Set the Pushgateway base URL or remote-write endpoint.
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set the push mode. Defaults to [`PushMode::Pushgateway`].
+    pub fn mode(mut self, mode: PushMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the job name. Defaults to "buswatch".
+    pub fn job(mut self, job: impl Into<String>) -> Self {
+        self.job = Some(job.into());
+        self
+    }
+
+    /// Add a grouping label.
+    pub fn grouping_label(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.grouping.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the namespace prefix for all metrics.
+    pub fn namespace(mut self, ns: impl Into<String>) -> Self {
+        self.namespace = Some(ns.into());
+        self
+    }
+
+    /// Delete the Pushgateway group on shutdown.
+    ///
+    /// Use this for long-running processes, whose last values would
+    /// otherwise stay on the Pushgateway after they exit. Batch jobs usually
+    /// want their final results kept. Has no effect in remote-write mode.
+    pub fn delete_on_shutdown(mut self, delete: bool) -> Self {
+        self.delete_on_shutdown = delete;
+        self
+    }
+
+    /// Set the timeout for each request. Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Build the PushConfig.
+    pub fn build(self) -> PushConfig {
+        PushConfig {
+            url: self
+                .url
+                .unwrap_or_else(|| "http://localhost:9091".to_string()),
+            mode: self.mode,
+            job: self.job.unwrap_or_else(|| "buswatch".to_string()),
+            grouping: self.grouping,
+            namespace: self.namespace,
+            delete_on_shutdown: self.delete_on_shutdown,
+            timeout: self.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        }
+    }
+}
+
+/// Pushes snapshots over HTTP.
+#[derive(Debug)]
+pub struct PushOutput {
+    config: PushConfig,
+    client: reqwest::Client,
+}
+
+impl PushOutput {
+    /// Create a new push output. Nothing is sent until the first push.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the TLS backend can't be initialized, like
+    /// `reqwest::Client::new`.
+    pub fn new(config: PushConfig) -> Self {
+        // A default client would have no timeout, so a stuck receiver would
+        // block pushes forever
+        let client = reqwest::Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .expect("failed to initialize the HTTP client for pushing metrics");
+        Self { config, client }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &PushConfig {
+        &self.config
+    }
+
+    /// Push a snapshot.
+    pub async fn push(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let request = match self.config.mode {
+            PushMode::Pushgateway => self
+                .client
+                .put(self.group_url())
+                .header(
+                    reqwest::header::CONTENT_TYPE,
+                    "text/plain; version=0.0.4; charset=utf-8",
+                )
+                .body(format_prometheus(
+                    snapshot,
+                    self.config.namespace.as_deref(),
+                )),
+            #[cfg(feature = "remote-write")]
+            PushMode::RemoteWrite => self
+                .client
+                .post(&self.config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/x-protobuf")
+                .header(reqwest::header::CONTENT_ENCODING, "snappy")
+                .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+                .body(remote_write::encode(snapshot, &self.config)?),
+        };
+
+        send(request).await
+    }
+
+    /// Delete the Pushgateway group, if configured to on shutdown.
+    pub async fn close(&self) -> io::Result<()> {
+        if self.config.delete_on_shutdown && self.config.mode == PushMode::Pushgateway {
+            send(self.client.delete(self.group_url())).await?;
+        }
+        Ok(())
+    }
+
+    /// The Pushgateway URL for this job's group.
+    fn group_url(&self) -> String {
+        let mut url = format!(
+            "{}/metrics/{}",
+            self.config.url.trim_end_matches('/'),
+            grouping_segment("job", &self.config.job)
+        );
+        for (name, value) in &self.config.grouping {
+            url.push('/');
+            url.push_str(&grouping_segment(name, value));
+        }
+        url
+    }
+}
+
+async fn send(request: reqwest::RequestBuilder) -> io::Result<()> {
+    let response = request.send().await.map_err(|e| {
+        let kind = if e.is_timeout() {
+            io::ErrorKind::TimedOut
+        } else {
+            io::ErrorKind::Other
+        };
+        io::Error::new(kind, e)
+    })?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    Err(io::Error::other(format!(
+        "push rejected with {}: {}",
+        status,
+        body.trim()
+    )))
+}
+
+/// Encode one `<label>/<value>` pair of a Pushgateway URL.
+///
+/// Values that aren't plain URL-safe text (including empty values and
+/// values containing `/`) use the Pushgateway's `@base64` form.
+fn grouping_segment(name: &str, value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~'));
+    if plain {
+        format!("{}/{}", name, value)
+    } else if value.is_empty() {
+        format!("{}@base64/=", name)
+    } else {
+        format!("{}@base64/{}", name, base64(value.as_bytes(), true))
+    }
+}
+
+#[cfg(feature = "remote-write")]
+mod remote_write {
+    //! The Prometheus remote-write 1.0 wire format.
+
+    use std::io;
+
+    use buswatch_types::Snapshot;
+    use prost::Message;
+
+    use super::PushConfig;
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct WriteRequest {
+        #[prost(message, repeated, tag = "1")]
+        pub timeseries: Vec<TimeSeries>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct TimeSeries {
+        #[prost(message, repeated, tag = "1")]
+        pub labels: Vec<Label>,
+        #[prost(message, repeated, tag = "2")]
+        pub samples: Vec<Sample>,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct Label {
+        #[prost(string, tag = "1")]
+        pub name: String,
+        #[prost(string, tag = "2")]
+        pub value: String,
+    }
+
+    #[derive(Clone, PartialEq, Message)]
+    pub(super) struct Sample {
+        #[prost(double, tag = "1")]
+        pub value: f64,
+        #[prost(int64, tag = "2")]
+        pub timestamp: i64,
+    }
+
+    /// Build the write request for a snapshot, with the same metrics as the
+    /// text format.
+    pub(super) fn write_request(snapshot: &Snapshot, config: &PushConfig) -> WriteRequest {
+        let prefix = config
+            .namespace
+            .as_deref()
+            .map(|n| format!("{}_", n))
+            .unwrap_or_default();
+        let timestamp = snapshot.timestamp_ms as i64;
+        let mut timeseries = Vec::new();
+
+        let mut push = |name: &str, module: &str, topic: Option<&str>, value: f64| {
+            let mut labels = vec![
+                label("__name__", &format!("{}{}", prefix, name)),
+                label("job", &config.job),
+                label("module", module),
+            ];
+            if let Some(topic) = topic {
+                labels.push(label("topic", topic));
+            }
+            for (name, value) in &config.grouping {
+                labels.push(label(name, value));
+            }
+            // Receivers require labels sorted by name
+            labels.sort_by(|a, b| a.name.cmp(&b.name));
+            labels.dedup_by(|a, b| a.name == b.name);
+
+            timeseries.push(TimeSeries {
+                labels,
+                samples: vec![Sample { value, timestamp }],
+            });
+        };
+
+        for (module, metrics) in &snapshot.modules {
+            for (topic, read) in &metrics.reads {
+                let topic = Some(topic.as_str());
+                push("buswatch_read_count", module, topic, read.count as f64);
+                if let Some(backlog) = read.backlog {
+                    push("buswatch_read_backlog", module, topic, backlog as f64);
+                }
+                if let Some(pending) = read.pending {
+                    let seconds = pending.to_duration().as_secs_f64();
+                    push("buswatch_read_pending_seconds", module, topic, seconds);
+                }
+                if let Some(rate) = read.rate {
+                    push("buswatch_read_rate_per_second", module, topic, rate);
+                }
+            }
+            for (topic, write) in &metrics.writes {
+                let topic = Some(topic.as_str());
+                push("buswatch_write_count", module, topic, write.count as f64);
+                if let Some(pending) = write.pending {
+                    let seconds = pending.to_duration().as_secs_f64();
+                    push("buswatch_write_pending_seconds", module, topic, seconds);
+                }
+                if let Some(rate) = write.rate {
+                    push("buswatch_write_rate_per_second", module, topic, rate);
+                }
+            }
+        }
+
+        WriteRequest { timeseries }
+    }
+
+    /// Encode and snappy-compress the write request for a snapshot.
+    pub(super) fn encode(snapshot: &Snapshot, config: &PushConfig) -> io::Result<Vec<u8>> {
+        let body = write_request(snapshot, config).encode_to_vec();
+        snap::raw::Encoder::new()
+            .compress_vec(&body)
+            .map_err(io::Error::other)
+    }
+
+    fn label(name: &str, value: &str) -> Label {
+        Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Bytes;
+    use hyper::server::conn::http1;
+    use hyper::service::service_fn;
+    use hyper::{Method, Request, Response, StatusCode};
+    use hyper_util::rt::TokioIo;
+    use parking_lot::Mutex;
+    use tokio::net::TcpListener;
+
+    /// A request received by the stand-in server.
+    #[derive(Debug, Clone)]
+    struct Received {
+        method: Method,
+        path: String,
+        content_encoding: Option<String>,
+        body: Bytes,
+    }
+
+    /// Start a stand-in HTTP server that answers every request with `status`.
+    async fn stand_in(status: StatusCode) -> (SocketAddr, Arc<Mutex<Vec<Received>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let received = Arc::new(Mutex::new(Vec::new()));
+
+        let log = received.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let log = log.clone();
+                        async move {
+                            let (parts, body) = req.into_parts();
+                            let body = body.collect().await.unwrap().to_bytes();
+                            log.lock().push(Received {
+                                method: parts.method,
+                                path: parts.uri.path().to_string(),
+                                content_encoding: parts
+                                    .headers
+                                    .get("content-encoding")
+                                    .map(|v| v.to_str().unwrap().to_string()),
+                                body,
+                            });
+                            Response::builder()
+                                .status(status)
+                                .body(Full::new(Bytes::from("stand-in")))
+                                .map_err(|e| e.to_string())
+                        }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        (addr, received)
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(1_703_160_000_000)
+            .module("worker", |m| {
+                m.read("jobs", |r| r.count(42).backlog(3))
+                    .write("results", |w| w.count(40))
+            })
+            .build()
+    }
+
+    #[test]
+    fn grouping_segments_are_escaped() {
+        assert_eq!(grouping_segment("job", "import"), "job/import");
+        assert_eq!(
+            grouping_segment("path", "/var/tmp"),
+            "path@base64/L3Zhci90bXA="
+        );
+        assert_eq!(grouping_segment("instance", ""), "instance@base64/=");
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config = PushConfig::builder().build();
+        assert_eq!(config.url, "http://localhost:9091");
+        assert_eq!(config.mode, PushMode::Pushgateway);
+        assert_eq!(config.job, "buswatch");
+        assert!(!config.delete_on_shutdown);
+        assert_eq!(config.timeout, DEFAULT_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn pushes_to_pushgateway_and_deletes_on_close() {
+        let (addr, received) = stand_in(StatusCode::OK).await;
+        let output = PushOutput::new(
+            PushConfig::builder()
+                .url(format!("http://{}/", addr))
+                .job("import")
+                .grouping_label("instance", "a/b")
+                .delete_on_shutdown(true)
+                .build(),
+        );
+
+        output.push(&snapshot()).await.unwrap();
+        output.close().await.unwrap();
+
+        let received = received.lock();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].method, Method::PUT);
+        assert_eq!(received[0].content_encoding, None);
+        assert_eq!(received[0].path, "/metrics/job/import/instance@base64/YS9i");
+        let body = std::str::from_utf8(&received[0].body).unwrap();
+        assert!(body.contains("buswatch_read_count{module=\"worker\",topic=\"jobs\"} 42"));
+        assert_eq!(received[1].method, Method::DELETE);
+        assert_eq!(received[1].path, received[0].path);
+    }
+
+    #[tokio::test]
+    async fn close_keeps_group_by_default() {
+        let (addr, received) = stand_in(StatusCode::OK).await;
+        let output = PushOutput::new(
+            PushConfig::builder()
+                .url(format!("http://{}", addr))
+                .build(),
+        );
+
+        output.close().await.unwrap();
+        assert!(received.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn rejected_push_is_an_error() {
+        let (addr, _) = stand_in(StatusCode::BAD_REQUEST).await;
+        let output = PushOutput::new(
+            PushConfig::builder()
+                .url(format!("http://{}", addr))
+                .build(),
+        );
+
+        let err = output.push(&snapshot()).await.unwrap_err();
+        assert!(err.to_string().contains("400"));
+        assert!(err.to_string().contains("stand-in"));
+    }
+
+    #[cfg(feature = "remote-write")]
+    #[tokio::test]
+    async fn remote_write_sends_snappy_protobuf() {
+        use prost::Message;
+        use remote_write::WriteRequest;
+
+        let (addr, received) = stand_in(StatusCode::NO_CONTENT).await;
+        let output = PushOutput::new(
+            PushConfig::builder()
+                .url(format!("http://{}/api/v1/write", addr))
+                .mode(PushMode::RemoteWrite)
+                .job("import")
+                .grouping_label("instance", "worker-1")
+                .delete_on_shutdown(true)
+                .build(),
+        );
+
+        output.push(&snapshot()).await.unwrap();
+        output.close().await.unwrap();
+
+        let received = received.lock();
+        // Nothing to delete in remote-write mode
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].method, Method::POST);
+        assert_eq!(received[0].path, "/api/v1/write");
+        assert_eq!(received[0].content_encoding.as_deref(), Some("snappy"));
+
+        let body = snap::raw::Decoder::new()
+            .decompress_vec(&received[0].body)
+            .unwrap();
+        let request = WriteRequest::decode(body.as_slice()).unwrap();
+        assert_eq!(request.timeseries.len(), 3);
+
+        let count = &request.timeseries[0];
+        let labels: Vec<_> = count
+            .labels
+            .iter()
+            .map(|l| (l.name.as_str(), l.value.as_str()))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("__name__", "buswatch_read_count"),
+                ("instance", "worker-1"),
+                ("job", "import"),
+                ("module", "worker"),
+                ("topic", "jobs"),
+            ]
+        );
+        assert_eq!(count.samples[0].value, 42.0);
+        assert_eq!(count.samples[0].timestamp, 1_703_160_000_000);
+    }
+}
@@ -1,28 +1,227 @@
 //! Internal state management for metrics collection.
 
-use std::collections::BTreeMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+use buswatch_types::{
+    BacklogSource, Clock, LatencyHistogram, Microseconds, ModuleMetrics, ReadMetrics, Snapshot,
+    SystemClock, WriteMetrics,
+};
+use parking_lot::{Mutex, RwLock};
+
+/// Upper bounds of the handling latency buckets, in microseconds.
+pub(crate) const LATENCY_BOUNDS_US: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Topic that absorbs metrics for new topics once a module has reached its
+/// topic limit.
+pub const OVERFLOW_TOPIC: &str = "_overflow";
+
+/// Bounds on how much state is kept for modules and topics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    /// Remove topics that have not been used for this long
+    pub topic_ttl: Option<Duration>,
+    /// Maximum read topics and write topics per module
+    pub max_topics: Option<usize>,
+    /// Remove modules once all of their handles have been dropped
+    pub remove_dropped_modules: bool,
+}
+
+/// The instrumentor's clock, shared by all of its state.
+#[derive(Clone)]
+pub struct SharedClock {
+    clock: Arc<dyn Clock>,
+    /// Reference point for the millisecond times kept in atomics
+    epoch: Instant,
+}
+
+impl SharedClock {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        let epoch = clock.now();
+        Self { clock, epoch }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    pub fn unix_millis(&self) -> u64 {
+        self.clock.unix_millis()
+    }
+
+    /// Milliseconds since the clock was shared, cheap enough to store on
+    /// every record.
+    pub fn millis(&self) -> u64 {
+        self.now().saturating_duration_since(self.epoch).as_millis() as u64
+    }
+}
+
+impl Default for SharedClock {
+    fn default() -> Self {
+        Self::new(Arc::new(SystemClock))
+    }
+}
+
+impl std::fmt::Debug for SharedClock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.clock.fmt(f)
+    }
+}
+
+/// Operations currently outstanding on a topic.
+#[derive(Debug, Default)]
+pub struct Inflight {
+    next_id: u64,
+    /// Start time of each outstanding operation, keyed by id in start order
+    started: BTreeMap<u64, Instant>,
+    /// Start time of each idle wait, which counts towards pending only
+    waiting: BTreeMap<u64, Instant>,
+    /// Pending start set directly rather than through a guard
+    manual: Option<Instant>,
+}
+
+impl Inflight {
+    /// Record the start of an operation, returning its id.
+    pub fn start(&mut self, now: Instant) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.started.insert(id, now);
+        id
+    }
+
+    /// Record the start of an idle wait, returning its id.
+    ///
+    /// A wait is reported as pending, but isn't an outstanding operation.
+    #[cfg_attr(not(any(feature = "tokio", feature = "futures")), allow(dead_code))]
+    pub fn wait(&mut self, now: Instant) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.waiting.insert(id, now);
+        id
+    }
+
+    /// Remove a finished operation or wait, returning when an operation
+    /// started.
+    pub fn finish(&mut self, id: u64) -> Option<Instant> {
+        self.waiting.remove(&id);
+        self.started.remove(&id)
+    }
+
+    /// Set or clear the directly-reported pending start.
+    pub fn set_manual(&mut self, since: Option<Instant>) {
+        self.manual = since;
+    }
+
+    /// Number of outstanding operations.
+    pub fn count(&self) -> u64 {
+        self.started.len() as u64 + u64::from(self.manual.is_some())
+    }
+
+    /// Start time of the oldest outstanding operation.
+    pub fn oldest(&self) -> Option<Instant> {
+        // Ids are assigned in start order, so the first entry is the oldest
+        [
+            self.started.values().next().copied(),
+            self.waiting.values().next().copied(),
+            self.manual,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Pending duration of the oldest operation and the outstanding count.
+    fn collect(&self, now: Instant) -> (Option<Microseconds>, Option<u64>) {
+        let pending = self
+            .oldest()
+            .map(|since| Microseconds::from(now.saturating_duration_since(since)));
+        let count = self.count();
+        (pending, (count > 0).then_some(count))
+    }
+}
+
+/// Lock-free histogram of how long operations took to complete.
+#[derive(Debug)]
+pub struct LatencyRecorder {
+    buckets: [AtomicU64; LATENCY_BOUNDS_US.len() + 1],
+    sum_us: AtomicU64,
+}
+
+impl Default for LatencyRecorder {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_us: AtomicU64::new(0),
+        }
+    }
+}
+
+impl LatencyRecorder {
+    /// Record how long a single operation took.
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        let bucket = LATENCY_BOUNDS_US.partition_point(|bound| *bound < micros);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_us.fetch_add(micros, Ordering::Relaxed);
+    }
+
+    /// Snapshot the histogram, or `None` if nothing has been recorded.
+    pub fn collect(&self) -> Option<LatencyHistogram> {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|b| b.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = counts.iter().sum();
+        if count == 0 {
+            return None;
+        }
 
-use buswatch_types::{Microseconds, ModuleMetrics, ReadMetrics, Snapshot, WriteMetrics};
-use parking_lot::RwLock;
+        Some(LatencyHistogram {
+            bounds: LATENCY_BOUNDS_US
+                .iter()
+                .map(|us| Microseconds::from_micros(*us))
+                .collect(),
+            counts,
+            count,
+            sum: Microseconds::from_micros(self.sum_us.load(Ordering::Relaxed)),
+        })
+    }
+}
 
 /// Thread-safe metrics for a single topic read stream.
 #[derive(Debug)]
 pub struct ReadState {
     pub count: AtomicU64,
-    pub pending_since: RwLock<Option<Instant>>,
+    /// Reads whose handling failed
+    pub errors: AtomicU64,
+    /// Reads currently being handled
+    pub inflight: Mutex<Inflight>,
+    /// How long completed reads took to handle
+    pub latency: LatencyRecorder,
+    /// Backlog measured from the underlying queue, if known
+    pub backlog: RwLock<Option<u64>>,
     /// Previous count and timestamp for rate computation
     pub prev_snapshot: RwLock<Option<(u64, Instant)>>,
+    /// When the topic was last used, from [`SharedClock::millis`]
+    pub last_active: AtomicU64,
 }
 
 impl Default for ReadState {
     fn default() -> Self {
         Self {
             count: AtomicU64::new(0),
-            pending_since: RwLock::new(None),
+            errors: AtomicU64::new(0),
+            inflight: Mutex::new(Inflight::default()),
+            latency: LatencyRecorder::default(),
+            backlog: RwLock::new(None),
             prev_snapshot: RwLock::new(None),
+            last_active: AtomicU64::new(0),
         }
     }
 }
@@ -31,19 +230,100 @@ impl Default for ReadState {
 #[derive(Debug)]
 pub struct WriteState {
     pub count: AtomicU64,
-    pub pending_since: RwLock<Option<Instant>>,
+    /// Writes that failed
+    pub errors: AtomicU64,
+    /// Writes currently in progress
+    pub inflight: Mutex<Inflight>,
+    /// How long completed writes took
+    pub latency: LatencyRecorder,
     /// Previous count and timestamp for rate computation
     pub prev_snapshot: RwLock<Option<(u64, Instant)>>,
+    /// When the topic was last used, from [`SharedClock::millis`]
+    pub last_active: AtomicU64,
+    /// Whether this is the module's overflow topic
+    pub overflow: bool,
 }
 
 impl Default for WriteState {
     fn default() -> Self {
         Self {
             count: AtomicU64::new(0),
-            pending_since: RwLock::new(None),
+            errors: AtomicU64::new(0),
+            inflight: Mutex::new(Inflight::default()),
+            latency: LatencyRecorder::default(),
             prev_snapshot: RwLock::new(None),
+            last_active: AtomicU64::new(0),
+            overflow: false,
+        }
+    }
+}
+
+/// Find or create the state for `topic`, falling back to the overflow topic
+/// once `max_topics` other topics exist.
+fn get_or_create_topic<T>(
+    topics: &RwLock<BTreeMap<String, Arc<T>>>,
+    topic: &str,
+    max_topics: Option<usize>,
+    now: u64,
+    last_active: impl Fn(&T) -> &AtomicU64,
+    overflow: impl FnOnce() -> T,
+) -> Arc<T>
+where
+    T: Default,
+{
+    // Fast path: check if it exists
+    {
+        let topics = topics.read();
+        if let Some(state) = topics.get(topic) {
+            last_active(state).store(now, Ordering::Relaxed);
+            return state.clone();
         }
     }
+
+    // Slow path: create it
+    // Double-check after acquiring write lock
+    let mut topics = topics.write();
+    if let Some(state) = topics.get(topic) {
+        last_active(state).store(now, Ordering::Relaxed);
+        return state.clone();
+    }
+
+    let tracked = topics.len() - usize::from(topics.contains_key(OVERFLOW_TOPIC));
+    if max_topics.is_some_and(|max| tracked >= max) {
+        let state = topics
+            .entry(OVERFLOW_TOPIC.to_string())
+            .or_insert_with(|| Arc::new(overflow()));
+        last_active(state).store(now, Ordering::Relaxed);
+        return state.clone();
+    }
+
+    let state = topics
+        .entry(topic.to_string())
+        .or_insert_with(|| Arc::new(T::default()));
+    last_active(state).store(now, Ordering::Relaxed);
+    state.clone()
+}
+
+/// Remove topics unused for at least `ttl` milliseconds that nothing else
+/// references, returning how many were removed.
+fn evict_topics<T>(
+    topics: &RwLock<BTreeMap<String, Arc<T>>>,
+    now: u64,
+    ttl: u64,
+    last_active: impl Fn(&T) -> &AtomicU64,
+    inflight: impl Fn(&T) -> u64,
+) -> u64 {
+    let mut evicted = 0;
+    topics.write().retain(|_, state| {
+        // Outstanding guards hold a reference, so this also spares
+        // topics with operations in flight
+        let idle = now.saturating_sub(last_active(state).load(Ordering::Relaxed)) >= ttl
+            && Arc::strong_count(state) == 1
+            && inflight(state) == 0;
+        evicted += u64::from(idle);
+        !idle
+    });
+    evicted
 }
 
 /// Compute rate (messages per second) from previous and current state.
@@ -66,51 +346,88 @@ fn compute_rate(prev: Option<(u64, Instant)>, current_count: u64, now: Instant)
 pub struct ModuleState {
     pub reads: RwLock<BTreeMap<String, Arc<ReadState>>>,
     pub writes: RwLock<BTreeMap<String, Arc<WriteState>>>,
+    pub limits: Limits,
+    pub clock: SharedClock,
+    /// Consumer group whose members share the reads of each topic
+    pub group: RwLock<Option<String>>,
+    /// Number of live `ModuleHandle`s for this module
+    pub handles: AtomicUsize,
+    /// Topics removed by TTL eviction
+    pub evicted: AtomicU64,
+    /// Topics in the last snapshot this module emitted (self-metrics only)
+    pub tracked_topics: RwLock<Option<u64>>,
 }
 
 impl ModuleState {
-    /// Get or create a read state for a topic.
-    pub fn get_or_create_read(&self, topic: &str) -> Arc<ReadState> {
-        // Fast path: check if it exists
-        {
-            let reads = self.reads.read();
-            if let Some(state) = reads.get(topic) {
-                return state.clone();
-            }
+    /// Create an empty module subject to `limits`.
+    pub fn new(limits: Limits, clock: SharedClock) -> Self {
+        Self {
+            limits,
+            clock,
+            ..Self::default()
         }
+    }
 
-        // Slow path: create it
-        // Double-check after acquiring write lock
-        let mut reads = self.reads.write();
-        reads
-            .entry(topic.to_string())
-            .or_insert_with(|| Arc::new(ReadState::default()))
-            .clone()
+    /// Get or create a read state for a topic.
+    ///
+    /// Once the module has `max_topics` read topics, new topics share the
+    /// [`OVERFLOW_TOPIC`] state.
+    pub fn get_or_create_read(&self, topic: &str) -> Arc<ReadState> {
+        get_or_create_topic(
+            &self.reads,
+            topic,
+            self.limits.max_topics,
+            self.clock.millis(),
+            |state| &state.last_active,
+            ReadState::default,
+        )
     }
 
     /// Get or create a write state for a topic.
+    ///
+    /// Once the module has `max_topics` write topics, new topics share the
+    /// [`OVERFLOW_TOPIC`] state.
     pub fn get_or_create_write(&self, topic: &str) -> Arc<WriteState> {
-        // Fast path: check if it exists
-        {
-            let writes = self.writes.read();
-            if let Some(state) = writes.get(topic) {
-                return state.clone();
-            }
-        }
+        get_or_create_topic(
+            &self.writes,
+            topic,
+            self.limits.max_topics,
+            self.clock.millis(),
+            |state| &state.last_active,
+            || WriteState {
+                overflow: true,
+                ..WriteState::default()
+            },
+        )
+    }
 
-        // Slow path: create it
-        let mut writes = self.writes.write();
-        writes
-            .entry(topic.to_string())
-            .or_insert_with(|| Arc::new(WriteState::default()))
-            .clone()
+    /// Remove topics that have not been used for `ttl`, returning how many
+    /// were removed.
+    pub fn evict_idle(&self, ttl: Duration) -> u64 {
+        let now = self.clock.millis();
+        let ttl = ttl.as_millis() as u64;
+        let evicted = evict_topics(
+            &self.reads,
+            now,
+            ttl,
+            |state| &state.last_active,
+            |state| state.inflight.lock().count(),
+        ) + evict_topics(
+            &self.writes,
+            now,
+            ttl,
+            |state| &state.last_active,
+            |state| state.inflight.lock().count(),
+        );
+        self.evicted.fetch_add(evicted, Ordering::Relaxed);
+        evicted
     }
 
     /// Collect current metrics into a ModuleMetrics snapshot.
     ///
     /// This also updates the previous snapshot for rate computation.
     pub fn collect(&self) -> ModuleMetrics {
-        let now = Instant::now();
+        let now = self.clock.now();
 
         let reads = self
             .reads
@@ -118,10 +435,7 @@ impl ModuleState {
             .iter()
             .map(|(topic, state)| {
                 let count = state.count.load(Ordering::Relaxed);
-                let pending = state.pending_since.read().map(|since| {
-                    let duration = now.duration_since(since);
-                    Microseconds::from(duration)
-                });
+                let (pending, inflight) = state.inflight.lock().collect(now);
 
                 // Compute rate from previous snapshot
                 let prev = *state.prev_snapshot.read();
@@ -130,13 +444,19 @@ impl ModuleState {
                 // Update previous snapshot for next collection
                 *state.prev_snapshot.write() = Some((count, now));
 
+                let backlog = *state.backlog.read();
                 (
                     topic.clone(),
                     ReadMetrics {
                         count,
-                        backlog: None, // SDK computes backlog at GlobalState level
+                        // Measured backlog if known, otherwise estimated at GlobalState level
+                        backlog,
                         pending,
                         rate,
+                        inflight,
+                        latency: state.latency.collect(),
+                        errors: Some(state.errors.load(Ordering::Relaxed)).filter(|e| *e > 0),
+                        backlog_source: backlog.map(|_| BacklogSource::Measured),
                     },
                 )
             })
@@ -148,10 +468,7 @@ impl ModuleState {
             .iter()
             .map(|(topic, state)| {
                 let count = state.count.load(Ordering::Relaxed);
-                let pending = state.pending_since.read().map(|since| {
-                    let duration = now.duration_since(since);
-                    Microseconds::from(duration)
-                });
+                let (pending, inflight) = state.inflight.lock().collect(now);
 
                 // Compute rate from previous snapshot
                 let prev = *state.prev_snapshot.read();
@@ -166,12 +483,21 @@ impl ModuleState {
                         count,
                         pending,
                         rate,
+                        inflight,
+                        latency: state.latency.collect(),
+                        errors: Some(state.errors.load(Ordering::Relaxed)).filter(|e| *e > 0),
                     },
                 )
             })
             .collect();
 
-        ModuleMetrics { reads, writes }
+        let evicted = self.evicted.load(Ordering::Relaxed);
+        ModuleMetrics {
+            reads,
+            writes,
+            evicted_topics: (evicted > 0).then_some(evicted),
+            tracked_topics: *self.tracked_topics.read(),
+        }
     }
 }
 
@@ -181,25 +507,77 @@ pub struct GlobalState {
     pub modules: RwLock<BTreeMap<String, Arc<ModuleState>>>,
     /// Track total writes per topic across all modules (for computing backlog)
     pub topic_write_counts: RwLock<BTreeMap<String, Arc<AtomicU64>>>,
+    pub limits: Limits,
+    pub clock: SharedClock,
+    /// Modules unregistered since the last collect, reported one last time
+    retired: Mutex<Vec<(String, Arc<ModuleState>)>>,
+    /// Modules holding a handle of their own until unregistered
+    retained: Mutex<BTreeMap<String, Arc<ModuleState>>>,
 }
 
 impl GlobalState {
-    /// Register a new module or get existing one.
-    pub fn register_module(&self, name: &str) -> Arc<ModuleState> {
-        // Fast path
+    /// Create empty state subject to `limits`.
+    pub fn with_limits(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Use `clock` for rates, pending durations and timestamps.
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Register a new module or get existing one, counting a new handle.
+    ///
+    /// The handle is counted under the lock so a concurrent collect can't
+    /// remove the module in between.
+    pub fn acquire_module(&self, name: &str) -> Arc<ModuleState> {
         {
             let modules = self.modules.read();
             if let Some(state) = modules.get(name) {
+                state.handles.fetch_add(1, Ordering::Relaxed);
                 return state.clone();
             }
         }
 
-        // Slow path
         let mut modules = self.modules.write();
-        modules
+        let state = modules
             .entry(name.to_string())
-            .or_insert_with(|| Arc::new(ModuleState::default()))
-            .clone()
+            .or_insert_with(|| Arc::new(ModuleState::new(self.limits, self.clock.clone())))
+            .clone();
+        state.handles.fetch_add(1, Ordering::Relaxed);
+        state
+    }
+
+    /// Like [`acquire_module`](Self::acquire_module), but the module also
+    /// keeps a handle of its own until it is unregistered, so it survives
+    /// between short-lived handles.
+    pub fn acquire_retained(&self, name: &str) -> Arc<ModuleState> {
+        let mut retained = self.retained.lock();
+        if let Some(state) = retained.get(name) {
+            state.handles.fetch_add(1, Ordering::Relaxed);
+            return state.clone();
+        }
+
+        let state = self.acquire_module(name);
+        state.handles.fetch_add(1, Ordering::Relaxed);
+        retained.insert(name.to_string(), state.clone());
+        state
+    }
+
+    /// Remove a module. It appears in the next snapshot one final time.
+    pub fn unregister(&self, name: &str) -> bool {
+        let Some(state) = self.modules.write().remove(name) else {
+            return false;
+        };
+        if let Some(retained) = self.retained.lock().remove(name) {
+            retained.handles.fetch_sub(1, Ordering::Relaxed);
+        }
+        self.retired.lock().push((name.to_string(), state));
+        true
     }
 
     /// Get or create a global write counter for a topic.
@@ -221,35 +599,123 @@ impl GlobalState {
     }
 
     /// Collect all modules into a Snapshot.
+    ///
+    /// This is also where limits are enforced: idle topics are evicted, and
+    /// unregistered or dropped modules are removed after being included.
     pub fn collect(&self) -> Snapshot {
-        let modules = self.modules.read();
-        let topic_writes = self.topic_write_counts.read();
+        let mut snapshot = Snapshot::builder().timestamp_ms(self.clock.unix_millis());
+        let mut dropped = Vec::new();
+        let mut pruned = false;
+
+        {
+            let modules = self.modules.read();
+            let topic_writes = self.topic_write_counts.read();
+            let retired = std::mem::take(&mut *self.retired.lock());
+            pruned |= !retired.is_empty();
+
+            // A module registered again under the same name replaces its
+            // retired predecessor
+            let retired = retired
+                .iter()
+                .filter(|(name, _)| !modules.contains_key(name))
+                .map(|(name, state)| (name, state));
+
+            let mut collected = Vec::new();
+            for (name, state) in retired.chain(modules.iter()) {
+                if let Some(ttl) = self.limits.topic_ttl {
+                    pruned |= state.evict_idle(ttl) > 0;
+                }
+                if self.limits.remove_dropped_modules && state.handles.load(Ordering::Relaxed) == 0
+                {
+                    dropped.push(name.clone());
+                }
 
-        let mut snapshot = Snapshot::builder();
+                collected.push((name, state.group.read().clone(), state.collect()));
+            }
 
-        for (name, state) in modules.iter() {
-            let mut metrics = state.collect();
+            // Members of a consumer group each see a share of a topic's
+            // messages, so the group's backlog is measured against their
+            // combined reads
+            let mut group_reads: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+            for (_, group, metrics) in &collected {
+                if let Some(group) = group {
+                    let topics = group_reads.entry(group.clone()).or_default();
+                    for (topic, read_metrics) in &metrics.reads {
+                        *topics.entry(topic.clone()).or_default() += read_metrics.count;
+                    }
+                }
+            }
 
-            // Compute backlog for each read topic
-            for (topic, read_metrics) in metrics.reads.iter_mut() {
-                if let Some(total_writes) = topic_writes.get(topic) {
-                    let total = total_writes.load(Ordering::Relaxed);
-                    if total > read_metrics.count {
-                        read_metrics.backlog = Some(total - read_metrics.count);
+            for (name, group, mut metrics) in collected {
+                // Estimate backlog for each read topic that has no measured value
+                for (topic, read_metrics) in metrics.reads.iter_mut() {
+                    if read_metrics.backlog.is_some() {
+                        continue;
+                    }
+                    let consumed = group
+                        .as_ref()
+                        .and_then(|group| group_reads.get(group)?.get(topic).copied())
+                        .unwrap_or(read_metrics.count);
+                    if let Some(total_writes) = topic_writes.get(topic) {
+                        let total = total_writes.load(Ordering::Relaxed);
+                        if total > consumed {
+                            read_metrics.backlog = Some(total - consumed);
+                            read_metrics.backlog_source = Some(BacklogSource::Estimated);
+                        }
                     }
                 }
+
+                snapshot = snapshot.module_metrics(name.clone(), metrics);
+            }
+        }
+
+        if !dropped.is_empty() {
+            let mut modules = self.modules.write();
+            for name in dropped {
+                // Skip modules that gained a handle since being collected
+                if modules
+                    .get(&name)
+                    .is_some_and(|state| state.handles.load(Ordering::Relaxed) == 0)
+                {
+                    modules.remove(&name);
+                    pruned = true;
+                }
             }
+        }
 
-            snapshot = snapshot.module_metrics(name.clone(), metrics);
+        if pruned {
+            self.prune_write_counts();
         }
 
         snapshot.build()
     }
+
+    /// Drop global write counters for topics no module uses any more.
+    fn prune_write_counts(&self) {
+        let modules = self.modules.read();
+        // Held while scanning so a counter created for a new topic
+        // afterwards is not removed
+        let mut counts = self.topic_write_counts.write();
+
+        let mut live = BTreeSet::new();
+        for state in modules.values() {
+            live.extend(state.reads.read().keys().cloned());
+            live.extend(state.writes.read().keys().cloned());
+        }
+        counts.retain(|topic, _| live.contains(topic));
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use buswatch_types::ManualClock;
+
+    fn manual_module(limits: Limits) -> (ModuleState, ManualClock) {
+        let clock = ManualClock::default();
+        let state = ModuleState::new(limits, SharedClock::new(Arc::new(clock.clone())));
+        (state, clock)
+    }
 
     #[test]
     fn test_module_state_read_write() {
@@ -270,8 +736,8 @@ mod tests {
     fn test_global_state_collect() {
         let global = GlobalState::default();
 
-        let module1 = global.register_module("service-a");
-        let module2 = global.register_module("service-b");
+        let module1 = global.acquire_module("service-a");
+        let module2 = global.acquire_module("service-b");
 
         module1
             .get_or_create_read("events")
@@ -320,11 +786,11 @@ mod tests {
     }
 
     #[test]
-    fn register_module_returns_same_arc_on_second_call() {
+    fn acquire_module_returns_same_arc_on_second_call() {
         let global = GlobalState::default();
 
-        let m1 = global.register_module("service");
-        let m2 = global.register_module("service");
+        let m1 = global.acquire_module("service");
+        let m2 = global.acquire_module("service");
 
         assert!(Arc::ptr_eq(&m1, &m2));
     }
@@ -333,8 +799,8 @@ mod tests {
     fn backlog_computed_correctly_when_writes_exceed_reads() {
         let global = GlobalState::default();
 
-        let producer = global.register_module("producer");
-        let consumer = global.register_module("consumer");
+        let producer = global.acquire_module("producer");
+        let consumer = global.acquire_module("consumer");
 
         // Producer writes 100 messages
         producer
@@ -363,7 +829,7 @@ mod tests {
     fn backlog_is_none_when_no_global_writes_tracked() {
         let global = GlobalState::default();
 
-        let consumer = global.register_module("consumer");
+        let consumer = global.acquire_module("consumer");
         consumer
             .get_or_create_read("events")
             .count
@@ -381,8 +847,8 @@ mod tests {
     fn backlog_is_none_when_reads_equal_writes() {
         let global = GlobalState::default();
 
-        let producer = global.register_module("producer");
-        let consumer = global.register_module("consumer");
+        let producer = global.acquire_module("producer");
+        let consumer = global.acquire_module("consumer");
 
         producer
             .get_or_create_write("events")
@@ -405,21 +871,178 @@ mod tests {
         assert_eq!(events_read.backlog, None);
     }
 
+    #[test]
+    fn measured_backlog_takes_precedence_over_estimate() {
+        let global = GlobalState::default();
+
+        let producer = global.acquire_module("producer");
+        let consumer = global.acquire_module("consumer");
+
+        producer
+            .get_or_create_write("events")
+            .count
+            .fetch_add(100, Ordering::Relaxed);
+        global
+            .get_topic_write_counter("events")
+            .fetch_add(100, Ordering::Relaxed);
+
+        let read = consumer.get_or_create_read("events");
+        read.count.fetch_add(70, Ordering::Relaxed);
+        *read.backlog.write() = Some(3);
+
+        let snapshot = global.collect();
+        let events_read = snapshot.modules["consumer"].reads.get("events").unwrap();
+        assert_eq!(events_read.backlog, Some(3));
+        assert_eq!(events_read.backlog_source, Some(BacklogSource::Measured));
+    }
+
+    #[test]
+    fn consumer_group_members_share_backlog() {
+        let global = GlobalState::default();
+        global
+            .get_topic_write_counter("events")
+            .fetch_add(100, Ordering::Relaxed);
+
+        for (name, reads) in [("worker-1", 40), ("worker-2", 35)] {
+            let worker = global.acquire_module(name);
+            *worker.group.write() = Some("workers".to_string());
+            worker
+                .get_or_create_read("events")
+                .count
+                .fetch_add(reads, Ordering::Relaxed);
+        }
+
+        let snapshot = global.collect();
+        for name in ["worker-1", "worker-2"] {
+            let read = &snapshot.modules[name].reads["events"];
+            assert_eq!(read.backlog, Some(25));
+            assert_eq!(read.backlog_source, Some(BacklogSource::Estimated));
+        }
+    }
+
+    #[test]
+    fn fan_out_consumers_have_independent_backlogs() {
+        let global = GlobalState::default();
+        global
+            .get_topic_write_counter("events")
+            .fetch_add(100, Ordering::Relaxed);
+
+        let audit = global.acquire_module("audit");
+        audit
+            .get_or_create_read("events")
+            .count
+            .fetch_add(90, Ordering::Relaxed);
+        let billing = global.acquire_module("billing");
+        *billing.group.write() = Some("billing".to_string());
+        billing
+            .get_or_create_read("events")
+            .count
+            .fetch_add(60, Ordering::Relaxed);
+
+        let snapshot = global.collect();
+        assert_eq!(snapshot.modules["audit"].reads["events"].backlog, Some(10));
+        assert_eq!(
+            snapshot.modules["billing"].reads["events"].backlog,
+            Some(40)
+        );
+    }
+
     #[test]
     fn pending_time_captured_in_collect() {
-        let state = ModuleState::default();
+        let (state, clock) = manual_module(Limits::default());
 
         let read = state.get_or_create_read("topic");
-        *read.pending_since.write() = Some(Instant::now());
-
-        // Small sleep to ensure measurable pending time
-        std::thread::sleep(std::time::Duration::from_millis(5));
+        read.inflight.lock().start(clock.now());
+        clock.advance(Duration::from_millis(5));
 
         let metrics = state.collect();
         let pending = metrics.reads.get("topic").unwrap().pending;
 
-        assert!(pending.is_some());
-        assert!(pending.unwrap().as_micros() >= 5000); // At least 5ms
+        assert_eq!(pending, Some(Microseconds::from_millis(5)));
+    }
+
+    #[test]
+    fn pending_reports_oldest_inflight_operation() {
+        let mut inflight = Inflight::default();
+        let now = Instant::now();
+
+        let first = inflight.start(now - Duration::from_millis(30));
+        let second = inflight.start(now - Duration::from_millis(10));
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(30)), Some(2))
+        );
+
+        // Finishing the newer operation leaves the oldest in place
+        inflight.finish(second);
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(30)), Some(1))
+        );
+
+        inflight.finish(first);
+        assert_eq!(inflight.collect(now), (None, None));
+    }
+
+    #[test]
+    fn manual_pending_counts_as_inflight() {
+        let mut inflight = Inflight::default();
+        let now = Instant::now();
+
+        inflight.start(now - Duration::from_millis(5));
+        inflight.set_manual(Some(now - Duration::from_millis(20)));
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(20)), Some(2))
+        );
+
+        inflight.set_manual(None);
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(5)), Some(1))
+        );
+    }
+
+    #[test]
+    fn waits_are_pending_but_not_inflight() {
+        let mut inflight = Inflight::default();
+        let now = Instant::now();
+
+        let wait = inflight.wait(now - Duration::from_millis(40));
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(40)), None)
+        );
+
+        inflight.start(now - Duration::from_millis(10));
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(40)), Some(1))
+        );
+
+        assert_eq!(inflight.finish(wait), None);
+        assert_eq!(
+            inflight.collect(now),
+            (Some(Microseconds::from_millis(10)), Some(1))
+        );
+    }
+
+    #[test]
+    fn latency_recorder_buckets_durations() {
+        let recorder = LatencyRecorder::default();
+        assert!(recorder.collect().is_none());
+
+        recorder.record(Duration::from_micros(80));
+        recorder.record(Duration::from_millis(1));
+        recorder.record(Duration::from_secs(30));
+
+        let histogram = recorder.collect().unwrap();
+        assert_eq!(histogram.count, 3);
+        assert_eq!(histogram.counts.len(), histogram.bounds.len() + 1);
+        assert_eq!(histogram.counts[0], 1);
+        assert_eq!(histogram.counts[3], 1); // 1ms is an inclusive bound
+        assert_eq!(*histogram.counts.last().unwrap(), 1);
+        assert_eq!(histogram.sum.as_micros(), 80 + 1_000 + 30_000_000);
     }
 
     #[test]
@@ -427,7 +1050,7 @@ mod tests {
         use std::thread;
 
         let global = Arc::new(GlobalState::default());
-        let module = global.register_module("test");
+        let module = global.acquire_module("test");
 
         let mut handles = vec![];
         for _ in 0..10 {
@@ -494,7 +1117,7 @@ mod tests {
 
     #[test]
     fn rate_computed_on_second_collection() {
-        let state = ModuleState::default();
+        let (state, clock) = manual_module(Limits::default());
 
         let read = state.get_or_create_read("topic");
         let write = state.get_or_create_write("output");
@@ -506,8 +1129,7 @@ mod tests {
         // First collection to establish baseline
         let _ = state.collect();
 
-        // Wait a bit and add some messages
-        std::thread::sleep(std::time::Duration::from_millis(50));
+        clock.advance(Duration::from_millis(50));
         read.count.fetch_add(100, Ordering::Relaxed);
         write.count.fetch_add(50, Ordering::Relaxed);
 
@@ -520,26 +1142,14 @@ mod tests {
         assert!(read_rate.is_some(), "Read rate should be computed");
         assert!(write_rate.is_some(), "Write rate should be computed");
 
-        // Rate should be approximately 100 messages / 0.05 seconds = 2000 msg/s
-        // Allow for timing variations
-        let read_rate = read_rate.unwrap();
-        assert!(
-            read_rate > 500.0 && read_rate < 10000.0,
-            "Read rate {} should be reasonable",
-            read_rate
-        );
-
-        let write_rate = write_rate.unwrap();
-        assert!(
-            write_rate > 250.0 && write_rate < 5000.0,
-            "Write rate {} should be reasonable",
-            write_rate
-        );
+        // 100 messages / 0.05 seconds = 2000 msg/s
+        assert!((read_rate.unwrap() - 2000.0).abs() < 1e-6);
+        assert!((write_rate.unwrap() - 1000.0).abs() < 1e-6);
     }
 
     #[test]
     fn rate_handles_zero_delta() {
-        let state = ModuleState::default();
+        let (state, clock) = manual_module(Limits::default());
 
         let read = state.get_or_create_read("topic");
         read.count.store(100, Ordering::Relaxed);
@@ -547,8 +1157,8 @@ mod tests {
         // First collection
         let _ = state.collect();
 
-        // Wait but don't add any messages
-        std::thread::sleep(std::time::Duration::from_millis(20));
+        // Time passes but no messages arrive
+        clock.advance(Duration::from_millis(20));
 
         // Second collection
         let metrics = state.collect();
@@ -589,4 +1199,135 @@ mod tests {
         let rate = compute_rate(None, 100, now);
         assert!(rate.is_none());
     }
+
+    #[test]
+    fn idle_topics_are_evicted_and_counted() {
+        let clock = ManualClock::default();
+        let global = GlobalState::with_limits(Limits {
+            topic_ttl: Some(Duration::from_secs(60)),
+            ..Limits::default()
+        })
+        .with_clock(SharedClock::new(Arc::new(clock.clone())));
+        let module = global.acquire_module("svc");
+        module
+            .get_or_create_write("events")
+            .count
+            .fetch_add(1, Ordering::Relaxed);
+        global
+            .get_topic_write_counter("events")
+            .fetch_add(1, Ordering::Relaxed);
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!global.collect().modules["svc"].writes.is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        let snapshot = global.collect();
+        let metrics = snapshot.modules.get("svc").unwrap();
+        assert!(metrics.writes.is_empty());
+        assert_eq!(metrics.evicted_topics, Some(1));
+        assert!(global.topic_write_counts.read().is_empty());
+    }
+
+    #[test]
+    fn pending_operations_prevent_eviction() {
+        let (state, clock) = manual_module(Limits::default());
+        let read = state.get_or_create_read("events");
+        read.inflight.lock().start(clock.now());
+        clock.advance(Duration::from_secs(60));
+
+        assert_eq!(state.evict_idle(Duration::from_secs(1)), 0);
+        drop(read);
+        assert_eq!(state.evict_idle(Duration::from_secs(1)), 0);
+        assert!(state.reads.read().contains_key("events"));
+    }
+
+    #[test]
+    fn topics_beyond_limit_share_overflow_topic() {
+        let state = ModuleState::new(
+            Limits {
+                max_topics: Some(2),
+                ..Limits::default()
+            },
+            SharedClock::default(),
+        );
+        for topic in ["a", "b", "c", "d"] {
+            state
+                .get_or_create_read(topic)
+                .count
+                .fetch_add(1, Ordering::Relaxed);
+        }
+
+        let metrics = state.collect();
+        assert_eq!(metrics.reads.len(), 3);
+        assert_eq!(metrics.reads.get(OVERFLOW_TOPIC).unwrap().count, 2);
+        assert!(!state.get_or_create_write("a").overflow);
+        assert!(!state.get_or_create_write("b").overflow);
+        assert!(state.get_or_create_write("c").overflow);
+    }
+
+    #[test]
+    fn unregistered_module_is_reported_once() {
+        let global = GlobalState::default();
+        global.acquire_module("svc");
+
+        assert!(global.unregister("svc"));
+        assert!(!global.unregister("svc"));
+        assert!(global.collect().modules.contains_key("svc"));
+        assert!(!global.collect().modules.contains_key("svc"));
+    }
+
+    #[test]
+    fn retained_modules_outlive_their_handles_until_unregistered() {
+        let global = GlobalState::with_limits(Limits {
+            remove_dropped_modules: true,
+            ..Limits::default()
+        });
+        let first = global.acquire_retained("svc");
+        first.handles.fetch_sub(1, Ordering::Relaxed);
+        global.collect();
+        global.collect();
+
+        let second = global.acquire_retained("svc");
+        assert!(Arc::ptr_eq(&first, &second));
+        second.handles.fetch_sub(1, Ordering::Relaxed);
+
+        assert!(global.unregister("svc"));
+        assert_eq!(second.handles.load(Ordering::Relaxed), 0);
+        let fresh = global.acquire_retained("svc");
+        assert!(!Arc::ptr_eq(&first, &fresh));
+    }
+
+    #[test]
+    fn modules_without_handles_are_removed_after_collect() {
+        let global = GlobalState::with_limits(Limits {
+            remove_dropped_modules: true,
+            ..Limits::default()
+        });
+        let held = global.acquire_module("held");
+        global.acquire_module("dropped");
+        global
+            .modules
+            .read()
+            .get("dropped")
+            .unwrap()
+            .handles
+            .fetch_sub(1, Ordering::Relaxed);
+
+        let snapshot = global.collect();
+        assert!(snapshot.modules.contains_key("dropped"));
+        let snapshot = global.collect();
+        assert!(!snapshot.modules.contains_key("dropped"));
+        assert!(snapshot.modules.contains_key("held"));
+        drop(held);
+    }
+
+    #[test]
+    fn snapshot_timestamp_comes_from_clock() {
+        let clock = ManualClock::new(1_703_160_000_000);
+        let global = GlobalState::default().with_clock(SharedClock::new(Arc::new(clock.clone())));
+
+        assert_eq!(global.collect().timestamp_ms, 1_703_160_000_000);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(global.collect().timestamp_ms, 1_703_160_001_000);
+    }
 }
@@ -0,0 +1,713 @@
+//! StatsD and DogStatsD output over UDP.
+//!
+//! Hosts that ship metrics through a local StatsD daemon or Datadog agent
+//! can receive snapshots without Prometheus or OTLP. Each snapshot becomes:
+//!
+//! - counters `read.count`, `read.errors`, `write.count` and `write.errors`,
+//!   sent as the increase since the previous snapshot
+//! - gauges `read.backlog`, `read.inflight`, `write.inflight`, and
+//!   `read.pending` / `write.pending` in milliseconds
+//! - timings `read.latency` / `write.latency`: the mean latency of the
+//!   operations completed since the previous snapshot, with a sample rate of
+//!   `1/n` so the daemon counts all `n` of them
+//!
+//! With [`StatsdFlavor::DogStatsd`] the module and topic are sent as tags
+//! (`|#module:orders,topic:orders.new`). Plain StatsD has no tags, so they
+//! become part of the metric name instead: `<prefix>.<module>.<topic>.read.count`,
+//! with characters other than letters, digits, `-` and `_` replaced by `_`.
+//!
+//! Lines are batched into packets of at most `max_packet_size` bytes. The
+//! socket never blocks the emitter: a packet the kernel can't take
+//! immediately is dropped and reported as an error. The address is resolved
+//! before the first send and again after a send fails, so a daemon that
+//! moves is found again.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use buswatch_sdk::Output;
+//! use buswatch_sdk::statsd::StatsdFlavor;
+//!
+//! let output = Output::statsd("127.0.0.1:8125", "myapp.bus", StatsdFlavor::DogStatsd);
+//! ```
+
+// Only the emitters send
+#![cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+
+use buswatch_types::{LatencyHistogram, Snapshot};
+use parking_lot::Mutex;
+
+/// Default packet size limit: an Ethernet MTU of 1500 bytes minus the IPv6
+/// and UDP headers, so packets are never fragmented.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 1432;
+
+/// Which StatsD dialect to send.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatsdFlavor {
+    /// Plain StatsD; module and topic are part of the metric name.
+    #[default]
+    Statsd,
+    /// DogStatsD; module and topic are sent as tags.
+    DogStatsd,
+}
+
+/// Configuration for the StatsD output.
+#[derive(Debug, Clone)]
+pub struct StatsdConfig {
+    /// Address of the StatsD daemon or agent (e.g., "127.0.0.1:8125")
+    pub addr: String,
+    /// Prefix for all metric names (e.g., "myapp.bus")
+    pub prefix: String,
+    /// Dialect to send
+    pub flavor: StatsdFlavor,
+    /// Tags added to every metric (DogStatsD only)
+    pub tags: Vec<(String, String)>,
+    /// Maximum size of a UDP packet in bytes
+    pub max_packet_size: usize,
+}
+
+impl StatsdConfig {
+    /// Create a new builder for StatsdConfig.
+    pub fn builder() -> StatsdConfigBuilder {
+        StatsdConfigBuilder::default()
+    }
+}
+
+/// Builder for StatsdConfig.
+#[derive(Debug, Default)]
+pub struct StatsdConfigBuilder {
+    addr: Option<String>,
+    prefix: Option<String>,
+    flavor: StatsdFlavor,
+    tags: Vec<(String, String)>,
+    max_packet_size: Option<usize>,
+}
+
+impl StatsdConfigBuilder {
+    /// Set the daemon address. Defaults to "127.0.0.1:8125".
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = Some(addr.into());
+        self
+    }
+
+    /// Set the metric name prefix. Defaults to "buswatch".
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = Some(prefix.into());
+        self
+    }
+
+    /// Set the dialect. Defaults to [`StatsdFlavor::Statsd`].
+    pub fn flavor(mut self, flavor: StatsdFlavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// Add a tag to every metric, e.g. `env:prod`.
+    ///
+    /// Ignored for plain StatsD, which has no tags.
+    pub fn tag(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((name.into(), value.into()));
+        self
+    }
+
+    /// Set the maximum packet size. Defaults to [`DEFAULT_MAX_PACKET_SIZE`].
+    ///
+    /// Raise it for a daemon on the loopback interface, whose MTU is much
+    /// larger, to send fewer packets.
+    pub fn max_packet_size(mut self, size: usize) -> Self {
+        self.max_packet_size = Some(size);
+        self
+    }
+
+    /// Build the StatsdConfig.
+    pub fn build(self) -> StatsdConfig {
+        StatsdConfig {
+            addr: self.addr.unwrap_or_else(|| "127.0.0.1:8125".to_string()),
+            prefix: self.prefix.unwrap_or_else(|| "buswatch".to_string()),
+            flavor: self.flavor,
+            tags: self.tags,
+            max_packet_size: self.max_packet_size.unwrap_or(DEFAULT_MAX_PACKET_SIZE),
+        }
+    }
+}
+
+/// Whether a series is a read or a write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Direction {
+    Read,
+    Write,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Read => "read",
+            Direction::Write => "write",
+        }
+    }
+}
+
+/// Cumulative values from the previous snapshot, for computing deltas.
+#[derive(Debug, Default)]
+struct Previous {
+    count: u64,
+    errors: u64,
+    latency: Option<LatencyHistogram>,
+}
+
+type SeriesKey = (String, String, Direction);
+
+/// Cumulative values of every series in a snapshot.
+type Baseline = HashMap<SeriesKey, Previous>;
+
+/// Sends snapshots to a StatsD daemon.
+#[derive(Debug)]
+pub struct StatsdOutput {
+    config: StatsdConfig,
+    /// Connected lazily and dropped after a failed send, so the address is
+    /// resolved again on the next snapshot if it fails to resolve or moves
+    socket: Mutex<Option<Arc<UdpSocket>>>,
+    /// Values deltas are computed against, updated once a snapshot has a
+    /// socket to go out on
+    previous: Mutex<Baseline>,
+}
+
+impl StatsdOutput {
+    /// Create a new StatsD output.
+    pub fn new(config: StatsdConfig) -> Self {
+        Self {
+            config,
+            socket: Mutex::new(None),
+            previous: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &StatsdConfig {
+        &self.config
+    }
+
+    /// Send a snapshot.
+    ///
+    /// All packets are attempted; the first failure is returned. Resolving
+    /// the address blocks the calling thread; in async code use
+    /// [`send_async`](Self::send_async).
+    pub fn send(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let (packets, current) = self.packets(snapshot);
+        if packets.is_empty() {
+            *self.previous.lock() = current;
+            return Ok(());
+        }
+
+        let cached = self.socket.lock().clone();
+        let socket = match cached {
+            Some(socket) => socket,
+            None => {
+                let addrs = self.config.addr.to_socket_addrs()?;
+                self.connect(resolve(&self.config.addr, addrs)?)?
+            }
+        };
+        *self.previous.lock() = current;
+        self.send_packets(&socket, &packets)
+    }
+
+    /// Send a snapshot, resolving the address without blocking the runtime.
+    ///
+    /// All packets are attempted; the first failure is returned.
+    #[cfg(feature = "tokio")]
+    pub async fn send_async(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let (packets, current) = self.packets(snapshot);
+        if packets.is_empty() {
+            *self.previous.lock() = current;
+            return Ok(());
+        }
+
+        let cached = self.socket.lock().clone();
+        let socket = match cached {
+            Some(socket) => socket,
+            None => {
+                let addrs = tokio::net::lookup_host(self.config.addr.as_str()).await?;
+                self.connect(resolve(&self.config.addr, addrs)?)?
+            }
+        };
+        *self.previous.lock() = current;
+        self.send_packets(&socket, &packets)
+    }
+
+    /// Connect a socket to `target` and cache it for later snapshots.
+    fn connect(&self, target: SocketAddr) -> io::Result<Arc<UdpSocket>> {
+        let socket = Arc::new(connect(target)?);
+        *self.socket.lock() = Some(socket.clone());
+        Ok(socket)
+    }
+
+    /// Send every packet, dropping the socket if one fails for any reason
+    /// other than a full send buffer.
+    fn send_packets(&self, socket: &Arc<UdpSocket>, packets: &[String]) -> io::Result<()> {
+        let mut first_error = None;
+        for packet in packets {
+            if let Err(e) = socket.send(packet.as_bytes()) {
+                first_error.get_or_insert(e);
+            }
+        }
+
+        let Some(e) = first_error else {
+            return Ok(());
+        };
+        if e.kind() != io::ErrorKind::WouldBlock {
+            let mut cached = self.socket.lock();
+            if cached.as_ref().is_some_and(|c| Arc::ptr_eq(c, socket)) {
+                *cached = None;
+            }
+        }
+        Err(e)
+    }
+
+    /// Convert a snapshot into packets of StatsD lines, with the baseline
+    /// for the next snapshot.
+    fn packets(&self, snapshot: &Snapshot) -> (Vec<String>, Baseline) {
+        let (lines, current) = self.lines(snapshot);
+        (batch(&lines, self.config.max_packet_size), current)
+    }
+
+    /// Convert a snapshot into StatsD lines, leaving the previous values
+    /// for the caller to replace with the returned ones once sent.
+    fn lines(&self, snapshot: &Snapshot) -> (Vec<String>, Baseline) {
+        let previous = self.previous.lock();
+        let mut current = HashMap::with_capacity(previous.len());
+        let mut lines = Vec::new();
+
+        for (module, metrics) in &snapshot.modules {
+            for (topic, read) in &metrics.reads {
+                let key = (module.clone(), topic.clone(), Direction::Read);
+                let before = previous.get(&key);
+                let series = self.series(module, topic, Direction::Read);
+                series.counter(
+                    &mut lines,
+                    "count",
+                    delta(read.count, before.as_ref().map(|p| p.count)),
+                );
+                if let Some(errors) = read.errors {
+                    series.counter(
+                        &mut lines,
+                        "errors",
+                        delta(errors, before.as_ref().map(|p| p.errors)),
+                    );
+                }
+                if let Some(backlog) = read.backlog {
+                    series.gauge(&mut lines, "backlog", backlog as f64);
+                }
+                if let Some(pending) = read.pending {
+                    series.gauge(&mut lines, "pending", pending.as_micros() as f64 / 1000.0);
+                }
+                if let Some(inflight) = read.inflight {
+                    series.gauge(&mut lines, "inflight", inflight as f64);
+                }
+                if let Some(latency) = &read.latency {
+                    series.timing(
+                        &mut lines,
+                        latency,
+                        before.as_ref().and_then(|p| p.latency.as_ref()),
+                    );
+                }
+                current.insert(
+                    key,
+                    Previous {
+                        count: read.count,
+                        errors: read.errors.unwrap_or(0),
+                        latency: read.latency.clone(),
+                    },
+                );
+            }
+
+            for (topic, write) in &metrics.writes {
+                let key = (module.clone(), topic.clone(), Direction::Write);
+                let before = previous.get(&key);
+                let series = self.series(module, topic, Direction::Write);
+                series.counter(
+                    &mut lines,
+                    "count",
+                    delta(write.count, before.as_ref().map(|p| p.count)),
+                );
+                if let Some(errors) = write.errors {
+                    series.counter(
+                        &mut lines,
+                        "errors",
+                        delta(errors, before.as_ref().map(|p| p.errors)),
+                    );
+                }
+                if let Some(pending) = write.pending {
+                    series.gauge(&mut lines, "pending", pending.as_micros() as f64 / 1000.0);
+                }
+                if let Some(inflight) = write.inflight {
+                    series.gauge(&mut lines, "inflight", inflight as f64);
+                }
+                if let Some(latency) = &write.latency {
+                    series.timing(
+                        &mut lines,
+                        latency,
+                        before.as_ref().and_then(|p| p.latency.as_ref()),
+                    );
+                }
+                current.insert(
+                    key,
+                    Previous {
+                        count: write.count,
+                        errors: write.errors.unwrap_or(0),
+                        latency: write.latency.clone(),
+                    },
+                );
+            }
+        }
+
+        // Topics missing from this snapshot start from zero if they return
+        (lines, current)
+    }
+
+    fn series(&self, module: &str, topic: &str, direction: Direction) -> Series {
+        let mut name = String::new();
+        let mut tags = String::new();
+        match self.config.flavor {
+            StatsdFlavor::Statsd => {
+                for segment in [
+                    self.config.prefix.as_str(),
+                    &sanitize(module),
+                    &sanitize(topic),
+                ] {
+                    if !segment.is_empty() {
+                        name.push_str(segment);
+                        name.push('.');
+                    }
+                }
+            }
+            StatsdFlavor::DogStatsd => {
+                if !self.config.prefix.is_empty() {
+                    name.push_str(&self.config.prefix);
+                    name.push('.');
+                }
+                let _ = write!(
+                    tags,
+                    "|#module:{},topic:{}",
+                    tag_value(module),
+                    tag_value(topic)
+                );
+                for (key, value) in &self.config.tags {
+                    let _ = write!(tags, ",{}:{}", tag_value(key), tag_value(value));
+                }
+            }
+        }
+        name.push_str(direction.as_str());
+        name.push('.');
+        Series { name, tags }
+    }
+}
+
+/// The name prefix and tag suffix shared by one topic's lines.
+struct Series {
+    name: String,
+    tags: String,
+}
+
+impl Series {
+    fn counter(&self, lines: &mut Vec<String>, metric: &str, value: u64) {
+        lines.push(format!("{}{}:{}|c{}", self.name, metric, value, self.tags));
+    }
+
+    fn gauge(&self, lines: &mut Vec<String>, metric: &str, value: f64) {
+        lines.push(format!("{}{}:{}|g{}", self.name, metric, value, self.tags));
+    }
+
+    /// Send the mean of the latencies recorded since `previous`, sampled at
+    /// `1/n` so the daemon counts all `n` operations.
+    fn timing(
+        &self,
+        lines: &mut Vec<String>,
+        current: &LatencyHistogram,
+        previous: Option<&LatencyHistogram>,
+    ) {
+        // A histogram with fewer observations has been reset
+        let previous = previous.filter(|p| p.count <= current.count);
+        let count = current.count - previous.map_or(0, |p| p.count);
+        let sum_us = current
+            .sum
+            .as_micros()
+            .saturating_sub(previous.map_or(0, |p| p.sum.as_micros()));
+        let Some(mean_us) = sum_us.checked_div(count) else {
+            return;
+        };
+
+        let mut line = format!("{}latency:{}|ms", self.name, mean_us as f64 / 1000.0);
+        if count > 1 {
+            let _ = write!(line, "|@{}", 1.0 / count as f64);
+        }
+        line.push_str(&self.tags);
+        lines.push(line);
+    }
+}
+
+/// The increase of a cumulative count; a count that went down was reset.
+fn delta(current: u64, previous: Option<u64>) -> u64 {
+    match previous {
+        Some(previous) if previous <= current => current - previous,
+        _ => current,
+    }
+}
+
+/// Make a metric name segment safe for StatsD and Graphite.
+fn sanitize(segment: &str) -> String {
+    segment
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Replace the characters that delimit DogStatsD tags.
+fn tag_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            ',' | '|' | '#' | '@' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Join lines into newline-separated packets of at most `max` bytes. A line
+/// longer than `max` is sent in a packet of its own.
+fn batch(lines: &[String], max: usize) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut packet = String::new();
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > max {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+    packets
+}
+
+/// The first address `addr` resolved to.
+fn resolve(addr: &str, mut addrs: impl Iterator<Item = SocketAddr>) -> io::Result<SocketAddr> {
+    addrs.next().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} did not resolve to an address", addr),
+        )
+    })
+}
+
+/// Connect a non-blocking socket of the matching family to `target`.
+fn connect(target: SocketAddr) -> io::Result<UdpSocket> {
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(target)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use buswatch_types::Microseconds;
+    use std::time::Duration;
+
+    fn receiver() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        (socket, addr)
+    }
+
+    /// Receive `n` packets.
+    fn packets(socket: &UdpSocket, n: usize) -> Vec<String> {
+        let mut buf = [0u8; 65536];
+        (0..n)
+            .map(|_| {
+                let len = socket.recv(&mut buf).unwrap();
+                String::from_utf8(buf[..len].to_vec()).unwrap()
+            })
+            .collect()
+    }
+
+    fn snapshot(reads: u64, latency: &[u64]) -> Snapshot {
+        let mut histogram = LatencyHistogram::new(vec![Microseconds(1_000), Microseconds(10_000)]);
+        for us in latency {
+            histogram.record(Microseconds(*us));
+        }
+        Snapshot::builder()
+            .module("orders", |m| {
+                m.read("orders.new", |r| {
+                    r.count(reads)
+                        .backlog(5)
+                        .pending(Duration::from_micros(1_500))
+                        .latency(histogram)
+                })
+                .write("orders.done", |w| w.count(reads / 2).errors(1))
+            })
+            .build()
+    }
+
+    #[test]
+    fn dogstatsd_lines_are_tagged() {
+        let (socket, addr) = receiver();
+        let config = StatsdConfig::builder()
+            .addr(addr)
+            .prefix("app.bus")
+            .flavor(StatsdFlavor::DogStatsd)
+            .tag("env", "prod")
+            .build();
+        let output = StatsdOutput::new(config);
+
+        output.send(&snapshot(10, &[2_000, 4_000])).unwrap();
+        let packet = &packets(&socket, 1)[0];
+        let tags = "|#module:orders,topic:orders.new,env:prod";
+        let lines: Vec<&str> = packet.lines().collect();
+        assert_eq!(
+            lines,
+            vec![
+                format!("app.bus.read.count:10|c{}", tags),
+                format!("app.bus.read.backlog:5|g{}", tags),
+                format!("app.bus.read.pending:1.5|g{}", tags),
+                format!("app.bus.read.latency:3|ms|@0.5{}", tags),
+                "app.bus.write.count:5|c|#module:orders,topic:orders.done,env:prod".to_string(),
+                "app.bus.write.errors:1|c|#module:orders,topic:orders.done,env:prod".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn counters_and_timings_are_deltas() {
+        let (socket, addr) = receiver();
+        let config = StatsdConfig::builder().addr(addr).prefix("bw").build();
+        let output = StatsdOutput::new(config);
+
+        output.send(&snapshot(10, &[2_000])).unwrap();
+        output.send(&snapshot(25, &[2_000, 500])).unwrap();
+        let second = &packets(&socket, 2)[1];
+        assert!(second.contains("bw.orders.orders_new.read.count:15|c"));
+        assert!(second.contains("bw.orders.orders_new.read.latency:0.5|ms\n"));
+        assert!(second.contains("bw.orders.orders_done.write.count:7|c"));
+        assert!(second.contains("bw.orders.orders_done.write.errors:0|c"));
+
+        // A count that goes down was reset, so all of it is new
+        output.send(&snapshot(4, &[])).unwrap();
+        let third = &packets(&socket, 1)[0];
+        assert!(third.contains("bw.orders.orders_new.read.count:4|c"));
+        assert!(!third.contains("latency"));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn failed_send_resolves_again() {
+        let (socket, addr) = receiver();
+        let port = socket.local_addr().unwrap().port();
+        let output = StatsdOutput::new(StatsdConfig::builder().addr(addr).build());
+
+        output.send_async(&snapshot(10, &[])).await.unwrap();
+        packets(&socket, 1);
+
+        // Sends to a closed port fail once the rejection comes back
+        drop(socket);
+        let mut error = None;
+        for reads in 11..100 {
+            if let Err(e) = output.send_async(&snapshot(reads, &[])).await {
+                error = Some(e);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(error.unwrap().kind(), io::ErrorKind::ConnectionRefused);
+        assert!(output.socket.lock().is_none());
+
+        let socket = UdpSocket::bind(("127.0.0.1", port)).unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        output.send_async(&snapshot(200, &[])).await.unwrap();
+        assert!(packets(&socket, 1)[0].contains("read.count:"));
+    }
+
+    #[test]
+    fn failed_lookup_keeps_previous_values() {
+        let (socket, addr) = receiver();
+        let mut output =
+            StatsdOutput::new(StatsdConfig::builder().addr("localhost:no-port").build());
+
+        assert!(output.send(&snapshot(10, &[])).is_err());
+
+        output.config.addr = addr;
+        output.send(&snapshot(25, &[])).unwrap();
+        assert!(packets(&socket, 1)[0].contains("read.count:25|c"));
+    }
+
+    #[test]
+    fn packets_respect_max_size() {
+        let (socket, addr) = receiver();
+        let config = StatsdConfig::builder()
+            .addr(addr)
+            .flavor(StatsdFlavor::DogStatsd)
+            .max_packet_size(120)
+            .build();
+
+        let mut builder = Snapshot::builder();
+        for i in 0..20 {
+            builder = builder.module(format!("module-{}", i), |m| {
+                m.read("events", |r| r.count(i))
+            });
+        }
+        let snapshot = builder.build();
+        let expected = StatsdOutput::new(config.clone()).packets(&snapshot).0.len();
+        assert!(expected > 1);
+
+        StatsdOutput::new(config).send(&snapshot).unwrap();
+        let received = packets(&socket, expected);
+        assert!(received.iter().all(|p| p.len() <= 120));
+        assert_eq!(received.iter().flat_map(|p| p.lines()).count(), 20);
+    }
+
+    #[test]
+    fn batch_keeps_oversized_lines_whole() {
+        let lines = vec!["a".repeat(10), "b".repeat(3), "c".repeat(3)];
+        assert_eq!(
+            batch(&lines, 8),
+            vec!["a".repeat(10), "bbb\nccc".to_string()]
+        );
+        assert!(batch(&[], 8).is_empty());
+    }
+
+    #[test]
+    fn names_and_tags_are_escaped() {
+        assert_eq!(sanitize("orders.new/eu west"), "orders_new_eu_west");
+        assert_eq!(tag_value("a,b|c#d@e f:g"), "a_b_c_d_e_f:g");
+
+        let output = StatsdOutput::new(StatsdConfig::builder().prefix("").build());
+        let snapshot = Snapshot::builder()
+            .module("svc", |m| m.read("a:b", |r| r.count(1)))
+            .build();
+        assert_eq!(output.lines(&snapshot).0, vec!["svc.a_b.read.count:1|c"]);
+    }
+}
@@ -0,0 +1,348 @@
+//! Instrumented adapters for `futures` streams and sinks.
+//!
+//! Message bus clients such as lapin and async-nats expose consumers as a
+//! `Stream` of deliveries. Wrapping them records reads without touching
+//! every handler:
+//!
+//! - [`InstrumentStreamExt::instrument_reads`] records a read for each item,
+//!   and sets read pending while the stream is waiting for the next one
+//! - [`InstrumentSinkExt::instrument_writes`] records a write for each item,
+//!   and sets write pending while the sink is applying backpressure
+//!
+//! # Example
+//!
+//! ```rust
+//! use buswatch_sdk::{InstrumentStreamExt, Instrumentor};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let instrumentor = Instrumentor::new();
+//!     let handle = instrumentor.register("consumer");
+//!
+//!     let deliveries = futures::stream::iter(vec!["a", "b", "c"]);
+//!     let mut deliveries = deliveries.instrument_reads(&handle, "orders");
+//!
+//!     while let Some(delivery) = deliveries.next().await {
+//!         // ... handle the delivery ...
+//!     }
+//!
+//!     let snapshot = instrumentor.collect();
+//!     assert_eq!(snapshot.modules["consumer"].reads["orders"].count, 3);
+//! }
+//! ```
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use pin_project_lite::pin_project;
+
+use crate::handle::{ModuleHandle, PendingGuard};
+
+pin_project! {
+    /// A stream that records a read for every item it yields.
+    ///
+    /// Created by [`InstrumentStreamExt::instrument_reads`].
+    pub struct InstrumentedStream<S> {
+        #[pin]
+        inner: S,
+        handle: ModuleHandle,
+        topic: Arc<str>,
+        // Held while the inner stream is waiting for its next item
+        pending: Option<PendingGuard>,
+    }
+}
+
+impl<S> InstrumentedStream<S> {
+    /// Returns the topic reads are recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns a reference to the wrapped stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume the wrapper, returning the wrapped stream.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Stream> Stream for InstrumentedStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        match this.inner.poll_next(cx) {
+            Poll::Pending => {
+                if this.pending.is_none() {
+                    *this.pending = Some(this.handle.wait_read(this.topic));
+                }
+                Poll::Pending
+            }
+            Poll::Ready(item) => {
+                *this.pending = None;
+                if item.is_some() {
+                    this.handle.record_read(this.topic, 1);
+                }
+                Poll::Ready(item)
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<S> std::fmt::Debug for InstrumentedStream<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedStream")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+pin_project! {
+    /// A sink that records a write for every item sent into it.
+    ///
+    /// Created by [`InstrumentSinkExt::instrument_writes`].
+    pub struct InstrumentedSink<S> {
+        #[pin]
+        inner: S,
+        handle: ModuleHandle,
+        topic: Arc<str>,
+        // Held while the inner sink is not ready to accept or flush items
+        pending: Option<PendingGuard>,
+    }
+}
+
+impl<S> InstrumentedSink<S> {
+    /// Returns the topic writes are recorded under.
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    /// Returns a reference to the wrapped sink.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consume the wrapper, returning the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> InstrumentedSink<S> {
+    /// Track backpressure from a poll of the inner sink.
+    fn track<T>(
+        pending: &mut Option<PendingGuard>,
+        handle: &ModuleHandle,
+        topic: &str,
+        poll: Poll<T>,
+    ) -> Poll<T> {
+        match poll {
+            Poll::Pending => {
+                if pending.is_none() {
+                    *pending = Some(handle.wait_write(topic));
+                }
+                Poll::Pending
+            }
+            Poll::Ready(result) => {
+                *pending = None;
+                Poll::Ready(result)
+            }
+        }
+    }
+}
+
+impl<S: Sink<Item>, Item> Sink<Item> for InstrumentedSink<S> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let poll = this.inner.poll_ready(cx);
+        Self::track(this.pending, this.handle, this.topic, poll)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        let this = self.project();
+        this.inner.start_send(item)?;
+        this.handle.record_write(this.topic, 1);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let poll = this.inner.poll_flush(cx);
+        Self::track(this.pending, this.handle, this.topic, poll)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.project();
+        let poll = this.inner.poll_close(cx);
+        Self::track(this.pending, this.handle, this.topic, poll)
+    }
+}
+
+impl<S> std::fmt::Debug for InstrumentedSink<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedSink")
+            .field("module", &self.handle.name())
+            .field("topic", &self.topic)
+            .finish()
+    }
+}
+
+/// Extension trait for wrapping any `Stream` with read instrumentation.
+pub trait InstrumentStreamExt: Stream + Sized {
+    /// Record a read on `handle` for each item this stream yields.
+    ///
+    /// While the stream is waiting for its next item, the topic's read
+    /// pending duration is set.
+    fn instrument_reads(self, handle: &ModuleHandle, topic: &str) -> InstrumentedStream<Self> {
+        InstrumentedStream {
+            inner: self,
+            handle: handle.clone(),
+            topic: Arc::from(topic),
+            pending: None,
+        }
+    }
+}
+
+impl<S: Stream> InstrumentStreamExt for S {}
+
+/// Extension trait for wrapping any `Sink` with write instrumentation.
+pub trait InstrumentSinkExt<Item>: Sink<Item> + Sized {
+    /// Record a write on `handle` for each item sent into this sink.
+    ///
+    /// While the sink is not ready to accept or flush items, the topic's
+    /// write pending duration is set.
+    fn instrument_writes(self, handle: &ModuleHandle, topic: &str) -> InstrumentedSink<Self> {
+        InstrumentedSink {
+            inner: self,
+            handle: handle.clone(),
+            topic: Arc::from(topic),
+            pending: None,
+        }
+    }
+}
+
+impl<S: Sink<Item>, Item> InstrumentSinkExt<Item> for S {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Instrumentor;
+    use futures::task::noop_waker_ref;
+    use futures::{SinkExt, StreamExt};
+
+    #[tokio::test]
+    async fn stream_records_each_item_as_a_read() {
+        let instrumentor = Instrumentor::new();
+        let handle = instrumentor.register("consumer");
+
+        let items: Vec<u32> = futures::stream::iter(vec![1, 2, 3])
+            .instrument_reads(&handle, "orders")
+            .collect()
+            .await;
+        assert_eq!(items, vec![1, 2, 3]);
+
+        let snapshot = instrumentor.collect();
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert_eq!(read.count, 3);
+        assert!(read.pending.is_none());
+    }
+
+    #[test]
+    fn stream_sets_read_pending_while_waiting() {
+        let instrumentor = Instrumentor::new();
+        let handle = instrumentor.register("consumer");
+
+        let (tx, rx) = futures::channel::mpsc::unbounded::<u32>();
+        let mut stream = rx.instrument_reads(&handle, "orders");
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        assert!(stream.poll_next_unpin(&mut cx).is_pending());
+        let snapshot = instrumentor.collect();
+        assert!(snapshot.modules["consumer"].reads["orders"]
+            .pending
+            .is_some());
+
+        tx.unbounded_send(1).unwrap();
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(Some(1)));
+
+        let snapshot = instrumentor.collect();
+        let read = &snapshot.modules["consumer"].reads["orders"];
+        assert!(read.pending.is_none());
+        assert_eq!(read.count, 1);
+    }
+
+    #[test]
+    fn stream_end_does_not_record_a_read() {
+        let instrumentor = Instrumentor::new();
+        let handle = instrumentor.register("consumer");
+
+        let mut stream = futures::stream::empty::<u32>().instrument_reads(&handle, "orders");
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        assert_eq!(stream.poll_next_unpin(&mut cx), Poll::Ready(None));
+        let snapshot = instrumentor.collect();
+        assert!(snapshot.modules["consumer"].reads.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sink_records_each_item_as_a_write() {
+        let instrumentor = Instrumentor::new();
+        let handle = instrumentor.register("producer");
+
+        let (tx, rx) = futures::channel::mpsc::channel::<u32>(8);
+        let mut sink = tx.instrument_writes(&handle, "orders");
+
+        sink.send(1).await.unwrap();
+        sink.send(2).await.unwrap();
+        drop(sink);
+
+        let received: Vec<u32> = rx.collect().await;
+        assert_eq!(received, vec![1, 2]);
+
+        let snapshot = instrumentor.collect();
+        assert_eq!(snapshot.modules["producer"].writes["orders"].count, 2);
+    }
+
+    #[test]
+    fn sink_sets_write_pending_under_backpressure() {
+        let instrumentor = Instrumentor::new();
+        let handle = instrumentor.register("producer");
+
+        // A zero-buffer futures channel accepts one message per sender
+        let (tx, mut rx) = futures::channel::mpsc::channel::<u32>(0);
+        let mut sink = tx.instrument_writes(&handle, "orders");
+        let mut cx = Context::from_waker(noop_waker_ref());
+
+        assert!(sink.poll_ready_unpin(&mut cx).is_ready());
+        sink.start_send_unpin(1).unwrap();
+        assert!(sink.poll_ready_unpin(&mut cx).is_pending());
+
+        let snapshot = instrumentor.collect();
+        assert!(snapshot.modules["producer"].writes["orders"]
+            .pending
+            .is_some());
+
+        assert_eq!(rx.try_recv().unwrap(), 1);
+        assert!(sink.poll_ready_unpin(&mut cx).is_ready());
+
+        let snapshot = instrumentor.collect();
+        let write = &snapshot.modules["producer"].writes["orders"];
+        assert!(write.pending.is_none());
+        assert_eq!(write.count, 1);
+    }
+}
@@ -0,0 +1,709 @@
+//! Persistent TCP snapshot streaming.
+//!
+//! Snapshots are sent as newline-delimited JSON over a single long-lived
+//! connection. Emitting only queues the snapshot; a background task owns
+//! the connection, writes queued snapshots in order, and reconnects with
+//! exponential backoff when the receiver goes away. The task is a tokio
+//! task, or a thread when the output is driven by the `thread` emitter.
+//!
+//! While disconnected, snapshots accumulate in a bounded queue. When it is
+//! full the oldest snapshot is dropped, so a receiver that comes back gets
+//! the most recent history rather than a stale backlog.
+//!
+//! Connection failures, write timeouts and dropped snapshots are returned
+//! from the next emit instead of being silently discarded.
+//!
+//! ## Example
+//!
+//! ```rust
+//! use buswatch_sdk::Output;
+//! use buswatch_sdk::tcp::TcpConfig;
+//! use std::time::Duration;
+//!
+//! let config = TcpConfig::builder()
+//!     .addr("monitoring-server:9090")
+//!     .queue_capacity(60)
+//!     .write_timeout(Duration::from_secs(2))
+//!     .build();
+//!
+//! let output = Output::tcp_with_config(config);
+//! ```
+
+// Only the connection task drives the queue
+#![cfg_attr(not(any(feature = "tokio", feature = "thread")), allow(dead_code))]
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(any(feature = "tokio", feature = "thread"))]
+use buswatch_types::Snapshot;
+use parking_lot::Mutex;
+
+/// Delay before the first reconnect attempt after a failure.
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bound for the reconnect delay.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// How often a flush checks whether the queue has drained.
+#[cfg(any(feature = "tokio", feature = "thread"))]
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Configuration for streaming snapshots to a TCP endpoint.
+#[derive(Debug, Clone)]
+pub struct TcpConfig {
+    /// Address to connect to (e.g., "localhost:9090")
+    pub addr: String,
+    /// Maximum number of snapshots held while disconnected
+    pub queue_capacity: usize,
+    /// Timeout for establishing a connection
+    pub connect_timeout: Duration,
+    /// Timeout for writing a single snapshot
+    pub write_timeout: Duration,
+}
+
+impl TcpConfig {
+    /// Create a new builder for TcpConfig.
+    pub fn builder() -> TcpConfigBuilder {
+        TcpConfigBuilder::default()
+    }
+}
+
+/// Builder for TcpConfig.
+#[derive(Debug, Default)]
+pub struct TcpConfigBuilder {
+    addr: Option<String>,
+    queue_capacity: Option<usize>,
+    connect_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+}
+
+impl TcpConfigBuilder {
+    /// Set the address to connect to.
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = Some(addr.into());
+        self
+    }
+
+    /// Set how many snapshots to keep while disconnected (minimum 1).
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Set the connect timeout.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Set the write timeout.
+    ///
+    /// A receiver that stops reading is treated as disconnected once a
+    /// write blocks for this long.
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Build the TcpConfig.
+    pub fn build(self) -> TcpConfig {
+        TcpConfig {
+            addr: self.addr.unwrap_or_else(|| "localhost:9090".to_string()),
+            queue_capacity: self.queue_capacity.unwrap_or(16).max(1),
+            connect_timeout: self.connect_timeout.unwrap_or(Duration::from_secs(5)),
+            write_timeout: self.write_timeout.unwrap_or(Duration::from_secs(5)),
+        }
+    }
+}
+
+/// State shared between a [`TcpOutput`] and its connection task.
+struct Shared {
+    queue: Mutex<VecDeque<Arc<[u8]>>>,
+    capacity: usize,
+    /// Latest failure not yet returned from an emit
+    error: Mutex<Option<io::Error>>,
+    /// Snapshots dropped since the last emit reported them
+    dropped_unreported: AtomicU64,
+    dropped_total: AtomicU64,
+    /// Payloads queued or being written
+    unsent: AtomicUsize,
+    connected: AtomicBool,
+    closed: AtomicBool,
+    #[cfg(feature = "tokio")]
+    wake: tokio::sync::Notify,
+    /// Signalled with the queue lock held when a payload is pushed or the
+    /// output is closed
+    #[cfg(feature = "thread")]
+    ready: parking_lot::Condvar,
+}
+
+impl Shared {
+    fn new(capacity: usize) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            error: Mutex::new(None),
+            dropped_unreported: AtomicU64::new(0),
+            dropped_total: AtomicU64::new(0),
+            unsent: AtomicUsize::new(0),
+            connected: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            #[cfg(feature = "tokio")]
+            wake: tokio::sync::Notify::new(),
+            #[cfg(feature = "thread")]
+            ready: parking_lot::Condvar::new(),
+        }
+    }
+
+    /// Queue a payload, dropping the oldest one if the queue is full.
+    fn push(&self, payload: Arc<[u8]>) {
+        let mut queue = self.queue.lock();
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            self.dropped_unreported.fetch_add(1, Ordering::Relaxed);
+            self.dropped_total.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.unsent.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(payload);
+    }
+
+    /// Put back a payload that could not be written, unless newer
+    /// snapshots have since filled the queue.
+    #[cfg(any(feature = "tokio", feature = "thread"))]
+    fn requeue(&self, payload: Arc<[u8]>) {
+        let mut queue = self.queue.lock();
+        if queue.len() < self.capacity {
+            queue.push_front(payload);
+        } else {
+            self.unsent.fetch_sub(1, Ordering::Relaxed);
+            self.dropped_unreported.fetch_add(1, Ordering::Relaxed);
+            self.dropped_total.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    fn pop(&self) -> Option<Arc<[u8]>> {
+        self.queue.lock().pop_front()
+    }
+
+    /// Wait for a payload, returning `None` once the output is closed.
+    #[cfg(feature = "thread")]
+    fn pop_blocking(&self) -> Option<Arc<[u8]>> {
+        let mut queue = self.queue.lock();
+        loop {
+            if self.closed.load(Ordering::Relaxed) {
+                return None;
+            }
+            if let Some(payload) = queue.pop_front() {
+                return Some(payload);
+            }
+            self.ready.wait(&mut queue);
+        }
+    }
+
+    /// Wake whichever connection task is running.
+    fn wake(&self) {
+        #[cfg(feature = "tokio")]
+        self.wake.notify_one();
+        #[cfg(feature = "thread")]
+        {
+            let _queue = self.queue.lock();
+            self.ready.notify_one();
+        }
+    }
+
+    fn fail(&self, error: io::Error) {
+        self.connected.store(false, Ordering::Relaxed);
+        *self.error.lock() = Some(error);
+    }
+
+    /// Take any failure that happened since the last call.
+    fn take_error(&self) -> Option<io::Error> {
+        if let Some(error) = self.error.lock().take() {
+            self.dropped_unreported.store(0, Ordering::Relaxed);
+            return Some(error);
+        }
+        match self.dropped_unreported.swap(0, Ordering::Relaxed) {
+            0 => None,
+            dropped => Some(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "TCP output queue full, dropped {} oldest snapshot(s)",
+                    dropped
+                ),
+            )),
+        }
+    }
+}
+
+/// Streams snapshots over a persistent TCP connection.
+///
+/// The connection task is started on the first emit, so the output can be
+/// created outside of a tokio runtime. Whichever emitter sends first
+/// decides whether it is a tokio task or a thread. It stops when the output
+/// is dropped.
+pub struct TcpOutput {
+    config: TcpConfig,
+    shared: Arc<Shared>,
+    #[cfg(any(feature = "tokio", feature = "thread"))]
+    started: std::sync::Once,
+}
+
+impl TcpOutput {
+    /// Create a new TCP output. No connection is made until the first emit.
+    pub fn new(config: TcpConfig) -> Self {
+        Self {
+            shared: Arc::new(Shared::new(config.queue_capacity)),
+            config,
+            #[cfg(any(feature = "tokio", feature = "thread"))]
+            started: std::sync::Once::new(),
+        }
+    }
+
+    /// Get the configuration.
+    pub fn config(&self) -> &TcpConfig {
+        &self.config
+    }
+
+    /// Whether the connection is currently established.
+    pub fn is_connected(&self) -> bool {
+        self.shared.connected.load(Ordering::Relaxed)
+    }
+
+    /// Number of snapshots waiting to be written.
+    pub fn queued(&self) -> usize {
+        self.shared.queue.lock().len()
+    }
+
+    /// Total number of snapshots dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped_total.load(Ordering::Relaxed)
+    }
+
+    /// Queue a snapshot for sending.
+    ///
+    /// Never waits on the network. Returns the most recent connection or
+    /// write failure, or a count of dropped snapshots, if either occurred
+    /// since the previous call.
+    #[cfg(feature = "tokio")]
+    pub async fn send(&self, snapshot: &Snapshot) -> io::Result<()> {
+        self.started.call_once(|| {
+            tokio::spawn(run(self.config.clone(), self.shared.clone()));
+        });
+        self.enqueue(snapshot)
+    }
+
+    /// Queue a snapshot for sending from a thread, without a tokio runtime.
+    ///
+    /// Behaves like [`send`](Self::send), with the connection owned by a
+    /// background thread.
+    #[cfg(feature = "thread")]
+    pub fn send_blocking(&self, snapshot: &Snapshot) -> io::Result<()> {
+        self.started.call_once(|| {
+            let config = self.config.clone();
+            let shared = self.shared.clone();
+            std::thread::Builder::new()
+                .name("buswatch-tcp".to_string())
+                .spawn(move || run_blocking(config, shared))
+                .expect("failed to spawn TCP output thread");
+        });
+        self.enqueue(snapshot)
+    }
+
+    #[cfg(any(feature = "tokio", feature = "thread"))]
+    fn enqueue(&self, snapshot: &Snapshot) -> io::Result<()> {
+        let mut payload = serde_json::to_vec(snapshot)?;
+        payload.push(b'\n');
+        self.shared.push(payload.into());
+        self.shared.wake();
+
+        match self.shared.take_error() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait until every queued snapshot has been written.
+    ///
+    /// Does not time out on its own; while the receiver is unreachable this
+    /// waits through reconnect attempts.
+    #[cfg(feature = "tokio")]
+    pub async fn flush(&self) -> io::Result<()> {
+        while self.shared.unsent.load(Ordering::Relaxed) > 0 {
+            tokio::time::sleep(FLUSH_POLL_INTERVAL).await;
+        }
+        self.flushed()
+    }
+
+    /// Wait up to `timeout` until every queued snapshot has been written,
+    /// blocking the current thread.
+    #[cfg(feature = "thread")]
+    pub fn flush_blocking(&self, timeout: Duration) -> io::Result<()> {
+        let deadline = std::time::Instant::now() + timeout;
+        while self.shared.unsent.load(Ordering::Relaxed) > 0 {
+            if std::time::Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out flushing to {}", self.config.addr),
+                ));
+            }
+            std::thread::sleep(FLUSH_POLL_INTERVAL);
+        }
+        self.flushed()
+    }
+
+    #[cfg(any(feature = "tokio", feature = "thread"))]
+    fn flushed(&self) -> io::Result<()> {
+        match self.shared.take_error() {
+            // Connection failures recovered once the queue drained, but
+            // dropped snapshots are lost for good
+            Some(error) if error.kind() == io::ErrorKind::WouldBlock => Err(error),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for TcpOutput {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        self.shared.wake();
+    }
+}
+
+impl std::fmt::Debug for TcpOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TcpOutput")
+            .field("config", &self.config)
+            .field("connected", &self.is_connected())
+            .finish()
+    }
+}
+
+/// Connection task: writes queued payloads, reconnecting as needed.
+#[cfg(feature = "tokio")]
+async fn run(config: TcpConfig, shared: Arc<Shared>) {
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpStream;
+    use tokio::time::timeout;
+
+    let mut stream: Option<TcpStream> = None;
+    let mut backoff = INITIAL_RECONNECT_DELAY;
+
+    while !shared.closed.load(Ordering::Relaxed) {
+        let Some(payload) = shared.pop() else {
+            shared.wake.notified().await;
+            continue;
+        };
+
+        let connection = match stream.as_mut() {
+            Some(connection) => connection,
+            None => match timeout(config.connect_timeout, TcpStream::connect(&config.addr)).await {
+                Ok(Ok(connection)) => {
+                    shared.connected.store(true, Ordering::Relaxed);
+                    stream.insert(connection)
+                }
+                Ok(Err(e)) => {
+                    shared.requeue(payload);
+                    shared.fail(e);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+                Err(_) => {
+                    shared.requeue(payload);
+                    shared.fail(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out connecting to {}", config.addr),
+                    ));
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            },
+        };
+
+        match timeout(config.write_timeout, connection.write_all(&payload)).await {
+            Ok(Ok(())) => {
+                shared.unsent.fetch_sub(1, Ordering::Relaxed);
+                backoff = INITIAL_RECONNECT_DELAY;
+            }
+            Ok(Err(e)) => {
+                stream = None;
+                shared.requeue(payload);
+                shared.fail(e);
+            }
+            Err(_) => {
+                // A partial write may have gone out; the receiver has to
+                // discard the incomplete line on reconnect
+                stream = None;
+                shared.requeue(payload);
+                shared.fail(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("timed out writing to {}", config.addr),
+                ));
+            }
+        }
+    }
+}
+
+/// Connection thread for the `thread` emitter; mirrors [`run`].
+#[cfg(feature = "thread")]
+fn run_blocking(config: TcpConfig, shared: Arc<Shared>) {
+    use std::io::Write;
+    use std::net::{TcpStream, ToSocketAddrs};
+
+    fn connect(config: &TcpConfig) -> io::Result<TcpStream> {
+        let mut last_error = io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} did not resolve to any address", config.addr),
+        );
+        for addr in config.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, config.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_write_timeout(Some(config.write_timeout))?;
+                    return Ok(stream);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    let mut stream: Option<TcpStream> = None;
+    let mut backoff = INITIAL_RECONNECT_DELAY;
+
+    while let Some(payload) = shared.pop_blocking() {
+        let connection = match stream.as_mut() {
+            Some(connection) => connection,
+            None => match connect(&config) {
+                Ok(connection) => {
+                    shared.connected.store(true, Ordering::Relaxed);
+                    stream.insert(connection)
+                }
+                Err(e) => {
+                    shared.requeue(payload);
+                    shared.fail(e);
+                    std::thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_RECONNECT_DELAY);
+                    continue;
+                }
+            },
+        };
+
+        match connection.write_all(&payload) {
+            Ok(()) => {
+                shared.unsent.fetch_sub(1, Ordering::Relaxed);
+                backoff = INITIAL_RECONNECT_DELAY;
+            }
+            Err(e) => {
+                // On a timeout, a partial write may have gone out
+                stream = None;
+                shared.requeue(payload);
+                shared.fail(e);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use tokio::net::TcpListener;
+
+    fn snapshot(count: u64) -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(count)
+            .module("producer", |m| m.write("orders", |w| w.count(count)))
+            .build()
+    }
+
+    async fn read_snapshot(
+        lines: &mut tokio::io::Lines<BufReader<tokio::net::TcpStream>>,
+    ) -> Snapshot {
+        let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+            .await
+            .expect("timed out waiting for snapshot")
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn queue_drops_oldest_when_full() {
+        let shared = Shared::new(2);
+        for payload in [b"1", b"2", b"3"] {
+            shared.push(Arc::from(&payload[..]));
+        }
+
+        assert_eq!(shared.pop().as_deref(), Some(&b"2"[..]));
+        assert_eq!(shared.pop().as_deref(), Some(&b"3"[..]));
+        assert_eq!(shared.dropped_total.load(Ordering::Relaxed), 1);
+
+        let err = shared.take_error().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert!(shared.take_error().is_none());
+    }
+
+    #[tokio::test]
+    async fn streams_snapshots_over_one_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let output = TcpOutput::new(TcpConfig::builder().addr(addr).build());
+
+        output.send(&snapshot(1)).await.unwrap();
+        output.send(&snapshot(2)).await.unwrap();
+
+        let (conn, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(conn).lines();
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 1);
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 2);
+
+        output.send(&snapshot(3)).await.unwrap();
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 3);
+        assert!(output.is_connected());
+    }
+
+    #[tokio::test]
+    async fn reports_connect_failure_and_delivers_backlog_once_up() {
+        // Reserve a port, then close it so connects are refused
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let output = TcpOutput::new(
+            TcpConfig::builder()
+                .addr(addr.to_string())
+                .queue_capacity(2)
+                .build(),
+        );
+
+        output.send(&snapshot(1)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let err = output.send(&snapshot(2)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+        assert!(!output.is_connected());
+
+        // Overflow the queue: snapshot 1 is dropped
+        let _ = output.send(&snapshot(3)).await;
+        assert_eq!(output.dropped(), 1);
+
+        // The receiver comes up and gets the newest snapshots in order
+        let listener = TcpListener::bind(addr).await.unwrap();
+        let (conn, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut lines = BufReader::new(conn).lines();
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 2);
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 3);
+    }
+
+    #[tokio::test]
+    async fn reconnects_after_receiver_restart() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let output = TcpOutput::new(TcpConfig::builder().addr(addr.to_string()).build());
+
+        output.send(&snapshot(1)).await.unwrap();
+        let (conn, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(conn).lines();
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 1);
+
+        // Receiver restarts
+        drop(lines);
+        drop(listener);
+        let listener = TcpListener::bind(addr).await.unwrap();
+
+        // Writes to the dead connection fail once the peer's reset arrives
+        for count in 2..=5 {
+            let _ = output.send(&snapshot(count)).await;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let (conn, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut lines = BufReader::new(conn).lines();
+        let first = read_snapshot(&mut lines).await.timestamp_ms;
+        assert!((2..=5).contains(&first));
+    }
+
+    #[tokio::test]
+    async fn flush_waits_until_queue_is_written() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let output = TcpOutput::new(TcpConfig::builder().addr(addr).build());
+
+        output.send(&snapshot(1)).await.unwrap();
+        output.send(&snapshot(2)).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), output.flush())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(output.queued(), 0);
+
+        let (conn, _) = listener.accept().await.unwrap();
+        let mut lines = BufReader::new(conn).lines();
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 1);
+        assert_eq!(read_snapshot(&mut lines).await.timestamp_ms, 2);
+    }
+}
+
+#[cfg(all(test, feature = "thread"))]
+mod thread_tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    fn snapshot(count: u64) -> Snapshot {
+        Snapshot::builder()
+            .timestamp_ms(count)
+            .module("producer", |m| m.write("orders", |w| w.count(count)))
+            .build()
+    }
+
+    #[test]
+    fn streams_snapshots_from_a_thread() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let output = TcpOutput::new(TcpConfig::builder().addr(addr).build());
+
+        output.send_blocking(&snapshot(1)).unwrap();
+        output.send_blocking(&snapshot(2)).unwrap();
+        output.flush_blocking(Duration::from_secs(5)).unwrap();
+        assert!(output.is_connected());
+
+        let (conn, _) = listener.accept().unwrap();
+        conn.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut lines = BufReader::new(conn).lines();
+        for expected in [1, 2] {
+            let line = lines.next().unwrap().unwrap();
+            let received: Snapshot = serde_json::from_str(&line).unwrap();
+            assert_eq!(received.timestamp_ms, expected);
+        }
+    }
+
+    #[test]
+    fn flush_blocking_times_out_while_disconnected() {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let output = TcpOutput::new(TcpConfig::builder().addr(addr.to_string()).build());
+
+        output.send_blocking(&snapshot(1)).unwrap();
+        let err = output
+            .flush_blocking(Duration::from_millis(100))
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(output.queued(), 1);
+    }
+}
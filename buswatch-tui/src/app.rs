@@ -1,6 +1,10 @@
 //! Application state and navigation logic.
 
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use anyhow::Result;
+use buswatch_types::{Clock, SystemClock};
 
 use crate::data::{History, MonitorData, Thresholds};
 use crate::source::DataSource;
@@ -99,7 +103,10 @@ pub struct App {
     pub theme: Theme,
 
     // Status message (temporary feedback)
-    pub status_message: Option<(String, std::time::Instant)>,
+    pub status_message: Option<(String, Instant)>,
+
+    // Time source for update ages, status messages and snapshots without a timestamp
+    clock: Arc<dyn Clock>,
 }
 
 impl App {
@@ -126,9 +133,22 @@ impl App {
             filter_active: false,
             theme: Theme::auto_detect(),
             status_message: None,
+            clock: Arc::new(SystemClock),
         }
     }
 
+    /// Use `clock` instead of the system clock, e.g. a
+    /// [`ManualClock`](buswatch_types::ManualClock) in tests.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// The current time according to the app's clock.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
     /// Returns a description of the current data source.
     pub fn source_description(&self) -> &str {
         self.source.description()
@@ -136,13 +156,13 @@ impl App {
 
     /// Set a temporary status message that will be shown for a few seconds.
     pub fn set_status_message(&mut self, message: String) {
-        self.status_message = Some((message, std::time::Instant::now()));
+        self.status_message = Some((message, self.now()));
     }
 
     /// Get the current status message if it hasn't expired (3 seconds).
     pub fn get_status_message(&self) -> Option<&str> {
         if let Some((msg, time)) = &self.status_message {
-            if time.elapsed() < std::time::Duration::from_secs(3) {
+            if self.now().saturating_duration_since(*time) < Duration::from_secs(3) {
                 return Some(msg);
             }
         }
@@ -185,15 +205,10 @@ impl App {
     /// Returns Ok(true) if new data was received, Ok(false) if no new data,
     /// or Err if there was an error.
     pub fn reload_data(&mut self) -> Result<bool> {
-        // Check for errors from the source
-        if let Some(err) = self.source.error() {
-            self.load_error = Some(err.to_string());
-            return Ok(false);
-        }
-
-        // Poll for new data
+        // Poll for new data, even after an error, so the source can recover
         if let Some(snapshot) = self.source.poll() {
-            let data = MonitorData::from_snapshot(snapshot, &self.thresholds);
+            let data =
+                MonitorData::from_snapshot_with_clock(snapshot, &self.thresholds, &*self.clock);
 
             // Record history before updating
             self.history.record(&data);
@@ -208,6 +223,10 @@ impl App {
             }
             Ok(true)
         } else {
+            // Check for errors from the source
+            if let Some(err) = self.source.error() {
+                self.load_error = Some(err.to_string());
+            }
             Ok(false)
         }
     }
@@ -0,0 +1,99 @@
+//! Helpers shared by the message bus subscription sources.
+
+use crate::source::Snapshot;
+use anyhow::Result;
+
+/// Decode a snapshot payload according to its content type.
+///
+/// JSON is used when the publisher says so; anything else is treated as
+/// CBOR, which is what Caryatid and the buswatch SDK send by default.
+pub(crate) fn decode_snapshot(content_type: Option<&str>, data: &[u8]) -> Result<Snapshot> {
+    if content_type.is_some_and(|ct| ct.eq_ignore_ascii_case("application/json")) {
+        Ok(serde_json::from_slice(data)?)
+    } else {
+        Ok(minicbor_serde::from_slice(data)?)
+    }
+}
+
+/// Reassembles full snapshots from per-module messages.
+///
+/// The SDK's Kafka and NATS outputs publish one message per module. Each
+/// message replaces that module's entry in the merged snapshot, so modules
+/// that publish at different moments all stay visible. Whole snapshots
+/// merge the same way.
+#[derive(Debug)]
+pub(crate) struct SnapshotMerger {
+    merged: Snapshot,
+}
+
+impl Default for SnapshotMerger {
+    fn default() -> Self {
+        // Snapshot::default() is stamped with the current time
+        Self {
+            merged: Snapshot::with_timestamp(0),
+        }
+    }
+}
+
+impl SnapshotMerger {
+    /// Merge a received snapshot and return the combined view.
+    pub(crate) fn merge(&mut self, part: Snapshot) -> &Snapshot {
+        self.merged.version = part.version;
+        self.merged.timestamp_ms = self.merged.timestamp_ms.max(part.timestamp_ms);
+        self.merged.modules.extend(part.modules);
+        &self.merged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_json_and_cbor() {
+        let snapshot = Snapshot::builder()
+            .timestamp_ms(1000)
+            .module("producer", |m| m.write("orders", |w| w.count(3)))
+            .build();
+
+        let json = serde_json::to_vec(&snapshot).unwrap();
+        let decoded = decode_snapshot(Some("application/json"), &json).unwrap();
+        assert_eq!(decoded, snapshot);
+
+        let cbor = minicbor_serde::to_vec(&snapshot).unwrap();
+        assert_eq!(decode_snapshot(None, &cbor).unwrap(), snapshot);
+        assert_eq!(
+            decode_snapshot(Some("application/cbor"), &cbor).unwrap(),
+            snapshot
+        );
+    }
+
+    #[test]
+    fn merger_replaces_modules_and_keeps_others() {
+        let mut merger = SnapshotMerger::default();
+
+        merger.merge(
+            Snapshot::builder()
+                .timestamp_ms(1000)
+                .module("producer", |m| m.write("orders", |w| w.count(1)))
+                .build(),
+        );
+        merger.merge(
+            Snapshot::builder()
+                .timestamp_ms(1500)
+                .module("consumer", |m| m.read("orders", |r| r.count(1)))
+                .build(),
+        );
+        let merged = merger.merge(
+            Snapshot::builder()
+                .timestamp_ms(1200)
+                .module("producer", |m| m.write("orders", |w| w.count(5)))
+                .build(),
+        );
+
+        assert_eq!(merged.len(), 2);
+        assert_eq!(merged.timestamp_ms, 1500);
+        assert_eq!(merged.modules["producer"].writes["orders"].count, 5);
+        assert_eq!(merged.modules["consumer"].reads["orders"].count, 1);
+    }
+}
@@ -94,6 +94,7 @@ mod tests {
         MonitorData {
             modules,
             last_updated: Instant::now(),
+            timestamp_ms: 0,
         }
     }
 
@@ -1,7 +1,6 @@
 //! Historical data tracking for sparklines and rate calculations.
 
 use std::collections::{HashMap, VecDeque};
-use std::time::Instant;
 
 use super::monitor::MonitorData;
 
@@ -18,8 +17,9 @@ pub struct History {
     pub module_reads: HashMap<String, VecDeque<u64>>,
     /// Historical write counts per module.
     pub module_writes: HashMap<String, VecDeque<u64>>,
-    /// Timestamps of snapshots for rate calculations.
-    pub timestamps: VecDeque<Instant>,
+    /// Snapshot timestamps (milliseconds since Unix epoch) for rate
+    /// calculations.
+    pub timestamps: VecDeque<u64>,
 }
 
 impl Default for History {
@@ -55,7 +55,9 @@ impl History {
             }
         }
 
-        self.timestamps.push_back(data.last_updated);
+        // The snapshot's own timestamp, so rates reflect when the producer
+        // sampled its counters rather than when the TUI happened to poll
+        self.timestamps.push_back(data.timestamp_ms);
         if self.timestamps.len() > MAX_HISTORY_SIZE {
             self.timestamps.pop_front();
         }
@@ -117,7 +119,7 @@ impl History {
 
         let current_time = self.timestamps.back()?;
         let previous_time = self.timestamps.get(self.timestamps.len() - 2)?;
-        let elapsed = current_time.duration_since(*previous_time).as_secs_f64();
+        let elapsed = current_time.saturating_sub(*previous_time) as f64 / 1000.0;
 
         if elapsed > 0.0 {
             Some(delta as f64 / elapsed)
@@ -134,6 +136,10 @@ mod tests {
     use buswatch_types::Snapshot;
 
     fn make_monitor_data(modules: Vec<(&str, u64, u64)>) -> MonitorData {
+        make_monitor_data_at(0, modules)
+    }
+
+    fn make_monitor_data_at(timestamp_ms: u64, modules: Vec<(&str, u64, u64)>) -> MonitorData {
         let module_data: Vec<ModuleData> = modules
             .into_iter()
             .map(|(name, reads, writes)| ModuleData {
@@ -148,7 +154,8 @@ mod tests {
 
         MonitorData {
             modules: module_data,
-            last_updated: Instant::now(),
+            last_updated: std::time::Instant::now(),
+            timestamp_ms,
         }
     }
 
@@ -253,19 +260,24 @@ mod tests {
     fn read_rate_calculated_from_last_two_readings() {
         let mut h = History::new();
 
-        let data1 = make_monitor_data(vec![("service", 100, 0)]);
+        let data1 = make_monitor_data_at(1000, vec![("service", 100, 0)]);
         h.record(&data1);
 
-        // Small delay to ensure non-zero elapsed time
-        std::thread::sleep(std::time::Duration::from_millis(10));
-
-        let data2 = make_monitor_data(vec![("service", 200, 0)]);
+        let data2 = make_monitor_data_at(1500, vec![("service", 200, 0)]);
         h.record(&data2);
 
-        let rate = h.get_read_rate("service");
-        assert!(rate.is_some());
-        // Rate should be positive (100 messages over ~10ms)
-        assert!(rate.unwrap() > 0.0);
+        // 100 messages over 500ms
+        assert_eq!(h.get_read_rate("service"), Some(200.0));
+    }
+
+    #[test]
+    fn read_rate_none_when_timestamps_do_not_advance() {
+        let mut h = History::new();
+
+        h.record(&make_monitor_data_at(1000, vec![("service", 100, 0)]));
+        h.record(&make_monitor_data_at(1000, vec![("service", 200, 0)]));
+
+        assert!(h.get_read_rate("service").is_none());
     }
 
     #[test]
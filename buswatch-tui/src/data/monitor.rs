@@ -8,6 +8,7 @@ use std::path::Path;
 use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use buswatch_types::{Clock, SystemClock};
 
 use crate::source::{ModuleMetrics, Snapshot};
 
@@ -114,6 +115,9 @@ pub struct MonitorData {
     pub modules: Vec<ModuleData>,
     /// When this snapshot was processed.
     pub last_updated: Instant,
+    /// When the snapshot was taken (milliseconds since Unix epoch), or when
+    /// it was processed if the snapshot carries no timestamp.
+    pub timestamp_ms: u64,
 }
 
 impl MonitorData {
@@ -135,6 +139,21 @@ impl MonitorData {
     ///
     /// This is the primary conversion method used by all data sources.
     pub fn from_snapshot(snapshot: Snapshot, thresholds: &Thresholds) -> Self {
+        Self::from_snapshot_with_clock(snapshot, thresholds, &SystemClock)
+    }
+
+    /// Convert a Snapshot into processed MonitorData, reading the time from
+    /// `clock`.
+    pub fn from_snapshot_with_clock(
+        snapshot: Snapshot,
+        thresholds: &Thresholds,
+        clock: &dyn Clock,
+    ) -> Self {
+        let timestamp_ms = match snapshot.timestamp_ms {
+            0 => clock.unix_millis(),
+            ts => ts,
+        };
+
         let mut modules: Vec<ModuleData> = snapshot
             .modules
             .into_iter()
@@ -146,7 +165,8 @@ impl MonitorData {
 
         Self {
             modules,
-            last_updated: Instant::now(),
+            last_updated: clock.now(),
+            timestamp_ms,
         }
     }
 
@@ -482,6 +502,17 @@ mod tests {
         let data = MonitorData::from_snapshot(snapshot, &default_thresholds());
         assert_eq!(data.modules.len(), 1);
         assert_eq!(data.modules[0].total_read, 42);
+        assert_eq!(data.timestamp_ms, 1000);
+    }
+
+    #[test]
+    fn missing_snapshot_timestamp_falls_back_to_clock() {
+        let clock = buswatch_types::ManualClock::new(5000);
+        let snapshot = Snapshot::builder().timestamp_ms(0).build();
+
+        let data = MonitorData::from_snapshot_with_clock(snapshot, &default_thresholds(), &clock);
+        assert_eq!(data.timestamp_ms, 5000);
+        assert_eq!(data.last_updated, clock.now());
     }
 
     #[test]
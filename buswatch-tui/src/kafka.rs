@@ -0,0 +1,96 @@
+//! Kafka subscription for receiving monitor snapshots.
+//!
+//! Consumes the records published by the buswatch SDK's Kafka output (one
+//! record per module, keyed by module name) and merges them into a single
+//! snapshot for the TUI.
+//!
+//! # Usage
+//!
+//! ```bash
+//! buswatch --kafka localhost:9092 --topic caryatid.monitor.snapshot
+//! ```
+
+use crate::bus::{decode_snapshot, SnapshotMerger};
+use crate::source::ChannelSource;
+use anyhow::{Context, Result};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::message::{BorrowedMessage, Headers};
+use rdkafka::Message;
+use std::time::Duration;
+
+/// Create a subscriber that consumes snapshots from a Kafka topic.
+///
+/// The consumer joins a private group and starts from the latest offset,
+/// so only snapshots published after startup are shown.
+///
+/// # Arguments
+///
+/// * `brokers` - Comma-separated bootstrap brokers
+/// * `topic` - The topic to consume from
+///
+/// # Returns
+///
+/// A tuple of (source, handle) where:
+/// - source is a ChannelSource for the TUI
+/// - handle is the background task reading from Kafka
+pub async fn create_kafka_subscriber(
+    brokers: &str,
+    topic: &str,
+) -> Result<(ChannelSource, tokio::task::JoinHandle<()>)> {
+    let consumer: StreamConsumer = ClientConfig::new()
+        .set("bootstrap.servers", brokers)
+        .set("group.id", format!("buswatch-{}", std::process::id()))
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "latest")
+        .create()
+        .context("Failed to create Kafka consumer")?;
+
+    consumer
+        .subscribe(&[topic])
+        .with_context(|| format!("Failed to subscribe to Kafka topic {}", topic))?;
+
+    // Create channel for forwarding to TUI
+    let (tx, source) = ChannelSource::create(&format!("kafka:{}", topic));
+
+    // Spawn background task to read records
+    let handle = tokio::spawn(async move {
+        let mut merger = SnapshotMerger::default();
+        loop {
+            match consumer.recv().await {
+                Ok(message) => {
+                    let Some(payload) = message.payload() else {
+                        continue;
+                    };
+                    match decode_snapshot(content_type(&message), payload) {
+                        Ok(part) => {
+                            if tx.send(merger.merge(part).clone()).is_err() {
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("Failed to deserialize snapshot: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    // librdkafka reconnects by itself; back off instead of spinning
+                    eprintln!("Consumer error: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+
+    Ok((source, handle))
+}
+
+/// The `content-type` header of a record, if present.
+fn content_type<'a>(message: &'a BorrowedMessage<'_>) -> Option<&'a str> {
+    message
+        .headers()?
+        .iter()
+        .find(|header| header.key.eq_ignore_ascii_case("content-type"))
+        .and_then(|header| header.value)
+        .and_then(|value| std::str::from_utf8(value).ok())
+}
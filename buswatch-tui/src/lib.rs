@@ -55,6 +55,10 @@
 //!
 //! # Monitor via TCP connection
 //! buswatch --connect localhost:9090
+//!
+//! # Consume snapshots from Kafka or NATS ("kafka" / "nats" features)
+//! buswatch --kafka localhost:9092 --topic caryatid.monitor.snapshot
+//! buswatch --nats nats://localhost:4222 --topic caryatid.monitor.snapshot
 //! ```
 //!
 //! ### As a library with file source
@@ -112,10 +116,22 @@ pub mod events;
 pub mod source;
 pub mod ui;
 
+// Shared decoding for the message bus subscriptions
+#[cfg(any(feature = "subscribe", feature = "kafka", feature = "nats"))]
+mod bus;
+
 // Caryatid integration module (requires "subscribe" feature)
 #[cfg(feature = "subscribe")]
 pub mod subscribe;
 
+// Kafka subscription (requires "kafka" feature)
+#[cfg(feature = "kafka")]
+pub mod kafka;
+
+// NATS subscription (requires "nats" feature)
+#[cfg(feature = "nats")]
+pub mod nats;
+
 // Re-export main types for convenience
 pub use app::App;
 pub use data::{HealthStatus, ModuleData, MonitorData, Thresholds, TopicRead, TopicWrite};
@@ -124,5 +140,8 @@ pub use source::{
     Snapshot, StreamSource, WriteMetrics,
 };
 
+#[cfg(any(feature = "rabbitmq-api", feature = "kafka-api", feature = "nats-api"))]
+pub use source::AdapterSource;
+
 // Re-export buswatch-types for direct access
 pub use buswatch_types;
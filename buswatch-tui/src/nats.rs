@@ -0,0 +1,71 @@
+//! NATS subscription for receiving monitor snapshots.
+//!
+//! Consumes the messages published by the buswatch SDK's NATS output (one
+//! message per module) and merges them into a single snapshot for the TUI.
+//!
+//! # Usage
+//!
+//! ```bash
+//! buswatch --nats nats://localhost:4222 --topic caryatid.monitor.snapshot
+//! ```
+
+use crate::bus::{decode_snapshot, SnapshotMerger};
+use crate::source::ChannelSource;
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+
+/// Create a subscriber that receives snapshots from a NATS subject.
+///
+/// # Arguments
+///
+/// * `url` - The NATS server URL
+/// * `subject` - The subject to subscribe to (wildcards are allowed)
+///
+/// # Returns
+///
+/// A tuple of (source, handle) where:
+/// - source is a ChannelSource for the TUI
+/// - handle is the background task reading from NATS
+pub async fn create_nats_subscriber(
+    url: &str,
+    subject: &str,
+) -> Result<(ChannelSource, tokio::task::JoinHandle<()>)> {
+    let client = async_nats::connect(url)
+        .await
+        .context("Failed to connect to NATS")?;
+
+    let mut subscriber = client
+        .subscribe(subject.to_string())
+        .await
+        .with_context(|| format!("Failed to subscribe to NATS subject {}", subject))?;
+
+    // Create channel for forwarding to TUI
+    let (tx, source) = ChannelSource::create(&format!("nats:{}", subject));
+
+    // Spawn background task to read messages. The client moves in with it
+    // to keep the connection open.
+    let handle = tokio::spawn(async move {
+        let _client = client;
+        let mut merger = SnapshotMerger::default();
+        while let Some(message) = subscriber.next().await {
+            let content_type = message
+                .headers
+                .as_ref()
+                .and_then(|headers| headers.get("Content-Type"))
+                .map(|value| value.as_str());
+
+            match decode_snapshot(content_type, &message.payload) {
+                Ok(part) => {
+                    if tx.send(merger.merge(part).clone()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to deserialize snapshot: {}", e);
+                }
+            }
+        }
+    });
+
+    Ok((source, handle))
+}
@@ -1,12 +1,19 @@
 //! Data source abstraction for receiving monitor snapshots.
 //!
 //! This module provides a trait-based abstraction for receiving monitor data
-//! from various sources (files, message bus channels, network streams, etc.).
+//! from various sources (files, message bus channels, network streams, broker
+//! APIs, etc.).
 
+#[cfg(any(feature = "rabbitmq-api", feature = "kafka-api", feature = "nats-api"))]
+mod adapter;
 mod channel;
 mod file;
 mod stream;
 
+#[cfg(feature = "rabbitmq-api")]
+pub(crate) use adapter::split_credentials;
+#[cfg(any(feature = "rabbitmq-api", feature = "kafka-api", feature = "nats-api"))]
+pub use adapter::AdapterSource;
 pub use channel::ChannelSource;
 pub use file::FileSource;
 pub use stream::StreamSource;
@@ -121,7 +121,7 @@ pub async fn create_subscriber(
     let handle = tokio::spawn(async move {
         while let Some(delivery) = consumer.next().await {
             match delivery {
-                Ok(delivery) => match minicbor_serde::from_slice::<Snapshot>(&delivery.data) {
+                Ok(delivery) => match decode_snapshot(&delivery) {
                     Ok(snapshot) => {
                         if tx.send(snapshot).is_err() {
                             break;
@@ -143,6 +143,12 @@ pub async fn create_subscriber(
     Ok((source, handle))
 }
 
+/// Decode a delivered snapshot according to its `content_type`.
+fn decode_snapshot(delivery: &lapin::message::Delivery) -> Result<Snapshot> {
+    let content_type = delivery.properties.content_type().as_ref();
+    crate::bus::decode_snapshot(content_type.map(|ct| ct.as_str()), &delivery.data)
+}
+
 /// Extract RabbitMQ URL and exchange from config.
 ///
 /// Supports two formats:
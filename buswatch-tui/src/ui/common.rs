@@ -151,7 +151,7 @@ pub fn render_status_bar(frame: &mut Frame, app: &App, area: Rect) {
     }
 
     let status = if let Some(ref data) = app.data {
-        let elapsed = data.last_updated.elapsed();
+        let elapsed = app.now().saturating_duration_since(data.last_updated);
 
         // Show breadcrumb
         let breadcrumb = app.breadcrumb();
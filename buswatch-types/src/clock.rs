@@ -0,0 +1,143 @@
+//! Time sources for producers and consumers of snapshots.
+//!
+//! Anything that computes rates, pending durations or timestamps reads the
+//! time through a [`Clock`], so tests can substitute a [`ManualClock`] and
+//! advance time explicitly instead of sleeping.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A source of monotonic and wall-clock time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// The current monotonic time, for measuring durations.
+    fn now(&self) -> Instant;
+
+    /// The current wall-clock time in milliseconds since the Unix epoch,
+    /// used for snapshot timestamps.
+    fn unix_millis(&self) -> u64;
+}
+
+/// The system clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+/// A clock that only moves when told to.
+///
+/// Clones share the same time, so a test can keep one and hand another to
+/// the code under test.
+///
+/// # Example
+///
+/// ```rust
+/// use buswatch_types::{Clock, ManualClock};
+/// use std::time::Duration;
+///
+/// let clock = ManualClock::new(1_703_160_000_000);
+/// let start = clock.now();
+///
+/// clock.advance(Duration::from_secs(2));
+///
+/// assert_eq!(clock.now() - start, Duration::from_secs(2));
+/// assert_eq!(clock.unix_millis(), 1_703_160_002_000);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+    start: Instant,
+    start_unix_millis: u64,
+    elapsed_nanos: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// Create a clock whose wall-clock time starts at `unix_millis`.
+    pub fn new(unix_millis: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            start_unix_millis: unix_millis,
+            elapsed_nanos: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, by: Duration) {
+        self.elapsed_nanos
+            .fetch_add(by.as_nanos() as u64, Ordering::SeqCst);
+    }
+
+    /// Time advanced since the clock was created.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_nanos.load(Ordering::SeqCst))
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        self.start_unix_millis + self.elapsed().as_millis() as u64
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Arc<C> {
+    fn now(&self) -> Instant {
+        (**self).now()
+    }
+
+    fn unix_millis(&self) -> u64 {
+        (**self).unix_millis()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock_only_moves_when_advanced() {
+        let clock = ManualClock::new(1000);
+        let start = clock.now();
+
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.unix_millis(), 1000);
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.now() - start, Duration::from_millis(1500));
+        assert_eq!(clock.unix_millis(), 2500);
+    }
+
+    #[test]
+    fn manual_clock_clones_share_time() {
+        let clock = ManualClock::default();
+        let other = clock.clone();
+
+        other.advance(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn system_clock_is_after_2020() {
+        assert!(SystemClock.unix_millis() > 1_577_836_800_000);
+    }
+}
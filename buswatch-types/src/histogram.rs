@@ -0,0 +1,151 @@
+//! Latency distribution type.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::Microseconds;
+
+/// Distribution of how long operations on a topic took to complete.
+///
+/// Buckets are cumulative since the stream was created, in the same way as
+/// Prometheus histograms, so consumers can compute deltas between snapshots.
+/// `counts` has one more entry than `bounds`: the final entry holds
+/// observations larger than the last bound.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "minicbor", derive(minicbor::Encode, minicbor::Decode))]
+pub struct LatencyHistogram {
+    /// Inclusive upper bound of each bucket, in ascending order.
+    #[cfg_attr(feature = "minicbor", n(0))]
+    pub bounds: Vec<Microseconds>,
+
+    /// Number of observations that fell into each bucket.
+    #[cfg_attr(feature = "minicbor", n(1))]
+    pub counts: Vec<u64>,
+
+    /// Total number of observations.
+    #[cfg_attr(feature = "minicbor", n(2))]
+    pub count: u64,
+
+    /// Sum of all observations.
+    #[cfg_attr(feature = "minicbor", n(3))]
+    pub sum: Microseconds,
+}
+
+impl LatencyHistogram {
+    /// Create an empty histogram with the given bucket bounds.
+    ///
+    /// Bounds should be in ascending order.
+    pub fn new(bounds: Vec<Microseconds>) -> Self {
+        let counts = vec![0; bounds.len() + 1];
+        Self {
+            bounds,
+            counts,
+            count: 0,
+            sum: Microseconds::default(),
+        }
+    }
+
+    /// Record a single observation.
+    pub fn record(&mut self, value: impl Into<Microseconds>) {
+        let value = value.into();
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        if let Some(count) = self.counts.get_mut(bucket) {
+            *count += 1;
+        }
+        self.count += 1;
+        self.sum = Microseconds(self.sum.0.saturating_add(value.0));
+    }
+
+    /// Check if no observations have been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Mean of all observations, if any were recorded.
+    pub fn mean(&self) -> Option<Microseconds> {
+        (self.count > 0).then(|| Microseconds(self.sum.0 / self.count))
+    }
+
+    /// Estimate a quantile (0.0 to 1.0) as the upper bound of the bucket
+    /// it falls into.
+    ///
+    /// Observations beyond the last bound are reported as the last bound.
+    pub fn quantile(&self, q: f64) -> Option<Microseconds> {
+        if self.count == 0 {
+            return None;
+        }
+
+        // Rank of the target observation, rounded up without needing std
+        let exact = (self.count as f64) * q.clamp(0.0, 1.0);
+        let mut target = exact as u64;
+        if (target as f64) < exact {
+            target += 1;
+        }
+        let target = target.max(1);
+
+        let mut seen = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return self.bounds.get(i).or(self.bounds.last()).copied();
+            }
+        }
+        self.bounds.last().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram() -> LatencyHistogram {
+        LatencyHistogram::new(vec![
+            Microseconds::from_millis(1),
+            Microseconds::from_millis(10),
+            Microseconds::from_millis(100),
+        ])
+    }
+
+    #[test]
+    fn new_histogram_is_empty() {
+        let h = histogram();
+        assert!(h.is_empty());
+        assert_eq!(h.counts, vec![0, 0, 0, 0]);
+        assert!(h.mean().is_none());
+        assert!(h.quantile(0.5).is_none());
+    }
+
+    #[test]
+    fn record_places_values_in_buckets() {
+        let mut h = histogram();
+        h.record(Microseconds::from_micros(500));
+        h.record(Microseconds::from_millis(1)); // inclusive upper bound
+        h.record(Microseconds::from_millis(50));
+        h.record(Microseconds::from_secs(2)); // overflow
+
+        assert_eq!(h.counts, vec![2, 0, 1, 1]);
+        assert_eq!(h.count, 4);
+        assert_eq!(h.sum.as_micros(), 500 + 1_000 + 50_000 + 2_000_000);
+    }
+
+    #[test]
+    fn mean_and_quantiles() {
+        let mut h = histogram();
+        for _ in 0..9 {
+            h.record(Microseconds::from_micros(200));
+        }
+        h.record(Microseconds::from_millis(80));
+
+        assert_eq!(h.mean(), Some(Microseconds::from_micros(8_180)));
+        assert_eq!(h.quantile(0.5), Some(Microseconds::from_millis(1)));
+        assert_eq!(h.quantile(0.99), Some(Microseconds::from_millis(100)));
+    }
+
+    #[test]
+    fn overflow_quantile_reports_last_bound() {
+        let mut h = histogram();
+        h.record(Microseconds::from_secs(5));
+        assert_eq!(h.quantile(1.0), Some(Microseconds::from_millis(100)));
+    }
+}
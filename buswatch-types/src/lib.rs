@@ -56,12 +56,18 @@
 
 extern crate alloc;
 
+#[cfg(feature = "std")]
+mod clock;
 mod duration;
+mod histogram;
 mod metrics;
 mod snapshot;
 mod version;
 
+#[cfg(feature = "std")]
+pub use clock::*;
 pub use duration::*;
+pub use histogram::*;
 pub use metrics::*;
 pub use snapshot::*;
 pub use version::*;
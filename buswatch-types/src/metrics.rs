@@ -3,7 +3,7 @@
 use alloc::collections::BTreeMap;
 use alloc::string::String;
 
-use crate::Microseconds;
+use crate::{LatencyHistogram, Microseconds};
 
 /// Metrics for a single module/consumer/producer in the message bus.
 ///
@@ -20,6 +20,22 @@ pub struct ModuleMetrics {
     /// Metrics for topics this module writes to (publications).
     #[cfg_attr(feature = "minicbor", n(1))]
     pub writes: BTreeMap<String, WriteMetrics>,
+
+    /// Number of idle topics removed from this module's metrics.
+    ///
+    /// An evicted topic that becomes active again starts counting from zero.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(2))]
+    pub evicted_topics: Option<u64>,
+
+    /// Number of topics in the last snapshot this module emitted, excluding
+    /// its own.
+    ///
+    /// Set by modules that export snapshots, such as the SDK's self-metrics
+    /// module, to track how many topics each snapshot carries.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(3))]
+    pub tracked_topics: Option<u64>,
 }
 
 impl ModuleMetrics {
@@ -75,6 +91,44 @@ pub struct ReadMetrics {
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     #[cfg_attr(feature = "minicbor", n(3))]
     pub rate: Option<f64>,
+
+    /// Number of reads currently being handled.
+    ///
+    /// When several reads are outstanding at once, `pending` reports the
+    /// oldest of them.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(4))]
+    pub inflight: Option<u64>,
+
+    /// Distribution of how long reads took to handle.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(5))]
+    pub latency: Option<LatencyHistogram>,
+
+    /// Number of read messages whose handling failed.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(6))]
+    pub errors: Option<u64>,
+
+    /// Where `backlog` came from, if it is set.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(7))]
+    pub backlog_source: Option<BacklogSource>,
+}
+
+/// How a read topic's backlog was determined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+#[cfg_attr(feature = "minicbor", derive(minicbor::Encode, minicbor::Decode))]
+#[cfg_attr(feature = "minicbor", cbor(index_only))]
+pub enum BacklogSource {
+    /// Reported by the bus itself, e.g. queue depth or consumer lag.
+    #[cfg_attr(feature = "minicbor", n(0))]
+    Measured,
+    /// Derived from counted writes and reads.
+    #[cfg_attr(feature = "minicbor", n(1))]
+    Estimated,
 }
 
 impl ReadMetrics {
@@ -122,6 +176,26 @@ pub struct WriteMetrics {
     #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
     #[cfg_attr(feature = "minicbor", n(2))]
     pub rate: Option<f64>,
+
+    /// Number of writes currently in progress.
+    ///
+    /// When several writes are outstanding at once, `pending` reports the
+    /// oldest of them.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(3))]
+    pub inflight: Option<u64>,
+
+    /// Distribution of how long writes took to complete.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(4))]
+    pub latency: Option<LatencyHistogram>,
+
+    /// Number of writes that failed.
+    ///
+    /// Failed writes are not included in `count`.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    #[cfg_attr(feature = "minicbor", n(5))]
+    pub errors: Option<u64>,
 }
 
 impl WriteMetrics {
@@ -155,6 +229,8 @@ impl WriteMetrics {
 pub struct ModuleMetricsBuilder {
     reads: BTreeMap<String, ReadMetrics>,
     writes: BTreeMap<String, WriteMetrics>,
+    evicted_topics: Option<u64>,
+    tracked_topics: Option<u64>,
 }
 
 impl ModuleMetricsBuilder {
@@ -183,11 +259,25 @@ impl ModuleMetricsBuilder {
         self
     }
 
+    /// Set the number of evicted topics.
+    pub fn evicted_topics(mut self, evicted: u64) -> Self {
+        self.evicted_topics = Some(evicted);
+        self
+    }
+
+    /// Set the number of topics in the last emitted snapshot.
+    pub fn tracked_topics(mut self, tracked: u64) -> Self {
+        self.tracked_topics = Some(tracked);
+        self
+    }
+
     /// Build the module metrics.
     pub fn build(self) -> ModuleMetrics {
         ModuleMetrics {
             reads: self.reads,
             writes: self.writes,
+            evicted_topics: self.evicted_topics,
+            tracked_topics: self.tracked_topics,
         }
     }
 }
@@ -199,6 +289,10 @@ pub struct ReadMetricsBuilder {
     backlog: Option<u64>,
     pending: Option<Microseconds>,
     rate: Option<f64>,
+    inflight: Option<u64>,
+    latency: Option<LatencyHistogram>,
+    errors: Option<u64>,
+    backlog_source: Option<BacklogSource>,
 }
 
 impl ReadMetricsBuilder {
@@ -231,6 +325,30 @@ impl ReadMetricsBuilder {
         self
     }
 
+    /// Set the number of operations currently in flight.
+    pub fn inflight(mut self, inflight: u64) -> Self {
+        self.inflight = Some(inflight);
+        self
+    }
+
+    /// Set the latency distribution.
+    pub fn latency(mut self, latency: LatencyHistogram) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Set the number of failed reads.
+    pub fn errors(mut self, errors: u64) -> Self {
+        self.errors = Some(errors);
+        self
+    }
+
+    /// Set where the backlog came from.
+    pub fn backlog_source(mut self, source: BacklogSource) -> Self {
+        self.backlog_source = Some(source);
+        self
+    }
+
     /// Build the read metrics.
     pub fn build(self) -> ReadMetrics {
         ReadMetrics {
@@ -238,6 +356,10 @@ impl ReadMetricsBuilder {
             backlog: self.backlog,
             pending: self.pending,
             rate: self.rate,
+            inflight: self.inflight,
+            latency: self.latency,
+            errors: self.errors,
+            backlog_source: self.backlog_source,
         }
     }
 }
@@ -248,6 +370,9 @@ pub struct WriteMetricsBuilder {
     count: u64,
     pending: Option<Microseconds>,
     rate: Option<f64>,
+    inflight: Option<u64>,
+    latency: Option<LatencyHistogram>,
+    errors: Option<u64>,
 }
 
 impl WriteMetricsBuilder {
@@ -274,12 +399,33 @@ impl WriteMetricsBuilder {
         self
     }
 
+    /// Set the number of operations currently in flight.
+    pub fn inflight(mut self, inflight: u64) -> Self {
+        self.inflight = Some(inflight);
+        self
+    }
+
+    /// Set the latency distribution.
+    pub fn latency(mut self, latency: LatencyHistogram) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Set the number of failed writes.
+    pub fn errors(mut self, errors: u64) -> Self {
+        self.errors = Some(errors);
+        self
+    }
+
     /// Build the write metrics.
     pub fn build(self) -> WriteMetrics {
         WriteMetrics {
             count: self.count,
             pending: self.pending,
             rate: self.rate,
+            inflight: self.inflight,
+            latency: self.latency,
+            errors: self.errors,
         }
     }
 }
@@ -438,11 +584,13 @@ mod tests {
             .count(1000)
             .pending(Duration::from_millis(500))
             .rate(100.0)
+            .errors(3)
             .build();
 
         assert_eq!(w.count, 1000);
         assert_eq!(w.pending, Some(Microseconds::from_millis(500)));
         assert_eq!(w.rate, Some(100.0));
+        assert_eq!(w.errors, Some(3));
     }
 
     #[test]
@@ -671,6 +819,23 @@ mod tests {
         assert_eq!(w.count, 0);
     }
 
+    #[test]
+    fn module_metrics_builder_evicted_topics() {
+        let m = ModuleMetrics::builder()
+            .read("events", |r| r.count(1))
+            .evicted_topics(4)
+            .build();
+        assert_eq!(m.evicted_topics, Some(4));
+        assert!(ModuleMetrics::builder().build().evicted_topics.is_none());
+    }
+
+    #[test]
+    fn module_metrics_builder_tracked_topics() {
+        let m = ModuleMetrics::builder().tracked_topics(12).build();
+        assert_eq!(m.tracked_topics, Some(12));
+        assert!(ModuleMetrics::builder().build().tracked_topics.is_none());
+    }
+
     #[test]
     fn module_metrics_builder_default() {
         let b = ModuleMetricsBuilder::default();
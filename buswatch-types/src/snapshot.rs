@@ -386,6 +386,31 @@ mod tests {
         assert_eq!(snapshot, parsed);
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip_with_inflight_and_latency() {
+        use crate::{LatencyHistogram, Microseconds};
+
+        let mut latency = LatencyHistogram::new(vec![Microseconds::from_millis(10)]);
+        latency.record(Microseconds::from_millis(3));
+
+        let snapshot = Snapshot::builder()
+            .timestamp_ms(1703160000000)
+            .module("test", |m| {
+                m.read("topic", |r| {
+                    r.count(42).inflight(3).latency(latency.clone())
+                })
+                .write("out", |w| w.count(7).inflight(1))
+            })
+            .build();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let parsed: Snapshot = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(snapshot, parsed);
+        assert_eq!(parsed.modules["test"].reads["topic"].inflight, Some(3));
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn serde_json_structure() {
@@ -431,6 +456,22 @@ mod tests {
         assert_eq!(service.reads.get("input").unwrap().backlog, Some(5));
     }
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_backlog_source_is_lowercase() {
+        let s = Snapshot::builder()
+            .module("test", |m| {
+                m.read("topic", |r| {
+                    r.backlog(5).backlog_source(crate::BacklogSource::Estimated)
+                })
+            })
+            .build();
+
+        let json: serde_json::Value = serde_json::to_value(&s).unwrap();
+        let read = &json["modules"]["test"]["reads"]["topic"];
+        assert_eq!(read["backlog_source"], "estimated");
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn serde_pretty_print() {
@@ -449,7 +490,13 @@ mod tests {
     fn test_minicbor_roundtrip() {
         let snapshot = Snapshot::builder()
             .timestamp_ms(1703160000000)
-            .module("test", |m| m.read("topic", |r| r.count(42).backlog(5)))
+            .module("test", |m| {
+                m.read("topic", |r| {
+                    r.count(42)
+                        .backlog(5)
+                        .backlog_source(crate::BacklogSource::Measured)
+                })
+            })
             .build();
 
         let bytes = minicbor::to_vec(&snapshot).unwrap();